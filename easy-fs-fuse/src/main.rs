@@ -1,7 +1,20 @@
-use clap::{App, Arg};
-use easy_fs::{BlockDevice, EasyFileSystem};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use easy_fs::{
+    block_cache_stats, block_cache_sync_all, dump, fsck, BlockDevice, EasyFileSystem, Inode,
+};
+#[cfg(test)]
+use easy_fs::{
+    get_block_cache, read_block_cached, write_block_cached, DirEntryError, Fat32FileSystem,
+    Fat32Inode, FsckProblem, SYMLINK_TARGET_LIMIT,
+};
+#[cfg(test)]
+use std::collections::BTreeMap;
 use std::fs::{read_dir, File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+#[cfg(test)]
+use std::ops::Range;
+#[cfg(test)]
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -29,12 +42,121 @@ impl BlockDevice for BlockFile {
     }
 }
 
-fn main() {
-    easy_fs_pack().expect("Error when packing easy-fs!");
+// 内存中的块设备，用于模拟崩溃。前writes_left次写入会持久化，之后的写入只在崩溃前可见，
+// 相当于写完这么多次之后掉电。
+#[cfg(test)]
+struct CrashDevice {
+    // 掉电后仍然保留的内容
+    persistent: Mutex<Vec<[u8; BLOCK_SZ]>>,
+    // 掉电时丢失的写入
+    volatile: Mutex<BTreeMap<usize, [u8; BLOCK_SZ]>>,
+    writes_left: Mutex<usize>,
+}
+
+#[cfg(test)]
+impl CrashDevice {
+    fn new(image: Vec<[u8; BLOCK_SZ]>, writes_left: usize) -> Arc<Self> {
+        Arc::new(Self {
+            persistent: Mutex::new(image),
+            volatile: Mutex::new(BTreeMap::new()),
+            writes_left: Mutex::new(writes_left),
+        })
+    }
+
+    // 掉电后磁盘上的内容
+    fn image(&self) -> Vec<[u8; BLOCK_SZ]> {
+        self.persistent.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl BlockDevice for CrashDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        match self.volatile.lock().unwrap().get(&block_id) {
+            Some(data) => buf.copy_from_slice(data),
+            None => buf.copy_from_slice(&self.persistent.lock().unwrap()[block_id]),
+        }
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut writes_left = self.writes_left.lock().unwrap();
+        if *writes_left > 0 {
+            *writes_left -= 1;
+            self.persistent.lock().unwrap()[block_id].copy_from_slice(buf);
+        } else {
+            let mut data = [0u8; BLOCK_SZ];
+            data.copy_from_slice(buf);
+            self.volatile.lock().unwrap().insert(block_id, data);
+        }
+    }
+
+    fn handle_irq(&self) {
+        unimplemented!();
+    }
 }
 
-fn easy_fs_pack() -> std::io::Result<()> {
-    let matches = App::new("EasyFileSystem packer")
+// 日志区域在超级块之后，第一个块是日志头
+#[cfg(test)]
+const JOURNAL_AREA: Range<usize> = 1..65;
+
+// 在事务进行期间写回缓存的块设备，模拟内核中事务在读写磁盘时被切换出去，其他任务调用sync或周期性写回。
+// 第一次将块写入日志时，写回所有的块缓存。记录写入的块号，以检查写入的顺序。
+#[cfg(test)]
+struct SyncingDevice {
+    inner: Arc<CrashDevice>,
+    armed: AtomicBool,
+    writes: Mutex<Vec<usize>>,
+}
+
+#[cfg(test)]
+impl BlockDevice for SyncingDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.inner.read_block(block_id, buf);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.inner.write_block(block_id, buf);
+        self.writes.lock().unwrap().push(block_id);
+        if block_id > JOURNAL_AREA.start
+            && JOURNAL_AREA.contains(&block_id)
+            && self.armed.swap(false, Ordering::SeqCst)
+        {
+            block_cache_sync_all();
+        }
+    }
+
+    fn handle_irq(&self) {
+        unimplemented!();
+    }
+}
+
+// 不指定大小时，镜像为16MiB，inode位图占一个块（最多4095个文件）
+const DEFAULT_TOTAL_BLOCKS: &str = "32768";
+const DEFAULT_INODES: &str = "4096";
+// 一个inode位图块能表示的inode数
+const INODES_PER_BITMAP_BLOCK: u32 = BLOCK_SZ as u32 * 8;
+
+// 命令行参数：不带子命令时打包应用，子命令用于操作已有的镜像
+fn app() -> App<'static, 'static> {
+    let image_arg = Arg::with_name("image")
+        .required(true)
+        .help("Path of the easy-fs image");
+    let path_arg = Arg::with_name("path")
+        .required(true)
+        .help("Absolute path inside the image");
+    let size_args = [
+        Arg::with_name("blocks")
+            .long("blocks")
+            .takes_value(true)
+            .default_value(DEFAULT_TOTAL_BLOCKS)
+            .help("Total number of 512-byte blocks in the image"),
+        Arg::with_name("inodes")
+            .long("inodes")
+            .takes_value(true)
+            .default_value(DEFAULT_INODES)
+            .help("Number of inodes, rounded up to a multiple of 4096"),
+    ];
+    App::new("EasyFileSystem packer")
         .arg(
             Arg::with_name("source")
                 .short("s")
@@ -49,25 +171,499 @@ fn easy_fs_pack() -> std::io::Result<()> {
                 .takes_value(true)
                 .help("Executable target dir(with backslash)"),
         )
-        .get_matches();
+        .args(&size_args)
+        .subcommand(
+            SubCommand::with_name("mkfs")
+                .about("Create an empty image")
+                .arg(image_arg.clone())
+                .args(&size_args),
+        )
+        .subcommand(
+            SubCommand::with_name("ls")
+                .about("List a directory")
+                .arg(image_arg.clone())
+                .arg(Arg::with_name("path").default_value("/")),
+        )
+        .subcommand(
+            SubCommand::with_name("cat")
+                .about("Print a file to stdout")
+                .arg(image_arg.clone())
+                .arg(path_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("put")
+                .about("Copy a host file into the image")
+                .arg(image_arg.clone())
+                .arg(Arg::with_name("host").required(true).help("Host file"))
+                .arg(path_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("Copy a file out of the image")
+                .arg(image_arg.clone())
+                .arg(path_arg.clone())
+                .arg(Arg::with_name("host").required(true).help("Host file")),
+        )
+        .subcommand(
+            SubCommand::with_name("rm")
+                .about("Remove a file or an empty directory")
+                .arg(image_arg.clone())
+                .arg(path_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("mkdir")
+                .about("Create a directory")
+                .arg(image_arg.clone())
+                .arg(path_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("ln")
+                .about("Create a symbolic link")
+                .arg(image_arg.clone())
+                .arg(
+                    Arg::with_name("target")
+                        .required(true)
+                        .help("Path the link points to"),
+                )
+                .arg(path_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("stat")
+                .about("Show the metadata of a file or directory")
+                .arg(image_arg.clone())
+                .arg(path_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("df")
+                .about("Show block and inode usage")
+                .arg(image_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("fsck")
+                .about("Check the consistency of an image")
+                .arg(image_arg.clone())
+                .arg(
+                    Arg::with_name("repair")
+                        .short("r")
+                        .long("repair")
+                        .help("Repair the problems found"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("dump")
+                .about("Print the layout, inode table and directory tree of an image")
+                .arg(image_arg),
+        )
+}
+
+fn main() {
+    let matches = app().get_matches();
+    if let ("fsck", Some(args)) = matches.subcommand() {
+        match easy_fs_fsck(args.value_of("image").unwrap(), args.is_present("repair")) {
+            Ok(code) => std::process::exit(code),
+            Err(err) => {
+                eprintln!("easy-fs-fuse: {}", err);
+                std::process::exit(8);
+            }
+        }
+    }
+    if let Err(err) = run(&matches, &mut std::io::stdout()) {
+        eprintln!("easy-fs-fuse: {}", err);
+        std::process::exit(1);
+    }
+}
+
+// 执行fsck之外的子命令，输出写到out
+fn run(matches: &ArgMatches, out: &mut dyn Write) -> std::io::Result<()> {
+    match matches.subcommand() {
+        ("mkfs", Some(args)) => easy_fs_mkfs(args),
+        ("ls", Some(args)) => easy_fs_ls(args, out),
+        ("cat", Some(args)) => easy_fs_cat(args, out),
+        ("put", Some(args)) => easy_fs_put(args),
+        ("get", Some(args)) => easy_fs_get(args),
+        ("rm", Some(args)) => easy_fs_rm(args),
+        ("mkdir", Some(args)) => easy_fs_mkdir(args),
+        ("ln", Some(args)) => easy_fs_ln(args),
+        ("stat", Some(args)) => easy_fs_stat(args, out),
+        ("df", Some(args)) => easy_fs_df(args, out),
+        ("dump", Some(args)) => easy_fs_dump(args.value_of("image").unwrap(), out),
+        _ => easy_fs_pack(matches, out),
+    }
+}
+
+fn error(kind: ErrorKind, msg: String) -> std::io::Error {
+    std::io::Error::new(kind, msg)
+}
+
+// 按照--blocks和--inodes创建镜像文件，并在其中新建文件系统，返回根目录
+fn create_image(path: &str, args: &ArgMatches) -> std::io::Result<Arc<Inode>> {
+    let parse = |name: &str| {
+        let value = args.value_of(name).unwrap();
+        value.parse::<u32>().ok().filter(|n| *n > 0).ok_or_else(|| {
+            error(
+                ErrorKind::InvalidInput,
+                format!("bad --{}: {}", name, value),
+            )
+        })
+    };
+    let total_blocks = parse("blocks")?;
+    let inode_bitmap_blocks = parse("inodes")?.div_ceil(INODES_PER_BITMAP_BLOCK);
+    let min_blocks = EasyFileSystem::min_total_blocks(inode_bitmap_blocks);
+    if total_blocks < min_blocks {
+        return Err(error(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} inodes need at least {} blocks",
+                inode_bitmap_blocks * INODES_PER_BITMAP_BLOCK,
+                min_blocks
+            ),
+        ));
+    }
+    let f = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    f.set_len(total_blocks as u64 * BLOCK_SZ as u64)?;
+    let block_file = Arc::new(BlockFile(Mutex::new(f)));
+    let efs = EasyFileSystem::create(block_file, total_blocks, inode_bitmap_blocks);
+    Ok(Arc::new(EasyFileSystem::root_inode(&efs)))
+}
+
+fn open_image(path: &str, writable: bool) -> std::io::Result<Arc<dyn BlockDevice>> {
+    let f = OpenOptions::new().read(true).write(writable).open(path)?;
+    Ok(Arc::new(BlockFile(Mutex::new(f))))
+}
+
+// 检查镜像。返回值作为进程的退出码：0表示没有问题，1表示问题都已修复，4表示还有未修复的问题。
+fn easy_fs_fsck(path: &str, repair: bool) -> std::io::Result<i32> {
+    let block_file = open_image(path, repair)?;
+    let report = fsck(&block_file, repair);
+    if report.journal_replayed {
+        println!("journal: replayed a committed transaction");
+    }
+    for problem in report.problems.iter() {
+        println!("{}", problem);
+    }
+    println!(
+        "{}: {} files, {} directories, {} blocks in use, {} problems",
+        path,
+        report.files,
+        report.directories,
+        report.data_blocks,
+        report.problems.len()
+    );
+    if report.is_clean() {
+        return Ok(0);
+    }
+    if !repair {
+        return Ok(4);
+    }
+    // 再检查一遍，确认修复后的镜像是一致的
+    let check = fsck(&block_file, false);
+    for problem in check.problems.iter() {
+        println!("after repair: {}", problem);
+    }
+    Ok(if check.is_clean() { 1 } else { 4 })
+}
+
+fn easy_fs_dump(path: &str, out: &mut dyn Write) -> std::io::Result<()> {
+    let block_file = open_image(path, false)?;
+    write!(out, "{}", dump(&block_file))
+}
+
+// 打开镜像中的文件系统，返回根目录。日志中有已提交的事务时会先重做。
+fn open_fs(args: &ArgMatches) -> std::io::Result<Arc<Inode>> {
+    let block_file = open_image(args.value_of("image").unwrap(), true)?;
+    let efs = EasyFileSystem::open(block_file);
+    Ok(Arc::new(EasyFileSystem::root_inode(&efs)))
+}
+
+// 将路径分为父目录和最后一个分量
+fn split_path(path: &str) -> std::io::Result<(&str, &str)> {
+    let path = path.trim_end_matches('/');
+    match path.rfind('/') {
+        Some(pos) if !path[pos + 1..].is_empty() => Ok((&path[..pos], &path[pos + 1..])),
+        None if !path.is_empty() => Ok(("", path)),
+        _ => Err(error(
+            ErrorKind::InvalidInput,
+            format!("bad path: {}", path),
+        )),
+    }
+}
+
+// 解析一个路径最多跟随的符号链接数，超过时认为符号链接形成了循环
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+// 从根目录开始，逐级查找路径上的inode。与内核的lookup一样跟随路径中的符号链接，处理"."和".."。
+// follow为false时，不跟随最后一个分量的符号链接，返回符号链接本身。
+fn lookup(root_inode: &Arc<Inode>, path: &str, follow: bool) -> std::io::Result<Arc<Inode>> {
+    let mut stack = vec![Arc::clone(root_inode)];
+    walk(&mut stack, path, follow, &mut 0)
+        .map_err(|msg| error(ErrorKind::NotFound, format!("{}: {}", path, msg)))?;
+    Ok(stack.pop().unwrap())
+}
+
+// 从stack栈顶的目录出发解析path，stack中依次是从根目录到当前inode的各级inode，用于处理".."。
+// 相对路径的符号链接从它所在的目录开始解析。follows记录已经跟随的符号链接数。
+fn walk(
+    stack: &mut Vec<Arc<Inode>>,
+    path: &str,
+    follow: bool,
+    follows: &mut usize,
+) -> Result<(), &'static str> {
+    if path.starts_with('/') {
+        stack.truncate(1);
+    }
+    let names: Vec<&str> = path
+        .split('/')
+        .filter(|name| !name.is_empty() && *name != ".")
+        .collect();
+    for (i, name) in names.iter().enumerate() {
+        let last = i + 1 == names.len();
+        let dir = Arc::clone(stack.last().unwrap());
+        if !dir.is_dir() {
+            return Err("not a directory");
+        }
+        if *name == ".." {
+            if stack.len() > 1 {
+                stack.pop();
+            }
+            continue;
+        }
+        let inode = dir.find(name).ok_or("no such file")?;
+        match inode.readlink() {
+            Some(target) if follow || !last => {
+                *follows += 1;
+                if *follows > MAX_SYMLINK_FOLLOWS {
+                    return Err("too many levels of symbolic links");
+                }
+                walk(stack, &target, true, follows)?;
+            }
+            _ => stack.push(inode),
+        }
+    }
+    Ok(())
+}
+
+fn read_all(inode: &Inode) -> Vec<u8> {
+    let mut data = vec![0u8; inode.size() as usize];
+    let len = inode.read_at(0, &mut data);
+    data.truncate(len);
+    data
+}
+
+fn easy_fs_mkfs(args: &ArgMatches) -> std::io::Result<()> {
+    create_image(args.value_of("image").unwrap(), args)?;
+    block_cache_sync_all();
+    Ok(())
+}
+
+fn easy_fs_ls(args: &ArgMatches, out: &mut dyn Write) -> std::io::Result<()> {
+    let root_inode = open_fs(args)?;
+    let path = args.value_of("path").unwrap();
+    // 路径本身是符号链接时列出链接，而不是它指向的目录
+    let inode = lookup(&root_inode, path, false)?;
+    let mut print = |name: &str, inode: &Inode| {
+        let stat = inode.stat();
+        match inode.readlink() {
+            Some(target) => writeln!(
+                out,
+                "{:>6} l {:>10} {} -> {}",
+                stat.inode, stat.size, name, target
+            ),
+            None => {
+                let type_ = if stat.is_dir { 'd' } else { '-' };
+                writeln!(
+                    out,
+                    "{:>6} {} {:>10} {}",
+                    stat.inode, type_, stat.size, name
+                )
+            }
+        }
+    };
+    if !inode.is_dir() {
+        return print(path, &inode);
+    }
+    for name in inode.ls() {
+        print(&name, &inode.find(&name).unwrap())?;
+    }
+    Ok(())
+}
+
+fn easy_fs_cat(args: &ArgMatches, out: &mut dyn Write) -> std::io::Result<()> {
+    let root_inode = open_fs(args)?;
+    let path = args.value_of("path").unwrap();
+    let inode = lookup(&root_inode, path, true)?;
+    if inode.is_dir() {
+        return Err(error(
+            ErrorKind::InvalidInput,
+            format!("{}: is a directory", path),
+        ));
+    }
+    out.write_all(&read_all(&inode))
+}
+
+fn easy_fs_put(args: &ArgMatches) -> std::io::Result<()> {
+    let mut data = Vec::new();
+    File::open(args.value_of("host").unwrap())?.read_to_end(&mut data)?;
+    let root_inode = open_fs(args)?;
+    let path = args.value_of("path").unwrap();
+    let (parent, name) = split_path(path)?;
+    let dir = lookup(&root_inode, parent, true)?;
+    // 文件已存在时覆盖它的内容，路径是符号链接时写入它指向的文件
+    let existing = dir
+        .find(name)
+        .map(|_| lookup(&root_inode, path, true))
+        .transpose()?;
+    let inode = match existing {
+        Some(inode) if inode.is_dir() => {
+            return Err(error(
+                ErrorKind::InvalidInput,
+                format!("{}: is a directory", path),
+            ))
+        }
+        Some(inode) => {
+            inode.clear();
+            inode
+        }
+        None => dir
+            .create(name)
+            .ok_or_else(|| error(ErrorKind::InvalidInput, format!("{}: cannot create", path)))?,
+    };
+    if inode.write_at(0, &data) < data.len() {
+        return Err(error(ErrorKind::Other, format!("{}: no space left", path)));
+    }
+    block_cache_sync_all();
+    Ok(())
+}
+
+fn easy_fs_get(args: &ArgMatches) -> std::io::Result<()> {
+    let root_inode = open_fs(args)?;
+    let path = args.value_of("path").unwrap();
+    let inode = lookup(&root_inode, path, true)?;
+    if inode.is_dir() {
+        return Err(error(
+            ErrorKind::InvalidInput,
+            format!("{}: is a directory", path),
+        ));
+    }
+    File::create(args.value_of("host").unwrap())?.write_all(&read_all(&inode))
+}
+
+fn easy_fs_rm(args: &ArgMatches) -> std::io::Result<()> {
+    let root_inode = open_fs(args)?;
+    let path = args.value_of("path").unwrap();
+    let (parent, name) = split_path(path)?;
+    let dir = lookup(&root_inode, parent, true)?;
+    // 删除符号链接本身，而不是它指向的文件
+    lookup(&root_inode, path, false)?;
+    if !dir.unlink(name) {
+        return Err(error(
+            ErrorKind::InvalidInput,
+            format!("{}: directory not empty", path),
+        ));
+    }
+    block_cache_sync_all();
+    Ok(())
+}
+
+fn easy_fs_mkdir(args: &ArgMatches) -> std::io::Result<()> {
+    let root_inode = open_fs(args)?;
+    let path = args.value_of("path").unwrap();
+    let (parent, name) = split_path(path)?;
+    let dir = lookup(&root_inode, parent, true)?;
+    if !dir.is_dir() {
+        return Err(error(
+            ErrorKind::NotFound,
+            format!("{}: not a directory", parent),
+        ));
+    }
+    dir.mkdir(name).ok_or_else(|| {
+        error(
+            ErrorKind::AlreadyExists,
+            format!("{}: already exists or bad name", path),
+        )
+    })?;
+    block_cache_sync_all();
+    Ok(())
+}
+
+fn easy_fs_ln(args: &ArgMatches) -> std::io::Result<()> {
+    let root_inode = open_fs(args)?;
+    let path = args.value_of("path").unwrap();
+    let (parent, name) = split_path(path)?;
+    let dir = lookup(&root_inode, parent, true)?;
+    if !dir.is_dir() {
+        return Err(error(
+            ErrorKind::NotFound,
+            format!("{}: not a directory", parent),
+        ));
+    }
+    dir.symlink(name, args.value_of("target").unwrap())
+        .ok_or_else(|| {
+            error(
+                ErrorKind::AlreadyExists,
+                format!("{}: already exists or bad name", path),
+            )
+        })?;
+    block_cache_sync_all();
+    Ok(())
+}
+
+fn easy_fs_stat(args: &ArgMatches, out: &mut dyn Write) -> std::io::Result<()> {
+    let root_inode = open_fs(args)?;
+    // 与lstat一样，不跟随最后一个分量的符号链接
+    let inode = lookup(&root_inode, args.value_of("path").unwrap(), false)?;
+    let stat = inode.stat();
+    writeln!(out, "inode: {}", stat.inode)?;
+    let type_ = match (stat.is_dir, stat.is_symlink) {
+        (true, _) => "directory",
+        (_, true) => "symbolic link",
+        _ => "file",
+    };
+    writeln!(out, "type: {}", type_)?;
+    if let Some(target) = inode.readlink() {
+        writeln!(out, "target: {}", target)?;
+    }
+    writeln!(out, "size: {}", stat.size)?;
+    writeln!(out, "blocks: {}", stat.blocks)
+}
+
+fn easy_fs_df(args: &ArgMatches, out: &mut dyn Write) -> std::io::Result<()> {
+    let block_file = open_image(args.value_of("image").unwrap(), true)?;
+    let stats = EasyFileSystem::open(block_file).statfs();
+    writeln!(out, "{:<8}{:>10}{:>10}{:>10}", "", "total", "used", "free")?;
+    writeln!(
+        out,
+        "{:<8}{:>10}{:>10}{:>10}",
+        "blocks",
+        stats.data_blocks,
+        stats.data_blocks - stats.free_data_blocks,
+        stats.free_data_blocks
+    )?;
+    writeln!(
+        out,
+        "{:<8}{:>10}{:>10}{:>10}",
+        "inodes",
+        stats.inodes,
+        stats.inodes - stats.free_inodes,
+        stats.free_inodes
+    )
+}
+
+fn easy_fs_pack(matches: &ArgMatches, out: &mut dyn Write) -> std::io::Result<()> {
     let src_path = matches.value_of("source").unwrap();
     let target_path = matches.value_of("target").unwrap();
-    println!("src_path = {}\ntarget_path = {}", src_path, target_path);
-    let block_file = Arc::new(BlockFile(Mutex::new({
-        let f = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(format!("{}{}", target_path, "fs.img"))?;
-        f.set_len(16 * 2048 * 512).unwrap();
-        f
-    })));
-    // 16MiB, at most 4095 files
-    let efs = EasyFileSystem::create(block_file, 16 * 2048, 1);
-    let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
+    writeln!(
+        out,
+        "src_path = {}\ntarget_path = {}",
+        src_path, target_path
+    )?;
+    let root_inode = create_image(&format!("{}{}", target_path, "fs.img"), matches)?;
     let apps: Vec<_> = read_dir(src_path)
         .unwrap()
-        .into_iter()
         .map(|dir_entry| {
             let mut name_with_ext = dir_entry.unwrap().file_name().into_string().unwrap();
             name_with_ext.drain(name_with_ext.find('.').unwrap()..name_with_ext.len());
@@ -84,6 +680,14 @@ fn easy_fs_pack() -> std::io::Result<()> {
         // write data to easy-fs
         inode.write_at(0, all_data.as_slice());
     }
+    // 块缓存采用写回策略，退出前将脏块写回镜像文件
+    block_cache_sync_all();
+    let stats = block_cache_stats();
+    writeln!(
+        out,
+        "block cache: {} hits, {} misses, {} evictions, {} writebacks",
+        stats.hits, stats.misses, stats.evictions, stats.writebacks
+    )?;
     // list apps
     // for app in root_inode.ls() {
     //     println!("{}", app);
@@ -98,6 +702,7 @@ fn efs_test() -> std::io::Result<()> {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open("target/fs.img")?;
         f.set_len(8192 * 512).unwrap();
         f
@@ -125,7 +730,7 @@ fn efs_test() -> std::io::Result<()> {
         use rand;
         // random digit
         for _ in 0..len {
-            str.push(char::from('0' as u8 + rand::random::<u8>() % 10));
+            str.push(char::from(b'0' + rand::random::<u8>() % 10));
         }
         filea.write_at(0, str.as_bytes());
         let mut read_buffer = [0u8; 127];
@@ -153,3 +758,1225 @@ fn efs_test() -> std::io::Result<()> {
 
     Ok(())
 }
+
+// 像在命令行上一样运行子命令，返回写到标准输出的内容
+#[cfg(test)]
+fn run_cli(args: &[&str]) -> std::io::Result<Vec<u8>> {
+    let matches = app()
+        .get_matches_from_safe(std::iter::once("easy-fs-fuse").chain(args.iter().copied()))
+        .unwrap();
+    let mut out = Vec::new();
+    run(&matches, &mut out)?;
+    Ok(out)
+}
+
+// 打开镜像，检查其中的文件系统是一致的，返回根目录
+#[cfg(test)]
+fn open_checked_image(path: &str) -> std::io::Result<(Arc<EasyFileSystem>, Inode)> {
+    let block_file = open_image(path, true)?;
+    let efs = EasyFileSystem::open(block_file.clone());
+    assert!(fsck(&block_file, false).is_clean());
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    Ok((efs, root_inode))
+}
+
+#[test]
+fn cli_test() -> std::io::Result<()> {
+    const IMAGE: &str = "target/cli_test.img";
+    const HOST: &str = "target/cli_test.in";
+    const HOST_OUT: &str = "target/cli_test.out";
+    let text = |out: Vec<u8>| String::from_utf8(out).unwrap();
+    // 镜像放不下指定数量的inode
+    let err = run_cli(&["mkfs", IMAGE, "--blocks", "100", "--inodes", "8192"]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    // inode数向上取整到位图块能表示的数量
+    run_cli(&["mkfs", IMAGE, "--blocks", "4096", "--inodes", "5000"])?;
+    assert_eq!(std::fs::metadata(IMAGE)?.len(), 4096 * BLOCK_SZ as u64);
+    let (efs, _) = open_checked_image(IMAGE)?;
+    let stats = efs.statfs();
+    assert_eq!(stats.total_blocks, 4096);
+    assert_eq!(stats.inodes, 2 * INODES_PER_BITMAP_BLOCK);
+
+    // put、cat、get：文件内容原样进出镜像，已有的文件被覆盖
+    let data: Vec<u8> = (0..3000).map(|i| (i % 251) as u8).collect();
+    std::fs::write(HOST, &data)?;
+    run_cli(&["mkdir", IMAGE, "/docs"])?;
+    run_cli(&["put", IMAGE, HOST, "/docs/a.bin"])?;
+    assert_eq!(run_cli(&["cat", IMAGE, "/docs/a.bin"])?, data);
+    run_cli(&["get", IMAGE, "/docs/a.bin", HOST_OUT])?;
+    assert_eq!(std::fs::read(HOST_OUT)?, data);
+    std::fs::write(HOST, b"first version")?;
+    run_cli(&["put", IMAGE, HOST, "/docs/b.txt"])?;
+    std::fs::write(HOST, b"short")?;
+    run_cli(&["put", IMAGE, HOST, "/docs/b.txt"])?;
+    assert_eq!(run_cli(&["cat", IMAGE, "/docs/b.txt"])?, b"short");
+    let err = run_cli(&["cat", IMAGE, "/docs"]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let err = run_cli(&["mkdir", IMAGE, "/docs"]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+    // ln：cat、get和put跟随符号链接，包括路径中间的和相对路径的符号链接
+    run_cli(&["ln", IMAGE, "/docs/a.bin", "/abs"])?;
+    run_cli(&["ln", IMAGE, "docs", "/dir"])?;
+    run_cli(&["ln", IMAGE, "../docs/b.txt", "/docs/rel"])?;
+    run_cli(&["ln", IMAGE, "/loop", "/loop"])?;
+    assert_eq!(run_cli(&["cat", IMAGE, "/abs"])?, data);
+    assert_eq!(run_cli(&["cat", IMAGE, "/dir/rel"])?, b"short");
+    run_cli(&["get", IMAGE, "/dir/./a.bin", HOST_OUT])?;
+    assert_eq!(std::fs::read(HOST_OUT)?, data);
+    std::fs::write(HOST, b"through the link")?;
+    run_cli(&["put", IMAGE, HOST, "/docs/rel"])?;
+    assert_eq!(
+        run_cli(&["cat", IMAGE, "/docs/b.txt"])?,
+        b"through the link"
+    );
+    let err = run_cli(&["cat", IMAGE, "/loop"]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    // stat不跟随最后一个分量的符号链接，ls列出符号链接的目标
+    let stat = |path: &str| -> std::io::Result<Vec<String>> {
+        let out = text(run_cli(&["stat", IMAGE, path])?);
+        Ok(out.lines().skip(1).map(String::from).collect())
+    };
+    assert_eq!(
+        stat("/docs/a.bin")?,
+        ["type: file", "size: 3000", "blocks: 6"]
+    );
+    assert_eq!(
+        stat("/abs")?,
+        [
+            "type: symbolic link",
+            "target: /docs/a.bin",
+            "size: 11",
+            "blocks: 1"
+        ]
+    );
+    assert_eq!(stat("/dir")?[0], "type: symbolic link");
+    assert_eq!(stat("/dir/..")?[0], "type: directory");
+    let ls = text(run_cli(&["ls", IMAGE, "/"])?);
+    let mut names: Vec<String> = ls
+        .lines()
+        .map(|line| {
+            line.split_whitespace()
+                .skip(3)
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    names.sort_unstable();
+    assert_eq!(
+        names,
+        ["abs -> /docs/a.bin", "dir -> docs", "docs", "loop -> /loop"]
+    );
+
+    // df的输出与镜像中的使用情况一致：8个inode，数据块包括各个文件和目录的块
+    let (efs, root_inode) = open_checked_image(IMAGE)?;
+    let stats = efs.statfs();
+    let df = text(run_cli(&["df", IMAGE])?);
+    let rows: Vec<Vec<&str>> = df
+        .lines()
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    let used = stats.data_blocks - stats.free_data_blocks;
+    assert_eq!(rows[0], ["total", "used", "free"]);
+    assert_eq!(
+        rows[1],
+        [
+            "blocks".to_string(),
+            stats.data_blocks.to_string(),
+            used.to_string(),
+            stats.free_data_blocks.to_string()
+        ]
+    );
+    assert_eq!(rows[2][..3], ["inodes", "8192", "8"]);
+
+    // 镜像中的内容
+    let docs = root_inode.find("docs").unwrap();
+    assert_eq!(read_all(&docs.find("a.bin").unwrap()), data);
+    assert_eq!(read_all(&docs.find("b.txt").unwrap()), b"through the link");
+    assert_eq!(
+        docs.find("rel").unwrap().readlink().as_deref(),
+        Some("../docs/b.txt")
+    );
+    assert_eq!(
+        root_inode.find("abs").unwrap().readlink().as_deref(),
+        Some("/docs/a.bin")
+    );
+    Ok(())
+}
+
+#[test]
+fn cli_rm_test() -> std::io::Result<()> {
+    const IMAGE: &str = "target/cli_rm_test.img";
+    const HOST: &str = "target/cli_rm_test.in";
+    run_cli(&["mkfs", IMAGE, "--blocks", "4096", "--inodes", "64"])?;
+    let data = vec![b'r'; 40 * BLOCK_SZ];
+    std::fs::write(HOST, &data)?;
+    run_cli(&["mkdir", IMAGE, "/d"])?;
+    run_cli(&["put", IMAGE, HOST, "/d/f"])?;
+    run_cli(&["ln", IMAGE, "/d/f", "/link"])?;
+
+    // 非空目录不能删除
+    let err = run_cli(&["rm", IMAGE, "/d"]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(err.to_string(), "/d: directory not empty");
+    // 删除符号链接本身，它指向的文件还在
+    run_cli(&["rm", IMAGE, "/link"])?;
+    assert_eq!(run_cli(&["cat", IMAGE, "/d/f"])?, data);
+    let err = run_cli(&["rm", IMAGE, "/link"]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    // 清空目录后可以删除
+    run_cli(&["rm", IMAGE, "/d/f"])?;
+    run_cli(&["rm", IMAGE, "/d"])?;
+    assert!(run_cli(&["ls", IMAGE, "/"])?.is_empty());
+
+    // 镜像中只剩下根目录，其他inode和块都被释放
+    let (efs, root_inode) = open_checked_image(IMAGE)?;
+    let stats = efs.statfs();
+    assert!(root_inode.ls().is_empty());
+    assert_eq!(stats.free_inodes, stats.inodes - 1);
+    assert_eq!(
+        stats.data_blocks - stats.free_data_blocks,
+        root_inode.stat().blocks
+    );
+    Ok(())
+}
+
+#[test]
+fn block_cache_test() -> std::io::Result<()> {
+    // 两个镜像共用全局的块缓存，相同块号的块不能混淆
+    let open_image = |path: &str| -> std::io::Result<Arc<BlockFile>> {
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        f.set_len(4096 * 512).unwrap();
+        Ok(Arc::new(BlockFile(Mutex::new(f))))
+    };
+    let image_a = open_image("target/cache_a.img")?;
+    let image_b = open_image("target/cache_b.img")?;
+    let efs_a = EasyFileSystem::create(image_a.clone(), 4096, 1);
+    let efs_b = EasyFileSystem::create(image_b.clone(), 4096, 1);
+    let file_a = EasyFileSystem::root_inode(&efs_a).create("file").unwrap();
+    let file_b = EasyFileSystem::root_inode(&efs_b).create("file").unwrap();
+    // 写入的数据远大于缓存的容量，读回时需要换出并重新加载
+    let data_a = vec![b'a'; 300 * BLOCK_SZ];
+    let data_b = vec![b'b'; 300 * BLOCK_SZ];
+    let before = block_cache_stats();
+    file_a.write_at(0, &data_a);
+    file_b.write_at(0, &data_b);
+    let mut buffer = vec![0u8; 300 * BLOCK_SZ];
+    assert_eq!(file_a.read_at(0, &mut buffer), buffer.len());
+    assert_eq!(buffer, data_a);
+    assert_eq!(file_b.read_at(0, &mut buffer), buffer.len());
+    assert_eq!(buffer, data_b);
+    let after = block_cache_stats();
+    assert!(after.hits > before.hits);
+    assert!(after.misses > before.misses);
+    assert!(after.evictions > before.evictions);
+    assert!(after.cached <= after.capacity);
+
+    // 写回后，重新打开镜像能读到相同的数据
+    block_cache_sync_all();
+    assert!(block_cache_stats().writebacks > before.writebacks);
+    let efs_a = EasyFileSystem::open(image_a);
+    let file_a = EasyFileSystem::root_inode(&efs_a).find("file").unwrap();
+    assert_eq!(file_a.read_at(0, &mut buffer), buffer.len());
+    assert_eq!(buffer, data_a);
+    Ok(())
+}
+
+#[test]
+fn block_cache_overflow_test() {
+    // 所有缓存块都在使用中时，缓存暂时超出容量，而不是等待其他线程释放缓存块
+    let capacity = block_cache_stats().capacity;
+    let device: Arc<dyn BlockDevice> =
+        CrashDevice::new(vec![[0u8; BLOCK_SZ]; capacity + 20], usize::MAX);
+    let before = block_cache_stats();
+    let pinned: Vec<_> = (0..capacity + 10)
+        .map(|block_id| get_block_cache(block_id, device.clone()))
+        .collect();
+    let after = block_cache_stats();
+    assert!(after.cached >= capacity + 10);
+    assert!(after.overflows >= before.overflows + 10);
+    for (block_id, cache) in pinned.iter().enumerate() {
+        assert_eq!(cache.lock().block_id(), block_id);
+    }
+    // 缓存块不再使用后，下一次缺失时换出它们，缓存回落到容量以内
+    drop(pinned);
+    get_block_cache(capacity + 10, device.clone());
+    assert!(block_cache_stats().cached <= capacity);
+}
+
+#[test]
+fn raw_block_access_test() -> std::io::Result<()> {
+    let f = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open("target/raw.img")?;
+    f.set_len(256 * 512).unwrap();
+    let image: Arc<dyn BlockDevice> = Arc::new(BlockFile(Mutex::new(f)));
+    // 块内偏移处的写入经过块缓存，同时直接写到磁盘上
+    write_block_cached(&image, 3, 100, b"raw data");
+    let mut buf = [0u8; 8];
+    read_block_cached(&image, 3, 100, &mut buf);
+    assert_eq!(&buf, b"raw data");
+    let mut block = [0u8; BLOCK_SZ];
+    image.read_block(3, &mut block);
+    assert_eq!(&block[100..108], b"raw data");
+    // 写入的块数超过缓存容量时，不会留下占满缓存的脏块
+    for block_id in 0..256 {
+        write_block_cached(&image, block_id, 0, &[block_id as u8; BLOCK_SZ]);
+    }
+    for block_id in 0..256 {
+        image.read_block(block_id, &mut block);
+        assert_eq!(block, [block_id as u8; BLOCK_SZ]);
+    }
+    Ok(())
+}
+
+#[test]
+fn journal_crash_test() {
+    const TOTAL_BLOCKS: usize = 2048;
+    const OLD_SIZE: usize = 3 * BLOCK_SZ;
+    const NEW_SIZE: usize = 40 * BLOCK_SZ;
+    // 初始镜像中有一个文件old
+    let device = CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX);
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    root_inode
+        .create("old")
+        .unwrap()
+        .write_at(0, &[b'o'; OLD_SIZE]);
+    block_cache_sync_all();
+    let image = device.image();
+
+    // 要在中途崩溃的操作：创建文件new并写入跨越多个事务的数据，再清空文件old
+    let operation = |device: Arc<CrashDevice>| {
+        let efs = EasyFileSystem::open(device);
+        let root_inode = EasyFileSystem::root_inode(&efs);
+        root_inode
+            .create("new")
+            .unwrap()
+            .write_at(0, &[b'n'; NEW_SIZE]);
+        root_inode.find("old").unwrap().clear();
+        block_cache_sync_all();
+    };
+    let read_all = |inode: &easy_fs::Inode| {
+        let mut data = vec![0u8; inode.size() as usize];
+        assert_eq!(inode.read_at(0, &mut data), data.len());
+        data
+    };
+    // 先完整执行一次，统计写入的次数
+    let full = CrashDevice::new(image.clone(), usize::MAX);
+    operation(full.clone());
+    let total_writes = usize::MAX - *full.writes_left.lock().unwrap();
+
+    // 在每一次写入之后崩溃，重新打开文件系统（重做日志）后，文件系统应该是一致的
+    for writes in 0..=total_writes {
+        let crashed = CrashDevice::new(image.clone(), writes);
+        operation(crashed.clone());
+        let device = CrashDevice::new(crashed.image(), usize::MAX);
+        let efs = EasyFileSystem::open(device.clone());
+        let device: Arc<dyn BlockDevice> = device;
+        assert!(fsck(&device, false).is_clean());
+        let root_inode = EasyFileSystem::root_inode(&efs);
+        // old要么没有被清空，要么被完全清空
+        let old = root_inode.find("old").unwrap();
+        let old_data = read_all(&old);
+        assert!(old_data.is_empty() || old_data == vec![b'o'; OLD_SIZE]);
+        // new要么不存在，要么只包含已提交的事务写入的数据
+        let new = root_inode.find("new");
+        let new_data = new.as_ref().map(|inode| read_all(inode));
+        if let Some(data) = &new_data {
+            assert!(data.len() <= NEW_SIZE);
+            assert!(data.iter().all(|&b| b == b'n'));
+        }
+        if writes == total_writes {
+            assert_eq!(new_data.as_ref().unwrap().len(), NEW_SIZE);
+            assert!(old_data.is_empty());
+        }
+        // 位图与inode一致：新分配的块不会覆盖已有文件的数据
+        let after = root_inode.create("after").unwrap();
+        after.write_at(0, &[b'a'; 20 * BLOCK_SZ]);
+        assert_eq!(read_all(&after), vec![b'a'; 20 * BLOCK_SZ]);
+        assert_eq!(read_all(&old), old_data);
+        if let (Some(new), Some(data)) = (&new, &new_data) {
+            assert_eq!(&read_all(new), data);
+        }
+    }
+}
+
+#[test]
+fn sync_during_transaction_test() {
+    const TOTAL_BLOCKS: usize = 2048;
+    let device = Arc::new(SyncingDevice {
+        inner: CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX),
+        armed: AtomicBool::new(false),
+        writes: Mutex::new(Vec::new()),
+    });
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    block_cache_sync_all();
+    device.writes.lock().unwrap().clear();
+    device.armed.store(true, Ordering::SeqCst);
+    root_inode
+        .create("file")
+        .unwrap()
+        .write_at(0, &[b'f'; 4 * BLOCK_SZ]);
+    assert!(!device.armed.load(Ordering::SeqCst));
+    // 写入日志头、提交第一个事务之前，事务修改的块没有被写回它们在磁盘上的位置
+    let writes = device.writes.lock().unwrap().clone();
+    let header = writes
+        .iter()
+        .position(|&block_id| block_id == JOURNAL_AREA.start)
+        .unwrap();
+    assert!(header > 0);
+    assert!(writes[..header]
+        .iter()
+        .all(|block_id| JOURNAL_AREA.contains(block_id)));
+    block_cache_sync_all();
+    let block_device: Arc<dyn BlockDevice> = device.inner.clone();
+    assert!(fsck(&block_device, false).is_clean());
+}
+
+#[test]
+fn fsck_test() {
+    const TOTAL_BLOCKS: usize = 4096;
+    const LARGE_SIZE: usize = 200 * BLOCK_SZ;
+    let device = CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX);
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    root_inode
+        .create("small")
+        .unwrap()
+        .write_at(0, &[b's'; 100]);
+    // 超过一级间接索引的范围，用到二级间接索引
+    root_inode
+        .create("large")
+        .unwrap()
+        .write_at(0, &vec![b'l'; LARGE_SIZE]);
+    root_inode.create("empty").unwrap();
+    block_cache_sync_all();
+    let mut image = device.image();
+    // 每次检查都使用新的设备，不会读到全局块缓存中旧的内容
+    let check = |image: &Vec<[u8; BLOCK_SZ]>, repair: bool| {
+        let device = CrashDevice::new(image.clone(), usize::MAX);
+        let block_device: Arc<dyn BlockDevice> = device.clone();
+        let report = fsck(&block_device, repair);
+        (report, device)
+    };
+    let (report, device) = check(&image, false);
+    assert!(report.is_clean());
+    assert_eq!((report.files, report.directories), (3, 1));
+    let block_device: Arc<dyn BlockDevice> = device;
+    let dump = dump(&block_device);
+    assert!(dump.contains("inode area"));
+    assert!(dump.contains("large (#2, 102400 bytes)"));
+
+    // 按照磁盘布局直接修改镜像中的字节
+    let read_u32 = |image: &Vec<[u8; BLOCK_SZ]>, block: usize, offset: usize| {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&image[block][offset..offset + 4]);
+        u32::from_le_bytes(bytes) as usize
+    };
+    let write_u32 = |image: &mut Vec<[u8; BLOCK_SZ]>, block: usize, offset: usize, value: usize| {
+        image[block][offset..offset + 4].copy_from_slice(&(value as u32).to_le_bytes());
+    };
+    let inode_bitmap_start = 1 + read_u32(&image, 0, 8);
+    let inode_area_start = inode_bitmap_start + read_u32(&image, 0, 12);
+    let data_bitmap_start = inode_area_start + read_u32(&image, 0, 16);
+    let data_area_start = data_bitmap_start + read_u32(&image, 0, 20);
+    // inode所在的块和块内偏移，direct[0]在inode中的偏移为8
+    let inode_pos = |inode: usize| (inode_area_start + inode / 4, inode % 4 * 128);
+    let (small_block, small_offset) = inode_pos(1);
+    let (large_block, large_offset) = inode_pos(2);
+    let (root_block, root_offset) = inode_pos(0);
+    // small和large引用同一个数据块
+    let shared = read_u32(&image, large_block, large_offset + 8);
+    let small_data = read_u32(&image, small_block, small_offset + 8);
+    write_u32(&mut image, small_block, small_offset + 8, shared);
+    // 根目录中第3个目录项（empty）的inode编号越界。前两个目录项各占16字节。
+    let root_data = read_u32(&image, root_block, root_offset + 8);
+    write_u32(&mut image, root_data, 32, 9999);
+    // 一个没有被使用的数据块被标记为已分配
+    let leaked = 2000;
+    image[data_bitmap_start][leaked / 8] |= 1 << (leaked % 8);
+    // large的inode在位图中没有被标记
+    image[inode_bitmap_start][0] &= !(1 << 2);
+
+    let (report, _) = check(&image, false);
+    let expected = [
+        FsckProblem::DuplicateBlock {
+            inode: 2,
+            block: shared as u32,
+            owner: 1,
+        },
+        FsckProblem::BadDirEntry {
+            dir: 0,
+            offset: 32,
+            reason: DirEntryError::InodeOutOfRange(9999),
+        },
+        FsckProblem::InodeLeaked { inode: 3 },
+        FsckProblem::InodeUnmarked { inode: 2 },
+        FsckProblem::BlockLeaked {
+            block: small_data as u32,
+        },
+        FsckProblem::BlockLeaked {
+            block: (data_area_start + leaked) as u32,
+        },
+    ];
+    for problem in expected.iter() {
+        assert!(report.problems.contains(problem), "missing {}", problem);
+    }
+    assert_eq!(report.problems.len(), expected.len());
+
+    // 修复后镜像是一致的，large的数据没有被small改写
+    let (report, repaired) = check(&image, true);
+    assert_eq!(report.problems.len(), expected.len());
+    let image = repaired.image();
+    let (report, _) = check(&image, false);
+    assert!(report.is_clean());
+    let efs = EasyFileSystem::open(CrashDevice::new(image, usize::MAX));
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(root_inode.ls(), vec!["small", "large"]);
+    let large = root_inode.find("large").unwrap();
+    let mut data = vec![0u8; LARGE_SIZE];
+    assert_eq!(large.read_at(0, &mut data), LARGE_SIZE);
+    assert_eq!(data, vec![b'l'; LARGE_SIZE]);
+    // 修复后的位图可以正常分配
+    root_inode
+        .create("new")
+        .unwrap()
+        .write_at(0, &vec![b'n'; 50 * BLOCK_SZ]);
+    assert_eq!(large.read_at(0, &mut data), LARGE_SIZE);
+    assert_eq!(data, vec![b'l'; LARGE_SIZE]);
+}
+
+#[test]
+fn mkdir_unlink_test() {
+    const TOTAL_BLOCKS: usize = 8192;
+    let device = CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX);
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let dir = root_inode.mkdir("dir").unwrap();
+    // 目录的块不会被释放，从根目录已经有了一个块之后开始统计
+    let free_before = efs.statfs();
+    assert!(dir.is_dir());
+    assert!(root_inode.mkdir("dir").is_none());
+    assert!(root_inode.create(&"n".repeat(256)).is_none());
+    assert!(root_inode.create("a/b").is_none());
+    for i in 0..40 {
+        dir.create(&format!("file{}", i))
+            .unwrap()
+            .write_at(0, &vec![b'x'; i * 5 * BLOCK_SZ]);
+    }
+    let large = dir.find("file39").unwrap();
+    assert!(large.stat().blocks > 195);
+    assert!(!root_inode.unlink("dir"));
+    for i in (0..40).filter(|i| i % 3 != 0) {
+        assert!(dir.unlink(&format!("file{}", i)));
+    }
+    assert!(!dir.unlink("file1"));
+    let mut names = dir.ls();
+    names.sort();
+    let mut expected: Vec<String> = (0..40)
+        .filter(|i| i % 3 == 0)
+        .map(|i| format!("file{}", i))
+        .collect();
+    expected.sort();
+    assert_eq!(names, expected);
+    let file39 = dir.find("file39").unwrap();
+    assert_eq!(read_all(&file39), vec![b'x'; 195 * BLOCK_SZ]);
+    block_cache_sync_all();
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    assert!(fsck(&block_device, false).is_clean());
+
+    // 删除所有文件后，块和inode都被释放
+    for name in expected {
+        assert!(dir.unlink(&name));
+    }
+    assert!(root_inode.unlink("dir"));
+    let free_after = efs.statfs();
+    assert_eq!(free_after.free_data_blocks, free_before.free_data_blocks);
+    assert_eq!(free_after.free_inodes, free_before.free_inodes + 1);
+    block_cache_sync_all();
+    assert!(fsck(&block_device, false).is_clean());
+}
+
+#[test]
+fn next_dirent_test() {
+    const TOTAL_BLOCKS: usize = 4096;
+    let device = CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX);
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let dir = root_inode.mkdir("dir").unwrap();
+    for i in 0..60 {
+        dir.create(&format!("old{}", i)).unwrap();
+    }
+    // 一边遍历一边删除和新建：位置严格递增，没有被删除的原有项恰好出现一次
+    let mut seen = BTreeMap::new();
+    let mut pos = 0;
+    let mut last = None;
+    while let Some((found, name)) = dir.next_dirent(pos) {
+        assert!(last.map_or(true, |last| found > last));
+        last = Some(found);
+        *seen.entry(name.clone()).or_insert(0) += 1;
+        pos = found + 1;
+        if let Some(i) = name
+            .strip_prefix("old")
+            .and_then(|i| i.parse::<usize>().ok())
+        {
+            // 删除一个已经读过的项和一个还没读到的项，再新建一个项
+            if i % 2 == 0 && i + 1 < 60 {
+                assert!(dir.unlink(&format!("old{}", i + 1)));
+            }
+            if i % 5 == 0 {
+                assert!(dir.unlink(&name));
+            }
+            dir.create(&format!("new{}", i)).unwrap();
+        }
+    }
+    for i in 0..60 {
+        let expected = if i % 2 == 1 { 0 } else { 1 };
+        assert_eq!(
+            seen.get(&format!("old{}", i)).copied().unwrap_or(0),
+            expected
+        );
+    }
+    assert!(seen.values().all(|&count| count == 1));
+    block_cache_sync_all();
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    assert!(fsck(&block_device, false).is_clean());
+}
+
+#[test]
+fn triple_indirect_test() {
+    // 超过二级间接索引的范围（约8MB），用到三级间接索引
+    const TOTAL_BLOCKS: usize = 24 * 1024;
+    const SIZE: usize = 10 * 1024 * 1024 + 100;
+    let device = CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX);
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("huge").unwrap();
+    let free_before = efs.statfs().free_data_blocks;
+    let data: Vec<u8> = (0..SIZE).map(|i| (i % 251) as u8).collect();
+    // 分多次写入，每次扩大文件时都要接着已有的索引块分配
+    for chunk in data.chunks(777 * BLOCK_SZ + 3).scan(0, |offset, chunk| {
+        *offset += chunk.len();
+        Some((*offset - chunk.len(), chunk))
+    }) {
+        assert_eq!(file.write_at(chunk.0, chunk.1), chunk.1.len());
+    }
+    assert_eq!(read_all(&file), data);
+    let stat = file.stat();
+    assert_eq!(stat.size as usize, SIZE);
+    assert_eq!(efs.statfs().free_data_blocks, free_before - stat.blocks);
+    block_cache_sync_all();
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    assert!(fsck(&block_device, false).is_clean());
+    // 在空的文件系统中，文件的块（包括索引块）是连续分配的
+    let dump = dump(&block_device);
+    let line = dump.lines().find(|line| line.contains("#1 ")).unwrap();
+    assert!(!line.contains(','), "{}", line);
+
+    // 释放后所有的块都回到位图中
+    file.clear();
+    assert_eq!(efs.statfs().free_data_blocks, free_before);
+    block_cache_sync_all();
+    assert!(fsck(&block_device, false).is_clean());
+}
+
+#[test]
+fn sparse_truncate_test() {
+    const TOTAL_BLOCKS: usize = 8192;
+    let device = CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX);
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("sparse").unwrap();
+    let free_before = efs.statfs().free_data_blocks;
+    // 在文件末尾之后写入，中间的空洞不占用数据块：只分配了二级间接索引的两个索引块和一个数据块
+    const OFFSET: usize = 4 * 1024 * 1024;
+    assert_eq!(file.write_at(OFFSET, b"tail"), 4);
+    let stat = file.stat();
+    assert_eq!(stat.size as usize, OFFSET + 4);
+    assert_eq!(stat.blocks, 3);
+    assert_eq!(efs.statfs().free_data_blocks, free_before - 3);
+    let mut buf = vec![0xffu8; 2 * BLOCK_SZ];
+    assert_eq!(file.read_at(OFFSET - BLOCK_SZ, &mut buf), BLOCK_SZ + 4);
+    assert!(buf[..BLOCK_SZ].iter().all(|&b| b == 0));
+    assert_eq!(&buf[BLOCK_SZ..BLOCK_SZ + 4], b"tail");
+    block_cache_sync_all();
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    assert!(fsck(&block_device, false).is_clean());
+
+    // 扩大文件不分配块
+    assert!(file.truncate(OFFSET * 2));
+    assert_eq!(file.stat().blocks, 3);
+    // 缩小到块的中间，释放之后的块和不再需要的索引块，块中新末尾之后的部分被清零
+    assert_eq!(file.write_at(0, &[b'a'; 1000]), 1000);
+    assert!(file.truncate(300));
+    assert_eq!(file.stat().blocks, 1);
+    assert_eq!(efs.statfs().free_data_blocks, free_before - 1);
+    assert!(file.truncate(1000));
+    assert_eq!(read_all(&file), [vec![b'a'; 300], vec![0; 700]].concat());
+    block_cache_sync_all();
+    assert!(fsck(&block_device, false).is_clean());
+
+    // 目录和超过上限的大小不能截断
+    assert!(!root_inode.truncate(0));
+    assert!(!file.truncate(usize::MAX));
+    assert!(file.truncate(0));
+    assert_eq!(efs.statfs().free_data_blocks, free_before);
+}
+
+#[test]
+fn large_shrink_test() {
+    const TOTAL_BLOCKS: usize = 4096;
+    const BLOCKS: usize = 300;
+    let device = CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX);
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    // 释放的块超过一个事务能修改的块数，截断、清空和删除都要拆成多个事务提交
+    let file = root_inode.create("large").unwrap();
+    let free_before = efs.statfs().free_data_blocks;
+    file.write_at(0, &[b'l'; BLOCKS * BLOCK_SZ]);
+    file.write_at(8 * 1024 * 1024, b"tail");
+    assert!(file.truncate(100 * BLOCK_SZ + 10));
+    assert_eq!(read_all(&file), vec![b'l'; 100 * BLOCK_SZ + 10]);
+    block_cache_sync_all();
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    assert!(fsck(&block_device, false).is_clean());
+    file.clear();
+    assert_eq!(file.stat().blocks, 0);
+    assert_eq!(efs.statfs().free_data_blocks, free_before);
+    file.write_at(0, &[b'l'; BLOCKS * BLOCK_SZ]);
+    assert!(root_inode.unlink("large"));
+    assert_eq!(efs.statfs().free_data_blocks, free_before);
+
+    // 删除大文件的途中崩溃：文件要么还在、只剩下前一部分数据，要么被完全删除
+    root_inode
+        .create("large")
+        .unwrap()
+        .write_at(0, &[b'l'; 100 * BLOCK_SZ]);
+    block_cache_sync_all();
+    let image_with_file = device.image();
+    let operation = |device: Arc<CrashDevice>| {
+        let efs = EasyFileSystem::open(device);
+        assert!(EasyFileSystem::root_inode(&efs).unlink("large"));
+        block_cache_sync_all();
+    };
+    let full = CrashDevice::new(image_with_file.clone(), usize::MAX);
+    operation(full.clone());
+    let total_writes = usize::MAX - *full.writes_left.lock().unwrap();
+    for writes in 0..=total_writes {
+        let crashed = CrashDevice::new(image_with_file.clone(), writes);
+        operation(crashed.clone());
+        let device = CrashDevice::new(crashed.image(), usize::MAX);
+        let efs = EasyFileSystem::open(device.clone());
+        let root_inode = EasyFileSystem::root_inode(&efs);
+        if let Some(file) = root_inode.find("large") {
+            let data = read_all(&file);
+            assert!(data.len() <= 100 * BLOCK_SZ);
+            assert!(data.iter().all(|&b| b == b'l'));
+        } else {
+            assert_eq!(efs.statfs().free_data_blocks, free_before);
+        }
+        block_cache_sync_all();
+        let device: Arc<dyn BlockDevice> = device;
+        assert!(fsck(&device, false).is_clean());
+    }
+}
+
+#[test]
+fn long_name_test() {
+    const TOTAL_BLOCKS: usize = 8192;
+    let device = CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX);
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    // 最长255字节的文件名，可以含有多字节的UTF-8字符
+    let longest = "l".repeat(255);
+    let unicode = "长文件名-".repeat(10);
+    root_inode.create(&longest).unwrap().write_at(0, b"longest");
+    root_inode.create(&unicode).unwrap();
+    assert!(root_inode.create(&"l".repeat(256)).is_none());
+    assert!(root_inode.create(&longest).is_none());
+    assert_eq!(
+        read_all(&root_inode.find(&longest).unwrap()),
+        b"longest".to_vec()
+    );
+    assert!(root_inode.find(&unicode).is_some());
+    assert!(root_inode.find(&"l".repeat(254)).is_none());
+
+    // 删除文件后空出的目录项空间被新的文件重用，目录不会变大
+    let dir = root_inode.mkdir("small").unwrap();
+    for i in 0..60 {
+        dir.create(&format!("file-{}", i)).unwrap();
+    }
+    let size = dir.size();
+    assert_eq!(size as usize % BLOCK_SZ, 0);
+    for i in 0..30 {
+        assert!(dir.unlink(&format!("file-{}", i * 2)));
+    }
+    for i in 0..30 {
+        dir.create(&format!("new-{}", i)).unwrap();
+    }
+    assert_eq!(dir.size(), size);
+    assert_eq!(dir.ls().len(), 60);
+
+    // 大目录通过哈希索引查找
+    let big = root_inode.mkdir("big").unwrap();
+    for i in 0..3000 {
+        big.create(&format!("entry-{}", i)).unwrap();
+    }
+    for i in (0..3000).step_by(3) {
+        assert!(big.unlink(&format!("entry-{}", i)));
+    }
+    for i in 0..3000 {
+        assert_eq!(big.find(&format!("entry-{}", i)).is_some(), i % 3 != 0);
+    }
+    let size = big.size();
+    for i in (0..3000).step_by(3) {
+        big.create(&format!("again-{}", i)).unwrap();
+    }
+    assert_eq!(big.size(), size);
+    assert_eq!(big.ls().len(), 3000);
+    block_cache_sync_all();
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    let report = fsck(&block_device, false);
+    assert!(report.is_clean());
+    assert_eq!((report.files, report.directories), (3062, 3));
+
+    // 重新打开文件系统后，索引按需重建
+    let efs = EasyFileSystem::open(device.clone());
+    let big = EasyFileSystem::root_inode(&efs).find("big").unwrap();
+    assert!(big.find("again-2997").is_some());
+    assert!(big.find("entry-2998").is_some());
+    assert!(big.find("entry-2997").is_none());
+}
+
+#[test]
+fn symlink_test() {
+    const TOTAL_BLOCKS: usize = 4096;
+    let device = CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX);
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    root_inode.create("file").unwrap().write_at(0, b"data");
+    let link = root_inode.symlink("link", "/file").unwrap();
+    assert!(link.is_symlink() && !link.is_dir());
+    assert_eq!(link.readlink().unwrap(), "/file");
+    assert!(root_inode.find("file").unwrap().readlink().is_none());
+    let stat = link.stat();
+    assert!(stat.is_symlink);
+    assert_eq!(stat.size, 5);
+    // 名字已存在，或者目标路径为空、过长时失败
+    assert!(root_inode.symlink("link", "other").is_none());
+    assert!(root_inode.symlink("empty", "").is_none());
+    assert!(root_inode
+        .symlink("long", &"x".repeat(SYMLINK_TARGET_LIMIT + 1))
+        .is_none());
+    // 最长的目标路径跨越多个块
+    let target = "d/".repeat(SYMLINK_TARGET_LIMIT / 2) + "f";
+    assert_eq!(
+        root_inode.symlink("longest", &target).unwrap().readlink(),
+        Some(target)
+    );
+    block_cache_sync_all();
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    let report = fsck(&block_device, false);
+    assert!(report.is_clean());
+    assert_eq!(report.files, 3);
+
+    // 删除符号链接时释放它的块，不影响目标文件
+    let free_before = efs.statfs().free_data_blocks;
+    assert!(root_inode.unlink("longest"));
+    assert!(root_inode.unlink("link"));
+    assert_eq!(efs.statfs().free_data_blocks, free_before + 9);
+    assert_eq!(
+        read_all(&root_inode.find("file").unwrap()),
+        b"data".to_vec()
+    );
+    block_cache_sync_all();
+    assert!(fsck(&block_device, false).is_clean());
+}
+
+#[test]
+fn concurrent_write_test() {
+    // 多个线程同时写各自的文件，并在同一个目录下创建和删除文件，同时有线程反复列出目录
+    const TOTAL_BLOCKS: usize = 16384;
+    const THREADS: usize = 8;
+    const TEMP_FILES: usize = 40;
+    let device = CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX);
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
+    let writers: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let root_inode = Arc::clone(&root_inode);
+            std::thread::spawn(move || {
+                let name = format!("file{}", t);
+                let file = root_inode.create(&name).unwrap();
+                let data: Vec<u8> = (0..100 * BLOCK_SZ + t * 77)
+                    .map(|i| (i * (t + 1) % 251) as u8)
+                    .collect();
+                // 每次写入随机长度，文件的块与其他线程的块交错分配
+                let mut offset = 0;
+                while offset < data.len() {
+                    let len =
+                        (rand::random::<usize>() % (4 * BLOCK_SZ) + 1).min(data.len() - offset);
+                    assert_eq!(file.write_at(offset, &data[offset..offset + len]), len);
+                    offset += len;
+                }
+                assert_eq!(read_all(&root_inode.find(&name).unwrap()), data);
+                for i in 0..TEMP_FILES {
+                    root_inode
+                        .create(&format!("tmp{}-{}", t, i))
+                        .unwrap()
+                        .write_at(0, &[t as u8; 700]);
+                }
+                for i in (0..TEMP_FILES).step_by(2) {
+                    assert!(root_inode.unlink(&format!("tmp{}-{}", t, i)));
+                }
+                for i in (1..TEMP_FILES).step_by(2) {
+                    let tmp = root_inode.find(&format!("tmp{}-{}", t, i)).unwrap();
+                    assert_eq!(read_all(&tmp), vec![t as u8; 700]);
+                }
+            })
+        })
+        .collect();
+    let lister = {
+        let root_inode = Arc::clone(&root_inode);
+        std::thread::spawn(move || {
+            for _ in 0..200 {
+                // 列出的每个名字都能找到，除非它刚好被删除
+                for name in root_inode.ls() {
+                    if let Some(inode) = root_inode.find(&name) {
+                        assert!(!inode.is_dir());
+                    }
+                }
+            }
+        })
+    };
+    for handle in writers {
+        handle.join().unwrap();
+    }
+    lister.join().unwrap();
+    assert_eq!(root_inode.ls().len(), THREADS * (1 + TEMP_FILES / 2));
+    block_cache_sync_all();
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    let report = fsck(&block_device, false);
+    assert!(report.is_clean());
+    assert_eq!(report.files, THREADS * (1 + TEMP_FILES / 2));
+}
+
+#[test]
+fn concurrent_read_test() {
+    // 多个线程同时读同一个文件，同时有一个线程在文件末尾追加数据
+    const TOTAL_BLOCKS: usize = 8192;
+    const READERS: usize = 8;
+    const APPENDS: usize = 50;
+    const APPEND_SIZE: usize = 1000;
+    let pattern = |i: usize| (i % 251) as u8;
+    let device = CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX);
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("shared").unwrap();
+    let initial: Vec<u8> = (0..200 * BLOCK_SZ).map(pattern).collect();
+    file.write_at(0, &initial);
+    let appender = {
+        let file = Arc::clone(&file);
+        std::thread::spawn(move || {
+            for i in 0..APPENDS {
+                let offset = initial.len() + i * APPEND_SIZE;
+                let data: Vec<u8> = (offset..offset + APPEND_SIZE).map(pattern).collect();
+                assert_eq!(file.write_at(offset, &data), APPEND_SIZE);
+            }
+        })
+    };
+    let readers: Vec<_> = (0..READERS)
+        .map(|_| {
+            // 每个读者通过自己的Inode对象访问文件，它们共享同一个inode锁
+            let file = root_inode.find("shared").unwrap();
+            std::thread::spawn(move || {
+                for _ in 0..20 {
+                    // 一次写入要么全部可见，要么都不可见
+                    let data = read_all(&file);
+                    assert_eq!((data.len() - 200 * BLOCK_SZ) % APPEND_SIZE, 0);
+                    assert!(data.iter().enumerate().all(|(i, b)| *b == pattern(i)));
+                    let offset = rand::random::<usize>() % data.len();
+                    let mut buf = [0u8; 3 * BLOCK_SZ];
+                    let len = file.read_at(offset, &mut buf);
+                    assert!(buf[..len]
+                        .iter()
+                        .enumerate()
+                        .all(|(i, b)| *b == pattern(offset + i)));
+                }
+            })
+        })
+        .collect();
+    appender.join().unwrap();
+    for handle in readers {
+        handle.join().unwrap();
+    }
+    assert_eq!(file.size() as usize, 200 * BLOCK_SZ + APPENDS * APPEND_SIZE);
+    block_cache_sync_all();
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    assert!(fsck(&block_device, false).is_clean());
+}
+
+#[test]
+fn concurrent_append_test() {
+    // 多个线程同时在同一个文件末尾追加记录，每条记录完整地写在不同的位置。
+    // 记录比一页长，内核把跨页的用户缓冲区拼成一段后追加，每条记录都会跨过页的边界。
+    const TOTAL_BLOCKS: usize = 8192;
+    const THREADS: usize = 8;
+    const RECORDS: usize = 20;
+    const RECORD_SIZE: usize = 4096 + 300;
+    let device = CrashDevice::new(vec![[0u8; BLOCK_SZ]; TOTAL_BLOCKS], usize::MAX);
+    let efs = EasyFileSystem::create(device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    root_inode.create("log").unwrap();
+    let appenders: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let file = root_inode.find("log").unwrap();
+            std::thread::spawn(move || {
+                for _ in 0..RECORDS {
+                    let (offset, len) = file.append(&[t as u8; RECORD_SIZE]);
+                    assert_eq!(len, RECORD_SIZE);
+                    assert_eq!(offset % RECORD_SIZE, 0);
+                }
+            })
+        })
+        .collect();
+    for handle in appenders {
+        handle.join().unwrap();
+    }
+    let data = read_all(&root_inode.find("log").unwrap());
+    assert_eq!(data.len(), THREADS * RECORDS * RECORD_SIZE);
+    let mut counts = [0; THREADS];
+    for record in data.chunks(RECORD_SIZE) {
+        assert!(record.iter().all(|b| *b == record[0]));
+        counts[record[0] as usize] += 1;
+    }
+    assert_eq!(counts, [RECORDS; THREADS]);
+    block_cache_sync_all();
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    assert!(fsck(&block_device, false).is_clean());
+}
+
+// 在path处创建一个total_sectors个扇区、每簇1个扇区的FAT32镜像。
+// 有mkfs.vfat时用它格式化；否则按mkfs.vfat的默认布局写入引导扇区、FSInfo扇区、备份引导扇区和FAT表。
+#[cfg(test)]
+fn make_fat32_image(path: &str, total_sectors: usize) -> std::io::Result<Arc<BlockFile>> {
+    const RESERVED: usize = 32;
+    const FATS: usize = 2;
+    let mut f = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    f.set_len((total_sectors * BLOCK_SZ) as u64)?;
+    let formatted = std::process::Command::new("mkfs.vfat")
+        .args(["-F", "32", "-s", "1", path])
+        .output()
+        .map_or(false, |output| output.status.success());
+    if !formatted {
+        let fat_sectors = ((total_sectors - RESERVED + 2) * 4 + BLOCK_SZ - 1) / BLOCK_SZ;
+        let clusters = total_sectors - RESERVED - FATS * fat_sectors;
+        let mut boot = [0u8; BLOCK_SZ];
+        boot[..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+        boot[3..11].copy_from_slice(b"mkfs.fat");
+        boot[11..13].copy_from_slice(&(BLOCK_SZ as u16).to_le_bytes());
+        boot[13] = 1;
+        boot[14..16].copy_from_slice(&(RESERVED as u16).to_le_bytes());
+        boot[16] = FATS as u8;
+        boot[21] = 0xF8;
+        boot[32..36].copy_from_slice(&(total_sectors as u32).to_le_bytes());
+        boot[36..40].copy_from_slice(&(fat_sectors as u32).to_le_bytes());
+        boot[44..48].copy_from_slice(&2u32.to_le_bytes());
+        boot[48..50].copy_from_slice(&1u16.to_le_bytes());
+        boot[50..52].copy_from_slice(&6u16.to_le_bytes());
+        boot[66] = 0x29;
+        boot[71..82].copy_from_slice(b"NO NAME    ");
+        boot[82..90].copy_from_slice(b"FAT32   ");
+        boot[510..].copy_from_slice(&[0x55, 0xAA]);
+        let mut fsinfo = [0u8; BLOCK_SZ];
+        fsinfo[..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+        fsinfo[484..488].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+        fsinfo[488..492].copy_from_slice(&(clusters as u32 - 1).to_le_bytes());
+        fsinfo[492..496].copy_from_slice(&3u32.to_le_bytes());
+        fsinfo[508..].copy_from_slice(&0xAA55_0000u32.to_le_bytes());
+        // FAT表的前两项是介质类型和保留项，第2簇是根目录
+        let mut fat = [0u8; BLOCK_SZ];
+        fat[..4].copy_from_slice(&0x0FFF_FFF8u32.to_le_bytes());
+        fat[4..12].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F]);
+        for (sector, data) in [(0, &boot), (1, &fsinfo), (6, &boot), (7, &fsinfo)] {
+            f.seek(SeekFrom::Start((sector * BLOCK_SZ) as u64))?;
+            f.write_all(data)?;
+        }
+        for i in 0..FATS {
+            f.seek(SeekFrom::Start(
+                ((RESERVED + i * fat_sectors) * BLOCK_SZ) as u64,
+            ))?;
+            f.write_all(&fat)?;
+        }
+    }
+    Ok(Arc::new(BlockFile(Mutex::new(f))))
+}
+
+#[cfg(test)]
+fn fat32_read_all(inode: &Fat32Inode) -> Vec<u8> {
+    let mut data = vec![0u8; inode.size()];
+    let len = inode.read_at(0, &mut data);
+    data.truncate(len);
+    data
+}
+
+#[test]
+fn fat32_test() -> std::io::Result<()> {
+    const PATH: &str = "target/fat32.img";
+    const TOTAL_SECTORS: usize = 128 * 1024;
+    const BIG_SIZE: usize = 300 * 1024 + 123;
+    let pattern = |i: usize| (i % 251) as u8;
+    let image = make_fat32_image(PATH, TOTAL_SECTORS)?;
+    let device: Arc<dyn BlockDevice> = image.clone();
+    assert!(!EasyFileSystem::probe(&device));
+    let fs = Fat32FileSystem::open(device.clone()).unwrap();
+    let root = Fat32FileSystem::root_inode(&fs);
+    let (_, initial_free) = fs.cluster_stats();
+
+    // 短文件名、长文件名和多级目录，查找时不区分大小写
+    root.create("hello.txt")
+        .unwrap()
+        .write_at(0, b"Hello, FAT32!");
+    let long_name = "A file with a rather long name.markdown";
+    root.create(long_name).unwrap().write_at(0, b"long");
+    let unicode = "长文件名-数据.txt";
+    root.create(unicode).unwrap();
+    assert!(root.create("HELLO.TXT").is_none());
+    assert!(root.create("bad:name").is_none());
+    let docs = root.mkdir("Docs").unwrap();
+    let sub = docs.mkdir("Sub dir").unwrap();
+    assert_eq!(
+        fat32_read_all(&root.find("HELLO.TXT").unwrap()),
+        b"Hello, FAT32!".to_vec()
+    );
+    assert!(root.find(unicode).is_some());
+    let mut names = root.ls();
+    names.sort();
+    assert_eq!(names, vec![long_name, "Docs", "hello.txt", unicode]);
+
+    // 跨越多个簇、不按扇区对齐的写入
+    let big = sub.create("big.bin").unwrap();
+    let data: Vec<u8> = (0..BIG_SIZE).map(pattern).collect();
+    let mut offset = 0;
+    for chunk in data.chunks(7000) {
+        assert_eq!(big.write_at(offset, chunk), chunk.len());
+        offset += chunk.len();
+    }
+    assert_eq!(fat32_read_all(&big), data);
+    let clusters = (BIG_SIZE + fs.cluster_size() - 1) / fs.cluster_size();
+    assert_eq!(fs.cluster_stats().1, initial_free - 2 - 2 - clusters);
+
+    // 截断释放多余的簇；在末尾之后写入时，中间的空洞读到0
+    assert!(big.truncate(1000));
+    assert_eq!(fs.cluster_stats().1, initial_free - 2 - 2 - 2);
+    assert_eq!(big.write_at(5000, b"tail"), 4);
+    let content = fat32_read_all(&big);
+    assert_eq!(content.len(), 5004);
+    assert_eq!(&content[..1000], &data[..1000]);
+    assert!(content[1000..5000].iter().all(|b| *b == 0));
+    assert_eq!(&content[5000..], b"tail");
+
+    // 目录项在磁盘上的格式：短目录项"HELLO   TXT"之前是长文件名"hello.txt"
+    block_cache_sync_all();
+    let mut boot = [0u8; BLOCK_SZ];
+    image.read_block(0, &mut boot);
+    let reserved = u16::from_le_bytes([boot[14], boot[15]]) as usize;
+    let fat_sectors = u32::from_le_bytes([boot[36], boot[37], boot[38], boot[39]]) as usize;
+    let root_sector = reserved + 2 * fat_sectors;
+    let mut dir = [0u8; BLOCK_SZ];
+    image.read_block(root_sector, &mut dir);
+    let short = dir
+        .chunks(32)
+        .position(|entry| &entry[..11] == b"HELLO   TXT")
+        .unwrap();
+    let lfn = &dir[(short - 1) * 32..short * 32];
+    assert_eq!(lfn[0], 0x41);
+    assert_eq!(lfn[11], 0x0F);
+    let checksum = dir[short * 32..short * 32 + 11]
+        .iter()
+        .fold(0u8, |sum, &b| {
+            ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b)
+        });
+    assert_eq!(lfn[13], checksum);
+    assert_eq!(&lfn[1..5], &[b'h', 0, b'e', 0]);
+
+    // 非空目录不能删除；删除所有文件后，空闲簇数恢复
+    assert!(!docs.unlink("Sub dir"));
+    assert!(sub.unlink("BIG.BIN"));
+    assert!(docs.unlink("sub DIR"));
+    assert!(root.unlink("Docs"));
+    assert!(root.unlink(long_name));
+    assert_eq!(fs.cluster_stats().1, initial_free - 1);
+    assert!(root.find("Docs").is_none());
+
+    // 重新打开文件系统，数据和FSInfo中的空闲簇数都已写回
+    block_cache_sync_all();
+    let image = Arc::new(BlockFile(Mutex::new(
+        OpenOptions::new().read(true).write(true).open(PATH)?,
+    )));
+    let device: Arc<dyn BlockDevice> = image.clone();
+    let fs = Fat32FileSystem::open(device).unwrap();
+    let root = Fat32FileSystem::root_inode(&fs);
+    assert_eq!(fs.cluster_stats().1, initial_free - 1);
+    let mut fsinfo = [0u8; BLOCK_SZ];
+    image.read_block(1, &mut fsinfo);
+    assert_eq!(
+        u32::from_le_bytes([fsinfo[488], fsinfo[489], fsinfo[490], fsinfo[491]]) as usize,
+        initial_free - 1
+    );
+    let mut names = root.ls();
+    names.sort();
+    assert_eq!(names, vec!["hello.txt", unicode]);
+    assert_eq!(
+        fat32_read_all(&root.find("hello.txt").unwrap()),
+        b"Hello, FAT32!".to_vec()
+    );
+
+    // 其他系统创建的目录项：卷标不是文件；按NT的小写标志显示短文件名
+    let mut label = [0u8; 32];
+    label[..11].copy_from_slice(b"MYDISK     ");
+    label[11] = 0x08;
+    let mut readme = [0u8; 32];
+    readme[..11].copy_from_slice(b"README  TXT");
+    readme[11] = 0x20;
+    readme[12] = 0x18;
+    image.read_block(root_sector, &mut dir);
+    let free = dir.chunks(32).position(|entry| entry[0] == 0).unwrap();
+    dir[free * 32..free * 32 + 32].copy_from_slice(&label);
+    dir[free * 32 + 32..free * 32 + 64].copy_from_slice(&readme);
+    image.write_block(root_sector, &dir);
+    let image: Arc<dyn BlockDevice> = Arc::new(BlockFile(Mutex::new(
+        OpenOptions::new().read(true).write(true).open(PATH)?,
+    )));
+    let root = Fat32FileSystem::root_inode(&Fat32FileSystem::open(image.clone()).unwrap());
+    let mut names = root.ls();
+    names.sort();
+    assert_eq!(names, vec!["hello.txt", "readme.txt", unicode]);
+    assert_eq!(root.find("README.TXT").unwrap().size(), 0);
+
+    // 有fsck.vfat时，用它检查镜像的一致性
+    block_cache_sync_all();
+    if let Ok(output) = std::process::Command::new("fsck.vfat")
+        .args(["-n", PATH])
+        .output()
+    {
+        assert!(output.status.success(), "{:?}", output);
+    }
+    Ok(())
+}
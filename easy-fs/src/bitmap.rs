@@ -1,5 +1,6 @@
 use crate::{block_cache::get_block_cache, block_dev::BlockDevice, BLOCK_SZ};
 use alloc::sync::Arc;
+use core::cmp::min;
 
 // 填充一个数据块的位图类型
 type BitmapBlock = [u64; 64];
@@ -54,6 +55,60 @@ impl Bitmap {
         None
     }
 
+    // 从第goal位开始向后查找值为0的比特位，到第limit位为止，再回到开头查找到goal为止，找到后设为1。
+    // 用于分配数据块：goal取文件最后一个块的下一个块，使文件的块尽量连续。
+    pub fn alloc_near(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        goal: usize,
+        limit: usize,
+    ) -> Option<usize> {
+        let limit = min(limit, self.maximum());
+        let goal = if goal < limit { goal } else { 0 };
+        [(goal, limit), (0, goal)]
+            .into_iter()
+            .find_map(|(start, end)| self.alloc_in(block_device, start, end))
+    }
+
+    // 在[start, end)范围内查找第一个值为0的比特位，设为1
+    fn alloc_in(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        start: usize,
+        end: usize,
+    ) -> Option<usize> {
+        let mut bit = start;
+        while bit < end {
+            let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+            let cache = get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device));
+            // 先只读地查找，找到后再修改，避免没有找到时把位图块标记为脏
+            let found = cache.lock().read(0, |bitmap_block: &BitmapBlock| {
+                (bits64_pos..bitmap_block.len()).find_map(|i| {
+                    // 第一个u64中，bit之前的位视为已分配
+                    let mask = if i == bits64_pos {
+                        (1u64 << inner_pos) - 1
+                    } else {
+                        0
+                    };
+                    let bits64 = bitmap_block[i] | mask;
+                    (bits64 != u64::MAX).then(|| (i, bits64.trailing_ones() as usize))
+                })
+            });
+            if let Some((i, pos)) = found {
+                let bit = block_pos * BLOCK_BITS + i * 64 + pos;
+                if bit >= end {
+                    return None;
+                }
+                cache.lock().modify(0, |bitmap_block: &mut BitmapBlock| {
+                    bitmap_block[i] |= 1u64 << pos;
+                });
+                return Some(bit);
+            }
+            bit = (block_pos + 1) * BLOCK_BITS;
+        }
+        None
+    }
+
     // 释放一个块，即将对应的比特位设为0
     pub fn dealloc(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) {
         let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
@@ -65,6 +120,46 @@ impl Bitmap {
             });
     }
 
+    // 检查一个比特位是否为1
+    pub fn is_set(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) -> bool {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+            .lock()
+            .read(0, |bitmap_block: &BitmapBlock| {
+                bitmap_block[bits64_pos] & (1u64 << inner_pos) != 0
+            })
+    }
+
+    // 将一个比特位设为value，用于修复位图
+    pub fn set(&self, block_device: &Arc<dyn BlockDevice>, bit: usize, value: bool) {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+            .lock()
+            .modify(0, |bitmap_block: &mut BitmapBlock| {
+                if value {
+                    bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+                } else {
+                    bitmap_block[bits64_pos] &= !(1u64 << inner_pos);
+                }
+            });
+    }
+
+    // 统计值为1的比特位数，即已分配的数量
+    pub fn count(&self, block_device: &Arc<dyn BlockDevice>) -> usize {
+        (0..self.blocks)
+            .map(|block_id| {
+                get_block_cache(block_id + self.start_block_id, Arc::clone(block_device))
+                    .lock()
+                    .read(0, |bitmap_block: &BitmapBlock| {
+                        bitmap_block
+                            .iter()
+                            .map(|bits64| bits64.count_ones() as usize)
+                            .sum::<usize>()
+                    })
+            })
+            .sum()
+    }
+
     // 获取最大可分配的块数
     pub fn maximum(&self) -> usize {
         self.blocks * BLOCK_BITS
@@ -1,10 +1,46 @@
 //! 块缓存管理模块
 
-use crate::{block_dev::BlockDevice, BLOCK_SZ};
-use alloc::{collections::vec_deque::VecDeque, sync::Arc};
+use crate::{block_dev::BlockDevice, journal::JOURNAL_MAX_TXN_BLOCKS, BLOCK_SZ};
+use alloc::{collections::BTreeSet, sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::*;
 use spin::Mutex;
 
+// 脏块写回磁盘的次数。写回发生在各个块缓存上，不经过管理器，因此单独计数。
+static WRITEBACKS: AtomicUsize = AtomicUsize::new(0);
+
+// 一个块设备上进行中的事务
+struct TxnBlocks {
+    device: usize,
+    // 事务修改过的块的块号。这些块只能由提交事务时写回，不能被换出或由sync写回。
+    blocks: BTreeSet<usize>,
+}
+
+// 进行中的事务，每个块设备最多一个。
+// 文件系统的修改都在事务中进行，开始事务需要获取日志的锁，因此事务进行期间，
+// 设备上的块只会被持有事务的一方修改。只记录修改而不记录读取：其他线程在事务期间读取的块不属于事务。
+// 持有该锁时不会再获取其他锁，因此可以在持有管理器或缓存块的锁时获取。
+static TRANSACTIONS: Mutex<Vec<TxnBlocks>> = Mutex::new(Vec::new());
+
+// 块是否被进行中的事务修改过
+fn in_transaction(device: usize, block_id: usize) -> bool {
+    TRANSACTIONS
+        .lock()
+        .iter()
+        .any(|txn| txn.device == device && txn.blocks.contains(&block_id))
+}
+
+// 设备上有进行中的事务时，记录事务修改了该块
+fn record_modified(device: usize, block_id: usize) {
+    if let Some(txn) = TRANSACTIONS
+        .lock()
+        .iter_mut()
+        .find(|txn| txn.device == device)
+    {
+        txn.blocks.insert(block_id);
+    }
+}
+
 pub struct BlockCache {
     // 要缓存的块数据
     cache: [u8; BLOCK_SZ],
@@ -53,6 +89,7 @@ impl BlockCache {
         let type_size = core::mem::size_of::<T>();
         assert!(offset + type_size <= BLOCK_SZ);
         self.modified = true;
+        record_modified(device_id(&self.block_device), self.block_id);
         let addr = self.addr_of_offset(offset);
         unsafe { &mut *(addr as *mut T) }
     }
@@ -65,11 +102,28 @@ impl BlockCache {
         f(self.get_mut(offset))
     }
 
+    pub fn block_id(&self) -> usize {
+        self.block_id
+    }
+
+    // 该缓存是否被修改过，尚未写回磁盘
+    pub fn is_dirty(&self) -> bool {
+        self.modified
+    }
+
+    // 不属于进行中的事务时，将缓存写回磁盘。用于事务之外的写回。
+    fn sync_outside_transaction(&mut self) {
+        if !in_transaction(device_id(&self.block_device), self.block_id) {
+            self.sync();
+        }
+    }
+
     // 将缓存写回磁盘
     pub fn sync(&mut self) {
         if self.modified {
             self.modified = false;
             self.block_device.write_block(self.block_id, &self.cache);
+            WRITEBACKS.fetch_add(1, Ordering::Relaxed);
         }
     }
 }
@@ -80,53 +134,241 @@ impl Drop for BlockCache {
     }
 }
 
-// 内存中最多缓存16个块
-const BLOCK_CACHE_SIZE: usize = 16;
+// 未调用init_block_cache时，内存中默认缓存128个块。
+// 事务进行期间，被修改的块不能被换出，因此容量应远大于一个事务最多修改的块数（JOURNAL_MAX_TXN_BLOCKS）。
+const DEFAULT_BLOCK_CACHE_SIZE: usize = 128;
+// 表示链表或哈希链的结尾
+const NIL: usize = usize::MAX;
+
+// 块设备的标识，即其数据的地址。缓存块持有块设备的引用，因此缓存期间该地址不会被复用。
+fn device_id(block_device: &Arc<dyn BlockDevice>) -> usize {
+    Arc::as_ptr(block_device) as *const () as usize
+}
+
+// 缓存槽。所有槽组成一个双向链表，按最近使用的时间排序。
+struct CacheSlot {
+    // 缓存的块所在的块设备。不同设备上相同块号的块，是不同的缓存块。
+    device: usize,
+    block_id: usize,
+    cache: Arc<Mutex<BlockCache>>,
+    // 链表中更近使用的槽
+    prev: usize,
+    // 链表中更久未使用的槽
+    next: usize,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+// 块缓存的统计信息
+pub struct BlockCacheStats {
+    // 缓存的容量（块数）
+    pub capacity: usize,
+    // 当前缓存的块数
+    pub cached: usize,
+    // 被修改过、尚未写回的块数
+    pub dirty: usize,
+    // 命中次数
+    pub hits: usize,
+    // 未命中，需要从磁盘读取的次数
+    pub misses: usize,
+    // 被换出的块数
+    pub evictions: usize,
+    // 所有缓存块都在使用中，缓存暂时超出容量的次数
+    pub overflows: usize,
+    // 写回磁盘的次数
+    pub writebacks: usize,
+}
 
-// 块缓存管理器
+// 块缓存管理器，采用LRU（最近最少使用）算法换出缓存块。
+// 通过哈希表按块号查找缓存槽，通过双向链表维护使用顺序，查找和调整顺序都不需要遍历所有缓存。
 pub struct BlockCacheManager {
-    // 缓存队列，每个元素表示(块号，块缓存)
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    // 最多缓存的块数
+    capacity: usize,
+    // 缓存槽。槽在换出时被复用，因此数量不超过capacity
+    slots: Vec<CacheSlot>,
+    // 哈希表，每个桶是一组槽的下标。桶的数量是2的幂，哈希函数直接取块号的低位，
+    // 这样相邻的块会落在不同的桶里。
+    buckets: Vec<Vec<usize>>,
+    // 链表头，最近使用的槽
+    head: usize,
+    // 链表尾，最久未使用的槽
+    tail: usize,
+    hits: usize,
+    misses: usize,
+    evictions: usize,
+    overflows: usize,
 }
 
 impl BlockCacheManager {
-    pub fn new() -> Self {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "block cache capacity must be positive");
         Self {
-            queue: VecDeque::new(),
+            capacity,
+            slots: Vec::with_capacity(capacity),
+            buckets: vec![Vec::new(); capacity.next_power_of_two()],
+            head: NIL,
+            tail: NIL,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+            overflows: 0,
         }
     }
 
+    fn bucket_of(&self, block_id: usize) -> usize {
+        block_id & (self.buckets.len() - 1)
+    }
+
+    // 按块设备和块号查找缓存槽
+    fn find(&self, device: usize, block_id: usize) -> Option<usize> {
+        self.buckets[self.bucket_of(block_id)]
+            .iter()
+            .copied()
+            .find(|&idx| self.slots[idx].device == device && self.slots[idx].block_id == block_id)
+    }
+
+    // 将槽从链表中摘下
+    fn unlink(&mut self, idx: usize) {
+        let (prev, next) = (self.slots[idx].prev, self.slots[idx].next);
+        if prev == NIL {
+            self.head = next;
+        } else {
+            self.slots[prev].next = next;
+        }
+        if next == NIL {
+            self.tail = prev;
+        } else {
+            self.slots[next].prev = prev;
+        }
+    }
+
+    // 将槽放到链表头，表示它刚被使用
+    fn push_front(&mut self, idx: usize) {
+        self.slots[idx].prev = NIL;
+        self.slots[idx].next = self.head;
+        if self.head != NIL {
+            self.slots[self.head].prev = idx;
+        } else {
+            self.tail = idx;
+        }
+        self.head = idx;
+    }
+
+    // 槽中的块能否被换出：没有在其他地方被引用，且不是事务中被修改过的块
+    fn evictable(&self, idx: usize) -> bool {
+        let slot = &self.slots[idx];
+        Arc::strong_count(&slot.cache) == 1
+            && !(in_transaction(slot.device, slot.block_id) && slot.cache.lock().is_dirty())
+    }
+
+    // 移除槽，其中被修改过的块在drop时写回磁盘。最后一个槽被移到idx处，使slots保持紧凑。
+    fn remove_slot(&mut self, idx: usize) {
+        self.unlink(idx);
+        let bucket = self.bucket_of(self.slots[idx].block_id);
+        self.buckets[bucket].retain(|&i| i != idx);
+        let last = self.slots.len() - 1;
+        if idx != last {
+            // 让链表和哈希桶中指向最后一个槽的下标，改为指向idx
+            let (prev, next) = (self.slots[last].prev, self.slots[last].next);
+            if prev == NIL {
+                self.head = idx;
+            } else {
+                self.slots[prev].next = idx;
+            }
+            if next == NIL {
+                self.tail = idx;
+            } else {
+                self.slots[next].prev = idx;
+            }
+            let bucket = self.bucket_of(self.slots[last].block_id);
+            for i in self.buckets[bucket].iter_mut().filter(|i| **i == last) {
+                *i = idx;
+            }
+        }
+        self.slots.swap_remove(idx);
+    }
+
+    // 从链表尾开始，找到最久未使用、且可以换出的槽
+    fn find_victim(&self) -> Option<usize> {
+        let mut idx = self.tail;
+        while idx != NIL {
+            if self.evictable(idx) {
+                return Some(idx);
+            }
+            idx = self.slots[idx].prev;
+        }
+        None
+    }
+
     // 从存储设备中读取一个块，并进行缓存。
     // 如果该块已经被缓存，则直接返回。
+    // 缓存超出容量时，从最久未使用的缓存块开始换出。所有缓存块都在使用中时，缓存暂时超出容量，
+    // 而不是等待：调用者可能持有文件系统的锁，等待会让持有缓存块的任务也无法继续。
+    // 之后有缓存块可以换出时，缓存再回落到容量以内。
     pub fn get_block_cache(
         &mut self,
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
     ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
-            Arc::clone(&pair.1)
-        } else {
-            // 如果缓存队列已满，则删除一个缓存块
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                // 类似FIFO算法，从队头开始，找到没有在其他地方被引用的缓存块，然后删除
-                if let Some((idx, _)) = self
-                    .queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
-                {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
+        let device = device_id(&block_device);
+        if let Some(idx) = self.find(device, block_id) {
+            self.hits += 1;
+            self.unlink(idx);
+            self.push_front(idx);
+            return Arc::clone(&self.slots[idx].cache);
+        }
+        self.misses += 1;
+        // 从磁盘加载块数据，并创建一个新的块缓存
+        let block_cache = Arc::new(Mutex::new(BlockCache::new(block_id, block_device)));
+        self.slots.push(CacheSlot {
+            device,
+            block_id,
+            cache: Arc::clone(&block_cache),
+            prev: NIL,
+            next: NIL,
+        });
+        let idx = self.slots.len() - 1;
+        let bucket = self.bucket_of(block_id);
+        self.buckets[bucket].push(idx);
+        self.push_front(idx);
+        // 新的块被block_cache引用，不会被换出
+        while self.slots.len() > self.capacity {
+            match self.find_victim() {
+                Some(victim) => {
+                    self.remove_slot(victim);
+                    self.evictions += 1;
+                }
+                None => {
+                    self.overflows += 1;
+                    break;
                 }
             }
-            // 从磁盘加载块数据，并创建一个新的块缓存
-            let block_cache = Arc::new(Mutex::new(BlockCache::new(
-                block_id,
-                Arc::clone(&block_device),
-            )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
-            block_cache
+        }
+        block_cache
+    }
+
+    // 所有缓存块的引用
+    fn caches(&self) -> Vec<Arc<Mutex<BlockCache>>> {
+        self.slots
+            .iter()
+            .map(|slot| Arc::clone(&slot.cache))
+            .collect()
+    }
+
+    pub fn stats(&self) -> BlockCacheStats {
+        BlockCacheStats {
+            capacity: self.capacity,
+            cached: self.slots.len(),
+            // 正被其他线程使用的块无法检查，不计入
+            dirty: self
+                .slots
+                .iter()
+                .filter(|slot| slot.cache.try_lock().map_or(false, |c| c.is_dirty()))
+                .count(),
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            overflows: self.overflows,
+            writebacks: WRITEBACKS.load(Ordering::Relaxed),
         }
     }
 }
@@ -134,10 +376,24 @@ impl BlockCacheManager {
 lazy_static! {
     // 全局的块缓存管理器。由于可能被多个线程访问，因此需要Mutex。
     pub static ref BLOCK_CACHE_MANAGER: Mutex<BlockCacheManager> =
-        Mutex::new(BlockCacheManager::new());
+        Mutex::new(BlockCacheManager::new(DEFAULT_BLOCK_CACHE_SIZE));
+}
+
+// 设置块缓存的容量（块数）。应在打开文件系统之前调用，原有的缓存块会被写回并丢弃。
+pub fn init_block_cache(capacity: usize) {
+    assert!(
+        capacity > 2 * JOURNAL_MAX_TXN_BLOCKS,
+        "block cache is too small for journal transactions"
+    );
+    let old = core::mem::replace(
+        &mut *BLOCK_CACHE_MANAGER.lock(),
+        BlockCacheManager::new(capacity),
+    );
+    // 在释放锁之后再drop旧的管理器，写回其中的脏块
+    drop(old);
 }
 
-// 拿到给定块号和块设备对应的块缓存
+// 拿到给定块号和块设备对应的块缓存。不会等待其他线程释放缓存块。
 pub fn get_block_cache(
     block_id: usize,
     block_device: Arc<dyn BlockDevice>,
@@ -147,10 +403,113 @@ pub fn get_block_cache(
         .get_block_cache(block_id, block_device)
 }
 
-// 将所有块缓存写回磁盘
+// 经过块缓存，读出块block_id中从offset开始的数据，写入buf。
+// 用于直接访问块设备：读到的内容与挂载在该设备上的文件系统看到的一致。
+pub fn read_block_cached(
+    block_device: &Arc<dyn BlockDevice>,
+    block_id: usize,
+    offset: usize,
+    buf: &mut [u8],
+) {
+    let cache = get_block_cache(block_id, Arc::clone(block_device));
+    let cache = cache.lock();
+    buf.copy_from_slice(&cache.cache[offset..offset + buf.len()]);
+}
+
+// 将buf写入块block_id中从offset开始的位置，同时更新块缓存。
+// 直接写穿到磁盘，不改变缓存块的脏位：这样不会在共享的缓存中留下脏块，
+// 也不会把文件系统尚未提交的修改提前写回。
+pub fn write_block_cached(
+    block_device: &Arc<dyn BlockDevice>,
+    block_id: usize,
+    offset: usize,
+    buf: &[u8],
+) {
+    let cache = get_block_cache(block_id, Arc::clone(block_device));
+    let mut cache = cache.lock();
+    cache.cache[offset..offset + buf.len()].copy_from_slice(buf);
+    if cache.modified {
+        // 缓存块中有文件系统的修改，磁盘上只更新这次写入的部分
+        let mut block = vec![0u8; BLOCK_SZ];
+        block_device.read_block(block_id, &mut block);
+        block[offset..offset + buf.len()].copy_from_slice(buf);
+        block_device.write_block(block_id, &block);
+    } else {
+        block_device.write_block(block_id, &cache.cache);
+    }
+}
+
+// 将所有块缓存写回磁盘，进行中的事务修改的块除外，它们在提交事务时写回。
+// 在写回每个块时才检查它是否属于事务，因为拿到引用之后，其他线程可能开始新的事务。
 pub fn block_cache_sync_all() {
-    let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, cache) in manager.queue.iter() {
-        cache.lock().sync();
+    // 先拿到所有缓存块的引用再释放管理器的锁，写回时不会阻塞其他线程获取缓存块
+    let caches = BLOCK_CACHE_MANAGER.lock().caches();
+    for cache in caches {
+        cache.lock().sync_outside_transaction();
+    }
+}
+
+// 尝试将所有块缓存写回磁盘，跳过正在被使用的缓存块。
+// 它不会等待任何锁，适合在周期性的后台写回中调用。
+pub fn block_cache_try_sync_all() {
+    let caches = match BLOCK_CACHE_MANAGER.try_lock() {
+        Some(manager) => manager.caches(),
+        None => return,
+    };
+    for cache in caches {
+        if let Some(mut cache) = cache.try_lock() {
+            cache.sync_outside_transaction();
+        }
     }
 }
+
+// 开始块设备上的事务。此后被修改的块会一直留在缓存中，直到release_block_device。
+pub(crate) fn hold_block_device(block_device: &Arc<dyn BlockDevice>) {
+    let device = device_id(block_device);
+    let mut transactions = TRANSACTIONS.lock();
+    assert!(
+        transactions.iter().all(|txn| txn.device != device),
+        "nested transaction"
+    );
+    transactions.push(TxnBlocks {
+        device,
+        blocks: BTreeSet::new(),
+    });
+}
+
+// 结束块设备上的事务，被修改的块可以正常换出和写回
+pub(crate) fn release_block_device(block_device: &Arc<dyn BlockDevice>) {
+    let device = device_id(block_device);
+    TRANSACTIONS.lock().retain(|txn| txn.device != device);
+}
+
+// 块设备上进行中的事务修改过、尚未写回的块，按块号排序。
+// 事务开始之前被修改、事务中没有再修改的块不包括在内，它们仍由sync或换出写回。
+pub(crate) fn transaction_dirty_caches(
+    block_device: &Arc<dyn BlockDevice>,
+) -> Vec<Arc<Mutex<BlockCache>>> {
+    let device = device_id(block_device);
+    let blocks: Vec<usize> = TRANSACTIONS
+        .lock()
+        .iter()
+        .find(|txn| txn.device == device)
+        .map_or(Vec::new(), |txn| txn.blocks.iter().copied().collect());
+    // 被修改过的块不会被换出，一定还在缓存中
+    let caches: Vec<_> = {
+        let manager = BLOCK_CACHE_MANAGER.lock();
+        blocks
+            .iter()
+            .filter_map(|&block_id| manager.find(device, block_id))
+            .map(|idx| Arc::clone(&manager.slots[idx].cache))
+            .collect()
+    };
+    caches
+        .into_iter()
+        .filter(|cache| cache.lock().is_dirty())
+        .collect()
+}
+
+// 块缓存的统计信息
+pub fn block_cache_stats() -> BlockCacheStats {
+    BLOCK_CACHE_MANAGER.lock().stats()
+}
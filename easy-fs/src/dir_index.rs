@@ -0,0 +1,132 @@
+//! 目录的哈希索引。
+//!
+//! 在目录中查找文件名需要逐块扫描整个目录。目录较大时，为它建立一个内存中的索引：
+//! 文件名的哈希值 -> 目录项的位置，查找时只需读出哈希值相同的目录项所在的块。
+//! 索引还记录每个目录块中可以分给新目录项的最大空间，插入目录项时不必扫描整个目录。
+//! 索引只存在于内存中，由文件系统的锁保护，修改目录时同步更新，重新打开文件系统后按需重建。
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+// 目录至少有这么多块时才建立索引，小目录直接扫描
+pub const DIR_INDEX_MIN_BLOCKS: usize = 4;
+// 最多同时为这么多目录建立索引，限制索引占用的内存
+const DIR_INDEX_CAPACITY: usize = 8;
+
+// 目录项的位置
+#[derive(Clone, Copy)]
+pub struct DirSlot {
+    // 目录中的第几个块
+    pub block: usize,
+    // 目录项在块中的偏移
+    pub offset: usize,
+    pub inode: u32,
+}
+
+pub struct DirIndex {
+    // 哈希值相同的文件名很少，用一个小数组存放它们的位置
+    slots: BTreeMap<u64, Vec<DirSlot>>,
+    // 每个目录块中可以分给新目录项的最大空间
+    spare: Vec<usize>,
+}
+
+// FNV-1a哈希
+fn name_hash(name: &[u8]) -> u64 {
+    name.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl DirIndex {
+    pub fn new() -> Self {
+        Self {
+            slots: BTreeMap::new(),
+            spare: Vec::new(),
+        }
+    }
+
+    // 可能存放着名为name的目录项的位置，需要读出目录项比较文件名
+    pub fn candidates(&self, name: &[u8]) -> &[DirSlot] {
+        self.slots
+            .get(&name_hash(name))
+            .map_or(&[], |slots| slots.as_slice())
+    }
+
+    // 记录目录项的位置。同一位置已经记录过时不重复记录。
+    pub fn insert(&mut self, name: &[u8], slot: DirSlot) {
+        let slots = self.slots.entry(name_hash(name)).or_default();
+        if !slots
+            .iter()
+            .any(|s| s.block == slot.block && s.offset == slot.offset)
+        {
+            slots.push(slot);
+        }
+    }
+
+    pub fn remove(&mut self, name: &[u8], block: usize, offset: usize) {
+        let hash = name_hash(name);
+        if let Some(slots) = self.slots.get_mut(&hash) {
+            slots.retain(|slot| slot.block != block || slot.offset != offset);
+            if slots.is_empty() {
+                self.slots.remove(&hash);
+            }
+        }
+    }
+
+    // 更新第block个目录块的最大空闲空间。block等于块数时表示目录新增了一个块。
+    pub fn set_spare(&mut self, block: usize, spare: usize) {
+        if block == self.spare.len() {
+            self.spare.push(spare);
+        } else {
+            self.spare[block] = spare;
+        }
+    }
+
+    // 找到第一个能放下needed字节的目录块
+    pub fn find_space(&self, needed: usize) -> Option<usize> {
+        self.spare.iter().position(|spare| *spare >= needed)
+    }
+}
+
+// 文件系统中所有目录的索引，以目录的inode编号为键
+pub struct DirIndexes {
+    indexes: BTreeMap<u32, DirIndex>,
+    // 最近使用的顺序，最近使用的目录在最后
+    recent: Vec<u32>,
+}
+
+impl DirIndexes {
+    pub fn new() -> Self {
+        Self {
+            indexes: BTreeMap::new(),
+            recent: Vec::new(),
+        }
+    }
+
+    fn touch(&mut self, dir: u32) {
+        self.recent.retain(|d| *d != dir);
+        self.recent.push(dir);
+    }
+
+    pub fn get(&mut self, dir: u32) -> Option<&mut DirIndex> {
+        if self.indexes.contains_key(&dir) {
+            self.touch(dir);
+        }
+        self.indexes.get_mut(&dir)
+    }
+
+    // 加入一个目录的索引。索引的数量达到上限时，丢弃最久没有使用的索引。
+    pub fn insert(&mut self, dir: u32, index: DirIndex) {
+        if self.indexes.len() >= DIR_INDEX_CAPACITY {
+            let victim = self.recent.remove(0);
+            self.indexes.remove(&victim);
+        }
+        self.touch(dir);
+        self.indexes.insert(dir, index);
+    }
+
+    // 目录被删除时丢弃它的索引，inode编号可能被新的目录重用
+    pub fn remove(&mut self, dir: u32) {
+        self.recent.retain(|d| *d != dir);
+        self.indexes.remove(&dir);
+    }
+}
@@ -4,35 +4,95 @@ use crate::{
     bitmap::Bitmap,
     block_cache::{block_cache_sync_all, get_block_cache},
     block_dev::BlockDevice,
+    dir_index::DirIndexes,
+    journal::{Journal, JOURNAL_BLOCKS},
     layout::{DiskInode, DiskInodeType, SuperBlock},
     vfs::Inode,
     BLOCK_SZ,
 };
-use alloc::sync::Arc;
-use spin::Mutex;
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+};
+use spin::{Mutex, MutexGuard, RwLock};
+
+// 文件系统内部的锁，按照获取的先后顺序：
+// 1. inode的读写锁（Inode::lock）：读文件、查找目录时持有读锁，写文件、修改目录时持有写锁。
+//    同时需要父目录和子inode的锁时，先获取父目录的锁。
+// 2. 日志锁（journal）：持有该锁期间就是一个事务，事务之间互斥。
+// 3. 分配器锁（allocator）、目录索引锁（dir_indexes）、inode锁表（inode_locks）：只在很短的时间内持有。
+// 不修改磁盘的操作不需要日志锁，不同文件的读操作、同一文件的多个读操作可以并行。
+
 ///An easy file system on block
 pub struct EasyFileSystem {
     // 块设备（如磁盘）
     pub block_device: Arc<dyn BlockDevice>,
-    // 记录inode的使用情况
-    pub inode_bitmap: Bitmap,
-    // 记录数据块的使用情况
-    pub data_bitmap: Bitmap,
+    // 分配器，记录inode和数据块的使用情况
+    allocator: Mutex<Allocator>,
+    // 元数据日志，保证修改多个块的操作是原子的
+    journal: Mutex<Journal>,
     inode_area_start_block: u32,
     data_area_start_block: u32,
+    // 数据区域的块数。数据位图的比特数可能多于数据块数，多出的比特位不能分配。
+    data_area_blocks: u32,
+    // 大目录的哈希索引
+    pub(crate) dir_indexes: Mutex<DirIndexes>,
+    // 各个inode的读写锁。同一个inode可能有多个Inode对象，它们共享一个锁。
+    inode_locks: Mutex<BTreeMap<u32, Weak<RwLock<()>>>>,
+}
+
+struct Allocator {
+    // 记录inode的使用情况
+    inode_bitmap: Bitmap,
+    // 记录数据块的使用情况
+    data_bitmap: Bitmap,
+}
+
+// 进行中的事务。它持有日志锁，提交之前其他事务不能开始。
+#[must_use]
+pub(crate) struct Transaction<'a> {
+    journal: MutexGuard<'a, Journal>,
+    block_device: &'a Arc<dyn BlockDevice>,
+}
+
+impl Transaction<'_> {
+    // 提交事务。事务修改的块不能超过日志的容量，修改更多块的操作需要自己拆成多个事务。
+    pub fn commit(mut self) {
+        self.journal.commit(self.block_device)
+    }
 }
 
 type DataBlock = [u8; BLOCK_SZ];
 
+// 文件系统的使用情况，以块和inode为单位
+#[derive(Debug)]
+pub struct FsStats {
+    pub total_blocks: u32,
+    pub data_blocks: u32,
+    pub free_data_blocks: u32,
+    pub inodes: u32,
+    pub free_inodes: u32,
+}
+
 impl EasyFileSystem {
     // 初始化块设备，新建文件系统
+    // 磁盘布局：超级块、日志区域、inode位图、inode区域、数据位图、数据区域
     pub fn create(
         block_device: Arc<dyn BlockDevice>,
         total_blocks: u32,
         inode_bitmap_blocks: u32,
-    ) -> Arc<Mutex<Self>> {
+    ) -> Arc<Self> {
+        assert!(
+            total_blocks >= Self::min_total_blocks(inode_bitmap_blocks),
+            "too few blocks for easy-fs"
+        );
+        // 超级块之后的JOURNAL_BLOCKS个块作为日志区域
+        let inode_bitmap_start_block = 1 + JOURNAL_BLOCKS;
         // 共inode_bitmap_blocks个块作为inode位图
-        let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks as usize);
+        let inode_bitmap = Bitmap::new(
+            inode_bitmap_start_block as usize,
+            inode_bitmap_blocks as usize,
+        );
         // 该inode位图最多能表示的inode编号
         let inode_num = inode_bitmap.maximum();
         // 存放这么多inode结构需要的块数，要向上取整
@@ -42,24 +102,32 @@ impl EasyFileSystem {
         };
         // 存放inode位图和inode数据类型的块的总数
         let inode_total_blocks = inode_bitmap_blocks + inode_area_blocks;
-        // 存放数据位图和数据的块数。-1是为了留出超级块的位置。
-        let data_total_blocks = total_blocks - 1 - inode_total_blocks;
+        // 存放数据位图和数据的块数。要留出超级块和日志区域的位置。
+        let data_total_blocks = total_blocks - inode_bitmap_start_block - inode_total_blocks;
         // 一个块的数据位图可表示4096个数据块的使用情况，所以1+4096为一组
         // 因此数据位图块的数量的计算方式为：剩余块数除以4097，再向上取整
         let data_bitmap_blocks = (data_total_blocks + (4097 - 1)) / 4097;
         // 存放数据的块数
         let data_area_blocks = data_total_blocks - data_bitmap_blocks;
-        // 数据位图块前面，是超级块、inode位图块、inode数据块
+        // 数据位图块前面，是超级块、日志区域、inode位图块、inode数据块
         let data_bitmap = Bitmap::new(
-            (1 + inode_bitmap_blocks + inode_area_blocks) as usize,
+            (inode_bitmap_start_block + inode_total_blocks) as usize,
             data_bitmap_blocks as usize,
         );
-        let mut efs = Self {
+        let efs = Self {
             block_device: Arc::clone(&block_device),
-            inode_bitmap,
-            data_bitmap,
-            inode_area_start_block: 1 + inode_bitmap_blocks,
-            data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            allocator: Mutex::new(Allocator {
+                inode_bitmap,
+                data_bitmap,
+            }),
+            journal: Mutex::new(Journal::new(1)),
+            inode_area_start_block: inode_bitmap_start_block + inode_bitmap_blocks,
+            data_area_start_block: inode_bitmap_start_block
+                + inode_total_blocks
+                + data_bitmap_blocks,
+            data_area_blocks,
+            dir_indexes: Mutex::new(DirIndexes::new()),
+            inode_locks: Mutex::new(BTreeMap::new()),
         };
         // 初始化块设备，将所有块清零
         for i in 0..total_blocks {
@@ -77,6 +145,7 @@ impl EasyFileSystem {
             |super_block: &mut SuperBlock| {
                 super_block.initialize(
                     total_blocks,
+                    JOURNAL_BLOCKS,
                     inode_bitmap_blocks,
                     inode_area_blocks,
                     data_bitmap_blocks,
@@ -94,38 +163,114 @@ impl EasyFileSystem {
             });
         // 写回磁盘
         block_cache_sync_all();
-        Arc::new(Mutex::new(efs))
+        Arc::new(efs)
+    }
+
+    // 使用inode_bitmap_blocks个块作为inode位图时，文件系统至少需要的块数：
+    // 超级块、日志区域、inode位图和inode区域，再加上一个数据位图块和一个数据块
+    pub fn min_total_blocks(inode_bitmap_blocks: u32) -> u32 {
+        let inode_num = inode_bitmap_blocks as usize * BLOCK_SZ * 8;
+        let inode_area_blocks = (inode_num * core::mem::size_of::<DiskInode>()).div_ceil(BLOCK_SZ);
+        1 + JOURNAL_BLOCKS + inode_bitmap_blocks + inode_area_blocks as u32 + 2
     }
 
-    // 从块设备中读取超级块，打开文件系统
-    pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
-        get_block_cache(0, Arc::clone(&block_device))
+    // 块设备上是否有easy-fs文件系统
+    pub fn probe(block_device: &Arc<dyn BlockDevice>) -> bool {
+        get_block_cache(0, Arc::clone(block_device))
             .lock()
-            .read(0, |super_block: &SuperBlock| {
+            .read(0, |super_block: &SuperBlock| super_block.is_valid())
+    }
+
+    // 从块设备中读取超级块，打开文件系统。
+    // 如果日志中有已提交、但没有写回完成的事务，则先重做该事务。
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Self> {
+        let efs = get_block_cache(0, Arc::clone(&block_device)).lock().read(
+            0,
+            |super_block: &SuperBlock| {
                 assert!(super_block.is_valid(), "Error loading EFS!");
+                let inode_bitmap_start_block = 1 + super_block.journal_blocks;
                 let inode_total_blocks =
                     super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
-                let efs = Self {
-                    block_device,
-                    inode_bitmap: Bitmap::new(1, super_block.inode_bitmap_blocks as usize),
-                    data_bitmap: Bitmap::new(
-                        (1 + inode_total_blocks) as usize,
-                        super_block.data_bitmap_blocks as usize,
-                    ),
-                    inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
-                    data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
-                };
-                Arc::new(Mutex::new(efs))
-            })
+                Self {
+                    block_device: Arc::clone(&block_device),
+                    allocator: Mutex::new(Allocator {
+                        inode_bitmap: Bitmap::new(
+                            inode_bitmap_start_block as usize,
+                            super_block.inode_bitmap_blocks as usize,
+                        ),
+                        data_bitmap: Bitmap::new(
+                            (inode_bitmap_start_block + inode_total_blocks) as usize,
+                            super_block.data_bitmap_blocks as usize,
+                        ),
+                    }),
+                    journal: Mutex::new(Journal::new(1)),
+                    inode_area_start_block: inode_bitmap_start_block
+                        + super_block.inode_bitmap_blocks,
+                    data_area_start_block: inode_bitmap_start_block
+                        + inode_total_blocks
+                        + super_block.data_bitmap_blocks,
+                    data_area_blocks: super_block.data_area_blocks,
+                    dir_indexes: Mutex::new(DirIndexes::new()),
+                    inode_locks: Mutex::new(BTreeMap::new()),
+                }
+            },
+        );
+        efs.journal.lock().replay(&block_device);
+        Arc::new(efs)
+    }
+
+    // 统计文件系统的使用情况
+    pub fn statfs(&self) -> FsStats {
+        let (total_blocks, data_blocks) = get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| {
+                (super_block.total_blocks, super_block.data_area_blocks)
+            });
+        let allocator = self.allocator.lock();
+        let inodes = allocator.inode_bitmap.maximum() as u32;
+        FsStats {
+            total_blocks,
+            data_blocks,
+            free_data_blocks: data_blocks - allocator.data_bitmap.count(&self.block_device) as u32,
+            inodes,
+            free_inodes: inodes - allocator.inode_bitmap.count(&self.block_device) as u32,
+        }
+    }
+
+    // 开始一个事务。此后对块的修改，在提交事务时才会原子地写回磁盘。
+    // 其他事务正在进行时，等待它提交。
+    pub(crate) fn begin_transaction(&self) -> Transaction<'_> {
+        let journal = self.journal.lock();
+        journal.begin(&self.block_device);
+        Transaction {
+            journal,
+            block_device: &self.block_device,
+        }
     }
 
     // 获取根目录的inode
-    pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
-        let block_device = Arc::clone(&efs.lock().block_device);
-        // acquire efs lock temporarily
-        let (block_id, block_offset) = efs.lock().get_disk_inode_pos(0);
-        // release efs lock
-        Inode::new(block_id, block_offset, Arc::clone(efs), block_device)
+    pub fn root_inode(efs: &Arc<Self>) -> Inode {
+        Inode::new(0, Arc::clone(efs))
+    }
+
+    // 拿到inode的读写锁，同一个inode的所有Inode对象共享一个锁
+    pub(crate) fn inode_lock(&self, inode_id: u32) -> Arc<RwLock<()>> {
+        let mut locks = self.inode_locks.lock();
+        if let Some(lock) = locks.get(&inode_id).and_then(Weak::upgrade) {
+            return lock;
+        }
+        let lock = Arc::new(RwLock::new(()));
+        locks.insert(inode_id, Arc::downgrade(&lock));
+        lock
+    }
+
+    // Inode对象被释放时调用。lock是它持有的锁，没有其他Inode对象共享该锁时，将它从锁表中删除。
+    pub(crate) fn release_inode_lock(&self, inode_id: u32, lock: &Arc<RwLock<()>>) {
+        let mut locks = self.inode_locks.lock();
+        // 持有锁表时，其他线程不能再拿到这个锁，引用计数不会增加
+        if Arc::strong_count(lock) == 1 {
+            locks.remove(&inode_id);
+        }
     }
 
     // 拿到inode编号所在的块号和块内偏移
@@ -139,23 +284,48 @@ impl EasyFileSystem {
         )
     }
 
+    // 由inode所在的块号和块内偏移，得到inode编号
+    pub fn get_inode_id(&self, block_id: u32, block_offset: usize) -> u32 {
+        let inode_size = core::mem::size_of::<DiskInode>();
+        let inodes_per_block = (BLOCK_SZ / inode_size) as u32;
+        (block_id - self.inode_area_start_block) * inodes_per_block
+            + (block_offset / inode_size) as u32
+    }
+
     // 拿到数据块编号（在数据块位图中的编号）的块号
     pub fn get_data_block_id(&self, data_block_id: u32) -> u32 {
         self.data_area_start_block + data_block_id
     }
 
     // 分配一个inode
-    pub fn alloc_inode(&mut self) -> u32 {
-        self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
+    pub fn alloc_inode(&self) -> u32 {
+        self.allocator
+            .lock()
+            .inode_bitmap
+            .alloc(&self.block_device)
+            .unwrap() as u32
     }
 
-    // 分配一个数据块
-    pub fn alloc_data(&mut self) -> u32 {
-        self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
+    // 释放一个inode
+    pub fn dealloc_inode(&self, inode_id: u32) {
+        self.allocator
+            .lock()
+            .inode_bitmap
+            .dealloc(&self.block_device, inode_id as usize)
     }
 
-    // 释放一个数据块，将其缓冲区全部清零
-    pub fn dealloc_data(&mut self, block_id: u32) {
+    // 分配一个数据块，并将其缓冲区全部清零。
+    // 优先分配块号为goal的块，它已被占用时分配其后最近的空闲块，使同一文件的块尽量连续。
+    // 在分配时（而不是释放时）清零，被清零的块与分配它的操作在同一个事务中写回。
+    pub fn alloc_data(&self, goal: u32) -> u32 {
+        let goal = goal.saturating_sub(self.data_area_start_block) as usize;
+        let block_id = self
+            .allocator
+            .lock()
+            .data_bitmap
+            .alloc_near(&self.block_device, goal, self.data_area_blocks as usize)
+            .unwrap() as u32
+            + self.data_area_start_block;
         get_block_cache(block_id as usize, Arc::clone(&self.block_device))
             .lock()
             .modify(0, |data_block: &mut DataBlock| {
@@ -163,7 +333,12 @@ impl EasyFileSystem {
                     *p = 0;
                 })
             });
-        self.data_bitmap.dealloc(
+        block_id
+    }
+
+    // 释放一个数据块
+    pub fn dealloc_data(&self, block_id: u32) {
+        self.allocator.lock().data_bitmap.dealloc(
             &self.block_device,
             (block_id - self.data_area_start_block) as usize,
         )
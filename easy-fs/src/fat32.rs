@@ -0,0 +1,1017 @@
+//! FAT32文件系统，用于和其他系统交换磁盘镜像。
+//!
+//! mkfs.vfat、Windows和Linux创建的FAT32卷都可以读写。支持长文件名（VFAT）、多级目录，
+//! 以及按簇分配和释放空间。不支持符号链接、权限和时间戳：新建的目录项一律记为1980年1月1日。
+//!
+//! 磁盘布局：保留扇区（包括引导扇区和FSInfo扇区）、若干份内容相同的FAT表、数据区。
+//! 数据区按簇划分，FAT表的第n项记录第n簇在簇链中的下一簇。文件和目录的内容都存放在簇链中，
+//! 目录的内容是一个个32字节的目录项。
+//!
+//! FAT32没有inode：文件的首簇号和长度记录在它的短目录项中，因此用短目录项在磁盘上的位置标识文件。
+//! 所有的读写都经过块缓存。整个文件系统用一个读写锁保护：读文件和目录可以并行，修改互斥。
+
+use crate::block_cache::{read_block_cached, write_block_cached};
+use crate::{BlockDevice, BLOCK_SZ};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::{max, min};
+use spin::{Mutex, RwLock};
+
+// 目录项的大小
+const DIR_ENTRY_SZ: usize = 32;
+const ENTRIES_PER_SECTOR: usize = BLOCK_SZ / DIR_ENTRY_SZ;
+// 一个目录最多有65536个目录项
+const DIR_ENTRY_LIMIT: usize = 65536;
+
+// 目录项的属性
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+// 长文件名目录项的属性，即只读、隐藏、系统、卷标四个属性的组合
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+// 目录项第一个字节的特殊取值：目录结束、已删除。
+// 短文件名的第一个字节真的是0xE5时，存为0x05。
+const ENTRY_END: u8 = 0x00;
+const ENTRY_DELETED: u8 = 0xE5;
+const ENTRY_E5: u8 = 0x05;
+// 短目录项中表示主文件名、扩展名显示为小写的标志
+const NT_LOWER_BASE: u8 = 0x08;
+const NT_LOWER_EXT: u8 = 0x10;
+
+// 长文件名目录项：序号的掩码、序号最大（物理上最靠前）的一项的标志
+const LFN_ORDER_MASK: u8 = 0x1F;
+const LFN_LAST: u8 = 0x40;
+// 每个长文件名目录项存放13个UTF-16字符，这是它们在目录项中的偏移
+const LFN_CHARS: usize = 13;
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+// 文件名的最大长度（UTF-16字符）
+const NAME_LENGTH_LIMIT: usize = 255;
+// 不能出现在文件名中的字符
+const INVALID_NAME_CHARS: &str = "\"*/:<>?\\|";
+// 可以出现在长文件名、但不能出现在短文件名中的字符
+const LONG_ONLY_CHARS: &str = "+,.;=[] ";
+
+// FAT表项只使用低28位
+const FAT_MASK: u32 = 0x0FFF_FFFF;
+const FAT_FREE: u32 = 0;
+// 簇链结束的标记。读取时，大于最大簇号的表项都表示簇链结束。
+const FAT_EOC: u32 = 0x0FFF_FFFF;
+// 数据区的第一个簇的编号
+const FIRST_CLUSTER: u32 = 2;
+
+// FSInfo扇区开头的签名，以及空闲簇数、下一个空闲簇的提示在扇区中的偏移
+const FSINFO_LEAD_SIG: u32 = 0x4161_5252;
+const FSINFO_FREE_COUNT: usize = 488;
+const FSINFO_NEXT_FREE: usize = 492;
+
+// 新建目录项的日期：1980年1月1日，即FAT能表示的最早日期
+const DEFAULT_DATE: u16 = (1 << 5) | 1;
+
+fn le16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn le32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+// 短文件名的校验和，记录在它前面的每个长文件名目录项中
+fn short_name_checksum(name: &[u8; 11]) -> u8 {
+    name.iter().fold(0u8, |sum, &byte| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(byte)
+    })
+}
+
+// 文件名是否可以作为长文件名
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.encode_utf16().count() <= NAME_LENGTH_LIMIT
+        && name != "."
+        && name != ".."
+        && !name.ends_with(['.', ' '])
+        && !name
+            .chars()
+            .any(|c| (c as u32) < 0x20 || INVALID_NAME_CHARS.contains(c))
+}
+
+// 短文件名中的一个字符：大写字母、数字和部分符号
+fn is_short_name_char(c: char) -> bool {
+    c.is_ascii_graphic()
+        && !c.is_ascii_lowercase()
+        && !INVALID_NAME_CHARS.contains(c)
+        && !LONG_ONLY_CHARS.contains(c)
+}
+
+// 文件名本身就是合法的8.3格式短文件名时（如"README.TXT"），返回它在目录项中的形式，不需要长文件名
+fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+    if !base.chars().chain(ext.chars()).all(is_short_name_char) {
+        return None;
+    }
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    if short[0] == ENTRY_DELETED {
+        short[0] = ENTRY_E5;
+    }
+    Some(short)
+}
+
+// 为长文件名生成的短文件名的基本部分：主文件名的前6个字符和扩展名的前3个字符，
+// 转为大写，不能出现在短文件名中的字符替换为'_'
+fn short_name_basis(name: &str) -> ([u8; 6], usize, [u8; 3]) {
+    let name = name.trim_start_matches(['.', ' ']);
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+    let convert = |c: char| -> Option<u8> {
+        match c {
+            ' ' | '.' => None,
+            _ if is_short_name_char(c.to_ascii_uppercase()) => Some(c.to_ascii_uppercase() as u8),
+            _ => Some(b'_'),
+        }
+    };
+    let mut basis = [b'_'; 6];
+    let mut basis_len = 0;
+    for byte in base.chars().filter_map(convert).take(6) {
+        basis[basis_len] = byte;
+        basis_len += 1;
+    }
+    // 主文件名中没有可用的字符时，用一个'_'
+    basis_len = max(basis_len, 1);
+    let mut extension = [b' '; 3];
+    for (i, byte) in ext.chars().filter_map(convert).take(3).enumerate() {
+        extension[i] = byte;
+    }
+    (basis, basis_len, extension)
+}
+
+// 一个32字节的目录项。目录项在扇区中不一定对齐，因此按字节解析。
+#[derive(Clone, Copy)]
+struct RawEntry([u8; DIR_ENTRY_SZ]);
+
+impl RawEntry {
+    fn new_short(name: &[u8; 11], attr: u8, first_cluster: u32) -> Self {
+        let mut entry = Self([0; DIR_ENTRY_SZ]);
+        entry.0[..11].copy_from_slice(name);
+        entry.0[11] = attr;
+        // 创建日期、访问日期和修改日期
+        for offset in [16, 18, 24] {
+            entry.0[offset..offset + 2].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+        }
+        entry.set_first_cluster(first_cluster);
+        entry
+    }
+
+    // 长文件名中序号为order（从1开始）的一段，即第order-1组13个字符
+    fn new_long(order: u8, last: bool, name: &[u16], checksum: u8) -> Self {
+        let mut entry = Self([0; DIR_ENTRY_SZ]);
+        entry.0[0] = if last { order | LFN_LAST } else { order };
+        entry.0[11] = ATTR_LONG_NAME;
+        entry.0[13] = checksum;
+        let start = (order as usize - 1) * LFN_CHARS;
+        for (i, &offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            // 文件名之后是一个0，再之后用0xFFFF填充
+            let unit = match (start + i).cmp(&name.len()) {
+                core::cmp::Ordering::Less => name[start + i],
+                core::cmp::Ordering::Equal => 0,
+                core::cmp::Ordering::Greater => 0xFFFF,
+            };
+            entry.0[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        entry
+    }
+
+    fn is_end(&self) -> bool {
+        self.0[0] == ENTRY_END
+    }
+    fn is_deleted(&self) -> bool {
+        self.0[0] == ENTRY_DELETED
+    }
+    fn attr(&self) -> u8 {
+        self.0[11]
+    }
+    fn is_long_name(&self) -> bool {
+        self.attr() & ATTR_LONG_NAME == ATTR_LONG_NAME
+    }
+    fn is_dir(&self) -> bool {
+        self.attr() & ATTR_DIRECTORY != 0
+    }
+    fn short_name(&self) -> [u8; 11] {
+        self.0[..11].try_into().unwrap()
+    }
+    fn first_cluster(&self) -> u32 {
+        ((le16(&self.0, 20) as u32) << 16) | le16(&self.0, 26) as u32
+    }
+    fn set_first_cluster(&mut self, cluster: u32) {
+        self.0[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        self.0[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+    }
+    fn size(&self) -> u32 {
+        le32(&self.0, 28)
+    }
+    fn set_size(&mut self, size: u32) {
+        self.0[28..32].copy_from_slice(&size.to_le_bytes());
+    }
+
+    // 短文件名的显示形式，如"README.TXT"。按Windows NT的标志，主文件名或扩展名可能显示为小写。
+    fn display_short_name(&self) -> String {
+        let mut name = self.short_name();
+        if name[0] == ENTRY_E5 {
+            name[0] = ENTRY_DELETED;
+        }
+        let convert = |bytes: &[u8], lower: bool| -> String {
+            let len = bytes.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+            bytes[..len]
+                .iter()
+                .map(|&b| {
+                    let c = b as char;
+                    if lower {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c
+                    }
+                })
+                .collect()
+        };
+        let mut display = convert(&name[..8], self.0[12] & NT_LOWER_BASE != 0);
+        let ext = convert(&name[8..], self.0[12] & NT_LOWER_EXT != 0);
+        if !ext.is_empty() {
+            display.push('.');
+            display.push_str(&ext);
+        }
+        display
+    }
+
+    // 长文件名目录项中的13个字符
+    fn long_name_units(&self) -> [u16; LFN_CHARS] {
+        LFN_CHAR_OFFSETS.map(|offset| le16(&self.0, offset))
+    }
+}
+
+// 正在解析的长文件名。长文件名目录项按序号从大到小排列，紧接着是它所属的短目录项。
+struct LongName {
+    units: Vec<u16>,
+    checksum: u8,
+    // 下一个长文件名目录项的序号，为0时表示长文件名已经完整
+    next_order: u8,
+    slots: Vec<(usize, usize)>,
+}
+
+impl LongName {
+    // 完整的长文件名，截止到第一个0
+    fn decode(&self) -> String {
+        let len = self
+            .units
+            .iter()
+            .position(|&u| u == 0)
+            .unwrap_or(self.units.len());
+        char::decode_utf16(self.units[..len].iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+}
+
+// 目录中的一项
+struct DirItem {
+    name: String,
+    entry: RawEntry,
+    // 该项占用的所有目录项的位置，最后一个是短目录项
+    slots: Vec<(usize, usize)>,
+    // 短目录项是目录中的第几个目录项。目录项不会移动，增删其他项时不变
+    pos: usize,
+}
+
+impl DirItem {
+    fn short_slot(&self) -> (usize, usize) {
+        *self.slots.last().unwrap()
+    }
+}
+
+struct ClusterAllocator {
+    free: u32,
+    // 下次从这个簇开始查找空闲簇
+    next: u32,
+}
+
+pub struct Fat32FileSystem {
+    block_device: Arc<dyn BlockDevice>,
+    sectors_per_cluster: usize,
+    // 第一份FAT表的起始扇区，每份FAT表的扇区数和FAT表的份数
+    fat_start: usize,
+    fat_sectors: usize,
+    fat_count: usize,
+    // 数据区的起始扇区，即第2簇的位置
+    data_start: usize,
+    // 最大的簇号
+    max_cluster: u32,
+    root_cluster: u32,
+    fsinfo_sector: Option<usize>,
+    allocator: Mutex<ClusterAllocator>,
+    lock: RwLock<()>,
+}
+
+impl Fat32FileSystem {
+    // 读取引导扇区，打开块设备上的FAT32文件系统。块设备上不是FAT32文件系统时返回None。
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> Option<Arc<Self>> {
+        let mut boot = [0u8; BLOCK_SZ];
+        read_block_cached(&block_device, 0, 0, &mut boot);
+        if boot[510..512] != [0x55, 0xAA] {
+            return None;
+        }
+        let bytes_per_sector = le16(&boot, 11) as usize;
+        let sectors_per_cluster = boot[13] as usize;
+        let reserved_sectors = le16(&boot, 14) as usize;
+        let fat_count = boot[16] as usize;
+        let root_entries = le16(&boot, 17);
+        let total_sectors_16 = le16(&boot, 19) as usize;
+        let fat_sectors_16 = le16(&boot, 22);
+        let total_sectors_32 = le32(&boot, 32) as usize;
+        let fat_sectors = le32(&boot, 36) as usize;
+        let root_cluster = le32(&boot, 44);
+        let fsinfo_sector = le16(&boot, 48) as usize;
+        // 只支持与块大小相同的512字节扇区。FAT32没有固定的根目录区，FAT12/16的FAT表长度字段为0。
+        if bytes_per_sector != BLOCK_SZ
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved_sectors == 0
+            || fat_count == 0
+            || root_entries != 0
+            || fat_sectors_16 != 0
+            || fat_sectors == 0
+        {
+            return None;
+        }
+        let total_sectors = if total_sectors_16 != 0 {
+            total_sectors_16
+        } else {
+            total_sectors_32
+        };
+        let data_start = reserved_sectors + fat_count * fat_sectors;
+        if total_sectors <= data_start + sectors_per_cluster {
+            return None;
+        }
+        // 簇的数量同时受数据区和FAT表大小的限制
+        let clusters = (total_sectors - data_start) / sectors_per_cluster;
+        let max_cluster = min(clusters + 1, fat_sectors * BLOCK_SZ / 4 - 1) as u32;
+        if !(FIRST_CLUSTER..=max_cluster).contains(&root_cluster) {
+            return None;
+        }
+        let fs = Self {
+            block_device,
+            sectors_per_cluster,
+            fat_start: reserved_sectors,
+            fat_sectors,
+            fat_count,
+            data_start,
+            max_cluster,
+            root_cluster,
+            fsinfo_sector: (1..reserved_sectors)
+                .contains(&fsinfo_sector)
+                .then_some(fsinfo_sector),
+            allocator: Mutex::new(ClusterAllocator {
+                free: 0,
+                next: FIRST_CLUSTER,
+            }),
+            lock: RwLock::new(()),
+        };
+        // FSInfo中的空闲簇数只是提示，可能已经过时，因此挂载时重新统计
+        let free = (FIRST_CLUSTER..=max_cluster)
+            .filter(|&cluster| fs.fat_entry(cluster) == FAT_FREE)
+            .count() as u32;
+        let next = fs.read_fsinfo(FSINFO_NEXT_FREE).unwrap_or(FIRST_CLUSTER);
+        *fs.allocator.lock() = ClusterAllocator {
+            free,
+            next: if (FIRST_CLUSTER..=max_cluster).contains(&next) {
+                next
+            } else {
+                FIRST_CLUSTER
+            },
+        };
+        Some(Arc::new(fs))
+    }
+
+    pub fn root_inode(fs: &Arc<Self>) -> Fat32Inode {
+        Fat32Inode {
+            fs: Arc::clone(fs),
+            entry: None,
+        }
+    }
+
+    // 簇的大小（字节）
+    pub fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster * BLOCK_SZ
+    }
+
+    // 数据区的总簇数和空闲簇数
+    pub fn cluster_stats(&self) -> (usize, usize) {
+        let total = (self.max_cluster - FIRST_CLUSTER + 1) as usize;
+        (total, self.allocator.lock().free as usize)
+    }
+
+    // FSInfo扇区中offset处的字段。没有FSInfo扇区或签名不对时返回None。
+    fn read_fsinfo(&self, offset: usize) -> Option<u32> {
+        let sector = self.fsinfo_sector?;
+        let mut buf = [0u8; 4];
+        read_block_cached(&self.block_device, sector, 0, &mut buf);
+        if u32::from_le_bytes(buf) != FSINFO_LEAD_SIG {
+            return None;
+        }
+        read_block_cached(&self.block_device, sector, offset, &mut buf);
+        Some(u32::from_le_bytes(buf))
+    }
+
+    // 将空闲簇数和下一个空闲簇写入FSInfo扇区，供其他系统挂载时使用
+    fn write_fsinfo(&self, allocator: &ClusterAllocator) {
+        if let Some(sector) = self.fsinfo_sector {
+            if self.read_fsinfo(FSINFO_FREE_COUNT).is_some() {
+                write_block_cached(
+                    &self.block_device,
+                    sector,
+                    FSINFO_FREE_COUNT,
+                    &allocator.free.to_le_bytes(),
+                );
+                write_block_cached(
+                    &self.block_device,
+                    sector,
+                    FSINFO_NEXT_FREE,
+                    &allocator.next.to_le_bytes(),
+                );
+            }
+        }
+    }
+
+    // 第一份FAT表中，簇cluster的表项所在的扇区和扇区内偏移
+    fn fat_position(&self, cluster: u32) -> (usize, usize) {
+        let offset = cluster as usize * 4;
+        (self.fat_start + offset / BLOCK_SZ, offset % BLOCK_SZ)
+    }
+
+    fn fat_entry(&self, cluster: u32) -> u32 {
+        let (sector, offset) = self.fat_position(cluster);
+        let mut buf = [0u8; 4];
+        read_block_cached(&self.block_device, sector, offset, &mut buf);
+        u32::from_le_bytes(buf) & FAT_MASK
+    }
+
+    // 修改簇cluster的表项，同时修改每一份FAT表。表项的高4位是保留的，保持不变。
+    fn set_fat_entry(&self, cluster: u32, value: u32) {
+        let (sector, offset) = self.fat_position(cluster);
+        for i in 0..self.fat_count {
+            let sector = sector + i * self.fat_sectors;
+            let mut buf = [0u8; 4];
+            read_block_cached(&self.block_device, sector, offset, &mut buf);
+            let entry = (u32::from_le_bytes(buf) & !FAT_MASK) | (value & FAT_MASK);
+            write_block_cached(&self.block_device, sector, offset, &entry.to_le_bytes());
+        }
+    }
+
+    // 从簇first开始的簇链。first为0（空文件）时为空。
+    // 簇链损坏（指向空闲簇、越界或形成循环）时在该处截断。
+    fn cluster_chain(&self, first: u32) -> Vec<u32> {
+        let mut chain = Vec::new();
+        let mut cluster = first;
+        while (FIRST_CLUSTER..=self.max_cluster).contains(&cluster)
+            && chain.len() < self.max_cluster as usize
+        {
+            chain.push(cluster);
+            cluster = self.fat_entry(cluster);
+        }
+        chain
+    }
+
+    // 簇的第一个扇区
+    fn cluster_sector(&self, cluster: u32) -> usize {
+        self.data_start + (cluster - FIRST_CLUSTER) as usize * self.sectors_per_cluster
+    }
+
+    // 分配一个空闲簇并清零，接在簇prev之后。没有空闲簇时返回None。
+    fn alloc_cluster(&self, prev: Option<u32>) -> Option<u32> {
+        let cluster = {
+            let mut allocator = self.allocator.lock();
+            if allocator.free == 0 {
+                return None;
+            }
+            let total = self.max_cluster - FIRST_CLUSTER + 1;
+            let start = allocator.next - FIRST_CLUSTER;
+            let cluster = (0..total)
+                .map(|i| FIRST_CLUSTER + (start + i) % total)
+                .find(|&cluster| self.fat_entry(cluster) == FAT_FREE)?;
+            self.set_fat_entry(cluster, FAT_EOC);
+            allocator.free -= 1;
+            allocator.next = if cluster == self.max_cluster {
+                FIRST_CLUSTER
+            } else {
+                cluster + 1
+            };
+            self.write_fsinfo(&allocator);
+            cluster
+        };
+        let sector = self.cluster_sector(cluster);
+        for sector in sector..sector + self.sectors_per_cluster {
+            write_block_cached(&self.block_device, sector, 0, &[0u8; BLOCK_SZ]);
+        }
+        if let Some(prev) = prev {
+            self.set_fat_entry(prev, cluster);
+        }
+        Some(cluster)
+    }
+
+    // 释放簇链中的簇
+    fn free_clusters(&self, clusters: &[u32]) {
+        if clusters.is_empty() {
+            return;
+        }
+        let mut allocator = self.allocator.lock();
+        for &cluster in clusters {
+            self.set_fat_entry(cluster, FAT_FREE);
+        }
+        allocator.free += clusters.len() as u32;
+        self.write_fsinfo(&allocator);
+    }
+
+    // 在簇链chain的末尾分配新的簇，直到簇链至少能存放size字节。空间不足时分配尽可能多的簇。
+    fn grow_chain(&self, chain: &mut Vec<u32>, size: usize) {
+        while chain.len() * self.cluster_size() < size {
+            match self.alloc_cluster(chain.last().copied()) {
+                Some(cluster) => chain.push(cluster),
+                None => break,
+            }
+        }
+    }
+
+    // 簇链中[offset, offset+len)范围对应的扇区片段：(扇区号, 扇区内偏移, 片段在范围内的位置, 长度)。
+    // 超出簇链的部分被忽略。
+    fn pieces(
+        &self,
+        chain: &[u32],
+        offset: usize,
+        len: usize,
+    ) -> Vec<(usize, usize, usize, usize)> {
+        let end = min(offset + len, chain.len() * self.cluster_size());
+        let mut pieces = Vec::new();
+        let mut pos = offset;
+        while pos < end {
+            let cluster = chain[pos / self.cluster_size()];
+            let sector = self.cluster_sector(cluster) + pos % self.cluster_size() / BLOCK_SZ;
+            let piece_len = min(BLOCK_SZ - pos % BLOCK_SZ, end - pos);
+            pieces.push((sector, pos % BLOCK_SZ, pos - offset, piece_len));
+            pos += piece_len;
+        }
+        pieces
+    }
+
+    // 将簇链中[start, end)范围清零
+    fn zero_range(&self, chain: &[u32], start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        let zero = [0u8; BLOCK_SZ];
+        for (sector, offset, _, len) in self.pieces(chain, start, end - start) {
+            write_block_cached(&self.block_device, sector, offset, &zero[..len]);
+        }
+    }
+
+    // 目录的簇链中所有目录项的位置
+    fn dir_slots(&self, first: u32) -> Vec<(usize, usize)> {
+        let mut slots = Vec::new();
+        for cluster in self.cluster_chain(first) {
+            let sector = self.cluster_sector(cluster);
+            for sector in sector..sector + self.sectors_per_cluster {
+                slots.extend((0..ENTRIES_PER_SECTOR).map(|i| (sector, i * DIR_ENTRY_SZ)));
+            }
+        }
+        slots
+    }
+
+    fn read_entry(&self, (sector, offset): (usize, usize)) -> RawEntry {
+        let mut entry = RawEntry([0; DIR_ENTRY_SZ]);
+        read_block_cached(&self.block_device, sector, offset, &mut entry.0);
+        entry
+    }
+
+    fn write_entry(&self, (sector, offset): (usize, usize), entry: &RawEntry) {
+        write_block_cached(&self.block_device, sector, offset, &entry.0);
+    }
+}
+
+// FAT32中的一个文件或目录
+pub struct Fat32Inode {
+    fs: Arc<Fat32FileSystem>,
+    // 短目录项的位置（扇区号，扇区内偏移）。根目录没有目录项，为None。
+    entry: Option<(usize, usize)>,
+}
+
+impl Fat32Inode {
+    fn wrap(&self, slot: (usize, usize)) -> Arc<Fat32Inode> {
+        Arc::new(Self {
+            fs: Arc::clone(&self.fs),
+            entry: Some(slot),
+        })
+    }
+
+    // 文件的短目录项。根目录没有目录项；文件已经被删除时，目录项可能被其他文件复用，也返回None。
+    fn short_entry(&self) -> Option<RawEntry> {
+        let entry = self.fs.read_entry(self.entry?);
+        (!entry.is_deleted()).then_some(entry)
+    }
+
+    fn update_short_entry(&self, f: impl FnOnce(&mut RawEntry)) {
+        if let Some(mut entry) = self.short_entry() {
+            f(&mut entry);
+            self.fs.write_entry(self.entry.unwrap(), &entry);
+        }
+    }
+
+    // 首簇号
+    fn first_cluster(&self) -> u32 {
+        match self.entry {
+            None => self.fs.root_cluster,
+            Some(_) => self.short_entry().map_or(0, |entry| entry.first_cluster()),
+        }
+    }
+
+    // 是否是普通文件
+    fn is_file(&self) -> bool {
+        self.short_entry().map_or(false, |entry| !entry.is_dir())
+    }
+
+    // 目录中的所有项，不包括"."、".."和卷标
+    fn read_dir(&self) -> Vec<DirItem> {
+        if !self.is_dir() {
+            return Vec::new();
+        }
+        let mut items = Vec::new();
+        let mut long_name: Option<LongName> = None;
+        let slots = self.fs.dir_slots(self.first_cluster());
+        for (pos, slot) in slots.into_iter().enumerate() {
+            let entry = self.fs.read_entry(slot);
+            if entry.is_end() {
+                break;
+            }
+            if entry.is_deleted() {
+                long_name = None;
+                continue;
+            }
+            if entry.is_long_name() {
+                let order = entry.0[0] & LFN_ORDER_MASK;
+                if entry.0[0] & LFN_LAST != 0 && order > 0 {
+                    long_name = Some(LongName {
+                        units: vec![0xFFFF; order as usize * LFN_CHARS],
+                        checksum: entry.0[13],
+                        next_order: order,
+                        slots: Vec::new(),
+                    });
+                }
+                // 序号或校验和不连续的长文件名目录项是孤立的，忽略它们
+                match long_name.as_mut() {
+                    Some(name)
+                        if order > 0
+                            && order == name.next_order
+                            && entry.0[13] == name.checksum =>
+                    {
+                        let start = (order as usize - 1) * LFN_CHARS;
+                        name.units[start..start + LFN_CHARS]
+                            .copy_from_slice(&entry.long_name_units());
+                        name.next_order -= 1;
+                        name.slots.push(slot);
+                    }
+                    _ => long_name = None,
+                }
+                continue;
+            }
+            let (name, mut slots) = match long_name.take() {
+                Some(name)
+                    if name.next_order == 0
+                        && name.checksum == short_name_checksum(&entry.short_name()) =>
+                {
+                    (name.decode(), name.slots)
+                }
+                _ => (entry.display_short_name(), Vec::new()),
+            };
+            if entry.attr() & ATTR_VOLUME_ID != 0 || name == "." || name == ".." {
+                continue;
+            }
+            slots.push(slot);
+            items.push(DirItem {
+                name,
+                entry,
+                slots,
+                pos,
+            });
+        }
+        items
+    }
+
+    // 在目录中找到count个连续的空闲目录项，不够时扩大目录
+    fn free_slots(&self, count: usize) -> Option<Vec<(usize, usize)>> {
+        let first = self.first_cluster();
+        let mut slots = self.fs.dir_slots(first);
+        let mut run = 0;
+        let mut end_seen = false;
+        for (i, &slot) in slots.iter().enumerate() {
+            let entry = self.fs.read_entry(slot);
+            // 目录结束标记之后的目录项都是空闲的
+            end_seen |= entry.is_end();
+            if end_seen || entry.is_deleted() {
+                run += 1;
+                if run == count {
+                    let found = slots[i + 1 - count..=i].to_vec();
+                    // 用掉了结束标记时，在新的目录项之后重新写一个
+                    if end_seen {
+                        if let Some(&next) = slots.get(i + 1) {
+                            self.fs.write_entry(next, &RawEntry([0; DIR_ENTRY_SZ]));
+                        }
+                    }
+                    return Some(found);
+                }
+            } else {
+                run = 0;
+            }
+        }
+        // 在目录末尾分配新的簇，新簇中的目录项都是0，即空闲的
+        // 新的目录项从末尾连续的空闲目录项开始
+        let mut chain = self.fs.cluster_chain(first);
+        let needed = slots.len() - run + count;
+        if needed > DIR_ENTRY_LIMIT {
+            return None;
+        }
+        let old_len = chain.len();
+        self.fs.grow_chain(&mut chain, needed * DIR_ENTRY_SZ);
+        if old_len == 0 || chain.len() * self.fs.cluster_size() < needed * DIR_ENTRY_SZ {
+            self.fs.free_clusters(&chain[old_len..]);
+            if old_len > 0 {
+                self.fs.set_fat_entry(chain[old_len - 1], FAT_EOC);
+            }
+            return None;
+        }
+        slots = self.fs.dir_slots(first);
+        let start = needed - count;
+        Some(slots[start..needed].to_vec())
+    }
+
+    // 为长文件名生成一个目录中没有的短文件名，形如"LONGFI~1.TXT"
+    fn unique_short_name(name: &str, items: &[DirItem]) -> Option<[u8; 11]> {
+        let (basis, basis_len, ext) = short_name_basis(name);
+        for n in 1..1_000_000u32 {
+            let mut tail = [0u8; 7];
+            let mut tail_len = 0;
+            let mut rest = n;
+            while rest > 0 {
+                tail[tail_len] = b'0' + (rest % 10) as u8;
+                tail_len += 1;
+                rest /= 10;
+            }
+            let mut short = [b' '; 11];
+            // "~n"和主文件名一共不超过8个字符
+            let keep = min(basis_len, 8 - 1 - tail_len);
+            short[..keep].copy_from_slice(&basis[..keep]);
+            short[keep] = b'~';
+            for i in 0..tail_len {
+                short[keep + 1 + i] = tail[tail_len - 1 - i];
+            }
+            short[8..].copy_from_slice(&ext);
+            if !items.iter().any(|item| item.entry.short_name() == short) {
+                return Some(short);
+            }
+        }
+        None
+    }
+
+    // 在目录中创建一个文件或子目录
+    fn create_entry(&self, name: &str, dir: bool) -> Option<Arc<Fat32Inode>> {
+        let _guard = self.fs.lock.write();
+        if !is_valid_name(name) {
+            return None;
+        }
+        let items = self.read_dir();
+        if !self.is_dir()
+            || items
+                .iter()
+                .any(|item| item.name.eq_ignore_ascii_case(name))
+        {
+            return None;
+        }
+        // 文件名转为大写后是8.3格式时，直接用作短文件名，否则生成一个"~n"形式的短文件名。
+        // 短文件名与文件名不同时（如文件名中有小写字母），在短目录项之前存放长文件名。
+        let short = match exact_short_name(&name.to_ascii_uppercase()) {
+            Some(short) if !items.iter().any(|item| item.entry.short_name() == short) => short,
+            _ => Self::unique_short_name(name, &items)?,
+        };
+        let long_name: Vec<u16> = if exact_short_name(name) == Some(short) {
+            Vec::new()
+        } else {
+            name.encode_utf16().collect()
+        };
+        let long_entries = (long_name.len() + LFN_CHARS - 1) / LFN_CHARS;
+        let slots = self.free_slots(long_entries + 1)?;
+        // 子目录的第一个簇存放"."和".."，".."指向根目录时记为0
+        let first_cluster = if dir {
+            let cluster = self.fs.alloc_cluster(None)?;
+            let sector = self.fs.cluster_sector(cluster);
+            let parent = match self.entry {
+                None => 0,
+                Some(_) => self.first_cluster(),
+            };
+            let dot = RawEntry::new_short(b".          ", ATTR_DIRECTORY, cluster);
+            let dotdot = RawEntry::new_short(b"..         ", ATTR_DIRECTORY, parent);
+            self.fs.write_entry((sector, 0), &dot);
+            self.fs.write_entry((sector, DIR_ENTRY_SZ), &dotdot);
+            cluster
+        } else {
+            0
+        };
+        let checksum = short_name_checksum(&short);
+        for (i, &slot) in slots[..long_entries].iter().enumerate() {
+            let order = (long_entries - i) as u8;
+            let entry = RawEntry::new_long(order, i == 0, &long_name, checksum);
+            self.fs.write_entry(slot, &entry);
+        }
+        let attr = if dir { ATTR_DIRECTORY } else { ATTR_ARCHIVE };
+        let short_slot = slots[long_entries];
+        self.fs.write_entry(
+            short_slot,
+            &RawEntry::new_short(&short, attr, first_cluster),
+        );
+        Some(self.wrap(short_slot))
+    }
+
+    // 文件的编号：根目录为1，其他文件为短目录项在磁盘上的序号
+    pub fn ino(&self) -> usize {
+        self.entry.map_or(1, |(sector, offset)| {
+            sector * ENTRIES_PER_SECTOR + offset / DIR_ENTRY_SZ
+        })
+    }
+
+    pub fn is_dir(&self) -> bool {
+        match self.entry {
+            None => true,
+            Some(_) => self.short_entry().map_or(false, |entry| entry.is_dir()),
+        }
+    }
+
+    // 文件的长度。目录的目录项中没有记录长度，为0。
+    pub fn size(&self) -> usize {
+        self.short_entry().map_or(0, |entry| entry.size() as usize)
+    }
+
+    // 在目录中按名字查找文件。与其他系统一致，不区分ASCII字母的大小写。
+    pub fn find(&self, name: &str) -> Option<Arc<Fat32Inode>> {
+        let _guard = self.fs.lock.read();
+        self.read_dir()
+            .into_iter()
+            .find(|item| item.name.eq_ignore_ascii_case(name))
+            .map(|item| self.wrap(item.short_slot()))
+    }
+
+    pub fn create(&self, name: &str) -> Option<Arc<Fat32Inode>> {
+        self.create_entry(name, false)
+    }
+
+    pub fn mkdir(&self, name: &str) -> Option<Arc<Fat32Inode>> {
+        self.create_entry(name, true)
+    }
+
+    // 删除目录中的一项并释放它的簇。不能删除非空的目录。
+    pub fn unlink(&self, name: &str) -> bool {
+        let _guard = self.fs.lock.write();
+        let Some(item) = self
+            .read_dir()
+            .into_iter()
+            .find(|item| item.name.eq_ignore_ascii_case(name))
+        else {
+            return false;
+        };
+        if item.entry.is_dir() && !self.wrap(item.short_slot()).read_dir().is_empty() {
+            return false;
+        }
+        let chain = self.fs.cluster_chain(item.entry.first_cluster());
+        self.fs.free_clusters(&chain);
+        for slot in item.slots {
+            let mut entry = self.fs.read_entry(slot);
+            entry.0[0] = ENTRY_DELETED;
+            self.fs.write_entry(slot, &entry);
+        }
+        true
+    }
+
+    // 目录中所有项的名字
+    pub fn ls(&self) -> Vec<String> {
+        let _guard = self.fs.lock.read();
+        self.read_dir().into_iter().map(|item| item.name).collect()
+    }
+
+    // 目录中位置不小于pos的第一项，返回它的位置和名字。位置是短目录项在目录中的序号。
+    pub fn next_dirent(&self, pos: usize) -> Option<(usize, String)> {
+        let _guard = self.fs.lock.read();
+        self.read_dir()
+            .into_iter()
+            .find(|item| item.pos >= pos)
+            .map(|item| (item.pos, item.name))
+    }
+
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let _guard = self.fs.lock.read();
+        let size = self.size();
+        if !self.is_file() || offset >= size {
+            return 0;
+        }
+        let len = min(buf.len(), size - offset);
+        let chain = self.fs.cluster_chain(self.first_cluster());
+        let mut read_size = 0;
+        for (sector, sector_offset, pos, len) in self.fs.pieces(&chain, offset, len) {
+            read_block_cached(
+                &self.fs.block_device,
+                sector,
+                sector_offset,
+                &mut buf[pos..pos + len],
+            );
+            read_size += len;
+        }
+        read_size
+    }
+
+    // 将buf写入offset处，文件长度不足时分配新的簇。空间不足时只写入一部分，返回写入的字节数。
+    // 文件长度不能超过4GiB-1字节。
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        let _guard = self.fs.lock.write();
+        if !self.is_file() {
+            return 0;
+        }
+        let size = self.size();
+        let end = min(offset + buf.len(), u32::MAX as usize);
+        if end <= offset {
+            return 0;
+        }
+        let mut chain = self.fs.cluster_chain(self.first_cluster());
+        let capacity = chain.len() * self.fs.cluster_size();
+        self.fs.grow_chain(&mut chain, end);
+        // 原来的末尾和offset之间的空洞读到的是0。新分配的簇已经清零，只需要清理原有的簇。
+        self.fs.zero_range(&chain, size, min(offset, capacity));
+        let mut write_size = 0;
+        for (sector, sector_offset, pos, len) in self.fs.pieces(&chain, offset, end - offset) {
+            write_block_cached(
+                &self.fs.block_device,
+                sector,
+                sector_offset,
+                &buf[pos..pos + len],
+            );
+            write_size += len;
+        }
+        let new_size = if write_size > 0 {
+            max(size, offset + write_size)
+        } else {
+            size
+        };
+        self.update_short_entry(|entry| {
+            entry.set_first_cluster(chain.first().copied().unwrap_or(0));
+            entry.set_size(new_size as u32);
+        });
+        write_size
+    }
+
+    // 将文件的长度改为size：超出的部分被丢弃并释放其簇，不足的部分用0填充。空间不足时返回false。
+    pub fn truncate(&self, size: usize) -> bool {
+        let _guard = self.fs.lock.write();
+        if !self.is_file() || size > u32::MAX as usize {
+            return false;
+        }
+        let old_size = self.size();
+        let mut chain = self.fs.cluster_chain(self.first_cluster());
+        let cluster_size = self.fs.cluster_size();
+        if size > old_size {
+            let old_len = chain.len();
+            self.fs.grow_chain(&mut chain, size);
+            if chain.len() * cluster_size < size {
+                // 空间不足，恢复原来的簇链
+                self.fs.free_clusters(&chain[old_len..]);
+                if old_len > 0 {
+                    self.fs.set_fat_entry(chain[old_len - 1], FAT_EOC);
+                }
+                return false;
+            }
+            self.fs
+                .zero_range(&chain, old_size, min(size, old_len * cluster_size));
+        } else {
+            let keep = (size + cluster_size - 1) / cluster_size;
+            if keep < chain.len() {
+                self.fs.free_clusters(&chain[keep..]);
+                if keep > 0 {
+                    self.fs.set_fat_entry(chain[keep - 1], FAT_EOC);
+                }
+                chain.truncate(keep);
+            }
+        }
+        self.update_short_entry(|entry| {
+            entry.set_first_cluster(chain.first().copied().unwrap_or(0));
+            entry.set_size(size as u32);
+        });
+        true
+    }
+}
@@ -0,0 +1,864 @@
+//! 离线的文件系统检查（fsck）和转储。
+//!
+//! 检查从根目录出发能到达的所有inode及其块树，与inode位图、数据位图相互比对，
+//! 发现重复分配或泄漏的块和inode、超出数据区域的块指针，以及损坏的目录项。
+//! 修复时以目录树为准：损坏的目录项被删除，重复引用的块被复制一份，无效的块指针指向新分配的空块，
+//! 最后按照目录树实际使用的inode和块重写两个位图。
+
+use crate::{
+    bitmap::Bitmap,
+    block_cache::{block_cache_sync_all, get_block_cache},
+    block_dev::BlockDevice,
+    journal::{Journal, JOURNAL_BLOCKS},
+    layout::{
+        BlockPointer, DirEntry, DiskInode, DiskInodeType, SuperBlock, MAX_FILE_SIZE,
+        SYMLINK_TARGET_LIMIT,
+    },
+    BLOCK_SZ,
+};
+use alloc::{collections::BTreeSet, string::String, sync::Arc, vec, vec::Vec};
+use core::fmt::{self, Display, Formatter, Write};
+
+type DataBlock = [u8; BLOCK_SZ];
+type IndirectBlock = [u32; BLOCK_SZ / 4];
+type InodeBytes = [u8; INODE_SIZE];
+
+const INODE_SIZE: usize = core::mem::size_of::<DiskInode>();
+const INODES_PER_BLOCK: u32 = (BLOCK_SZ / INODE_SIZE) as u32;
+// 一个位图块能表示的比特数
+const BITMAP_BLOCK_BITS: u32 = BLOCK_SZ as u32 * 8;
+
+// 目录项损坏的原因
+#[derive(Debug, PartialEq)]
+pub enum DirEntryError {
+    // 文件名不是有效的UTF-8，或者含有'/'、'\0'
+    BadName,
+    // 同一目录下已有同名的目录项
+    DuplicateName,
+    // inode编号超出了inode区域
+    InodeOutOfRange(u32),
+    // inode已被其他目录项引用（easy-fs不支持硬链接）
+    MultipleLinks(u32),
+    // 引用的inode本身已损坏
+    BadInode(u32),
+}
+
+// fsck发现的问题
+#[derive(Debug, PartialEq)]
+pub enum FsckProblem {
+    // 超级块无效，无法继续检查
+    BadSuperBlock(&'static str),
+    // 日志中有已提交、但没有写回的事务，只检查不修复时不会重做它
+    JournalPending,
+    // 根目录的inode不是目录或者已损坏
+    BadRoot,
+    // inode的类型字段无效
+    BadInodeType {
+        inode: u32,
+    },
+    // 文件大小超过了块树能表示的范围
+    BadInodeSize {
+        inode: u32,
+        size: u32,
+    },
+    // 块指针指向数据区域之外
+    BadBlockPointer {
+        inode: u32,
+        block: u32,
+    },
+    // 块已经被owner引用
+    DuplicateBlock {
+        inode: u32,
+        block: u32,
+        owner: u32,
+    },
+    // 目录的大小不是块大小的整数倍
+    BadDirSize {
+        inode: u32,
+        size: u32,
+    },
+    // 目录dir中的记录链在偏移offset处损坏，该块中的目录项都被丢弃
+    BadDirBlock {
+        dir: u32,
+        offset: usize,
+    },
+    // 目录dir中偏移offset处的目录项损坏
+    BadDirEntry {
+        dir: u32,
+        offset: usize,
+        reason: DirEntryError,
+    },
+    // inode在位图中被标记为已分配，但从根目录不可达
+    InodeLeaked {
+        inode: u32,
+    },
+    // inode从根目录可达，但在位图中没有被标记
+    InodeUnmarked {
+        inode: u32,
+    },
+    // 数据块在位图中被标记为已分配，但没有被任何inode引用
+    BlockLeaked {
+        block: u32,
+    },
+    // 数据块被inode引用，但在位图中没有被标记
+    BlockUnmarked {
+        block: u32,
+    },
+    // 修复时没有空闲块可以分配
+    NoSpace,
+}
+
+impl Display for DirEntryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadName => write!(f, "bad name"),
+            Self::DuplicateName => write!(f, "duplicate name"),
+            Self::InodeOutOfRange(inode) => write!(f, "inode {} out of range", inode),
+            Self::MultipleLinks(inode) => write!(f, "inode {} already linked", inode),
+            Self::BadInode(inode) => write!(f, "inode {} is corrupted", inode),
+        }
+    }
+}
+
+impl Display for FsckProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadSuperBlock(reason) => write!(f, "bad super block: {}", reason),
+            Self::JournalPending => write!(f, "journal has a committed transaction"),
+            Self::BadRoot => write!(f, "root inode is not a valid directory"),
+            Self::BadInodeType { inode } => write!(f, "inode {}: bad type", inode),
+            Self::BadInodeSize { inode, size } => write!(f, "inode {}: bad size {}", inode, size),
+            Self::BadBlockPointer { inode, block } => {
+                write!(f, "inode {}: block {} outside data area", inode, block)
+            }
+            Self::DuplicateBlock {
+                inode,
+                block,
+                owner,
+            } => write!(
+                f,
+                "inode {}: block {} already used by inode {}",
+                inode, block, owner
+            ),
+            Self::BadDirSize { inode, size } => {
+                write!(f, "directory {}: bad size {}", inode, size)
+            }
+            Self::BadDirBlock { dir, offset } => {
+                write!(f, "directory {}: broken record at {}", dir, offset)
+            }
+            Self::BadDirEntry {
+                dir,
+                offset,
+                reason,
+            } => {
+                write!(f, "directory {}: entry at {}: {}", dir, offset, reason)
+            }
+            Self::InodeLeaked { inode } => write!(f, "inode {}: allocated but unreachable", inode),
+            Self::InodeUnmarked { inode } => write!(f, "inode {}: in use but not allocated", inode),
+            Self::BlockLeaked { block } => write!(f, "block {}: allocated but unused", block),
+            Self::BlockUnmarked { block } => write!(f, "block {}: in use but not allocated", block),
+            Self::NoSpace => write!(f, "no free block left for repair"),
+        }
+    }
+}
+
+// 检查的结果
+pub struct FsckReport {
+    pub problems: Vec<FsckProblem>,
+    // 是否重做了日志中的事务
+    pub journal_replayed: bool,
+    // 从根目录可达的文件和目录数
+    pub files: usize,
+    pub directories: usize,
+    // 被引用的数据块数（包括索引块）
+    pub data_blocks: usize,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+// 由超级块算出的磁盘布局
+struct Geometry {
+    super_block: SuperBlockInfo,
+    inode_bitmap: Bitmap,
+    inode_count: u32,
+    inode_area_start: u32,
+    data_bitmap: Bitmap,
+    data_bitmap_start: u32,
+    data_area_start: u32,
+}
+
+// 超级块中各字段的副本
+struct SuperBlockInfo {
+    total_blocks: u32,
+    journal_blocks: u32,
+    inode_bitmap_blocks: u32,
+    inode_area_blocks: u32,
+    data_bitmap_blocks: u32,
+    data_area_blocks: u32,
+}
+
+impl Geometry {
+    // 读取并检查超级块
+    fn read(block_device: &Arc<dyn BlockDevice>) -> Result<Self, &'static str> {
+        let info = get_block_cache(0, Arc::clone(block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| {
+                super_block.is_valid().then_some(SuperBlockInfo {
+                    total_blocks: super_block.total_blocks,
+                    journal_blocks: super_block.journal_blocks,
+                    inode_bitmap_blocks: super_block.inode_bitmap_blocks,
+                    inode_area_blocks: super_block.inode_area_blocks,
+                    data_bitmap_blocks: super_block.data_bitmap_blocks,
+                    data_area_blocks: super_block.data_area_blocks,
+                })
+            })
+            .ok_or("bad magic")?;
+        if info.journal_blocks != JOURNAL_BLOCKS {
+            return Err("bad journal size");
+        }
+        let regions = [
+            1,
+            info.journal_blocks,
+            info.inode_bitmap_blocks,
+            info.inode_area_blocks,
+            info.data_bitmap_blocks,
+            info.data_area_blocks,
+        ];
+        if regions.iter().try_fold(0u32, |sum, n| sum.checked_add(*n)) != Some(info.total_blocks) {
+            return Err("region sizes do not add up to total blocks");
+        }
+        if info.inode_bitmap_blocks == 0 {
+            return Err("no inode bitmap");
+        }
+        let inode_count = info.inode_bitmap_blocks * BITMAP_BLOCK_BITS;
+        if (info.inode_area_blocks as u64) * (INODES_PER_BLOCK as u64) < inode_count as u64 {
+            return Err("inode area smaller than inode bitmap");
+        }
+        if (info.data_bitmap_blocks as u64) * (BITMAP_BLOCK_BITS as u64)
+            < info.data_area_blocks as u64
+        {
+            return Err("data bitmap smaller than data area");
+        }
+        let inode_bitmap_start = 1 + info.journal_blocks;
+        let inode_area_start = inode_bitmap_start + info.inode_bitmap_blocks;
+        let data_bitmap_start = inode_area_start + info.inode_area_blocks;
+        let data_area_start = data_bitmap_start + info.data_bitmap_blocks;
+        Ok(Self {
+            inode_bitmap: Bitmap::new(
+                inode_bitmap_start as usize,
+                info.inode_bitmap_blocks as usize,
+            ),
+            inode_count,
+            inode_area_start,
+            data_bitmap: Bitmap::new(data_bitmap_start as usize, info.data_bitmap_blocks as usize),
+            data_bitmap_start,
+            data_area_start,
+            super_block: info,
+        })
+    }
+
+    // inode所在的块号和块内偏移
+    fn inode_pos(&self, inode: u32) -> (usize, usize) {
+        (
+            (self.inode_area_start + inode / INODES_PER_BLOCK) as usize,
+            (inode % INODES_PER_BLOCK) as usize * INODE_SIZE,
+        )
+    }
+
+    // 块在数据区域中的编号。块不在数据区域中时返回None。
+    fn data_index(&self, block: u32) -> Option<usize> {
+        let index = block.checked_sub(self.data_area_start)?;
+        (index < self.super_block.data_area_blocks).then_some(index as usize)
+    }
+}
+
+// 检查inode的类型字段。类型无效的inode不能当作DiskInode读取。
+fn inode_type_is_valid(
+    block_device: &Arc<dyn BlockDevice>,
+    geometry: &Geometry,
+    inode: u32,
+) -> bool {
+    let (block_id, offset) = geometry.inode_pos(inode);
+    get_block_cache(block_id, Arc::clone(block_device))
+        .lock()
+        .read(offset, |bytes: &InodeBytes| {
+            DiskInode::has_valid_type(bytes)
+        })
+}
+
+// 读取类型有效的inode
+fn read_inode<V>(
+    block_device: &Arc<dyn BlockDevice>,
+    geometry: &Geometry,
+    inode: u32,
+    f: impl FnOnce(&DiskInode) -> V,
+) -> V {
+    let (block_id, offset) = geometry.inode_pos(inode);
+    get_block_cache(block_id, Arc::clone(block_device))
+        .lock()
+        .read(offset, f)
+}
+
+fn modify_inode<V>(
+    block_device: &Arc<dyn BlockDevice>,
+    geometry: &Geometry,
+    inode: u32,
+    f: impl FnOnce(&mut DiskInode) -> V,
+) -> V {
+    let (block_id, offset) = geometry.inode_pos(inode);
+    get_block_cache(block_id, Arc::clone(block_device))
+        .lock()
+        .modify(offset, f)
+}
+
+// 读出目录中的所有目录项（不包括空闲的记录）及其在目录中的字节偏移，以及记录链损坏的位置。
+// data为目录的各个数据块，None表示块无效，其中的目录项被跳过。
+fn read_dir_entries(
+    block_device: &Arc<dyn BlockDevice>,
+    data: &[Option<u32>],
+) -> (Vec<(usize, DirEntry)>, Vec<usize>) {
+    let mut entries = Vec::new();
+    let mut broken = Vec::new();
+    for (i, block) in data.iter().enumerate() {
+        let Some(block) = block else {
+            continue;
+        };
+        let parsed = get_block_cache(*block as usize, Arc::clone(block_device))
+            .lock()
+            .read(0, DirEntry::parse_block);
+        match parsed {
+            Ok(records) => entries.extend(
+                records
+                    .into_iter()
+                    .filter(|dirent| !dirent.is_free())
+                    .map(|dirent| (i * BLOCK_SZ + dirent.offset, dirent)),
+            ),
+            Err(offset) => broken.push(i * BLOCK_SZ + offset),
+        }
+    }
+    (entries, broken)
+}
+
+// 检查通过的inode
+struct InodeInfo {
+    is_dir: bool,
+    size: u32,
+    // 各个数据块（不包括索引块），None表示块指针无效
+    data: Vec<Option<u32>>,
+}
+
+struct Checker<'a> {
+    block_device: &'a Arc<dyn BlockDevice>,
+    geometry: Geometry,
+    repair: bool,
+    // 数据区域中每个块被哪个inode引用
+    owners: Vec<Option<u32>>,
+    // 从根目录可达的inode
+    reachable: Vec<bool>,
+    problems: Vec<FsckProblem>,
+    files: usize,
+    directories: usize,
+}
+
+impl Checker<'_> {
+    // 分配一个没有被引用的块，优先选择位图中空闲的块
+    fn alloc_block(&mut self) -> Option<u32> {
+        let device = self.block_device;
+        let bitmap = &self.geometry.data_bitmap;
+        let free = |i: usize| !bitmap.is_set(device, i);
+        let index = (0..self.owners.len())
+            .find(|&i| self.owners[i].is_none() && free(i))
+            .or_else(|| (0..self.owners.len()).find(|&i| self.owners[i].is_none()));
+        let Some(index) = index else {
+            if !self.problems.contains(&FsckProblem::NoSpace) {
+                self.problems.push(FsckProblem::NoSpace);
+            }
+            return None;
+        };
+        self.geometry.data_bitmap.set(device, index, true);
+        Some(self.geometry.data_area_start + index as u32)
+    }
+
+    // 为无效或重复引用的块指针分配一个新块。old为重复引用的块时，复制它的内容。
+    fn replace_block(&mut self, inode: u32, old: Option<u32>) -> Option<u32> {
+        let new = self.alloc_block()?;
+        let mut data = [0u8; BLOCK_SZ];
+        if let Some(old) = old {
+            get_block_cache(old as usize, Arc::clone(self.block_device))
+                .lock()
+                .read(0, |block: &DataBlock| data.copy_from_slice(block));
+        }
+        get_block_cache(new as usize, Arc::clone(self.block_device))
+            .lock()
+            .modify(0, |block: &mut DataBlock| block.copy_from_slice(&data));
+        self.owners[self.geometry.data_index(new).unwrap()] = Some(inode);
+        Some(new)
+    }
+
+    // 记录inode对一个块的引用。返回该指针实际应该使用的块号，修复时可能是新分配的块。
+    fn claim(&mut self, inode: u32, block: u32) -> Option<u32> {
+        let Some(index) = self.geometry.data_index(block) else {
+            self.problems
+                .push(FsckProblem::BadBlockPointer { inode, block });
+            return if self.repair {
+                self.replace_block(inode, None)
+            } else {
+                None
+            };
+        };
+        match self.owners[index] {
+            None => {
+                self.owners[index] = Some(inode);
+                Some(block)
+            }
+            Some(owner) => {
+                self.problems.push(FsckProblem::DuplicateBlock {
+                    inode,
+                    block,
+                    owner,
+                });
+                if self.repair {
+                    self.replace_block(inode, Some(block))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    // 遍历inode的块树，记录它引用的块，修复时改写无效的块指针。
+    // 常规文件中块号为0的指针是空洞；目录和符号链接不能有空洞，块号0按无效的指针处理。
+    fn walk_inode(&mut self, inode: u32) -> Vec<Option<u32>> {
+        let device = self.block_device;
+        let mut data = Vec::new();
+        let mut updates: Vec<(BlockPointer, u32)> = Vec::new();
+        // 复制一份，遍历时不持有inode所在块的锁
+        let disk_inode = read_inode(device, &self.geometry, inode, DiskInode::clone);
+        let sparse = disk_inode.is_file();
+        disk_inode.walk_blocks(device, |pointer, block, is_index| {
+            if block == 0 && sparse {
+                if !is_index {
+                    data.push(None);
+                }
+                return None;
+            }
+            let result = self.claim(inode, block);
+            if let Some(new) = result.filter(|new| *new != block) {
+                updates.push((pointer, new));
+            }
+            if !is_index {
+                data.push(result);
+            }
+            result
+        });
+        for (pointer, block) in updates {
+            match pointer {
+                BlockPointer::Direct(i) => {
+                    modify_inode(device, &self.geometry, inode, |d| d.direct[i] = block)
+                }
+                BlockPointer::Indirect(level) => modify_inode(device, &self.geometry, inode, |d| {
+                    d.set_indirect(level, block)
+                }),
+                BlockPointer::Index {
+                    block: index_block,
+                    index,
+                } => get_block_cache(index_block as usize, Arc::clone(device))
+                    .lock()
+                    .modify(0, |entries: &mut IndirectBlock| entries[index] = block),
+            }
+        }
+        data
+    }
+
+    // 检查一个inode。inode损坏、无法使用时返回None。
+    fn check_inode(&mut self, inode: u32) -> Option<InodeInfo> {
+        if !inode_type_is_valid(self.block_device, &self.geometry, inode) {
+            self.problems.push(FsckProblem::BadInodeType { inode });
+            return None;
+        }
+        let (is_dir, is_symlink, size) =
+            read_inode(self.block_device, &self.geometry, inode, |d| {
+                (d.is_dir(), d.is_symlink(), d.size)
+            });
+        // 符号链接的目标路径不能为空，也不能超过长度限制
+        let bad_symlink = is_symlink && (size == 0 || size as usize > SYMLINK_TARGET_LIMIT);
+        if size as usize > MAX_FILE_SIZE || bad_symlink {
+            self.problems
+                .push(FsckProblem::BadInodeSize { inode, size });
+            return None;
+        }
+        let data = self.walk_inode(inode);
+        Some(InodeInfo { is_dir, size, data })
+    }
+
+    // 用保留下来的目录项重写目录，释放不再需要的块。
+    // 目录项依次紧凑地放入各个块，每个块的最后一条记录延伸到块的末尾。
+    fn rewrite_dir(&mut self, dir: u32, data: &[Option<u32>], entries: &[DirEntry]) {
+        let mut pending = entries;
+        let mut blocks = 0;
+        for block in data.iter() {
+            let Some(block) = block else {
+                break;
+            };
+            if pending.is_empty() {
+                break;
+            }
+            let mut count = 0;
+            let mut used = 0;
+            while let Some(dirent) = pending.get(count) {
+                let rec_len = DirEntry::record_len(dirent.name_bytes().len());
+                if used + rec_len > BLOCK_SZ {
+                    break;
+                }
+                used += rec_len;
+                count += 1;
+            }
+            get_block_cache(*block as usize, Arc::clone(self.block_device))
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| {
+                    DirEntry::init_block(data_block);
+                    let mut offset = 0;
+                    for (j, dirent) in pending[..count].iter().enumerate() {
+                        let rec_len = match j + 1 == count {
+                            true => BLOCK_SZ - offset,
+                            false => DirEntry::record_len(dirent.name_bytes().len()),
+                        };
+                        let name = dirent.name_bytes();
+                        DirEntry::write(data_block, offset, rec_len, dirent.inode_number(), name);
+                        offset += rec_len;
+                    }
+                });
+            pending = &pending[count..];
+            blocks += 1;
+        }
+        // 目录变小后，大小之外的块不再属于它
+        for owner in self.owners.iter_mut() {
+            if *owner == Some(dir) {
+                *owner = None;
+            }
+        }
+        modify_inode(self.block_device, &self.geometry, dir, |d| {
+            d.size = (blocks * BLOCK_SZ) as u32
+        });
+        self.walk_inode(dir);
+    }
+
+    // 从根目录出发，检查目录树
+    fn check_tree(&mut self) {
+        let root = match self.check_inode(0) {
+            Some(info) if info.is_dir => info,
+            _ => {
+                self.problems.push(FsckProblem::BadRoot);
+                if !self.repair {
+                    return;
+                }
+                // 将根目录重置为空目录，其余的inode和块都将被视为泄漏而释放
+                for owner in self.owners.iter_mut() {
+                    if *owner == Some(0) {
+                        *owner = None;
+                    }
+                }
+                let (block_id, offset) = self.geometry.inode_pos(0);
+                get_block_cache(block_id, Arc::clone(self.block_device))
+                    .lock()
+                    .modify(offset, |bytes: &mut InodeBytes| bytes.fill(0));
+                modify_inode(self.block_device, &self.geometry, 0, |d| {
+                    d.initialize(DiskInodeType::Directory)
+                });
+                InodeInfo {
+                    is_dir: true,
+                    size: 0,
+                    data: Vec::new(),
+                }
+            }
+        };
+        self.reachable[0] = true;
+        self.directories += 1;
+        let mut dirs = vec![(0u32, root)];
+        while let Some((dir, info)) = dirs.pop() {
+            let mut changed = info.size as usize % BLOCK_SZ != 0;
+            if changed {
+                self.problems.push(FsckProblem::BadDirSize {
+                    inode: dir,
+                    size: info.size,
+                });
+            }
+            let (entries, broken) = read_dir_entries(self.block_device, &info.data);
+            for offset in broken {
+                self.problems.push(FsckProblem::BadDirBlock { dir, offset });
+                changed = true;
+            }
+            let mut names = BTreeSet::new();
+            let mut kept = Vec::new();
+            for (offset, dirent) in entries {
+                let error = match dirent.try_name() {
+                    None => Some(DirEntryError::BadName),
+                    Some(name) if name.contains(['/', '\0']) => Some(DirEntryError::BadName),
+                    Some(name) if names.contains(name) => Some(DirEntryError::DuplicateName),
+                    Some(name) => {
+                        let inode = dirent.inode_number();
+                        if inode >= self.geometry.inode_count {
+                            Some(DirEntryError::InodeOutOfRange(inode))
+                        } else if self.reachable[inode as usize] {
+                            Some(DirEntryError::MultipleLinks(inode))
+                        } else if let Some(child) = self.check_inode(inode) {
+                            self.reachable[inode as usize] = true;
+                            names.insert(String::from(name));
+                            if child.is_dir {
+                                self.directories += 1;
+                                dirs.push((inode, child));
+                            } else {
+                                self.files += 1;
+                            }
+                            None
+                        } else {
+                            Some(DirEntryError::BadInode(inode))
+                        }
+                    }
+                };
+                match error {
+                    Some(reason) => {
+                        self.problems.push(FsckProblem::BadDirEntry {
+                            dir,
+                            offset,
+                            reason,
+                        });
+                        changed = true;
+                    }
+                    None => kept.push(dirent),
+                }
+            }
+            if changed && self.repair {
+                self.rewrite_dir(dir, &info.data, &kept);
+            }
+        }
+    }
+
+    // 以目录树为准，检查inode位图和数据位图
+    fn check_bitmaps(&mut self) {
+        let device = self.block_device;
+        for inode in 0..self.geometry.inode_count {
+            let marked = self.geometry.inode_bitmap.is_set(device, inode as usize);
+            let used = self.reachable[inode as usize];
+            match (marked, used) {
+                (true, false) => self.problems.push(FsckProblem::InodeLeaked { inode }),
+                (false, true) => self.problems.push(FsckProblem::InodeUnmarked { inode }),
+                _ => continue,
+            }
+            if self.repair {
+                self.geometry.inode_bitmap.set(device, inode as usize, used);
+            }
+        }
+        // 数据位图中超出数据区域的比特位应当为0
+        for index in 0..self.geometry.data_bitmap.maximum() {
+            let marked = self.geometry.data_bitmap.is_set(device, index);
+            let used = self.owners.get(index).is_some_and(|owner| owner.is_some());
+            let block = self.geometry.data_area_start + index as u32;
+            match (marked, used) {
+                (true, false) => self.problems.push(FsckProblem::BlockLeaked { block }),
+                (false, true) => self.problems.push(FsckProblem::BlockUnmarked { block }),
+                _ => continue,
+            }
+            if self.repair {
+                self.geometry.data_bitmap.set(device, index, used);
+            }
+        }
+    }
+}
+
+// 检查块设备上的文件系统。repair为true时修复发现的问题，并重做日志中已提交的事务。
+pub fn fsck(block_device: &Arc<dyn BlockDevice>, repair: bool) -> FsckReport {
+    let mut report = FsckReport {
+        problems: Vec::new(),
+        journal_replayed: false,
+        files: 0,
+        directories: 0,
+        data_blocks: 0,
+    };
+    let geometry = match Geometry::read(block_device) {
+        Ok(geometry) => geometry,
+        Err(reason) => {
+            report.problems.push(FsckProblem::BadSuperBlock(reason));
+            return report;
+        }
+    };
+    let mut journal = Journal::new(1);
+    if journal.pending(block_device) {
+        if repair {
+            report.journal_replayed = journal.replay(block_device);
+        } else {
+            report.problems.push(FsckProblem::JournalPending);
+        }
+    }
+    let mut checker = Checker {
+        block_device,
+        owners: vec![None; geometry.super_block.data_area_blocks as usize],
+        reachable: vec![false; geometry.inode_count as usize],
+        geometry,
+        repair,
+        problems: report.problems,
+        files: 0,
+        directories: 0,
+    };
+    checker.check_tree();
+    checker.check_bitmaps();
+    if repair {
+        block_cache_sync_all();
+    }
+    report.problems = checker.problems;
+    report.files = checker.files;
+    report.directories = checker.directories;
+    report.data_blocks = checker.owners.iter().filter(|o| o.is_some()).count();
+    report
+}
+
+// 将连续的块号合并为区间，如"70-73, 80"
+fn block_ranges(blocks: &[u32]) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < blocks.len() {
+        let mut j = i;
+        while j + 1 < blocks.len() && blocks[j + 1] == blocks[j].wrapping_add(1) {
+            j += 1;
+        }
+        if !out.is_empty() {
+            out.push_str(", ");
+        }
+        if i == j {
+            let _ = write!(out, "{}", blocks[i]);
+        } else {
+            let _ = write!(out, "{}-{}", blocks[i], blocks[j]);
+        }
+        i = j + 1;
+    }
+    out
+}
+
+// 转储文件系统：磁盘布局、inode表和目录树。只读取，不修改块设备。
+pub fn dump(block_device: &Arc<dyn BlockDevice>) -> String {
+    let mut out = String::new();
+    let geometry = match Geometry::read(block_device) {
+        Ok(geometry) => geometry,
+        Err(reason) => {
+            let _ = writeln!(out, "bad super block: {}", reason);
+            return out;
+        }
+    };
+    let sb = &geometry.super_block;
+    let _ = writeln!(
+        out,
+        "layout ({} blocks of {} bytes):",
+        sb.total_blocks, BLOCK_SZ
+    );
+    let journal = Journal::new(1);
+    let pending = if journal.pending(block_device) {
+        "committed transaction pending"
+    } else {
+        "clean"
+    };
+    let regions = [
+        (0, 1, String::from("super block")),
+        (1, sb.journal_blocks, alloc::format!("journal, {}", pending)),
+        (
+            1 + sb.journal_blocks,
+            sb.inode_bitmap_blocks,
+            String::from("inode bitmap"),
+        ),
+        (
+            geometry.inode_area_start,
+            sb.inode_area_blocks,
+            alloc::format!("inode area, {} inodes", geometry.inode_count),
+        ),
+        (
+            geometry.data_bitmap_start,
+            sb.data_bitmap_blocks,
+            String::from("data bitmap"),
+        ),
+        (
+            geometry.data_area_start,
+            sb.data_area_blocks,
+            String::from("data area"),
+        ),
+    ];
+    for (start, blocks, name) in regions.iter() {
+        let _ = writeln!(out, "  [{:>6}, {:>6})  {}", start, start + blocks, name);
+    }
+    // inode表：位图中标记为已分配的inode
+    let _ = writeln!(out, "inodes:");
+    let mut used_blocks = 0;
+    for inode in 0..geometry.inode_count {
+        if !geometry.inode_bitmap.is_set(block_device, inode as usize) {
+            continue;
+        }
+        if !inode_type_is_valid(block_device, &geometry, inode) {
+            let _ = writeln!(out, "  #{:<5} bad type", inode);
+            continue;
+        }
+        let (is_dir, size) = read_inode(block_device, &geometry, inode, |d| (d.is_dir(), d.size));
+        let mut blocks = Vec::new();
+        read_inode(block_device, &geometry, inode, |d| {
+            d.walk_blocks(block_device, |_, block, _| {
+                // 跳过空洞
+                if block == 0 {
+                    return None;
+                }
+                blocks.push(block);
+                geometry.data_index(block).map(|_| block)
+            })
+        });
+        used_blocks += blocks.len();
+        let _ = writeln!(
+            out,
+            "  #{:<5} {} size={} blocks=[{}]",
+            inode,
+            if is_dir { "dir " } else { "file" },
+            size,
+            block_ranges(&blocks)
+        );
+    }
+    let free_blocks = (0..sb.data_area_blocks as usize)
+        .filter(|i| !geometry.data_bitmap.is_set(block_device, *i))
+        .count();
+    let _ = writeln!(
+        out,
+        "  {} blocks referenced, {} of {} data blocks free",
+        used_blocks, free_blocks, sb.data_area_blocks
+    );
+    // 目录树
+    let _ = writeln!(out, "tree:");
+    let mut visited = BTreeSet::new();
+    let mut stack = vec![(0u32, String::from("/"), 0usize)];
+    while let Some((inode, name, depth)) = stack.pop() {
+        let indent = depth * 2 + 2;
+        if inode >= geometry.inode_count || !inode_type_is_valid(block_device, &geometry, inode) {
+            let _ = writeln!(out, "{:indent$}{} -> #{} (bad inode)", "", name, inode);
+            continue;
+        }
+        let (is_dir, size) = read_inode(block_device, &geometry, inode, |d| (d.is_dir(), d.size));
+        let _ = writeln!(out, "{:indent$}{} (#{}, {} bytes)", "", name, inode, size);
+        if !is_dir || !visited.insert(inode) || size as usize > MAX_FILE_SIZE {
+            continue;
+        }
+        let mut data = Vec::new();
+        read_inode(block_device, &geometry, inode, |d| {
+            d.walk_blocks(block_device, |_, block, is_index| {
+                let block = geometry.data_index(block).map(|_| block);
+                if !is_index {
+                    data.push(block);
+                }
+                block
+            })
+        });
+        let (entries, _) = read_dir_entries(block_device, &data);
+        // 逆序入栈，使输出按目录项的顺序排列
+        for (_, dirent) in entries.iter().rev() {
+            let name = dirent.try_name().unwrap_or("<bad name>");
+            stack.push((dirent.inode_number(), String::from(name), depth + 1));
+        }
+    }
+    out
+}
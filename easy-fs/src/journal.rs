@@ -0,0 +1,223 @@
+//! 预写日志（write-ahead journal），保证文件系统在掉电等崩溃后仍然一致。
+//!
+//! 修改多个块的操作（如创建文件、扩大文件）被包装成事务。事务进行期间，被修改的块只保存在块缓存中，
+//! 不会被换出或写回磁盘。提交事务时：
+//! 1. 将事务修改的所有块，依次写入日志区域中日志头之后的块；
+//! 2. 写入日志头，记录这些块的块号和校验和。日志头写入完成，事务即提交；
+//! 3. 将这些块写回它们在磁盘上的位置（checkpoint）；
+//! 4. 清空日志头。
+//!
+//! 打开文件系统时，如果日志头中有已提交的事务，说明上次在第3步完成之前崩溃，重新执行第3、4步（replay）。
+//! 如果在第2步完成之前崩溃，事务没有提交，磁盘上的块都未被修改，相当于该操作没有发生。
+
+use crate::{
+    block_cache::{
+        get_block_cache, hold_block_device, release_block_device, transaction_dirty_caches,
+    },
+    block_dev::BlockDevice,
+    BLOCK_SZ,
+};
+use alloc::{sync::Arc, vec::Vec};
+
+// 日志头的魔数
+const JOURNAL_MAGIC: u32 = 0x4a52_4e4c;
+// 日志区域的块数，包括一个日志头
+pub const JOURNAL_BLOCKS: u32 = 64;
+// 一个事务最多能修改的块数
+pub const JOURNAL_MAX_TXN_BLOCKS: usize = JOURNAL_BLOCKS as usize - 1;
+// 日志头中，块号列表之前的字段数（magic、count、sequence、checksum）
+const HEADER_FIELDS: usize = 4;
+
+type DataBlock = [u8; BLOCK_SZ];
+
+// 日志头，存放在日志区域的第一个块中
+struct JournalHeader {
+    // 已提交的事务修改的块数。为0表示日志中没有待写回的事务。
+    count: u32,
+    // 事务的序号，每提交一个事务加1
+    sequence: u32,
+    // 块号和日志中的块数据的校验和，用于发现没有写完整的日志
+    checksum: u32,
+    // 事务修改的块的块号，依次对应日志头之后的各个块
+    blocks: Vec<u32>,
+}
+
+impl JournalHeader {
+    // 从磁盘上的块解析日志头。魔数不正确时，视为空的日志。
+    fn decode(data: &DataBlock) -> Self {
+        let field = |i: usize| u32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+        let count = field(1) as usize;
+        if field(0) != JOURNAL_MAGIC || count > JOURNAL_MAX_TXN_BLOCKS {
+            return Self {
+                count: 0,
+                sequence: 0,
+                checksum: 0,
+                blocks: Vec::new(),
+            };
+        }
+        Self {
+            count: count as u32,
+            sequence: field(2),
+            checksum: field(3),
+            blocks: (0..count).map(|i| field(HEADER_FIELDS + i)).collect(),
+        }
+    }
+
+    fn encode(&self) -> DataBlock {
+        let mut data = [0u8; BLOCK_SZ];
+        let fields = [JOURNAL_MAGIC, self.count, self.sequence, self.checksum];
+        for (i, value) in fields.iter().chain(self.blocks.iter()).enumerate() {
+            data[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        data
+    }
+}
+
+// FNV-1a校验和
+fn checksum(state: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(state, |hash, &b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+const CHECKSUM_INIT: u32 = 0x811c_9dc5;
+
+// 文件系统的日志，位于块设备上从start_block开始的JOURNAL_BLOCKS个块
+pub struct Journal {
+    start_block: usize,
+    // 下一个事务的序号
+    sequence: u32,
+}
+
+impl Journal {
+    pub fn new(start_block: usize) -> Self {
+        Self {
+            start_block,
+            sequence: 1,
+        }
+    }
+
+    fn read_header(&self, block_device: &Arc<dyn BlockDevice>) -> JournalHeader {
+        let mut data = [0u8; BLOCK_SZ];
+        block_device.read_block(self.start_block, &mut data);
+        JournalHeader::decode(&data)
+    }
+
+    fn write_header(&self, block_device: &Arc<dyn BlockDevice>, header: &JournalHeader) {
+        block_device.write_block(self.start_block, &header.encode());
+    }
+
+    // 清空日志头，表示日志中没有待写回的事务
+    fn clear(&self, block_device: &Arc<dyn BlockDevice>) {
+        self.write_header(
+            block_device,
+            &JournalHeader {
+                count: 0,
+                sequence: self.sequence,
+                checksum: 0,
+                blocks: Vec::new(),
+            },
+        );
+    }
+
+    // 开始一个事务。事务不能嵌套。
+    pub fn begin(&self, block_device: &Arc<dyn BlockDevice>) {
+        hold_block_device(block_device);
+    }
+
+    // 提交事务：先将事务修改的块写入日志，再写回它们在磁盘上的位置。
+    // 修改的块超过日志的容量时，事务无法原子地提交。绕过日志直接写回会在崩溃时破坏文件系统，
+    // 因此直接panic：每个操作都要把修改拆成不超过JOURNAL_MAX_TXN_BLOCKS块的事务。
+    pub fn commit(&mut self, block_device: &Arc<dyn BlockDevice>) {
+        let caches = transaction_dirty_caches(block_device);
+        if caches.is_empty() {
+            release_block_device(block_device);
+            return;
+        }
+        assert!(
+            caches.len() <= JOURNAL_MAX_TXN_BLOCKS,
+            "transaction modified {} blocks, the journal holds at most {}",
+            caches.len(),
+            JOURNAL_MAX_TXN_BLOCKS
+        );
+        // 将块写入日志
+        let mut header = JournalHeader {
+            count: caches.len() as u32,
+            sequence: self.sequence,
+            checksum: CHECKSUM_INIT,
+            blocks: Vec::with_capacity(caches.len()),
+        };
+        for (i, cache) in caches.iter().enumerate() {
+            // 复制出块的内容，写入日志时不持有缓存块的锁
+            let (block_id, data) = {
+                let cache = cache.lock();
+                (
+                    cache.block_id() as u32,
+                    cache.read(0, |data: &DataBlock| *data),
+                )
+            };
+            header.blocks.push(block_id);
+            header.checksum = checksum(header.checksum, &block_id.to_le_bytes());
+            header.checksum = checksum(header.checksum, &data);
+            block_device.write_block(self.start_block + 1 + i, &data);
+        }
+        // 写入日志头，提交事务
+        self.write_header(block_device, &header);
+        self.sequence = self.sequence.wrapping_add(1);
+        // 写回磁盘上的位置
+        for cache in caches.iter() {
+            cache.lock().sync();
+        }
+        self.clear(block_device);
+        release_block_device(block_device);
+    }
+
+    // 读出日志中已提交的事务：块号和块数据。日志为空或者没有写完整（校验和不正确）时返回None。
+    fn read_committed(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Option<(Vec<u32>, Vec<DataBlock>)> {
+        let header = self.read_header(block_device);
+        if header.count == 0 {
+            return None;
+        }
+        let mut blocks: Vec<DataBlock> = Vec::with_capacity(header.blocks.len());
+        let mut sum = CHECKSUM_INIT;
+        for (i, block_id) in header.blocks.iter().enumerate() {
+            let mut data = [0u8; BLOCK_SZ];
+            block_device.read_block(self.start_block + 1 + i, &mut data);
+            sum = checksum(sum, &block_id.to_le_bytes());
+            sum = checksum(sum, &data);
+            blocks.push(data);
+        }
+        (sum == header.checksum).then_some((header.blocks, blocks))
+    }
+
+    // 日志中是否有已提交、但可能没有写回完成的事务
+    pub fn pending(&self, block_device: &Arc<dyn BlockDevice>) -> bool {
+        self.read_committed(block_device).is_some()
+    }
+
+    // 重做日志中已提交、但可能没有写回完成的事务。返回是否重做了事务。
+    pub fn replay(&mut self, block_device: &Arc<dyn BlockDevice>) -> bool {
+        let header = self.read_header(block_device);
+        self.sequence = header.sequence.wrapping_add(1);
+        if header.count == 0 {
+            return false;
+        }
+        // 校验和不正确，说明日志没有写完整，事务未提交
+        let Some((block_ids, blocks)) = self.read_committed(block_device) else {
+            self.clear(block_device);
+            return false;
+        };
+        // 通过块缓存写回，使缓存中的内容与磁盘保持一致
+        for (block_id, data) in block_ids.iter().zip(blocks.iter()) {
+            let cache = get_block_cache(*block_id as usize, Arc::clone(block_device));
+            let mut cache = cache.lock();
+            cache.modify(0, |block: &mut DataBlock| block.copy_from_slice(data));
+            cache.sync();
+        }
+        self.clear(block_device);
+        true
+    }
+}
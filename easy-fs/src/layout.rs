@@ -10,24 +10,39 @@ use core::{
     fmt::{Debug, Formatter, Result},
 };
 
-// 检查文件系统是否有效的魔数
-const EFS_MAGIC: u32 = 0x3b800001;
+// 检查文件系统是否有效的魔数。磁盘布局变化时（加入日志区域、三级间接索引、变长目录项），魔数也随之改变。
+const EFS_MAGIC: u32 = 0x3b800004;
 // inode的文件名的最大长度
-const NAME_LENGTH_LIMIT: usize = 27;
+pub const NAME_LENGTH_LIMIT: usize = 255;
+// 符号链接的目标路径的最大长度
+pub const SYMLINK_TARGET_LIMIT: usize = 4095;
 
 // 能用直接索引方式找到的块的数量
-const INODE_DIRECT_COUNT: usize = 28;
-// 能用一级间接索引方式找到的块的数量
+const INODE_DIRECT_COUNT: usize = 27;
+// 一个索引块中的块编号数，即一级间接索引能找到的块的数量
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
 // 能用二级间接索引方式找到的块的数量
 const INODE_INDIRECT2_COUNT: usize = INODE_INDIRECT1_COUNT * INODE_INDIRECT1_COUNT;
+// 能用三级间接索引方式找到的块的数量
+const INODE_INDIRECT3_COUNT: usize = INODE_INDIRECT2_COUNT * INODE_INDIRECT1_COUNT;
 // 0..DIRECT_BOUND的块使用直接索引
 const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
 // DIRECT_BOUND..INDIRECT1_BOUND的块使用一级间接索引
 const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
-#[allow(unused)]
 // INDIRECT1_BOUND..INDIRECT2_BOUND的块使用二级间接索引
 const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
+// INDIRECT2_BOUND..INDIRECT3_BOUND的块使用三级间接索引
+const INDIRECT3_BOUND: usize = INDIRECT2_BOUND + INODE_INDIRECT3_COUNT;
+// 第1、2、3级间接索引负责的块的起始位置和数量
+const INDIRECT_START: [usize; 4] = [0, DIRECT_BOUND, INDIRECT1_BOUND, INDIRECT2_BOUND];
+const INDIRECT_COUNT: [usize; 4] = [
+    INODE_DIRECT_COUNT,
+    INODE_INDIRECT1_COUNT,
+    INODE_INDIRECT2_COUNT,
+    INODE_INDIRECT3_COUNT,
+];
+// 一个文件的最大字节数（约1GB）。文件大小用u32表示，不会溢出。
+pub const MAX_FILE_SIZE: usize = INDIRECT3_BOUND * BLOCK_SZ;
 
 #[repr(C)]
 // 文件系统的超级块
@@ -36,6 +51,8 @@ pub struct SuperBlock {
     magic: u32,
     // 总块数
     pub total_blocks: u32,
+    // 日志区域所占的块数。日志区域紧跟在超级块之后。
+    pub journal_blocks: u32,
     // 下面表示inode位图、inode区域、数据位图、数据区域所占的块数
     pub inode_bitmap_blocks: u32,
     pub inode_area_blocks: u32,
@@ -43,11 +60,14 @@ pub struct SuperBlock {
     pub data_area_blocks: u32,
 }
 
-// Inode的类型
-#[derive(PartialEq)]
+// Inode的类型。在磁盘上占一个字节。
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
 pub enum DiskInodeType {
     File,
     Directory,
+    // 符号链接，数据是目标路径
+    Symlink,
 }
 
 // 存放块索引的块对应的类型，每个元素是块编号（数据块或下一级的索引块）
@@ -56,6 +76,7 @@ type IndirectBlock = [u32; BLOCK_SZ / 4];
 type DataBlock = [u8; BLOCK_SZ];
 
 #[repr(C)]
+#[derive(Clone)]
 // 文件/目录的inode结构
 // 这里将DiskInode的结构设置为128字节，每个块恰好能存放4个inode
 pub struct DiskInode {
@@ -63,9 +84,9 @@ pub struct DiskInode {
     pub size: u32,
     // inode的类型
     type_: DiskInodeType,
-    // 有三个级别的索引，它们能同时使用。
+    // 有四个级别的索引，它们能同时使用。块编号为0表示该块没有分配，即文件中的空洞，读出来是0。
     // 直接索引：直接指向块
-    // 总共能容纳：INODE_DIRECT_COUNT * BLOCK_SZ ~= 14KB
+    // 总共能容纳：INODE_DIRECT_COUNT * BLOCK_SZ ~= 13.5KB
     pub direct: [u32; INODE_DIRECT_COUNT],
     // 一级间接索引，指向一个包含多个块编号的块，每个编号是u32
     // 总共能容纳：(BLOCK_SZ / 4) * BLOCK_SZ ~= 64KB
@@ -73,12 +94,27 @@ pub struct DiskInode {
     // 二级简介索引：指向一个包含多个一级间接索引块编号的块
     // 总共能容纳：(BLOCK_SZ / 4) * (BLOCK_SZ / 4) * BLOCK_SZ ~= 8MB
     pub indirect2: u32,
+    // 三级间接索引：指向一个包含多个二级间接索引块编号的块
+    // 总共能容纳：(BLOCK_SZ / 4)^3 * BLOCK_SZ ~= 1GB
+    pub indirect3: u32,
+}
+
+// 块树中一个块指针所在的位置
+#[derive(Clone, Copy, Debug)]
+pub enum BlockPointer {
+    // inode中的第i个直接索引
+    Direct(usize),
+    // inode中的第level级间接索引（1~3）
+    Indirect(usize),
+    // 索引块block中的第index项
+    Index { block: u32, index: usize },
 }
 
 impl Debug for SuperBlock {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         f.debug_struct("SuperBlock")
             .field("total_blocks", &self.total_blocks)
+            .field("journal_blocks", &self.journal_blocks)
             .field("inode_bitmap_blocks", &self.inode_bitmap_blocks)
             .field("inode_area_blocks", &self.inode_area_blocks)
             .field("data_bitmap_blocks", &self.data_bitmap_blocks)
@@ -92,6 +128,7 @@ impl SuperBlock {
     pub fn initialize(
         &mut self,
         total_blocks: u32,
+        journal_blocks: u32,
         inode_bitmap_blocks: u32,
         inode_area_blocks: u32,
         data_bitmap_blocks: u32,
@@ -100,6 +137,7 @@ impl SuperBlock {
         *self = Self {
             magic: EFS_MAGIC,
             total_blocks,
+            journal_blocks,
             inode_bitmap_blocks,
             inode_area_blocks,
             data_bitmap_blocks,
@@ -114,12 +152,13 @@ impl SuperBlock {
 }
 
 impl DiskInode {
-    // 初始化inode。一开始只使用直接索引，当数据块不够用时，再分配一级和二级间接索引
+    // 初始化inode。一开始只使用直接索引，写入的位置超出直接索引的范围时，再逐级分配间接索引
     pub fn initialize(&mut self, type_: DiskInodeType) {
         self.size = 0;
         self.direct.iter_mut().for_each(|v| *v = 0);
         self.indirect1 = 0;
         self.indirect2 = 0;
+        self.indirect3 = 0;
         self.type_ = type_;
     }
 
@@ -127,11 +166,111 @@ impl DiskInode {
         self.type_ == DiskInodeType::Directory
     }
 
-    #[allow(unused)]
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
 
+    pub fn is_symlink(&self) -> bool {
+        self.type_ == DiskInodeType::Symlink
+    }
+
+    // 检查inode的原始字节中的类型字段是否有效。
+    // 损坏的磁盘上可能出现任意值，必须先检查，才能把这些字节当作DiskInode读取。
+    pub fn has_valid_type(bytes: &[u8]) -> bool {
+        let type_ = bytes[core::mem::offset_of!(DiskInode, type_)];
+        type_ == DiskInodeType::File as u8
+            || type_ == DiskInodeType::Directory as u8
+            || type_ == DiskInodeType::Symlink as u8
+    }
+
+    // 第level级间接索引的根
+    pub fn indirect(&self, level: usize) -> u32 {
+        match level {
+            1 => self.indirect1,
+            2 => self.indirect2,
+            3 => self.indirect3,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn set_indirect(&mut self, level: usize, block_id: u32) {
+        match level {
+            1 => self.indirect1 = block_id,
+            2 => self.indirect2 = block_id,
+            3 => self.indirect3 = block_id,
+            _ => unreachable!(),
+        }
+    }
+
+    // 第inner_id个数据块由哪一级索引负责（0表示直接索引），以及它在这一级中的偏移
+    fn locate(inner_id: usize) -> (usize, usize) {
+        let level = (1..4)
+            .rev()
+            .find(|level| inner_id >= INDIRECT_START[*level])
+            .unwrap_or(0);
+        (level, inner_id - INDIRECT_START[level])
+    }
+
+    // 前data_blocks个数据块中，由第level级索引负责的块数
+    fn blocks_in_level(data_blocks: usize, level: usize) -> usize {
+        min(
+            data_blocks.saturating_sub(INDIRECT_START[level]),
+            INDIRECT_COUNT[level],
+        )
+    }
+
+    // 一个索引块中的一项能找到的数据块数。height为该索引块到数据块的层数，最下层的索引块为1。
+    fn span(height: usize) -> usize {
+        INODE_INDIRECT1_COUNT.pow(height as u32 - 1)
+    }
+
+    // 按顺序访问存放数据所需的每个块指针（包括各级间接索引块）。
+    // visit的参数为指针的位置、块号、是否为索引块，返回实际使用的块号。块号为0的指针是空洞。
+    // 返回None表示该块号无效或是空洞，不再访问它指向的索引块中的指针。
+    pub fn walk_blocks(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        mut visit: impl FnMut(BlockPointer, u32, bool) -> Option<u32>,
+    ) {
+        // 大小损坏时，最多访问块树能表示的块数
+        let data_blocks = min(self.data_blocks() as usize, INDIRECT3_BOUND);
+        for i in 0..Self::blocks_in_level(data_blocks, 0) {
+            visit(BlockPointer::Direct(i), self.direct[i], false);
+        }
+        for level in 1..4 {
+            let count = Self::blocks_in_level(data_blocks, level);
+            if count == 0 {
+                break;
+            }
+            if let Some(root) = visit(BlockPointer::Indirect(level), self.indirect(level), true) {
+                Self::walk_index(block_device, root, level, count, &mut visit);
+            }
+        }
+    }
+
+    // 访问索引块block（高度为height）中，前count个数据块用到的指针
+    fn walk_index(
+        block_device: &Arc<dyn BlockDevice>,
+        block: u32,
+        height: usize,
+        count: usize,
+        visit: &mut impl FnMut(BlockPointer, u32, bool) -> Option<u32>,
+    ) {
+        let entries = get_block_cache(block as usize, Arc::clone(block_device))
+            .lock()
+            .read(0, |index: &IndirectBlock| *index);
+        let span = Self::span(height);
+        for (index, entry) in entries.iter().enumerate().take(count.div_ceil(span)) {
+            let pointer = BlockPointer::Index { block, index };
+            if height == 1 {
+                visit(pointer, *entry, false);
+            } else if let Some(child) = visit(pointer, *entry, true) {
+                let child_count = min(span, count - index * span);
+                Self::walk_index(block_device, child, height - 1, child_count, visit);
+            }
+        }
+    }
+
     // 返回存放数据所需的块数量（不包括inode的索引）
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
@@ -141,206 +280,222 @@ impl DiskInode {
         size.div_ceil(BLOCK_SZ as u32)
     }
 
-    // 返回存放数据及其inode的一级/二级间接索引所需的块数量
-    pub fn total_blocks(size: u32) -> u32 {
-        let data_blocks = Self::_data_blocks(size) as usize;
-        let mut total = data_blocks;
-        // 一级间接索引
-        if data_blocks > DIRECT_BOUND {
-            total += 1;
-        }
-        // 二级间接索引
-        if data_blocks > INDIRECT1_BOUND {
-            // 存放二级间接索引的块
-            total += 1;
-            // 向上取整
-            total += (data_blocks - INDIRECT1_BOUND).div_ceil(INODE_INDIRECT1_COUNT);
-        }
-        total as u32
-    }
-
-    // 计算将数据大小扩展到new_size，还需要多少个块
-    pub fn blocks_num_needed(&self, new_size: u32) -> u32 {
-        assert!(new_size >= self.size);
-        Self::total_blocks(new_size) - Self::total_blocks(self.size)
+    // 实际分配的块数，包括数据块和各级间接索引块，不包括空洞
+    pub fn allocated_blocks(&self, block_device: &Arc<dyn BlockDevice>) -> u32 {
+        let mut count = 0;
+        self.walk_blocks(block_device, |_, block, _| {
+            count += (block != 0) as u32;
+            (block != 0).then_some(block)
+        });
+        count
     }
 
-    // 拿到第inner_id个的块编号。返回0表示没有分配
+    // 拿到第inner_id个的块编号。返回0表示没有分配，即该块是空洞
     pub fn get_block_id(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> u32 {
-        let inner_id = inner_id as usize;
-        if inner_id < INODE_DIRECT_COUNT {
-            self.direct[inner_id]
-        } else if inner_id < INDIRECT1_BOUND {
-            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-                .lock()
-                .read(0, |indirect_block: &IndirectBlock| {
-                    indirect_block[inner_id - INODE_DIRECT_COUNT]
-                })
-        } else {
-            let last = inner_id - INDIRECT1_BOUND;
-            let indirect1 = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+        let (level, offset) = Self::locate(inner_id as usize);
+        if level == 0 {
+            return self.direct[offset];
+        }
+        // 从根开始，逐级查找索引块，途经的索引块没有分配时，整个范围都是空洞
+        let mut block = self.indirect(level);
+        for height in (1..=level).rev() {
+            if block == 0 {
+                return 0;
+            }
+            let span = Self::span(height);
+            block = get_block_cache(block as usize, Arc::clone(block_device))
                 .lock()
-                .read(0, |indirect2: &IndirectBlock| {
-                    indirect2[last / INODE_INDIRECT1_COUNT]
+                .read(0, |index: &IndirectBlock| {
+                    index[offset / span % INODE_INDIRECT1_COUNT]
                 });
-            get_block_cache(indirect1 as usize, Arc::clone(block_device))
-                .lock()
-                .read(0, |indirect1: &IndirectBlock| {
-                    indirect1[last % INODE_INDIRECT1_COUNT]
-                })
         }
+        block
     }
 
-    // 将数据大小扩容到new_size。
-    // new_blocks是扩容要用的块编号，需要提前算出来，只能多不能少。
-    pub fn increase_size(
+    // 为第start到第end个（不含）数据块中的空洞分配块，途经的索引块没有分配时也一并分配。
+    // alloc分配一个已清零的块并返回块号，参数是希望分配的块号：紧跟在前一个块之后，使文件的块尽量连续。
+    // 不改变数据大小。
+    pub fn alloc_blocks(
         &mut self,
-        new_size: u32,
-        new_blocks: Vec<u32>,
+        start: usize,
+        end: usize,
         block_device: &Arc<dyn BlockDevice>,
+        mut alloc: impl FnMut(u32) -> u32,
     ) {
-        let mut current_blocks = self.data_blocks();
-        self.size = new_size;
-        let mut total_blocks = self.data_blocks();
-        let mut new_blocks = new_blocks.into_iter();
-        // 分配给直接索引，并更新直接索引数组
-        while current_blocks < min(total_blocks, INODE_DIRECT_COUNT as u32) {
-            self.direct[current_blocks as usize] = new_blocks.next().unwrap();
-            current_blocks += 1;
+        let mut goal = match start {
+            0 => 0,
+            _ => match self.get_block_id(start as u32 - 1, block_device) {
+                0 => 0,
+                block => block + 1,
+            },
+        };
+        for inner_id in start..end {
+            let (level, offset) = Self::locate(inner_id);
+            if level == 0 {
+                if self.direct[offset] == 0 {
+                    self.direct[offset] = alloc(goal);
+                }
+                goal = self.direct[offset] + 1;
+                continue;
+            }
+            if self.indirect(level) == 0 {
+                let root = alloc(goal);
+                self.set_indirect(level, root);
+                goal = root + 1;
+            }
+            // 从根往下，逐级找到或分配索引块，最后是数据块
+            let mut block = self.indirect(level);
+            for height in (1..=level).rev() {
+                let index = offset / Self::span(height) % INODE_INDIRECT1_COUNT;
+                let cache = get_block_cache(block as usize, Arc::clone(block_device));
+                let child = cache
+                    .lock()
+                    .read(0, |entries: &IndirectBlock| entries[index]);
+                block = if child == 0 {
+                    let child = alloc(goal);
+                    cache
+                        .lock()
+                        .modify(0, |entries: &mut IndirectBlock| entries[index] = child);
+                    child
+                } else {
+                    child
+                };
+                goal = block + 1;
+            }
         }
-        // 如果不够，分配一级索引
-        if total_blocks > INODE_DIRECT_COUNT as u32 {
-            if current_blocks == INODE_DIRECT_COUNT as u32 {
-                self.indirect1 = new_blocks.next().unwrap();
+    }
+
+    // 序号小于end的数据块中，从后往前数第n个已分配的数据块的序号，不足n个时返回0。
+    // 把数据块数缩小到这个序号，最多释放n个已分配的数据块
+    pub fn nth_allocated_before(
+        &self,
+        end: usize,
+        mut n: usize,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> usize {
+        assert!(n > 0);
+        let end = min(end, INDIRECT3_BOUND);
+        for level in (1..4).rev() {
+            let count = Self::blocks_in_level(end, level);
+            let root = self.indirect(level);
+            if count == 0 || root == 0 {
+                continue;
+            }
+            if let Some(id) = Self::nth_allocated_in_index(block_device, root, level, count, &mut n)
+            {
+                return INDIRECT_START[level] + id;
             }
-            current_blocks -= INODE_DIRECT_COUNT as u32;
-            total_blocks -= INODE_DIRECT_COUNT as u32;
-        } else {
-            return;
         }
-        // 更新一级索引块
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
-                while current_blocks < min(total_blocks, INODE_INDIRECT1_COUNT as u32) {
-                    indirect1[current_blocks as usize] = new_blocks.next().unwrap();
-                    current_blocks += 1;
+        for i in (0..Self::blocks_in_level(end, 0)).rev() {
+            if self.direct[i] != 0 {
+                n -= 1;
+                if n == 0 {
+                    return i;
                 }
-            });
-        // 如果还不够，分配二级索引
-        if total_blocks > INODE_INDIRECT1_COUNT as u32 {
-            if current_blocks == INODE_INDIRECT1_COUNT as u32 {
-                self.indirect2 = new_blocks.next().unwrap();
             }
-            current_blocks -= INODE_INDIRECT1_COUNT as u32;
-            total_blocks -= INODE_INDIRECT1_COUNT as u32;
-        } else {
-            return;
         }
-        // 更新二级索引块
-        // 对于某个要分配的块，a0/b0表示：
-        // - a0：二级索引中，对应项的偏移
-        // - a0：一级索引（由二级索引找过来的）中，对应项的偏移
-        let mut a0 = current_blocks as usize / INODE_INDIRECT1_COUNT;
-        let mut b0 = current_blocks as usize % INODE_INDIRECT1_COUNT;
-        let a1 = total_blocks as usize / INODE_INDIRECT1_COUNT;
-        let b1 = total_blocks as usize % INODE_INDIRECT1_COUNT;
-        // alloc low-level indirect1
-        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+        0
+    }
+
+    // 索引块block（高度为height）负责的前count个数据块中，从后往前找第n个已分配的数据块，
+    // 返回它在block负责的范围内的序号。每经过一个已分配的数据块，n减一
+    fn nth_allocated_in_index(
+        block_device: &Arc<dyn BlockDevice>,
+        block: u32,
+        height: usize,
+        count: usize,
+        n: &mut usize,
+    ) -> Option<usize> {
+        let entries = get_block_cache(block as usize, Arc::clone(block_device))
             .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
-                while (a0 < a1) || (a0 == a1 && b0 < b1) {
-                    if b0 == 0 {
-                        indirect2[a0] = new_blocks.next().unwrap();
-                    }
-                    // fill current
-                    get_block_cache(indirect2[a0] as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            indirect1[b0] = new_blocks.next().unwrap();
-                        });
-                    // move to next
-                    b0 += 1;
-                    if b0 == INODE_INDIRECT1_COUNT {
-                        b0 = 0;
-                        a0 += 1;
-                    }
+            .read(0, |index: &IndirectBlock| *index);
+        let span = Self::span(height);
+        for index in (0..count.div_ceil(span)).rev() {
+            let entry = entries[index];
+            if entry == 0 {
+                continue;
+            }
+            if height == 1 {
+                *n -= 1;
+                if *n == 0 {
+                    return Some(index);
                 }
-            });
+            } else {
+                let child_count = min(span, count - index * span);
+                if let Some(id) =
+                    Self::nth_allocated_in_index(block_device, entry, height - 1, child_count, n)
+                {
+                    return Some(index * span + id);
+                }
+            }
+        }
+        None
     }
 
-    // 释放inode所使用的块（包括存放数据和间接索引的块）。只是释放，并不清空缓冲区或磁盘上的数据。
-    // 返回释放的块编号
-    pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+    // 将数据大小缩小到new_size，返回不再需要的块编号（包括不再需要的间接索引块）。
+    // 被释放的块指针清零，之后再扩大时，新增的部分是空洞。
+    pub fn decrease_size(
+        &mut self,
+        new_size: u32,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<u32> {
+        assert!(new_size <= self.size);
+        let keep = Self::_data_blocks(new_size) as usize;
         let mut v: Vec<u32> = Vec::new();
-        let mut data_blocks = self.data_blocks() as usize;
-        self.size = 0;
-        let mut current_blocks = 0usize;
-        // direct
-        while current_blocks < min(data_blocks, INODE_DIRECT_COUNT) {
-            v.push(self.direct[current_blocks]);
-            self.direct[current_blocks] = 0;
-            current_blocks += 1;
+        for pointer in self.direct.iter_mut().skip(keep) {
+            if *pointer != 0 {
+                v.push(*pointer);
+                *pointer = 0;
+            }
         }
-        // indirect1 block
-        if data_blocks > INODE_DIRECT_COUNT {
-            v.push(self.indirect1);
-            data_blocks -= INODE_DIRECT_COUNT;
-            current_blocks = 0;
-        } else {
-            return v;
+        for level in 1..4 {
+            let root = self.indirect(level);
+            let keep = Self::blocks_in_level(keep, level);
+            if root != 0 && Self::truncate_index(block_device, root, level, keep, &mut v) {
+                self.set_indirect(level, 0);
+            }
         }
-        // indirect1
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
-                while current_blocks < min(data_blocks, INODE_INDIRECT1_COUNT) {
-                    v.push(indirect1[current_blocks]);
-                    //indirect1[current_blocks] = 0;
-                    current_blocks += 1;
+        self.size = new_size;
+        v
+    }
+
+    // 释放索引块block（高度为height）负责的、第keep个之后的数据块，以及不再需要的下一级索引块，
+    // 块编号加入v中。keep为0时，block本身也不再需要，将它加入v并返回true。
+    fn truncate_index(
+        block_device: &Arc<dyn BlockDevice>,
+        block: u32,
+        height: usize,
+        keep: usize,
+        v: &mut Vec<u32>,
+    ) -> bool {
+        let span = Self::span(height);
+        let cache = get_block_cache(block as usize, Arc::clone(block_device));
+        let mut entries = cache.lock().read(0, |index: &IndirectBlock| *index);
+        // 从第first项开始，负责的块全部被释放
+        let first = keep.div_ceil(span);
+        for (index, entry) in entries.iter_mut().enumerate().skip(keep / span) {
+            if *entry == 0 {
+                continue;
+            }
+            if index >= first {
+                if height == 1 {
+                    v.push(*entry);
+                } else {
+                    Self::truncate_index(block_device, *entry, height - 1, 0, v);
                 }
-            });
-        self.indirect1 = 0;
-        // indirect2 block
-        if data_blocks > INODE_INDIRECT1_COUNT {
-            v.push(self.indirect2);
-            data_blocks -= INODE_INDIRECT1_COUNT;
-        } else {
-            return v;
+                *entry = 0;
+            } else if height > 1 {
+                Self::truncate_index(block_device, *entry, height - 1, keep - index * span, v);
+            }
+        }
+        if keep == 0 {
+            v.push(block);
+            return true;
         }
-        // indirect2
-        assert!(data_blocks <= INODE_INDIRECT2_COUNT);
-        let a1 = data_blocks / INODE_INDIRECT1_COUNT;
-        let b1 = data_blocks % INODE_INDIRECT1_COUNT;
-        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+        cache
             .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
-                // full indirect1 blocks
-                for entry in indirect2.iter_mut().take(a1) {
-                    v.push(*entry);
-                    get_block_cache(*entry as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter() {
-                                v.push(*entry);
-                            }
-                        });
-                }
-                // last indirect1 block
-                if b1 > 0 {
-                    v.push(indirect2[a1]);
-                    get_block_cache(indirect2[a1] as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter().take(b1) {
-                                v.push(*entry);
-                            }
-                        });
-                    //indirect2[a1] = 0;
-                }
-            });
-        self.indirect2 = 0;
-        v
+            .modify(0, |index: &mut IndirectBlock| *index = entries);
+        false
     }
 
     // 从inode中读取数据到buf中，返回读取的字节数
@@ -365,12 +520,17 @@ impl DiskInode {
             let block_read_size = end_current_block - start;
             let dst = &mut buf[read_size..read_size + block_read_size];
             let block_id = self.get_block_id(start_block as u32, block_device) as usize;
-            get_block_cache(block_id, Arc::clone(block_device))
-                .lock()
-                .read(0, |data_block: &DataBlock| {
-                    let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
-                    dst.copy_from_slice(src);
-                });
+            if block_id == 0 {
+                // 空洞中的数据都是0
+                dst.fill(0);
+            } else {
+                get_block_cache(block_id, Arc::clone(block_device))
+                    .lock()
+                    .read(0, |data_block: &DataBlock| {
+                        let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
+                        dst.copy_from_slice(src);
+                    });
+            }
             read_size += block_read_size;
             if end_current_block == end {
                 break;
@@ -381,7 +541,7 @@ impl DiskInode {
         read_size
     }
 
-    // 从buf中写入数据到inode中，返回写入的字节数
+    // 从buf中写入数据到inode中，返回写入的字节数。写入范围内的块必须已经分配，不能是空洞。
     pub fn write_at(
         &mut self,
         offset: usize,
@@ -391,6 +551,9 @@ impl DiskInode {
         let mut start = offset;
         let end = min(offset + buf.len(), self.size as usize);
         assert!(start <= end);
+        if start == end {
+            return 0;
+        }
         let mut start_block = start / BLOCK_SZ;
         let mut write_size = 0usize;
         loop {
@@ -400,6 +563,7 @@ impl DiskInode {
             // 写入的字节数
             let block_write_size = end_current_block - start;
             let block_id = self.get_block_id(start_block as u32, block_device) as usize;
+            assert_ne!(block_id, 0, "writing to a hole");
             get_block_cache(block_id, Arc::clone(block_device))
                 .lock()
                 .modify(0, |data_block: &mut DataBlock| {
@@ -419,47 +583,111 @@ impl DiskInode {
     }
 }
 
-#[repr(C)]
+// 目录项头部的大小：inode编号（4字节）、记录长度（2字节）、文件名长度（1字节）、保留（1字节）
+pub const DIRENT_HEADER_SZ: usize = 8;
+// 目录项按4字节对齐
+const DIRENT_ALIGN: usize = 4;
+
 // 目录下的一个目录项
-// 类型为目录的DiskInode中，它的数据块存放的是DirEntry
+// 类型为目录的DiskInode中，它的数据块存放的是变长的目录项：头部之后紧跟文件名（不以\0结尾），
+// 每条记录按4字节对齐，且不跨越块的边界，一个块中的记录首尾相接，正好铺满整个块。
+// 记录长度可以大于存放文件名所需的长度，多出的部分是空闲空间，插入新目录项时可以分出去；
+// 文件名长度为0的记录是空闲的记录。
 pub struct DirEntry {
-    // 多1个字节，用来存放\0
-    name: [u8; NAME_LENGTH_LIMIT + 1],
+    // 记录在目录块中的偏移
+    pub offset: usize,
+    // 记录长度，包括头部、文件名和空闲空间
+    pub rec_len: usize,
     inode_number: u32,
+    name: Vec<u8>,
 }
-// 目录项的大小（ 27 + 1 + 4 = 32）
-pub const DIRENT_SZ: usize = 32;
 
 impl DirEntry {
-    // 创建一个空的目录项
-    pub fn empty() -> Self {
-        Self {
-            name: [0u8; NAME_LENGTH_LIMIT + 1],
-            inode_number: 0,
-        }
+    // 存放长度为name_len的文件名所需的最短记录长度
+    pub fn record_len(name_len: usize) -> usize {
+        (DIRENT_HEADER_SZ + name_len).next_multiple_of(DIRENT_ALIGN)
     }
-    // 创建一个新的目录项
-    pub fn new(name: &str, inode_number: u32) -> Self {
-        let mut bytes = [0u8; NAME_LENGTH_LIMIT + 1];
-        bytes[..name.len()].copy_from_slice(name.as_bytes());
-        Self {
-            name: bytes,
-            inode_number,
+
+    // 解析目录块中的所有记录（包括空闲的记录）。
+    // 记录链损坏（长度不对齐、越过块的边界、放不下文件名）时，返回出错的偏移。
+    pub fn parse_block(block: &DataBlock) -> core::result::Result<Vec<DirEntry>, usize> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset < BLOCK_SZ {
+            let field = |start: usize, len: usize| {
+                block[offset + start..offset + start + len]
+                    .iter()
+                    .rev()
+                    .fold(0usize, |value, b| value << 8 | *b as usize)
+            };
+            let rec_len = field(4, 2);
+            let name_len = field(6, 1);
+            if rec_len < DIRENT_HEADER_SZ
+                || rec_len % DIRENT_ALIGN != 0
+                || offset + rec_len > BLOCK_SZ
+                || DIRENT_HEADER_SZ + name_len > rec_len
+            {
+                return Err(offset);
+            }
+            let name_start = offset + DIRENT_HEADER_SZ;
+            entries.push(DirEntry {
+                offset,
+                rec_len,
+                inode_number: field(0, 4) as u32,
+                name: block[name_start..name_start + name_len].to_vec(),
+            });
+            offset += rec_len;
         }
+        Ok(entries)
+    }
+
+    // 在目录块的offset处写入一条记录。name为空时写入空闲的记录。
+    pub fn write(
+        block: &mut DataBlock,
+        offset: usize,
+        rec_len: usize,
+        inode_number: u32,
+        name: &[u8],
+    ) {
+        assert!(rec_len >= Self::record_len(name.len()) && offset + rec_len <= BLOCK_SZ);
+        block[offset..offset + 4].copy_from_slice(&inode_number.to_le_bytes());
+        block[offset + 4..offset + 6].copy_from_slice(&(rec_len as u16).to_le_bytes());
+        block[offset + 6] = name.len() as u8;
+        block[offset + 7] = 0;
+        let name_start = offset + DIRENT_HEADER_SZ;
+        block[name_start..name_start + name.len()].copy_from_slice(name);
     }
 
-    // 将自身序列化为字节数组
-    pub fn as_bytes(&self) -> &[u8] {
-        unsafe { core::slice::from_raw_parts(self as *const _ as usize as *const u8, DIRENT_SZ) }
+    // 初始化一个空的目录块：只有一条覆盖整个块的空闲记录
+    pub fn init_block(block: &mut DataBlock) {
+        block.fill(0);
+        Self::write(block, 0, BLOCK_SZ, 0, &[]);
     }
 
-    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
-        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, DIRENT_SZ) }
+    pub fn is_free(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    // 记录中可以分给新目录项的空间。空闲的记录可以整条使用。
+    pub fn spare(&self) -> usize {
+        if self.is_free() {
+            self.rec_len
+        } else {
+            self.rec_len - Self::record_len(self.name.len())
+        }
+    }
+
+    pub fn name_bytes(&self) -> &[u8] {
+        &self.name
     }
 
     pub fn name(&self) -> &str {
-        let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
-        core::str::from_utf8(&self.name[..len]).unwrap()
+        self.try_name().unwrap()
+    }
+
+    // 文件名。不是有效的UTF-8时返回None。
+    pub fn try_name(&self) -> Option<&str> {
+        core::str::from_utf8(&self.name).ok()
     }
 
     pub fn inode_number(&self) -> u32 {
@@ -4,12 +4,23 @@ extern crate alloc;
 mod bitmap;
 mod block_cache;
 mod block_dev;
+mod dir_index;
 mod efs;
+mod fat32;
+mod fsck;
+mod journal;
 mod layout;
 mod vfs;
 
 // 每个块的大小是512字节，正好等于一个扇区
 pub const BLOCK_SZ: usize = 512;
+pub use block_cache::{
+    block_cache_stats, block_cache_sync_all, block_cache_try_sync_all, get_block_cache,
+    init_block_cache, read_block_cached, write_block_cached, BlockCacheStats,
+};
 pub use block_dev::BlockDevice;
-pub use efs::EasyFileSystem;
-pub use vfs::Inode;
+pub use efs::{EasyFileSystem, FsStats};
+pub use fat32::{Fat32FileSystem, Fat32Inode};
+pub use fsck::{dump, fsck, DirEntryError, FsckProblem, FsckReport};
+pub use layout::SYMLINK_TARGET_LIMIT;
+pub use vfs::{Inode, InodeStat};
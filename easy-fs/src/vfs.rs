@@ -3,42 +3,82 @@
 
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
-use spin::{Mutex, MutexGuard};
+use spin::RwLock;
 
 use crate::{
-    block_cache::{block_cache_sync_all, get_block_cache},
+    block_cache::get_block_cache,
     block_dev::BlockDevice,
+    dir_index::{DirIndex, DirIndexes, DirSlot, DIR_INDEX_MIN_BLOCKS},
     efs::EasyFileSystem,
-    layout::{DirEntry, DiskInode, DiskInodeType, DIRENT_SZ},
+    layout::{
+        DirEntry, DiskInode, DiskInodeType, MAX_FILE_SIZE, NAME_LENGTH_LIMIT, SYMLINK_TARGET_LIMIT,
+    },
+    BLOCK_SZ,
 };
 
+type DataBlock = [u8; BLOCK_SZ];
+
+// 一个事务最多写入或释放的数据块数。加上索引块、位图块和inode所在的块，不超过日志的容量。
+const TXN_DATA_BLOCKS: usize = 16;
+
+// inode的元数据
+#[derive(Debug)]
+pub struct InodeStat {
+    // inode编号
+    pub inode: u32,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    // 数据的字节大小
+    pub size: u32,
+    // 占用的块数，包括间接索引块，不包括空洞
+    pub blocks: u32,
+}
+
+// 目录块中可以分给新目录项的最大空间
+fn block_spare(entries: &[DirEntry]) -> usize {
+    entries.iter().map(DirEntry::spare).max().unwrap_or(0)
+}
+
 // DiskInode 放在磁盘存放的inode，而Inode内存存放的inode
 // 每个Inode会指向某个DiskInode。该类型只用于常规文件和目录，不用于特殊文件（如socket）
 pub struct Inode {
     block_id: usize,
     block_offset: usize,
-    fs: Arc<Mutex<EasyFileSystem>>,
+    inode_id: u32,
+    // inode的读写锁，与指向同一个DiskInode的其他Inode对象共享
+    lock: Arc<RwLock<()>>,
+    fs: Arc<EasyFileSystem>,
     block_device: Arc<dyn BlockDevice>,
 }
 
+impl Drop for Inode {
+    fn drop(&mut self) {
+        self.fs.release_inode_lock(self.inode_id, &self.lock);
+    }
+}
+
 impl Inode {
-    pub fn new(
-        block_id: u32,
-        block_offset: usize,
-        fs: Arc<Mutex<EasyFileSystem>>,
-        block_device: Arc<dyn BlockDevice>,
-    ) -> Self {
+    pub(crate) fn new(inode_id: u32, fs: Arc<EasyFileSystem>) -> Self {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
         Self {
             block_id: block_id as usize,
             block_offset,
+            inode_id,
+            lock: fs.inode_lock(inode_id),
+            block_device: Arc::clone(&fs.block_device),
             fs,
-            block_device,
         }
     }
 
+    // inode编号
+    pub fn inode_id(&self) -> u32 {
+        self.inode_id
+    }
+
     pub fn size(&self) -> u32 {
-        let _fs = self.fs.lock();
+        let _guard = self.lock.read();
         self.read_disk_inode(|disk_inode| disk_inode.size)
     }
 
@@ -49,159 +89,464 @@ impl Inode {
             .read(self.block_offset, f)
     }
 
-    // 修改磁盘中的inode
-    fn modify_disk_inode<V>(&self, f: impl FnOnce(&mut DiskInode) -> V) -> V {
+    // 复制一份磁盘中的inode。调用者持有inode的锁，复制期间inode不会被修改。
+    // 读写数据时使用副本，不必一直持有inode所在的块，同一块中的其他inode可以同时被访问。
+    fn disk_inode(&self) -> DiskInode {
+        self.read_disk_inode(DiskInode::clone)
+    }
+
+    // 修改磁盘中的inode：在副本上修改，再写回inode所在的块。调用者持有inode的写锁。
+    fn update_disk_inode<V>(&self, f: impl FnOnce(&mut DiskInode) -> V) -> V {
+        let mut disk_inode = self.disk_inode();
+        let ret = f(&mut disk_inode);
         get_block_cache(self.block_id, Arc::clone(&self.block_device))
             .lock()
-            .modify(self.block_offset, f)
+            .modify(self.block_offset, |d: &mut DiskInode| *d = disk_inode);
+        ret
+    }
+
+    // 读出目录中第block个块的所有记录。记录链损坏的块当作没有空闲空间的空块，留给fsck修复。
+    fn read_dir_block(&self, disk_inode: &DiskInode, block: usize) -> Vec<DirEntry> {
+        let block_id = disk_inode.get_block_id(block as u32, &self.block_device);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .read(0, |data: &DataBlock| {
+                DirEntry::parse_block(data).unwrap_or_default()
+            })
+    }
+
+    // 修改目录中第block个块
+    fn modify_dir_block<V>(
+        &self,
+        disk_inode: &DiskInode,
+        block: usize,
+        f: impl FnOnce(&mut DataBlock) -> V,
+    ) -> V {
+        let block_id = disk_inode.get_block_id(block as u32, &self.block_device);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(0, f)
+    }
+
+    // 目录的哈希索引。目录较小时返回None；目录足够大但还没有索引时，扫描整个目录建立索引。
+    fn dir_index<'a>(
+        &self,
+        disk_inode: &DiskInode,
+        indexes: &'a mut DirIndexes,
+    ) -> Option<&'a mut DirIndex> {
+        let blocks = disk_inode.data_blocks() as usize;
+        if blocks < DIR_INDEX_MIN_BLOCKS {
+            return None;
+        }
+        let dir = self.inode_id;
+        if indexes.get(dir).is_none() {
+            let mut index = DirIndex::new();
+            for block in 0..blocks {
+                let entries = self.read_dir_block(disk_inode, block);
+                for dirent in entries.iter().filter(|dirent| !dirent.is_free()) {
+                    let slot = DirSlot {
+                        block,
+                        offset: dirent.offset,
+                        inode: dirent.inode_number(),
+                    };
+                    index.insert(dirent.name_bytes(), slot);
+                }
+                index.set_spare(block, block_spare(&entries));
+            }
+            indexes.insert(dir, index);
+        }
+        indexes.get(dir)
     }
 
-    // 在类型为目录的inode中，查找名为name的inode
-    fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
+    // 在类型为目录的inode中，查找名为name的目录项
+    fn find_dirent(&self, name: &str, disk_inode: &DiskInode) -> Option<DirSlot> {
         assert!(disk_inode.is_dir());
-        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-        let mut dirent = DirEntry::empty();
-        for i in 0..file_count {
-            assert_eq!(
-                disk_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device,),
-                DIRENT_SZ,
+        let name = name.as_bytes();
+        if let Some(index) = self.dir_index(disk_inode, &mut self.fs.dir_indexes.lock()) {
+            // 哈希值相同的文件名可能不同，需要读出目录项比较
+            return index.candidates(name).iter().copied().find(|slot| {
+                self.read_dir_block(disk_inode, slot.block)
+                    .iter()
+                    .any(|dirent| dirent.offset == slot.offset && dirent.name_bytes() == name)
+            });
+        }
+        (0..disk_inode.data_blocks() as usize).find_map(|block| {
+            self.read_dir_block(disk_inode, block)
+                .into_iter()
+                .find(|dirent| !dirent.is_free() && dirent.name_bytes() == name)
+                .map(|dirent| DirSlot {
+                    block,
+                    offset: dirent.offset,
+                    inode: dirent.inode_number(),
+                })
+        })
+    }
+
+    // 在目录中写入一个目录项。优先使用已有目录块中的空闲空间，都放不下时为目录增加一个块。
+    fn insert_dirent(&self, name: &[u8], inode: u32, disk_inode: &mut DiskInode) {
+        let needed = DirEntry::record_len(name.len());
+        let blocks = disk_inode.data_blocks() as usize;
+        let found = match self.dir_index(disk_inode, &mut self.fs.dir_indexes.lock()) {
+            Some(index) => index.find_space(needed),
+            None => (0..blocks)
+                .find(|&block| block_spare(&self.read_dir_block(disk_inode, block)) >= needed),
+        };
+        let block = found.unwrap_or_else(|| {
+            self.alloc_range(blocks * BLOCK_SZ, (blocks + 1) * BLOCK_SZ, disk_inode);
+            self.modify_dir_block(disk_inode, blocks, DirEntry::init_block);
+            blocks
+        });
+        let entries = self.read_dir_block(disk_inode, block);
+        let target = entries
+            .iter()
+            .find(|dirent| dirent.spare() >= needed)
+            .unwrap();
+        let offset = self.modify_dir_block(disk_inode, block, |data| {
+            if target.is_free() {
+                // 整条使用空闲的记录
+                DirEntry::write(data, target.offset, target.rec_len, inode, name);
+                target.offset
+            } else {
+                // 从已有记录的末尾分出空闲空间
+                let used = DirEntry::record_len(target.name_bytes().len());
+                DirEntry::write(
+                    data,
+                    target.offset,
+                    used,
+                    target.inode_number(),
+                    target.name_bytes(),
+                );
+                DirEntry::write(
+                    data,
+                    target.offset + used,
+                    target.rec_len - used,
+                    inode,
+                    name,
+                );
+                target.offset + used
+            }
+        });
+        let spare = block_spare(&self.read_dir_block(disk_inode, block));
+        if let Some(index) = self.dir_index(disk_inode, &mut self.fs.dir_indexes.lock()) {
+            index.insert(
+                name,
+                DirSlot {
+                    block,
+                    offset,
+                    inode,
+                },
             );
-            if dirent.name() == name {
-                return Some(dirent.inode_number());
+            index.set_spare(block, spare);
+        }
+    }
+
+    // 删除目录项：并入同一块中的前一条记录；是块中的第一条记录时，改为空闲的记录。
+    // 目录的块不会被释放，空出的空间留给之后创建的文件。
+    fn remove_dirent(&self, name: &str, slot: DirSlot, disk_inode: &DiskInode) {
+        let entries = self.read_dir_block(disk_inode, slot.block);
+        let i = entries
+            .iter()
+            .position(|dirent| dirent.offset == slot.offset)
+            .unwrap();
+        self.modify_dir_block(disk_inode, slot.block, |data| {
+            let rec_len = entries[i].rec_len;
+            match i {
+                0 => DirEntry::write(data, 0, rec_len, 0, &[]),
+                _ => {
+                    let prev = &entries[i - 1];
+                    DirEntry::write(
+                        data,
+                        prev.offset,
+                        prev.rec_len + rec_len,
+                        prev.inode_number(),
+                        prev.name_bytes(),
+                    )
+                }
             }
+        });
+        let spare = block_spare(&self.read_dir_block(disk_inode, slot.block));
+        if let Some(index) = self.dir_index(disk_inode, &mut self.fs.dir_indexes.lock()) {
+            index.remove(name.as_bytes(), slot.block, slot.offset);
+            index.set_spare(slot.block, spare);
         }
-        None
+    }
+
+    // 目录中是否没有任何目录项
+    fn dir_is_empty(&self, disk_inode: &DiskInode) -> bool {
+        (0..disk_inode.data_blocks() as usize).all(|block| {
+            self.read_dir_block(disk_inode, block)
+                .iter()
+                .all(DirEntry::is_free)
+        })
     }
 
     // 如果self是类型为目录的inode，则查找名为name的inode项；否则报错。
     pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
-        let fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(name, disk_inode).map(|inode_id| {
-                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-                Arc::new(Self::new(
-                    block_id,
-                    block_offset,
-                    self.fs.clone(),
-                    self.block_device.clone(),
-                ))
-            })
-        })
+        let _guard = self.lock.read();
+        self.find_dirent(name, &self.disk_inode())
+            .map(|slot| Arc::new(Self::new(slot.inode, self.fs.clone())))
     }
 
-    // 增加inode的大小，如果新的大小比原来的大，则分配新的数据块
-    fn increase_size(
-        &self,
-        new_size: u32,
-        disk_inode: &mut DiskInode,
-        fs: &mut MutexGuard<EasyFileSystem>,
-    ) {
-        if new_size < disk_inode.size {
+    // 为数据中[start, end)字节范围内的空洞分配清零的块。end超过数据大小时，扩大到end。
+    fn alloc_range(&self, start: usize, end: usize, disk_inode: &mut DiskInode) {
+        if start >= end {
             return;
         }
-        let blocks_needed = disk_inode.blocks_num_needed(new_size);
-        let mut v: Vec<u32> = Vec::new();
-        for _ in 0..blocks_needed {
-            v.push(fs.alloc_data());
+        disk_inode.alloc_blocks(
+            start / BLOCK_SZ,
+            end.div_ceil(BLOCK_SZ),
+            &self.block_device,
+            |goal| self.fs.alloc_data(goal),
+        );
+        if end > disk_inode.size as usize {
+            disk_inode.size = end as u32;
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        let _guard = self.lock.read();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        let _guard = self.lock.read();
+        self.read_disk_inode(|disk_inode| disk_inode.is_symlink())
+    }
+
+    pub fn stat(&self) -> InodeStat {
+        let _guard = self.lock.read();
+        let disk_inode = self.disk_inode();
+        InodeStat {
+            inode: self.inode_id,
+            is_dir: disk_inode.is_dir(),
+            is_symlink: disk_inode.is_symlink(),
+            size: disk_inode.size,
+            blocks: disk_inode.allocated_blocks(&self.block_device),
         }
-        disk_inode.increase_size(new_size, v, &self.block_device);
     }
 
     // 若当前inode是目录，则创建一个名为name的文件。如果创建成功，则返回该文件的inode；
     pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
-        let mut fs = self.fs.lock();
-        let op = |root_inode: &DiskInode| {
-            // 必须是目录
-            assert!(root_inode.is_dir());
-            // has the file been created?
-            self.find_inode_id(name, root_inode)
-        };
-        if self.read_disk_inode(op).is_some() {
+        self.create_inode(name, DiskInodeType::File, &[])
+    }
+
+    // 若当前inode是目录，则创建一个名为name的子目录
+    pub fn mkdir(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Directory, &[])
+    }
+
+    // 若当前inode是目录，则创建一个名为name、指向target的符号链接。
+    // 目标路径只被保存下来，不检查它是否存在。
+    pub fn symlink(&self, name: &str, target: &str) -> Option<Arc<Inode>> {
+        if target.is_empty() || target.len() > SYMLINK_TARGET_LIMIT {
             return None;
         }
-        // 分配一个新的inode
-        let new_inode_id = fs.alloc_inode();
-        // 初始化该inode为文件
-        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
-            .lock()
-            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(DiskInodeType::File);
-            });
+        self.create_inode(name, DiskInodeType::Symlink, target.as_bytes())
+    }
+
+    // 读出符号链接的目标路径。不是符号链接时返回None。
+    pub fn readlink(&self) -> Option<String> {
+        let _guard = self.lock.read();
+        let disk_inode = self.disk_inode();
+        if !disk_inode.is_symlink() {
+            return None;
+        }
+        let mut target = vec![0u8; disk_inode.size as usize];
+        disk_inode.read_at(0, &mut target, &self.block_device);
+        String::from_utf8(target).ok()
+    }
+
+    // 在当前目录下创建一个类型为type_、内容为data的inode。名字无效或已存在时返回None。
+    fn create_inode(&self, name: &str, type_: DiskInodeType, data: &[u8]) -> Option<Arc<Inode>> {
+        if name.is_empty() || name.len() > NAME_LENGTH_LIMIT || name.contains('/') {
+            return None;
+        }
+        let _guard = self.lock.write();
+        // has the file been created?
+        if self.find_dirent(name, &self.disk_inode()).is_some() {
+            return None;
+        }
+        // 分配inode、初始化inode、写入目录项，在同一个事务中完成
+        let txn = self.fs.begin_transaction();
+        // 分配一个新的inode。它还没有加入目录，其他线程访问不到，不需要加锁。
+        let new_inode = Self::new(self.fs.alloc_inode(), self.fs.clone());
+        new_inode.update_disk_inode(|disk_inode| {
+            disk_inode.initialize(type_);
+            // 数据不超过符号链接的长度限制，和其他修改放在同一个事务中写入
+            new_inode.alloc_range(0, data.len(), disk_inode);
+            disk_inode.write_at(0, data, &self.block_device);
+        });
         // 将该目录项写入当前的目录类型的inode中
-        self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
+        self.update_disk_inode(|root_inode| {
+            self.insert_dirent(name.as_bytes(), new_inode.inode_id, root_inode);
         });
+        txn.commit();
+        Some(Arc::new(new_inode))
+    }
 
-        block_cache_sync_all();
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        // return inode
-        Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
-            self.fs.clone(),
-            self.block_device.clone(),
-        )))
+    // 删除当前目录下名为name的文件或空目录，释放它的inode和数据块。
+    // 不存在或者是非空目录时返回false。
+    pub fn unlink(&self, name: &str) -> bool {
+        let _guard = self.lock.write();
+        // 找到目录项的位置和它指向的inode
+        let disk_inode = self.disk_inode();
+        let Some(slot) = self.find_dirent(name, &disk_inode) else {
+            return false;
+        };
+        // 先获取父目录的锁，再获取子inode的锁
+        let child = Self::new(slot.inode, self.fs.clone());
+        let _child_guard = child.lock.write();
+        let is_dir = child.read_disk_inode(|d| d.is_dir());
+        if is_dir && !self.dir_is_empty(&child.disk_inode()) {
+            return false;
+        }
+        if is_dir {
+            self.fs.dir_indexes.lock().remove(slot.inode);
+        }
+        // 先分多个事务释放它的块，再在同一个事务中释放inode、删除目录项。
+        // 崩溃时，文件可能只剩下一部分数据，但不会出现没有目录项指向的inode
+        child.shrink_locked(0);
+        let txn = self.fs.begin_transaction();
+        self.fs.dealloc_inode(slot.inode);
+        self.remove_dirent(name, slot, &disk_inode);
+        txn.commit();
+        true
     }
 
     // 若当前inode是目录，则返回目录下的所有文件名；
     pub fn ls(&self) -> Vec<String> {
-        let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
-            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-            let mut v: Vec<String> = Vec::new();
-            for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device,),
-                    DIRENT_SZ,
-                );
-                v.push(String::from(dirent.name()));
+        let _guard = self.lock.read();
+        let disk_inode = self.disk_inode();
+        let mut v: Vec<String> = Vec::new();
+        for block in 0..disk_inode.data_blocks() as usize {
+            for dirent in self.read_dir_block(&disk_inode, block) {
+                if !dirent.is_free() {
+                    v.push(String::from(dirent.name()));
+                }
             }
-            v
+        }
+        v
+    }
+
+    // 目录中位置不小于pos的第一个目录项，返回它的位置和名字。
+    // 位置是目录项在目录数据中的字节偏移：目录项在被删除之前不会移动，增删其他项时位置不变。
+    pub fn next_dirent(&self, pos: usize) -> Option<(usize, String)> {
+        let _guard = self.lock.read();
+        let disk_inode = self.disk_inode();
+        (pos / BLOCK_SZ..disk_inode.data_blocks() as usize).find_map(|block| {
+            let start = block * BLOCK_SZ;
+            self.read_dir_block(&disk_inode, block)
+                .into_iter()
+                .find(|dirent| !dirent.is_free() && start + dirent.offset >= pos)
+                .map(|dirent| (start + dirent.offset, String::from(dirent.name())))
         })
     }
 
-    // 将数据读到buf中
+    // 将数据读到buf中。同一文件的多个读操作可以并行。
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
-        let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
+        let _guard = self.lock.read();
+        self.disk_inode().read_at(offset, buf, &self.block_device)
     }
 
-    // 将数据写入inode，并同步到磁盘上
+    // 将数据写入inode。数据先写在块缓存中，提交事务时才写回磁盘。
+    // 每个事务修改的块数不能超过日志的容量，因此大的写入被拆成多个事务，每个事务写入一部分数据。
+    // 崩溃时，写入的数据可能只有前一部分生效，但文件系统总是一致的。
+    // 写入期间一直持有inode的写锁，其他线程读到的要么是写入前、要么是写入后的内容。
+    // 写入位置在文件末尾之后时，原来的末尾和写入位置之间是空洞，不占用数据块。
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
-        let mut fs = self.fs.lock();
-        let size = self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
-        });
-        block_cache_sync_all();
-        size
+        let _guard = self.lock.write();
+        self.write_locked(offset, buf)
+    }
+
+    // 将数据写到文件末尾，返回写入的位置和写入的字节数。
+    // 读取文件大小和写入期间一直持有inode的写锁，多个线程同时追加时，写入的数据不会互相覆盖。
+    pub fn append(&self, buf: &[u8]) -> (usize, usize) {
+        let _guard = self.lock.write();
+        let offset = self.read_disk_inode(|disk_inode| disk_inode.size as usize);
+        (offset, self.write_locked(offset, buf))
+    }
+
+    // 写入数据，调用者需要持有inode的写锁
+    fn write_locked(&self, offset: usize, buf: &[u8]) -> usize {
+        let mut write_size = 0usize;
+        for chunk in buf.chunks(TXN_DATA_BLOCKS * BLOCK_SZ) {
+            let start = offset + write_size;
+            let txn = self.fs.begin_transaction();
+            write_size += self.update_disk_inode(|disk_inode| {
+                self.alloc_range(start, start + chunk.len(), disk_inode);
+                disk_inode.write_at(start, chunk, &self.block_device)
+            });
+            txn.commit();
+        }
+        write_size
     }
 
     // 在磁盘上清除inode的数据
     pub fn clear(&self) {
-        let mut fs = self.fs.lock();
-        self.modify_disk_inode(|disk_inode| {
-            let size = disk_inode.size;
-            // 释放inode所用的块
-            let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
-            assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
-            for data_block in data_blocks_dealloc.into_iter() {
-                fs.dealloc_data(data_block);
+        let _guard = self.lock.write();
+        self.shrink_locked(0);
+    }
+
+    // 将文件的大小改为size。缩小时释放多出的块，并将最后一块中新末尾之后的部分清零；
+    // 扩大时新增的部分是空洞，读出来是0，写入之前不占用数据块。
+    // 不是常规文件或size超过文件大小的上限时返回false。
+    pub fn truncate(&self, size: usize) -> bool {
+        if size > MAX_FILE_SIZE {
+            return false;
+        }
+        let _guard = self.lock.write();
+        if !self.read_disk_inode(DiskInode::is_file) {
+            return false;
+        }
+        if size >= self.read_disk_inode(|disk_inode| disk_inode.size as usize) {
+            let txn = self.fs.begin_transaction();
+            self.update_disk_inode(|disk_inode| disk_inode.size = size as u32);
+            txn.commit();
+            return true;
+        }
+        self.shrink_locked(size);
+        true
+    }
+
+    // 将数据缩小到size，调用者需要持有inode的写锁。
+    // 释放的块数没有上限，因此从文件末尾开始拆成多个事务，每个事务最多释放TXN_DATA_BLOCKS个数据块。
+    // 崩溃时，文件可能只缩小了一部分，但文件系统总是一致的。
+    fn shrink_locked(&self, size: usize) {
+        loop {
+            let txn = self.fs.begin_transaction();
+            let done = self.update_disk_inode(|disk_inode| {
+                let end = disk_inode.data_blocks() as usize;
+                let step =
+                    disk_inode.nth_allocated_before(end, TXN_DATA_BLOCKS, &self.block_device);
+                let new_size = size.max(step * BLOCK_SZ);
+                for data_block in disk_inode.decrease_size(new_size as u32, &self.block_device) {
+                    self.fs.dealloc_data(data_block);
+                }
+                new_size == size
+            });
+            if done {
+                self.zero_tail(size);
             }
-        });
-        block_cache_sync_all();
+            txn.commit();
+            if done {
+                break;
+            }
+        }
+    }
+
+    // 将最后一块中size之后的部分清零，之后再扩大文件时，原来的数据不能重新出现
+    fn zero_tail(&self, size: usize) {
+        if size % BLOCK_SZ == 0 {
+            return;
+        }
+        let block_id =
+            self.read_disk_inode(|d| d.get_block_id((size / BLOCK_SZ) as u32, &self.block_device));
+        if block_id != 0 {
+            get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| {
+                    data_block[size % BLOCK_SZ..].fill(0)
+                });
+        }
     }
 }
@@ -1,7 +1,19 @@
 use crate::config::VIRT_PLIC;
-use crate::drivers::block::BLOCK_DEVICE;
+use crate::drivers::block::{BLOCK_DEVICE, BLOCK_DEVICE2};
 use crate::drivers::chardev::{CharDevice, UART};
 use crate::drivers::plic::{IntrTargetPriority, PLIC};
+use crate::sync::UPIntrFreeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+// 要响应的外设中断号（Interrupt Request）和对应的设备
+const IRQS: [(usize, &str); 4] = [(5, "keyboard"), (6, "vdb"), (8, "vda"), (10, "uart")];
+
+lazy_static! {
+    // 每个外设中断发生的次数，与IRQS一一对应
+    static ref IRQ_COUNTS: UPIntrFreeCell<[usize; IRQS.len()]> =
+        unsafe { UPIntrFreeCell::new([0; IRQS.len()]) };
+}
 
 // 初始化PLIC和sie寄存器，使其能够响应外设中断
 pub fn device_init() {
@@ -13,9 +25,9 @@ pub fn device_init() {
     // 设置M和S特权级下，PLIC要响应的外设中断阈值
     plic.set_threshold(hart_id, supervisor, 0);
     plic.set_threshold(hart_id, machine, 1);
-    // S特权级下，允许PLIC传递键盘/鼠标/块设备/串口外设中断
-    // irq（Interrupt Request）编号: 5 键盘、 6 鼠标、8 块设备、10 uart（串口）
-    for intr_src_id in [5usize, 6, 8, 10] {
+    // S特权级下，允许PLIC传递键盘/第二个块设备/块设备/串口外设中断
+    // irq（Interrupt Request）编号: 5 键盘、 6 第二个块设备、8 块设备、10 uart（串口）
+    for (intr_src_id, _) in IRQS {
         plic.enable(hart_id, supervisor, intr_src_id);
         plic.set_priority(intr_src_id, 1);
     }
@@ -30,7 +42,13 @@ pub fn irq_handler() {
     let mut plic = unsafe { PLIC::new(VIRT_PLIC) };
     // 读取PLIC的Claim寄存器，获得接收到的外设中断号
     let intr_src_id = plic.claim(0, IntrTargetPriority::Supervisor);
+    // 外设中断到来的时刻难以预测，将其混入熵池
+    crate::random::add_entropy(intr_src_id as usize);
+    if let Some(index) = IRQS.iter().position(|(id, _)| *id == intr_src_id as usize) {
+        IRQ_COUNTS.exclusive_access()[index] += 1;
+    }
     match intr_src_id {
+        6 => BLOCK_DEVICE2.as_ref().unwrap().handle_irq(),
         8 => BLOCK_DEVICE.handle_irq(),
         10 => UART.handle_irq(),
         _ => panic!("unsupported IRQ {}", intr_src_id),
@@ -38,3 +56,12 @@ pub fn irq_handler() {
     // 通知PLIC中断已处理完毕
     plic.complete(0, IntrTargetPriority::Supervisor, intr_src_id);
 }
+
+// 每个外设中断的中断号、设备名和发生的次数
+pub fn irq_counts() -> Vec<(usize, &'static str, usize)> {
+    let counts = *IRQ_COUNTS.exclusive_access();
+    IRQS.iter()
+        .zip(counts)
+        .map(|(&(id, device), count)| (id, device, count))
+        .collect()
+}
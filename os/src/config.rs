@@ -1,12 +1,35 @@
 // 用户栈和内核栈的大小（KB）
 pub const USER_STACK_SIZE: usize = 4096;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
-pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
+// 内核堆的初始大小。堆空间耗尽时，会从物理页帧分配器申请页帧来扩容
+pub const KERNEL_HEAP_SIZE: usize = 0x10_0000;
+// 内核堆每次扩容时，至少申请的页数
+pub const KERNEL_HEAP_GROW_PAGES: usize = 64;
 // 页面大小为4KB
 pub const PAGE_SIZE: usize = 4096;
 // 需要12位才能表示页面的任意位置。这是页内偏移（Page Offset）的位长。
 pub const PAGE_SIZE_BITS: usize = 12;
 
+// 用户程序堆的起始地址。它与帧缓冲区（0x1000_0000）和mmap区域（0x1_0000_0000）都不重叠。
+pub const USER_HEAP_BASE: usize = 0x4000_0000;
+// 位置无关的可执行文件（ET_DYN）的加载地址
+pub const ET_DYN_BASE: usize = 0x2000_0000;
+
+// 开启地址空间布局随机化（ASLR）时，各区域起始地址的随机偏移范围（页数）
+pub const ASLR_STACK_PAGES: usize = 0x1000; // 16MB
+pub const ASLR_HEAP_PAGES: usize = 0x4000; // 64MB
+pub const ASLR_MMAP_PAGES: usize = 0x1_0000; // 256MB
+pub const ASLR_ET_DYN_PAGES: usize = 0x4000; // 64MB
+
+// 文件系统的块缓存最多缓存的块数（每块512字节）
+pub const BLOCK_CACHE_SIZE: usize = 256;
+// 周期性地将块缓存中的脏块写回磁盘的间隔（毫秒）
+pub const BLOCK_CACHE_FLUSH_INTERVAL_MS: usize = 5_000;
+// 目录项缓存最多缓存的项数
+pub const DENTRY_CACHE_SIZE: usize = 256;
+// 每个tmpfs实例中，文件数据最多占用的页数（16MB）
+pub const TMPFS_MAX_PAGES: usize = 4096;
+
 // 空间地址的高256GB存放（按高位到低位）：
 // - 跳板（Trampoline）：存放__alltraps和__restore代码，用于进入/退出Trap
 // - TrapContext：保存Trap的上下文
@@ -36,9 +59,13 @@ pub const MMIO: &[(usize, usize)] = &[
 pub const VIRT_PLIC: usize = 0xC00_0000;
 pub const VIRT_UART: usize = 0x1000_0000;
 
-// VirtIO设备的MMIO地址
+// VirtIO设备的MMIO地址。QEMU从最高的槽位开始，按命令行中的顺序为设备分配槽位。
 pub const VIRTIO0: usize = 0x10_008_000; // virtio_blk
-// pub const VIRTIO5: usize = 0x10_005_000; // virtio_keyboard
-// pub const VIRTIO6: usize = 0x10_006_000; // virtio_mouse
+                                         // pub const VIRTIO5: usize = 0x10_005_000; // virtio_keyboard
+pub const VIRTIO6: usize = 0x10_006_000; // 第二个virtio_blk（可选）
 pub const VIRTIO7: usize = 0x10_007_000; // virtio_gpu
-// pub const VIRTIO8: usize = 0x10_004_000; // virtio_net
+                                         // pub const VIRTIO8: usize = 0x10_004_000; // virtio_net
+
+// virtio-gpu显示的分辨率，即QEMU中virtio-gpu设备默认的1280x800。每个像素占4字节。
+pub const VIRTGPU_XRES: usize = 1280;
+pub const VIRTGPU_YRES: usize = 800;
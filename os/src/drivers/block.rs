@@ -3,6 +3,7 @@ use crate::sync::Condvar;
 use crate::sync::UPIntrFreeCell;
 use crate::task::schedule;
 
+use crate::config::{VIRTIO0, VIRTIO6};
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use easy_fs::BlockDevice;
@@ -10,14 +11,53 @@ use lazy_static::*;
 use virtio_drivers::{BlkResp, RespStatus, VirtIOBlk, VirtIOHeader};
 
 lazy_static! {
-    // 用于访问块设备的全局变量
-    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(VirtIOBlock::new());
+    // 用于访问块设备的全局变量，即存放根文件系统的磁盘vda
+    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(VirtIOBlock::new(VIRTIO0));
+    // 第二块磁盘vdb。只有启动QEMU时指定了第二个磁盘镜像才存在，可以挂载到根文件系统的目录下
+    pub static ref BLOCK_DEVICE2: Option<Arc<dyn BlockDevice>> = is_virtio_block(VIRTIO6)
+        .then(|| Arc::new(VirtIOBlock::new(VIRTIO6)) as Arc<dyn BlockDevice>);
     // 该字段表示，是否以非阻塞方式（中断）方式访问块设备
     pub static ref DEV_NON_BLOCKING_ACCESS: UPIntrFreeCell<bool> = unsafe {
         UPIntrFreeCell::new(false)
     };
 }
 
+// VirtIO MMIO寄存器中的魔数（"virt"）和块设备的设备类型
+const VIRTIO_MAGIC: u32 = 0x7472_6976;
+const VIRTIO_DEVICE_BLOCK: u32 = 2;
+
+// 检查MMIO地址addr处是否接入了VirtIO块设备。没有接入设备的槽位，设备类型为0。
+fn is_virtio_block(addr: usize) -> bool {
+    unsafe {
+        (addr as *const u32).read_volatile() == VIRTIO_MAGIC
+            && ((addr + 8) as *const u32).read_volatile() == VIRTIO_DEVICE_BLOCK
+    }
+}
+
+// VirtIO MMIO寄存器中，设备配置空间的偏移。块设备的配置空间以磁盘的扇区数开头。
+const VIRTIO_CONFIG: usize = 0x100;
+const SECTOR_SIZE: usize = 512;
+
+// 按名字查找块设备，用于挂载文件系统
+pub fn block_device_by_name(name: &str) -> Option<Arc<dyn BlockDevice>> {
+    match name {
+        "vda" => Some(BLOCK_DEVICE.clone()),
+        "vdb" => BLOCK_DEVICE2.clone(),
+        _ => None,
+    }
+}
+
+// 块设备的容量（字节）。设备不存在时返回None。
+pub fn block_device_size(name: &str) -> Option<usize> {
+    let addr = match name {
+        "vda" => VIRTIO0,
+        "vdb" if BLOCK_DEVICE2.is_some() => VIRTIO6,
+        _ => return None,
+    };
+    let sectors = unsafe { ((addr + VIRTIO_CONFIG) as *const u64).read_volatile() };
+    Some(sectors as usize * SECTOR_SIZE)
+}
+
 #[allow(unused)]
 pub fn block_device_test() {
     let block_device = BLOCK_DEVICE.clone();
@@ -42,12 +82,12 @@ struct VirtIOBlock {
 }
 
 impl VirtIOBlock {
-    pub fn new() -> Self {
+    // 初始化MMIO地址addr处的VirtIO块设备
+    pub fn new(addr: usize) -> Self {
         let virtio_blk = unsafe {
             UPIntrFreeCell::new(
                 // 以MMIO方式访问VirtIO块设备的寄存器，VirtIOHeader表示该组寄存器
-                VirtIOBlk::<VirtioHal>::new(&mut *(crate::config::VIRTIO0 as *mut VirtIOHeader))
-                    .unwrap(),
+                VirtIOBlk::<VirtioHal>::new(&mut *(addr as *mut VirtIOHeader)).unwrap(),
             )
         };
         let mut condvars = BTreeMap::new();
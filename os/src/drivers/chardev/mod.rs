@@ -5,14 +5,19 @@ use lazy_static::*;
 pub use ns16550a::NS16550a;
 
 use crate::config::VIRT_UART;
+use crate::sync::Waiter;
 
 pub type CharDeviceImpl = crate::drivers::chardev::NS16550a<VIRT_UART>;
 
 pub trait CharDevice {
     fn init(&self);
     fn read(&self) -> u8;
+    // 不阻塞地读取一个字符，没有数据时返回None
+    fn try_read(&self) -> Option<u8>;
     fn write(&self, ch: u8);
     fn handle_irq(&self);
+    // 是否有数据可以不阻塞地读取。waiter不为None时，在有新数据到达时唤醒它。
+    fn poll_read(&self, waiter: Option<&Arc<Waiter>>) -> bool;
 }
 
 lazy_static! {
@@ -3,9 +3,10 @@
 //! - ns16550a datasheet: https://datasheetspdf.com/pdf-file/605590/NationalSemiconductor/NS16550A/1
 //! - ns16450 datasheet: https://datasheetspdf.com/pdf-file/1311818/NationalSemiconductor/NS16450/1
 use super::CharDevice;
-use crate::sync::{Condvar, UPIntrFreeCell};
+use crate::sync::{Condvar, UPIntrFreeCell, WaitQueue, Waiter};
 use crate::task::schedule;
 use alloc::collections::VecDeque;
+use alloc::sync::Arc;
 use bitflags::*;
 use volatile::{ReadOnly, Volatile, WriteOnly};
 
@@ -113,6 +114,8 @@ struct NS16550aInner {
 pub struct NS16550a<const BASE_ADDR: usize> {
     inner: UPIntrFreeCell<NS16550aInner>,
     condvar: Condvar,
+    // 在poll中等待数据到达的任务。它们不读取数据，所以要全部唤醒。
+    poll_waiters: WaitQueue,
 }
 
 impl<const BASE_ADDR: usize> NS16550a<BASE_ADDR> {
@@ -124,6 +127,7 @@ impl<const BASE_ADDR: usize> NS16550a<BASE_ADDR> {
         Self {
             inner: unsafe { UPIntrFreeCell::new(inner) },
             condvar: Condvar::new(),
+            poll_waiters: WaitQueue::new(),
         }
     }
 
@@ -156,6 +160,11 @@ impl<const BASE_ADDR: usize> CharDevice for NS16550a<BASE_ADDR> {
         }
     }
 
+    fn try_read(&self) -> Option<u8> {
+        self.inner
+            .exclusive_session(|inner| inner.read_buffer.pop_front())
+    }
+
     fn write(&self, ch: u8) {
         let mut inner = self.inner.exclusive_access();
         // 写很简单，就是向MMIO起始地址开始，偏移为0的控制寄存器写入8位的字符。
@@ -173,6 +182,16 @@ impl<const BASE_ADDR: usize> CharDevice for NS16550a<BASE_ADDR> {
         if count > 0 {
             // 串口设备有数据可读，唤醒等待队列上的任务。
             self.condvar.signal();
+            self.poll_waiters.wake_all();
         }
     }
+
+    fn poll_read(&self, waiter: Option<&Arc<Waiter>>) -> bool {
+        self.inner.exclusive_session(|inner| {
+            if let Some(waiter) = waiter {
+                self.poll_waiters.register(waiter);
+            }
+            !inner.read_buffer.is_empty()
+        })
+    }
 }
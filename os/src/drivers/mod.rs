@@ -4,6 +4,6 @@ pub mod chardev;
 pub mod gpu;
 pub mod plic;
 
-pub use block::{BLOCK_DEVICE, DEV_NON_BLOCKING_ACCESS};
+pub use block::{block_device_by_name, block_device_size, BLOCK_DEVICE, DEV_NON_BLOCKING_ACCESS};
 pub use chardev::{CharDevice, UART};
 pub use gpu::GPU_DEVICE;
@@ -0,0 +1,127 @@
+//! 目录项缓存（dentry cache）。
+//!
+//! 按(文件系统, 目录的inode号, 名字)缓存目录查找的结果，解析常用的路径时不必每次都读取目录。
+//! 缓存的容量有限，满时换出最久未使用的项。
+
+use super::vfs::{superblock_id, Inode, InodeType};
+use crate::config::DENTRY_CACHE_SIZE;
+use crate::sync::UPIntrFreeCell;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+// 目录项的键：文件系统的标识、目录的inode号和名字
+type DentryKey = (usize, usize, String);
+
+struct DentryCache {
+    entries: BTreeMap<DentryKey, (Arc<dyn Inode>, u64)>,
+    // 按最近使用的时间排序的键，最前面的是最久未使用的
+    lru: BTreeMap<u64, DentryKey>,
+    // 逻辑时钟，每次访问加1
+    clock: u64,
+}
+
+impl DentryCache {
+    fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, key: &DentryKey) -> Option<Arc<dyn Inode>> {
+        let now = self.tick();
+        let (inode, stamp) = self.entries.get_mut(key)?;
+        let old = core::mem::replace(stamp, now);
+        let inode = inode.clone();
+        let key = self.lru.remove(&old).unwrap();
+        self.lru.insert(now, key);
+        Some(inode)
+    }
+
+    fn insert(&mut self, key: DentryKey, inode: Arc<dyn Inode>) {
+        self.remove(&key);
+        if self.entries.len() >= DENTRY_CACHE_SIZE {
+            if let Some((_, oldest)) = self.lru.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        let now = self.tick();
+        self.lru.insert(now, key.clone());
+        self.entries.insert(key, (inode, now));
+    }
+
+    fn remove(&mut self, key: &DentryKey) -> Option<Arc<dyn Inode>> {
+        let (inode, stamp) = self.entries.remove(key)?;
+        self.lru.remove(&stamp);
+        Some(inode)
+    }
+}
+
+lazy_static! {
+    static ref DENTRY_CACHE: UPIntrFreeCell<DentryCache> =
+        unsafe { UPIntrFreeCell::new(DentryCache::new()) };
+}
+
+fn key(dir: &Arc<dyn Inode>, name: &str) -> DentryKey {
+    (
+        superblock_id(&dir.superblock()),
+        dir.ino(),
+        name.to_string(),
+    )
+}
+
+// 在目录dir中查找name，优先使用缓存
+pub fn lookup(dir: &Arc<dyn Inode>, name: &str) -> Option<Arc<dyn Inode>> {
+    if !dir.superblock().cacheable() {
+        return dir.lookup(name);
+    }
+    let key = key(dir, name);
+    if let Some(inode) = DENTRY_CACHE.exclusive_access().get(&key) {
+        return Some(inode);
+    }
+    // 查找目录可能需要读磁盘，不能在持有缓存的锁时进行
+    let inode = dir.lookup(name)?;
+    DENTRY_CACHE.exclusive_access().insert(key, inode.clone());
+    Some(inode)
+}
+
+// 在目录dir中创建name，并加入缓存
+pub fn create(dir: &Arc<dyn Inode>, name: &str, type_: InodeType) -> Option<Arc<dyn Inode>> {
+    let inode = dir.create(name, type_)?;
+    DENTRY_CACHE
+        .exclusive_access()
+        .insert(key(dir, name), inode.clone());
+    Some(inode)
+}
+
+// 在目录dir中创建指向target的符号链接name，并加入缓存
+pub fn symlink(dir: &Arc<dyn Inode>, name: &str, target: &str) -> Option<Arc<dyn Inode>> {
+    let inode = dir.symlink(name, target)?;
+    DENTRY_CACHE
+        .exclusive_access()
+        .insert(key(dir, name), inode.clone());
+    Some(inode)
+}
+
+// 移出文件系统fs在缓存中的所有项。卸载文件系统前调用。
+pub fn purge(fs: usize) {
+    let mut cache = DENTRY_CACHE.exclusive_access();
+    let keys: Vec<DentryKey> = cache
+        .entries
+        .keys()
+        .filter(|key| key.0 == fs)
+        .cloned()
+        .collect();
+    let purged: Vec<_> = keys.iter().filter_map(|key| cache.remove(key)).collect();
+    drop(cache);
+    drop(purged);
+}
@@ -0,0 +1,451 @@
+//! devfs：把内核中的设备以文件的形式提供给用户程序，挂载在/dev上。
+//!
+//! 目录中的每一项对应一个设备，打开时得到读写该设备的DeviceFile，而不是普通文件的OSInode：
+//! - console、tty：串口终端
+//! - null、zero、random：丢弃写入的数据并读到文件末尾、读到0、读到随机数
+//! - vda、vdb：virtio块设备，按字节偏移读写，经过块缓存，与挂载在其上的文件系统看到的内容一致；
+//!   写入直接写到磁盘上，不在共享的块缓存中留下脏块
+//! - fb0：virtio-gpu的显存，可以读写，也可以通过mmap映射到用户空间
+//!
+//! 设备的控制命令（如终端的窗口大小、块设备的容量）通过ioctl系统调用访问。
+
+use super::vfs::{FileSystemType, Inode, InodeType, SuperBlock};
+use super::{File, OpenFlags, PollEvents, EAGAIN};
+use crate::config::{VIRTGPU_XRES, VIRTGPU_YRES};
+use crate::drivers::chardev::{CharDevice, UART};
+use crate::drivers::{block_device_by_name, block_device_size, GPU_DEVICE};
+use crate::mm::{translated_refmut, PhysAddr, UserBuffer};
+use crate::random::{add_entropy, random_u64};
+use crate::sync::{UPIntrFreeCell, Waiter};
+use crate::task::current_user_token;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cmp::min;
+use easy_fs::{block_cache_sync_all, read_block_cached, write_block_cached, BlockDevice, BLOCK_SZ};
+
+// ioctl命令，取值与Linux相同
+// 终端的窗口大小，参数为WinSize
+const TIOCGWINSZ: usize = 0x5413;
+// 块设备的容量（字节），参数为u64
+const BLKGETSIZE64: usize = 0x8008_1272;
+// 将块设备的脏块写回磁盘
+const BLKFLSBUF: usize = 0x1261;
+// 显示器的分辨率和像素格式，参数为FbVarScreenInfo
+const FBIOGET_VSCREENINFO: usize = 0x4600;
+// 将显存的内容显示出来
+const FBIOPAN_DISPLAY: usize = 0x4606;
+
+// 串口终端没有窗口，报告一个常见的默认大小
+const TTY_ROWS: u16 = 24;
+const TTY_COLS: u16 = 80;
+// 显存中每个像素的位数
+const FB_BITS_PER_PIXEL: u32 = 32;
+
+#[repr(C)]
+struct WinSize {
+    ws_row: u16,
+    ws_col: u16,
+    ws_xpixel: u16,
+    ws_ypixel: u16,
+}
+
+#[repr(C)]
+struct FbVarScreenInfo {
+    xres: u32,
+    yres: u32,
+    bits_per_pixel: u32,
+}
+
+pub struct DevfsType;
+
+impl FileSystemType for DevfsType {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+    fn requires_device(&self) -> bool {
+        false
+    }
+    fn mount(&self, _device: Option<Arc<dyn BlockDevice>>) -> Option<Arc<dyn SuperBlock>> {
+        Some(Arc::new(DevfsSuperBlock::new()))
+    }
+}
+
+#[derive(Clone)]
+enum Device {
+    Tty,
+    Null,
+    Zero,
+    Random,
+    // 块设备的名字和块设备
+    Block(&'static str, Arc<dyn BlockDevice>),
+    FrameBuffer,
+}
+
+impl Device {
+    // 可以按偏移读写的设备（块设备和显存）的大小，其他设备为0
+    fn size(&self) -> usize {
+        match self {
+            Device::Block(name, _) => block_device_size(name).unwrap_or(0),
+            Device::FrameBuffer => GPU_DEVICE.get_framebuffer().len(),
+            _ => 0,
+        }
+    }
+
+    // 从offset处读取数据到buf中，不超过设备的末尾。只用于块设备和显存。
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let size = self.size();
+        if offset >= size {
+            return 0;
+        }
+        let len = min(buf.len(), size - offset);
+        match self {
+            Device::Block(_, device) => {
+                let mut done = 0;
+                while done < len {
+                    let pos = offset + done;
+                    let block_len = min(BLOCK_SZ - pos % BLOCK_SZ, len - done);
+                    read_block_cached(
+                        device,
+                        pos / BLOCK_SZ,
+                        pos % BLOCK_SZ,
+                        &mut buf[done..done + block_len],
+                    );
+                    done += block_len;
+                }
+            }
+            Device::FrameBuffer => {
+                buf[..len].copy_from_slice(&GPU_DEVICE.get_framebuffer()[offset..offset + len])
+            }
+            _ => return 0,
+        }
+        len
+    }
+
+    // 将buf写入offset处，不超过设备的末尾。只用于块设备和显存。
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        let size = self.size();
+        if offset >= size {
+            return 0;
+        }
+        let len = min(buf.len(), size - offset);
+        match self {
+            Device::Block(_, device) => {
+                let mut done = 0;
+                while done < len {
+                    let pos = offset + done;
+                    let block_len = min(BLOCK_SZ - pos % BLOCK_SZ, len - done);
+                    write_block_cached(
+                        device,
+                        pos / BLOCK_SZ,
+                        pos % BLOCK_SZ,
+                        &buf[done..done + block_len],
+                    );
+                    done += block_len;
+                }
+            }
+            Device::FrameBuffer => {
+                GPU_DEVICE.get_framebuffer()[offset..offset + len].copy_from_slice(&buf[..len])
+            }
+            _ => return 0,
+        }
+        len
+    }
+}
+
+pub struct DevfsSuperBlock {
+    // 设备文件的名字和对应的设备，按目录中的顺序排列
+    devices: Vec<(&'static str, Device)>,
+}
+
+impl DevfsSuperBlock {
+    fn new() -> Self {
+        let mut devices = vec![
+            ("console", Device::Tty),
+            ("tty", Device::Tty),
+            ("null", Device::Null),
+            ("zero", Device::Zero),
+            ("random", Device::Random),
+        ];
+        for name in ["vda", "vdb"] {
+            if let Some(device) = block_device_by_name(name) {
+                devices.push((name, Device::Block(name, device)));
+            }
+        }
+        devices.push(("fb0", Device::FrameBuffer));
+        Self { devices }
+    }
+}
+
+impl SuperBlock for DevfsSuperBlock {
+    fn root_inode(self: Arc<Self>) -> Arc<dyn Inode> {
+        Arc::new(DevfsInode {
+            index: None,
+            sb: self,
+        })
+    }
+    fn sync(&self) {}
+}
+
+// devfs中的根目录（index为None）或一个设备文件（index为它在devices中的下标）
+pub struct DevfsInode {
+    index: Option<usize>,
+    sb: Arc<DevfsSuperBlock>,
+}
+
+impl DevfsInode {
+    fn device(&self) -> Option<&(&'static str, Device)> {
+        self.sb.devices.get(self.index?)
+    }
+}
+
+impl Inode for DevfsInode {
+    fn superblock(&self) -> Arc<dyn SuperBlock> {
+        self.sb.clone()
+    }
+    // 根目录的编号为1，设备文件从2开始编号
+    fn ino(&self) -> usize {
+        self.index.map_or(1, |index| index + 2)
+    }
+    fn inode_type(&self) -> InodeType {
+        match self.index {
+            None => InodeType::Dir,
+            Some(_) => InodeType::Device,
+        }
+    }
+    fn size(&self) -> usize {
+        match self.device() {
+            Some((_, device)) => device.size(),
+            None => self.sb.devices.len(),
+        }
+    }
+    // 设备只能打开后通过DeviceFile读写
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> usize {
+        0
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+    fn truncate(&self, _size: usize) -> bool {
+        false
+    }
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        if self.index.is_some() {
+            return None;
+        }
+        let index = self.sb.devices.iter().position(|(n, _)| *n == name)?;
+        Some(Arc::new(Self {
+            index: Some(index),
+            sb: self.sb.clone(),
+        }))
+    }
+    // 设备由内核提供，不能在devfs中创建或删除文件
+    fn create(&self, _name: &str, _type_: InodeType) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    fn symlink(&self, _name: &str, _target: &str) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    fn readlink(&self) -> Option<String> {
+        None
+    }
+    fn unlink(&self, _name: &str) -> bool {
+        false
+    }
+    fn list(&self) -> Vec<String> {
+        if self.index.is_some() {
+            return Vec::new();
+        }
+        self.sb
+            .devices
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+    fn open_device(&self, readable: bool, writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        let (name, device) = self.device()?.clone();
+        Some(Arc::new(DeviceFile::new(name, device, readable, writable)))
+    }
+}
+
+// 打开串口终端/dev/console，作为进程的标准输入、输出
+pub fn open_console(readable: bool, writable: bool) -> Arc<dyn File + Send + Sync> {
+    Arc::new(DeviceFile::new("console", Device::Tty, readable, writable))
+}
+
+// 一个被打开的设备文件
+pub struct DeviceFile {
+    name: &'static str,
+    device: Device,
+    readable: bool,
+    writable: bool,
+    // 块设备和显存的读写偏移量，其他设备不使用
+    offset: UPIntrFreeCell<usize>,
+    // 状态标志，只支持NONBLOCK。只有终端的读会等待，其他设备忽略它。
+    status: UPIntrFreeCell<OpenFlags>,
+}
+
+impl DeviceFile {
+    fn new(name: &'static str, device: Device, readable: bool, writable: bool) -> Self {
+        Self {
+            name,
+            device,
+            readable,
+            writable,
+            offset: unsafe { UPIntrFreeCell::new(0) },
+            status: unsafe { UPIntrFreeCell::new(OpenFlags::empty()) },
+        }
+    }
+
+    // 设备文件在/dev下的名字
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl File for DeviceFile {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn status_flags(&self) -> OpenFlags {
+        *self.status.exclusive_access()
+    }
+    fn set_status_flags(&self, flags: OpenFlags) -> bool {
+        if !(flags - OpenFlags::NONBLOCK).is_empty() {
+            return false;
+        }
+        *self.status.exclusive_access() = flags;
+        true
+    }
+    fn read(&self, mut buf: UserBuffer) -> isize {
+        match self.device {
+            // 每次只读取一个字符，没有输入时等待；设置了NONBLOCK时返回-EAGAIN
+            Device::Tty => match buf.buffers.first_mut().and_then(|b| b.first_mut()) {
+                Some(byte) => {
+                    if self.status_flags().contains(OpenFlags::NONBLOCK) {
+                        match UART.try_read() {
+                            Some(ch) => *byte = ch,
+                            None => return -EAGAIN,
+                        }
+                    } else {
+                        *byte = UART.read();
+                    }
+                    1
+                }
+                None => 0,
+            },
+            Device::Null => 0,
+            Device::Zero | Device::Random => {
+                for byte in buf.buffers.iter_mut().flat_map(|b| b.iter_mut()) {
+                    *byte = match self.device {
+                        Device::Zero => 0,
+                        _ => random_u64() as u8,
+                    };
+                }
+                buf.len() as isize
+            }
+            Device::Block(..) | Device::FrameBuffer => {
+                let mut offset = self.offset.exclusive_access();
+                let mut total_read_size = 0usize;
+                for slice in buf.buffers.iter_mut() {
+                    let read_size = self.device.read_at(*offset, slice);
+                    *offset += read_size;
+                    total_read_size += read_size;
+                    if read_size < slice.len() {
+                        break;
+                    }
+                }
+                total_read_size as isize
+            }
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> isize {
+        match self.device {
+            Device::Tty => {
+                for byte in buf.buffers.iter().flat_map(|b| b.iter()) {
+                    UART.write(*byte);
+                }
+                buf.len() as isize
+            }
+            Device::Null | Device::Zero => buf.len() as isize,
+            // 写入的数据被混入熵池
+            Device::Random => {
+                for byte in buf.buffers.iter().flat_map(|b| b.iter()) {
+                    add_entropy(*byte as usize);
+                }
+                buf.len() as isize
+            }
+            Device::Block(..) | Device::FrameBuffer => {
+                let mut offset = self.offset.exclusive_access();
+                let mut total_write_size = 0usize;
+                for slice in buf.buffers.iter() {
+                    let write_size = self.device.write_at(*offset, slice);
+                    *offset += write_size;
+                    total_write_size += write_size;
+                    // 到达设备末尾时，只返回已经写入的部分
+                    if write_size < slice.len() {
+                        break;
+                    }
+                }
+                total_write_size as isize
+            }
+        }
+    }
+    // 终端在串口收到输入时可读，其他设备总是可以读写
+    fn poll(&self, events: PollEvents, waiter: Option<&Arc<Waiter>>) -> PollEvents {
+        match self.device {
+            Device::Tty => {
+                let mut revents = PollEvents::OUT;
+                if self.readable && UART.poll_read(waiter) {
+                    revents |= PollEvents::IN;
+                }
+                revents & events
+            }
+            _ => events & (PollEvents::IN | PollEvents::OUT),
+        }
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        let token = current_user_token();
+        match (&self.device, cmd) {
+            (Device::Tty, TIOCGWINSZ) => {
+                *translated_refmut(token, arg as *mut WinSize) = WinSize {
+                    ws_row: TTY_ROWS,
+                    ws_col: TTY_COLS,
+                    ws_xpixel: 0,
+                    ws_ypixel: 0,
+                };
+            }
+            (Device::Block(..), BLKGETSIZE64) => {
+                *translated_refmut(token, arg as *mut u64) = self.device.size() as u64;
+            }
+            (Device::Block(..), BLKFLSBUF) => block_cache_sync_all(),
+            (Device::FrameBuffer, FBIOGET_VSCREENINFO) => {
+                *translated_refmut(token, arg as *mut FbVarScreenInfo) = FbVarScreenInfo {
+                    xres: VIRTGPU_XRES as u32,
+                    yres: VIRTGPU_YRES as u32,
+                    bits_per_pixel: FB_BITS_PER_PIXEL,
+                };
+            }
+            (Device::FrameBuffer, FBIOPAN_DISPLAY) => GPU_DEVICE.flush(),
+            _ => return -1,
+        }
+        0
+    }
+    fn mmap_region(&self) -> Option<(PhysAddr, usize)> {
+        match self.device {
+            Device::FrameBuffer => {
+                let fb = GPU_DEVICE.get_framebuffer();
+                Some((PhysAddr::from(fb.as_ptr() as usize), fb.len()))
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! 将easy-fs接入内核的VFS
+
+use super::vfs::{FileSystemType, Inode, InodeType, SuperBlock};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::{block_cache_sync_all, BlockDevice, EasyFileSystem};
+
+pub struct EasyFsType;
+
+impl FileSystemType for EasyFsType {
+    fn name(&self) -> &'static str {
+        "easyfs"
+    }
+    fn requires_device(&self) -> bool {
+        true
+    }
+    fn mount(&self, device: Option<Arc<dyn BlockDevice>>) -> Option<Arc<dyn SuperBlock>> {
+        let device = device?;
+        // 设备上不是easy-fs时（例如FAT32镜像）挂载失败，而不是按错误的格式解析
+        if !EasyFileSystem::probe(&device) {
+            return None;
+        }
+        let efs = EasyFileSystem::open(device);
+        Some(Arc::new(EasyFsSuperBlock { efs }))
+    }
+}
+
+pub struct EasyFsSuperBlock {
+    efs: Arc<EasyFileSystem>,
+}
+
+impl SuperBlock for EasyFsSuperBlock {
+    fn root_inode(self: Arc<Self>) -> Arc<dyn Inode> {
+        let inode = Arc::new(EasyFileSystem::root_inode(&self.efs));
+        Arc::new(EasyFsInode { inode, sb: self })
+    }
+    fn sync(&self) {
+        // 块缓存由所有easy-fs实例共享，这里将所有脏块一起写回
+        block_cache_sync_all();
+    }
+}
+
+pub struct EasyFsInode {
+    inode: Arc<easy_fs::Inode>,
+    sb: Arc<EasyFsSuperBlock>,
+}
+
+impl EasyFsInode {
+    fn wrap(&self, inode: Arc<easy_fs::Inode>) -> Arc<dyn Inode> {
+        Arc::new(Self {
+            inode,
+            sb: self.sb.clone(),
+        })
+    }
+}
+
+impl Inode for EasyFsInode {
+    fn superblock(&self) -> Arc<dyn SuperBlock> {
+        self.sb.clone()
+    }
+    fn ino(&self) -> usize {
+        self.inode.inode_id() as usize
+    }
+    fn inode_type(&self) -> InodeType {
+        if self.inode.is_dir() {
+            InodeType::Dir
+        } else if self.inode.is_symlink() {
+            InodeType::Symlink
+        } else {
+            InodeType::File
+        }
+    }
+    fn size(&self) -> usize {
+        self.inode.size() as usize
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        self.inode.read_at(offset, buf)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        self.inode.write_at(offset, buf)
+    }
+    fn append(&self, buf: &[u8]) -> (usize, usize) {
+        self.inode.append(buf)
+    }
+    fn truncate(&self, size: usize) -> bool {
+        self.inode.truncate(size)
+    }
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        self.inode.find(name).map(|inode| self.wrap(inode))
+    }
+    fn create(&self, name: &str, type_: InodeType) -> Option<Arc<dyn Inode>> {
+        let inode = match type_ {
+            InodeType::File => self.inode.create(name)?,
+            InodeType::Dir => self.inode.mkdir(name)?,
+            _ => return None,
+        };
+        Some(self.wrap(inode))
+    }
+    fn symlink(&self, name: &str, target: &str) -> Option<Arc<dyn Inode>> {
+        self.inode
+            .symlink(name, target)
+            .map(|inode| self.wrap(inode))
+    }
+    fn readlink(&self) -> Option<String> {
+        self.inode.readlink()
+    }
+    fn unlink(&self, name: &str) -> bool {
+        self.inode.unlink(name)
+    }
+    fn list(&self) -> Vec<String> {
+        self.inode.ls()
+    }
+    fn next_entry(&self, pos: usize) -> Option<(usize, String)> {
+        self.inode.next_dirent(pos)
+    }
+}
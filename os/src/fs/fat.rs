@@ -0,0 +1,106 @@
+//! 将FAT32文件系统接入内核的VFS
+
+use super::vfs::{FileSystemType, Inode, InodeType, SuperBlock};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::{block_cache_sync_all, BlockDevice, Fat32FileSystem, Fat32Inode};
+
+pub struct Fat32Type;
+
+impl FileSystemType for Fat32Type {
+    fn name(&self) -> &'static str {
+        "vfat"
+    }
+    fn requires_device(&self) -> bool {
+        true
+    }
+    fn mount(&self, device: Option<Arc<dyn BlockDevice>>) -> Option<Arc<dyn SuperBlock>> {
+        let fs = Fat32FileSystem::open(device?)?;
+        Some(Arc::new(Fat32SuperBlock { fs }))
+    }
+}
+
+pub struct Fat32SuperBlock {
+    fs: Arc<Fat32FileSystem>,
+}
+
+impl SuperBlock for Fat32SuperBlock {
+    fn root_inode(self: Arc<Self>) -> Arc<dyn Inode> {
+        let inode = Arc::new(Fat32FileSystem::root_inode(&self.fs));
+        Arc::new(Fat32FsInode { inode, sb: self })
+    }
+    fn sync(&self) {
+        // FAT表、FSInfo扇区和文件数据都经过块缓存
+        block_cache_sync_all();
+    }
+}
+
+pub struct Fat32FsInode {
+    inode: Arc<Fat32Inode>,
+    sb: Arc<Fat32SuperBlock>,
+}
+
+impl Fat32FsInode {
+    fn wrap(&self, inode: Arc<Fat32Inode>) -> Arc<dyn Inode> {
+        Arc::new(Self {
+            inode,
+            sb: self.sb.clone(),
+        })
+    }
+}
+
+impl Inode for Fat32FsInode {
+    fn superblock(&self) -> Arc<dyn SuperBlock> {
+        self.sb.clone()
+    }
+    fn ino(&self) -> usize {
+        self.inode.ino()
+    }
+    fn inode_type(&self) -> InodeType {
+        if self.inode.is_dir() {
+            InodeType::Dir
+        } else {
+            InodeType::File
+        }
+    }
+    fn size(&self) -> usize {
+        self.inode.size()
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        self.inode.read_at(offset, buf)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        self.inode.write_at(offset, buf)
+    }
+    fn truncate(&self, size: usize) -> bool {
+        self.inode.truncate(size)
+    }
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        self.inode.find(name).map(|inode| self.wrap(inode))
+    }
+    fn create(&self, name: &str, type_: InodeType) -> Option<Arc<dyn Inode>> {
+        let inode = match type_ {
+            InodeType::File => self.inode.create(name)?,
+            InodeType::Dir => self.inode.mkdir(name)?,
+            _ => return None,
+        };
+        Some(self.wrap(inode))
+    }
+    // FAT32没有符号链接
+    fn symlink(&self, _name: &str, _target: &str) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    fn readlink(&self) -> Option<String> {
+        None
+    }
+    fn unlink(&self, name: &str) -> bool {
+        self.inode.unlink(name)
+    }
+    fn list(&self) -> Vec<String> {
+        self.inode.ls()
+    }
+    fn next_entry(&self, pos: usize) -> Option<(usize, String)> {
+        self.inode.next_dirent(pos)
+    }
+}
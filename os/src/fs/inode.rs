@@ -1,14 +1,19 @@
 //! 将文件系统的inode包装成内核的inode，即OSInode。该类型供进程使用，表示一个被打开的文件。
+//! 以及解析路径，按路径打开、创建文件。
 
-use super::File;
+use super::lock::{inode_key, release_lock, InodeKey, LockOwner};
+use super::mount::{self, root_inode};
+use super::vfs::{Inode, InodeType};
+use super::{dcache, File};
+use crate::mm::MapPermission;
 use crate::mm::UserBuffer;
 use crate::sync::UPIntrFreeCell;
-use crate::{drivers::BLOCK_DEVICE, mm::MapPermission};
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
-use easy_fs::{EasyFileSystem, Inode};
-use lazy_static::*;
+use core::any::Any;
 
 // OSInode表示一个被打开的文件。多个进程可打开同一个文件。
 pub struct OSInode {
@@ -20,15 +25,23 @@ pub struct OSInode {
 pub struct OSInodeInner {
     // 进程读写文件的偏移量
     offset: usize,
-    inode: Arc<Inode>,
+    // 文件的状态标志，只有APPEND和NONBLOCK
+    status: OpenFlags,
+    inode: Arc<dyn Inode>,
 }
 
 impl OSInode {
-    pub fn new(readable: bool, writable: bool, inode: Arc<Inode>) -> Self {
+    pub fn new(readable: bool, writable: bool, inode: Arc<dyn Inode>) -> Self {
         Self {
             readable,
             writable,
-            inner: unsafe { UPIntrFreeCell::new(OSInodeInner { offset: 0, inode }) },
+            inner: unsafe {
+                UPIntrFreeCell::new(OSInodeInner {
+                    offset: 0,
+                    status: OpenFlags::empty(),
+                    inode,
+                })
+            },
         }
     }
 
@@ -48,10 +61,79 @@ impl OSInode {
         v
     }
 
-    pub fn clone_inner_inode(&self) -> Arc<Inode> {
+    // 当前的读写偏移量
+    pub fn offset(&self) -> usize {
+        self.inner.exclusive_access().offset
+    }
+
+    // 下一次写入的位置。指定了APPEND时，总是写在文件末尾。
+    pub fn write_offset(&self) -> usize {
+        let inner = self.inner.exclusive_access();
+        if inner.status.contains(OpenFlags::APPEND) {
+            inner.inode.size()
+        } else {
+            inner.offset
+        }
+    }
+
+    // 文件的inode号和当前的读写偏移量。
+    // 文件正在被读写时返回None，例如读取procfs中列出当前进程打开的文件时，该文件本身正在被读取。
+    pub fn try_ino_and_offset(&self) -> Option<(usize, usize)> {
+        let inner = self.inner.try_exclusive_access()?;
+        Some((inner.inode.ino(), inner.offset))
+    }
+
+    // 从当前偏移量开始读取目录项，按getdents64的格式（linux_dirent64）编码，总长度不超过len。
+    // 目录的偏移量是下一项在目录中的位置（见Inode::next_entry），增删其他项时不会失效，
+    // 因此多次调用依次返回目录中的各项，已有的项不会重复或遗漏。
+    // 不是目录，或者len放不下下一个目录项时返回None；已经读完时返回空的Vec。
+    pub fn read_dirents(&self, len: usize) -> Option<Vec<u8>> {
+        let mut inner = self.inner.exclusive_access();
+        if !inner.inode.is_dir() {
+            return None;
+        }
+        let mut buf = Vec::new();
+        while let Some((pos, name)) = inner.inode.next_entry(inner.offset) {
+            // 找到之后被删除的项直接跳过
+            let Some(child) = dcache::lookup(&inner.inode, &name) else {
+                inner.offset = pos + 1;
+                continue;
+            };
+            // 记录头是8字节的inode号、8字节的偏移量、2字节的记录长度和1字节的类型，
+            // 之后是以\0结尾的名字，整个记录按8字节对齐
+            let reclen = (DIRENT_HEADER_SIZE + name.len() + 1 + 7) & !7;
+            if buf.len() + reclen > len {
+                if buf.is_empty() {
+                    return None;
+                }
+                break;
+            }
+            inner.offset = pos + 1;
+            let start = buf.len();
+            buf.extend_from_slice(&(child.ino() as u64).to_le_bytes());
+            buf.extend_from_slice(&(inner.offset as i64).to_le_bytes());
+            buf.extend_from_slice(&(reclen as u16).to_le_bytes());
+            buf.push(dirent_type(child.inode_type()));
+            buf.extend_from_slice(name.as_bytes());
+            buf.resize(start + reclen, 0);
+        }
+        Some(buf)
+    }
+
+    pub fn clone_inner_inode(&self) -> Arc<dyn Inode> {
         self.inner.exclusive_access().inode.clone()
     }
 
+    // 文件上的锁所属的inode
+    pub fn lock_key(&self) -> InodeKey {
+        inode_key(&self.inner.exclusive_access().inode)
+    }
+
+    // 这个打开的文件作为flock锁的持有者
+    pub fn flock_owner(&self) -> LockOwner {
+        LockOwner::File(self as *const Self as usize)
+    }
+
     pub fn map_permission(&self) -> MapPermission {
         if self.readable && self.writable {
             MapPermission::R | MapPermission::W
@@ -65,18 +147,36 @@ impl OSInode {
     }
 }
 
-lazy_static! {
-    // 根目录的inode
-    pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
-        Arc::new(EasyFileSystem::root_inode(&efs))
-    };
+// 引用该打开文件的最后一个描述符被关闭时，释放它的flock锁
+impl Drop for OSInode {
+    fn drop(&mut self) {
+        release_lock(self.lock_key(), self.flock_owner(), 0, usize::MAX);
+    }
+}
+
+// getdents64返回的目录项记录中，名字之前的部分的长度
+const DIRENT_HEADER_SIZE: usize = 19;
+
+// 目录项记录中的文件类型（d_type）
+const DT_CHR: u8 = 2;
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+const DT_LNK: u8 = 10;
+
+fn dirent_type(type_: InodeType) -> u8 {
+    match type_ {
+        InodeType::File => DT_REG,
+        InodeType::Dir => DT_DIR,
+        InodeType::Symlink => DT_LNK,
+        // devfs中的设备都按字符设备报告
+        InodeType::Device => DT_CHR,
+    }
 }
 
 // 列出根目录下的所有应用程序
 pub fn list_apps() {
     println!("/**** APPS ****");
-    for app in ROOT_INODE.ls() {
+    for app in root_inode().list() {
         println!("{}", app);
     }
     println!("**************/");
@@ -88,8 +188,14 @@ bitflags! {
         const RDONLY = 0;       // 只读
         const WRONLY = 1 << 0;  // 只写
         const RDWR = 1 << 1;    // 读写
-        const CREATE = 1 << 9;  // 创建。如果文件存在，则截断文件
+        const EXCL = 1 << 7;    // 与CREATE一起使用，文件已经存在时打开失败
+        const CREATE = 1 << 9;  // 文件不存在时创建它
         const TRUNC = 1 << 10;  // 截断，即删除文件中原有的内容
+        const NONBLOCK = 1 << 11;   // 读写不阻塞
+        const APPEND = 1 << 12;     // 每次写入前，将偏移量移到文件末尾
+        const DIRECTORY = 1 << 16;  // 路径指向的不是目录时，打开失败
+        const NOFOLLOW = 1 << 17;   // 路径的最后一个分量是符号链接时，打开失败
+        const CLOEXEC = 1 << 19;    // 为文件描述符设置FD_CLOEXEC，执行新程序时关闭它
     }
 }
 
@@ -104,28 +210,180 @@ impl OpenFlags {
             (true, true)
         }
     }
+
+    // 打开文件后仍然有效，可以用fcntl修改的状态标志
+    pub fn status(&self) -> Self {
+        *self & (Self::APPEND | Self::NONBLOCK)
+    }
 }
 
-// 打开根目录下的一个文件
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
-    let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
-            // 如果文件存在，则清空文件
-            inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
+// 解析一个路径最多跟随的符号链接数，超过时认为符号链接形成了循环
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+// 路径解析的结果
+enum Walk {
+    // 找到了路径指向的inode，位于栈顶
+    Found,
+    // 路径的最后一个分量不存在，栈顶是它所在的目录
+    Missing(String),
+}
+
+// 从stack栈顶的目录出发解析path，stack中依次是从根目录到当前inode的各级inode，用于处理".."。
+// 经过挂载点时，进入挂载在它上面的文件系统；在被挂载的文件系统的根目录处，".."回到挂载点所在的目录。
+// follow为false时，不跟随最后一个分量的符号链接。follows记录已经跟随的符号链接数。
+// 路径无效、中间的分量不存在或符号链接过多时返回None。
+fn walk(
+    stack: &mut Vec<Arc<dyn Inode>>,
+    path: &str,
+    follow: bool,
+    follows: &mut usize,
+) -> Option<Walk> {
+    if path.is_empty() {
+        return None;
+    }
+    if path.starts_with('/') {
+        stack.truncate(1);
+    }
+    let names: Vec<&str> = path
+        .split('/')
+        .filter(|name| !name.is_empty() && *name != ".")
+        .collect();
+    for (i, name) in names.iter().enumerate() {
+        let last = i + 1 == names.len();
+        let dir = stack.last().unwrap().clone();
+        if !dir.is_dir() {
+            return None;
+        }
+        if *name == ".." {
+            if stack.len() > 1 {
+                stack.pop();
+            }
+            continue;
+        }
+        let Some(inode) = dcache::lookup(&dir, name) else {
+            return last.then(|| Walk::Missing(String::from(*name)));
+        };
+        let inode = mount::covering(inode);
+        if inode.is_symlink() && (follow || !last) {
+            *follows += 1;
+            if *follows > MAX_SYMLINK_FOLLOWS {
+                return None;
+            }
+            // 相对路径的目标从符号链接所在的目录开始解析
+            let target = inode.readlink()?;
+            if let Walk::Missing(name) = walk(stack, &target, true, follows)? {
+                // 悬空的符号链接：只有最后一个分量可以指向不存在的文件
+                return last.then_some(Walk::Missing(name));
+            }
         } else {
-            ROOT_INODE
-                .create(name)
-                .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
+            stack.push(inode);
         }
-    } else {
-        ROOT_INODE.find(name).map(|inode| {
+    }
+    Some(Walk::Found)
+}
+
+// 解析从根目录开始的路径，跟随路径中的符号链接。
+// follow为false时，不跟随最后一个分量的符号链接，返回符号链接本身。
+pub fn lookup(path: &str, follow: bool) -> Option<Arc<dyn Inode>> {
+    let mut stack = vec![root_inode()];
+    match walk(&mut stack, path, follow, &mut 0)? {
+        Walk::Found => stack.pop(),
+        Walk::Missing(_) => None,
+    }
+}
+
+// 按路径找到要打开的inode，文件不存在且指定了CREATE时创建它。
+// 路径中的符号链接会被跟随，指定NOFOLLOW时，最后一个分量不能是符号链接。
+// 指定DIRECTORY时，路径必须指向一个已经存在的目录。
+// 同时指定CREATE和EXCL时，路径必须不存在，最后一个分量是符号链接时也失败，即使它指向的文件不存在。
+fn open_inode(path: &str, flags: OpenFlags) -> Option<Arc<dyn Inode>> {
+    let (_, writable) = flags.read_write();
+    let mut stack = vec![root_inode()];
+    let exclusive = flags.contains(OpenFlags::CREATE | OpenFlags::EXCL);
+    let follow = !flags.contains(OpenFlags::NOFOLLOW) && !exclusive;
+    let inode = match walk(&mut stack, path, follow, &mut 0)? {
+        Walk::Found => {
+            let inode = stack.pop().unwrap();
+            if inode.is_symlink() || exclusive {
+                return None;
+            }
+            if flags.contains(OpenFlags::DIRECTORY) && !inode.is_dir() {
+                return None;
+            }
+            // 目录不能以写的方式打开
+            if inode.is_dir() && (writable || flags.contains(OpenFlags::TRUNC)) {
+                return None;
+            }
+            // 指定TRUNC时清空已经存在的文件
             if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
+                inode.truncate(0);
             }
-            Arc::new(OSInode::new(readable, writable, inode))
-        })
+            inode
+        }
+        Walk::Missing(name)
+            if flags.contains(OpenFlags::CREATE) && !flags.contains(OpenFlags::DIRECTORY) =>
+        {
+            dcache::create(stack.last().unwrap(), &name, InodeType::File)?
+        }
+        Walk::Missing(_) => return None,
+    };
+    Some(inode)
+}
+
+// 打开一个常规文件或目录，用于内核自己读取文件（如加载应用程序）。不能打开设备文件。
+pub fn open_file(path: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+    let (readable, writable) = flags.read_write();
+    let inode = open_inode(path, flags)?;
+    if inode.inode_type() == InodeType::Device {
+        return None;
+    }
+    Some(Arc::new(OSInode::new(readable, writable, inode)))
+}
+
+// 为进程打开一个文件。设备文件得到读写该设备的File对象，其他文件得到OSInode。
+// 文件不支持flags中的状态标志时打开失败。
+pub fn open(path: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+    let (readable, writable) = flags.read_write();
+    let inode = open_inode(path, flags)?;
+    let file: Arc<dyn File + Send + Sync> = if inode.inode_type() == InodeType::Device {
+        inode.open_device(readable, writable)?
+    } else {
+        Arc::new(OSInode::new(readable, writable, inode))
+    };
+    file.set_status_flags(flags.status()).then_some(file)
+}
+
+// 在路径path处创建一个指向target的符号链接。path已经存在时失败。
+pub fn create_symlink(target: &str, path: &str) -> Option<Arc<dyn Inode>> {
+    let mut stack = vec![root_inode()];
+    match walk(&mut stack, path, false, &mut 0)? {
+        Walk::Found => None,
+        Walk::Missing(name) => dcache::symlink(stack.last().unwrap(), &name, target),
+    }
+}
+
+// 在路径path处创建一个目录。path已经存在时失败。
+pub fn create_dir(path: &str) -> Option<Arc<dyn Inode>> {
+    let mut stack = vec![root_inode()];
+    match walk(&mut stack, path, false, &mut 0)? {
+        Walk::Found => None,
+        Walk::Missing(name) => dcache::create(stack.last().unwrap(), &name, InodeType::Dir),
+    }
+}
+
+// 将块设备source上、类型为fs_type的文件系统挂载到目录target上
+pub fn mount_at(source: &str, target: &str, fs_type: &str) -> bool {
+    match lookup(target, true) {
+        Some(dir) => mount::mount(source, &dir, fs_type),
+        None => false,
+    }
+}
+
+// 卸载挂载在target上的文件系统
+pub fn umount_at(target: &str) -> bool {
+    match lookup(target, true) {
+        Some(root) => mount::umount(&root),
+        None => false,
     }
 }
 
@@ -136,7 +394,17 @@ impl File for OSInode {
     fn writable(&self) -> bool {
         self.writable
     }
-    fn read(&self, mut buf: UserBuffer) -> usize {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn status_flags(&self) -> OpenFlags {
+        self.inner.exclusive_access().status
+    }
+    fn set_status_flags(&self, flags: OpenFlags) -> bool {
+        self.inner.exclusive_access().status = flags;
+        true
+    }
+    fn read(&self, mut buf: UserBuffer) -> isize {
         let mut inner = self.inner.exclusive_access();
         let mut total_read_size = 0usize;
         for slice in buf.buffers.iter_mut() {
@@ -147,17 +415,30 @@ impl File for OSInode {
             inner.offset += read_size;
             total_read_size += read_size;
         }
-        total_read_size
+        total_read_size as isize
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    // 指定了APPEND时，由文件系统追加到文件末尾，其他打开该文件的进程同时写入时也不会覆盖。
+    // 跨页的用户缓冲区先拼成一段再追加，不会被其他进程的写入从中间分开。
+    fn write(&self, buf: UserBuffer) -> isize {
         let mut inner = self.inner.exclusive_access();
+        if inner.status.contains(OpenFlags::APPEND) {
+            let (offset, write_size) = match buf.buffers.as_slice() {
+                [slice] => inner.inode.append(slice),
+                slices => inner.inode.append(&slices.concat()),
+            };
+            inner.offset = offset + write_size;
+            return write_size as isize;
+        }
         let mut total_write_size = 0usize;
         for slice in buf.buffers.iter() {
             let write_size = inner.inode.write_at(inner.offset, *slice);
-            assert_eq!(write_size, slice.len());
             inner.offset += write_size;
             total_write_size += write_size;
+            // 文件系统已满或不可写时，只返回已经写入的部分
+            if write_size < slice.len() {
+                break;
+            }
         }
-        total_write_size
+        total_write_size as isize
     }
 }
@@ -0,0 +1,226 @@
+//! 文件的建议锁（advisory lock）。锁只在获取锁时互相检查，不阻止没有获取锁的读写。
+//!
+//! 有两种锁，它们互相独立、不会冲突：
+//! - flock锁：锁住整个文件，属于打开的文件（OSInode）。复制的文件描述符和fork出的子进程共享同一把锁，
+//!   引用该打开文件的最后一个描述符被关闭时释放。
+//! - fcntl记录锁：锁住文件中的一段字节，属于进程。进程关闭该文件的任意一个描述符或退出时释放。
+//!
+//! 获取锁可以阻塞等待。只检测记录锁之间的死锁：flock锁的持有者是打开的文件，可能被多个进程共享，
+//! 无法确定在等待它的是哪个进程。
+
+use super::vfs::{superblock_id, Inode};
+use crate::sync::UPIntrFreeCell;
+use crate::task::{
+    block_current_and_run_next, current_process, current_task, wakeup_task, TaskControlBlock,
+};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+// 锁的持有者
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum LockOwner {
+    // flock锁的持有者是打开的文件，用OSInode的地址标识
+    File(usize),
+    // 记录锁的持有者是进程，用PID标识
+    Process(usize),
+}
+
+// 文件上的一把锁
+#[derive(Copy, Clone)]
+pub struct FileLock {
+    pub owner: LockOwner,
+    // 独占锁（写锁）还是共享锁（读锁）
+    pub exclusive: bool,
+    // 锁住的字节范围[start, end)。end为usize::MAX时，锁住start之后的所有字节，包括文件以后扩大的部分。
+    pub start: usize,
+    pub end: usize,
+}
+
+impl FileLock {
+    fn is_flock(&self) -> bool {
+        matches!(self.owner, LockOwner::File(_))
+    }
+
+    // 两把锁是否冲突：不同持有者的同种锁，范围重叠，且至少有一把是独占锁
+    fn conflicts(&self, other: &FileLock) -> bool {
+        self.is_flock() == other.is_flock()
+            && self.owner != other.owner
+            && (self.exclusive || other.exclusive)
+            && self.start < other.end
+            && other.start < self.end
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+// fcntl的F_GETLK、F_SETLK、F_SETLKW命令使用的记录锁描述，与Linux的struct flock相同
+pub struct Flock {
+    // 锁的类型：F_RDLCK、F_WRLCK或F_UNLCK
+    pub l_type: i16,
+    // l_start的起点：SEEK_SET、SEEK_CUR或SEEK_END
+    pub l_whence: i16,
+    pub l_start: i64,
+    // 锁住的字节数。0表示到文件末尾之后的所有字节，负数表示l_start之前的字节。
+    pub l_len: i64,
+    // F_GETLK返回的、持有冲突的锁的进程
+    pub l_pid: i32,
+}
+
+// inode的标识：所在文件系统的超级块，和inode在文件系统内的编号
+pub type InodeKey = (usize, usize);
+
+pub fn inode_key(inode: &Arc<dyn Inode>) -> InodeKey {
+    (superblock_id(&inode.superblock()), inode.ino())
+}
+
+// 一个阻塞等待锁的任务
+struct Waiter {
+    task: Arc<TaskControlBlock>,
+    pid: usize,
+    key: InodeKey,
+    lock: FileLock,
+}
+
+struct LockTable {
+    // 每个inode上被持有的锁。没有锁的inode不在表中。
+    locks: BTreeMap<InodeKey, Vec<FileLock>>,
+    waiters: Vec<Waiter>,
+}
+
+impl LockTable {
+    // inode上第一把与lock冲突的锁
+    fn conflict(&self, key: InodeKey, lock: &FileLock) -> Option<FileLock> {
+        self.locks
+            .get(&key)?
+            .iter()
+            .find(|held| held.conflicts(lock))
+            .copied()
+    }
+
+    // 进程pid阻塞等待lock是否会导致死锁：沿着“持有冲突的锁的进程正在等待的锁”查找，
+    // 最终回到pid自己持有的锁时，这些进程会互相等待下去
+    fn would_deadlock(&self, pid: usize, key: InodeKey, lock: &FileLock) -> bool {
+        if lock.is_flock() {
+            return false;
+        }
+        let mut pending = vec![(key, *lock)];
+        let mut visited: Vec<usize> = Vec::new();
+        while let Some((key, lock)) = pending.pop() {
+            let Some(locks) = self.locks.get(&key) else {
+                continue;
+            };
+            for held in locks.iter().filter(|held| held.conflicts(&lock)) {
+                let LockOwner::Process(holder) = held.owner else {
+                    continue;
+                };
+                if holder == pid {
+                    return true;
+                }
+                if visited.contains(&holder) {
+                    continue;
+                }
+                visited.push(holder);
+                pending.extend(
+                    self.waiters
+                        .iter()
+                        .filter(|waiter| waiter.pid == holder)
+                        .map(|waiter| (waiter.key, waiter.lock)),
+                );
+            }
+        }
+        false
+    }
+
+    // 删除owner在inode上持有的锁中，[start, end)范围内的部分。跨过范围边界的锁被拆分。
+    // 然后唤醒等待该inode的任务，让它们重新尝试获取锁。
+    fn remove(&mut self, key: InodeKey, owner: LockOwner, start: usize, end: usize) {
+        if let Some(locks) = self.locks.get_mut(&key) {
+            let mut kept = Vec::new();
+            for held in locks.drain(..) {
+                if held.owner != owner || held.end <= start || end <= held.start {
+                    kept.push(held);
+                    continue;
+                }
+                if held.start < start {
+                    kept.push(FileLock { end: start, ..held });
+                }
+                if end < held.end {
+                    kept.push(FileLock { start: end, ..held });
+                }
+            }
+            if kept.is_empty() {
+                self.locks.remove(&key);
+            } else {
+                *locks = kept;
+            }
+        }
+        self.wake(key);
+    }
+
+    fn wake(&mut self, key: InodeKey) {
+        let (woken, waiting): (Vec<Waiter>, Vec<Waiter>) =
+            self.waiters.drain(..).partition(|waiter| waiter.key == key);
+        self.waiters = waiting;
+        for waiter in woken {
+            wakeup_task(waiter.task);
+        }
+    }
+}
+
+lazy_static! {
+    static ref LOCK_TABLE: UPIntrFreeCell<LockTable> = unsafe {
+        UPIntrFreeCell::new(LockTable {
+            locks: BTreeMap::new(),
+            waiters: Vec::new(),
+        })
+    };
+}
+
+// 在inode上获取锁lock。持有者在范围内已有的锁被替换，可以借此将共享锁升级为独占锁，或反过来。
+// 与其他持有者的锁冲突时，wait为false则立即返回false；否则阻塞到冲突的锁被释放，
+// 但等待会导致死锁时返回false。
+pub fn acquire_lock(key: InodeKey, lock: FileLock, wait: bool) -> bool {
+    let pid = current_process().getpid();
+    loop {
+        let mut table = LOCK_TABLE.exclusive_access();
+        if table.conflict(key, &lock).is_none() {
+            table.remove(key, lock.owner, lock.start, lock.end);
+            table.locks.entry(key).or_default().push(lock);
+            return true;
+        }
+        if !wait || table.would_deadlock(pid, key, &lock) {
+            return false;
+        }
+        table.waiters.push(Waiter {
+            task: current_task().unwrap(),
+            pid,
+            key,
+            lock,
+        });
+        drop(table);
+        block_current_and_run_next();
+    }
+}
+
+// 释放owner在inode上[start, end)范围内的锁
+pub fn release_lock(key: InodeKey, owner: LockOwner, start: usize, end: usize) {
+    LOCK_TABLE.exclusive_access().remove(key, owner, start, end);
+}
+
+// inode上第一把与lock冲突的锁。没有冲突时返回None，即lock可以被获取。
+pub fn conflicting_lock(key: InodeKey, lock: &FileLock) -> Option<FileLock> {
+    LOCK_TABLE.exclusive_access().conflict(key, lock)
+}
+
+// 进程pid退出：释放它的所有记录锁，并丢弃它的线程的等待
+pub fn release_process_locks(pid: usize) {
+    let mut table = LOCK_TABLE.exclusive_access();
+    table.waiters.retain(|waiter| waiter.pid != pid);
+    let keys: Vec<InodeKey> = table.locks.keys().copied().collect();
+    for key in keys {
+        table.remove(key, LockOwner::Process(pid), 0, usize::MAX);
+    }
+}
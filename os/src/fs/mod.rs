@@ -1,17 +1,103 @@
-use crate::mm::UserBuffer;
+use crate::config::BLOCK_CACHE_FLUSH_INTERVAL_MS;
+use crate::mm::{PhysAddr, UserBuffer};
+use crate::sync::{UPIntrFreeCell, Waiter};
+use crate::timer::get_time_ms;
+use alloc::sync::Arc;
+use core::any::Any;
+use lazy_static::*;
 
+mod dcache;
+mod devfs;
+mod efs;
+mod fat;
 mod inode;
+mod lock;
+mod mount;
 mod pipe;
-mod stdio;
+mod poll;
+mod procfs;
+mod tmpfs;
+mod vfs;
 
-pub use inode::{list_apps, open_file, OSInode, OpenFlags, ROOT_INODE};
+pub use devfs::open_console;
+pub use inode::{
+    create_dir, create_symlink, list_apps, lookup, mount_at, open, open_file, umount_at, OSInode,
+    OpenFlags,
+};
+pub use lock::{
+    acquire_lock, conflicting_lock, release_lock, release_process_locks, FileLock, Flock, LockOwner,
+};
+pub use mount::sync_all;
 pub use pipe::make_pipe;
-pub use stdio::{Stdin, Stdout};
+pub use poll::{wait_ready, Epoll, EpollEvent, PollEvents};
+pub use vfs::{Inode, InodeType};
+
+// 读写文件失败时返回的错误码（返回值为它的相反数），与Linux相同
+pub const EAGAIN: isize = 11; // 文件设置了NONBLOCK，读写需要等待
+pub const EPIPE: isize = 32; // 管道的读端已经全部关闭
 
 // 内核的文件抽象
 pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
-    fn read(&self, buf: UserBuffer) -> usize;
-    fn write(&self, buf: UserBuffer) -> usize;
+    // 转换为Any，以便向下转型为具体的文件类型，如OSInode
+    fn as_any(&self) -> &dyn Any;
+    // 读写文件，返回读写的字节数，失败时返回负数的错误码
+    fn read(&self, buf: UserBuffer) -> isize;
+    fn write(&self, buf: UserBuffer) -> isize;
+    // 文件的状态标志（APPEND、NONBLOCK），由fcntl的F_GETFL读取
+    fn status_flags(&self) -> OpenFlags {
+        OpenFlags::empty()
+    }
+    // 修改文件的状态标志，flags中只有APPEND和NONBLOCK。不支持这些标志的文件只能清除它们，否则返回false。
+    fn set_status_flags(&self, flags: OpenFlags) -> bool {
+        flags.is_empty()
+    }
+    // 文件是否就绪：返回events中现在可以不阻塞地进行的读写（IN、OUT），以及已经发生的ERR和HUP。
+    // waiter不为None时，还要在文件的状态改变、可能变为就绪时唤醒它。普通文件总是可以读写。
+    fn poll(&self, events: PollEvents, _waiter: Option<&Arc<Waiter>>) -> PollEvents {
+        events & (PollEvents::IN | PollEvents::OUT)
+    }
+    // 设备的控制命令，arg通常是用户空间中参数结构的地址。不支持的命令返回-1。
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> isize {
+        -1
+    }
+    // 可以直接映射到用户空间的物理内存区域（起始物理地址，长度），如显存。
+    // 普通文件通过文件映射按页读入，返回None。
+    fn mmap_region(&self) -> Option<(PhysAddr, usize)> {
+        None
+    }
+}
+
+// 挂载内核提供的文件系统：在/tmp上挂载tmpfs，在/proc上挂载procfs，在/dev上挂载devfs。
+// 根文件系统中没有这些目录时先创建它们。
+pub fn init() {
+    for (target, fs_type) in [("/tmp", "tmpfs"), ("/proc", "proc"), ("/dev", "devfs")] {
+        if lookup(target, true).is_none() {
+            create_dir(target);
+        }
+        if !mount_at("none", target, fs_type) {
+            println_kernel!("failed to mount {} on {}", fs_type, target);
+        }
+    }
+}
+
+lazy_static! {
+    // 上次周期性写回脏块的时间（毫秒）
+    static ref LAST_FLUSH_MS: UPIntrFreeCell<usize> = unsafe { UPIntrFreeCell::new(0) };
+}
+
+// 每隔BLOCK_CACHE_FLUSH_INTERVAL_MS，将块缓存中的脏块写回磁盘。
+// 在系统调用返回用户态之前调用，此时当前任务不持有文件系统的锁，写回时可以等待磁盘。
+// 正在被其他任务使用的缓存块会被跳过，留到下一次写回。
+pub fn flush_block_cache_periodically() {
+    let now = get_time_ms();
+    {
+        let mut last = LAST_FLUSH_MS.exclusive_access();
+        if now - *last < BLOCK_CACHE_FLUSH_INTERVAL_MS {
+            return;
+        }
+        *last = now;
+    }
+    easy_fs::block_cache_try_sync_all();
 }
@@ -0,0 +1,174 @@
+//! 挂载表。
+//!
+//! 文件系统挂载在某个目录（挂载点）上之后，解析路径时经过该目录，会进入被挂载文件系统的根目录。
+//! 挂载点按(文件系统, inode号)记录，因此不依赖于目录项缓存中的对象。
+
+use super::dcache;
+use super::devfs::DevfsType;
+use super::efs::EasyFsType;
+use super::fat::Fat32Type;
+use super::procfs::ProcfsType;
+use super::tmpfs::TmpfsType;
+use super::vfs::{superblock_id, FileSystemType, Inode, SuperBlock};
+use crate::config::BLOCK_CACHE_SIZE;
+use crate::drivers::{block_device_by_name, BLOCK_DEVICE};
+use crate::sync::UPIntrFreeCell;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use easy_fs::init_block_cache;
+use lazy_static::*;
+
+// 一个已挂载的文件系统
+struct Mount {
+    // 挂载点：被覆盖的目录所在文件系统的标识和inode号。根文件系统没有挂载点。
+    mountpoint: Option<(usize, usize)>,
+    // 文件系统的来源，即块设备的名字。不需要块设备的文件系统为"none"。
+    source: String,
+    sb: Arc<dyn SuperBlock>,
+    root: Arc<dyn Inode>,
+}
+
+impl Mount {
+    fn new(mountpoint: Option<(usize, usize)>, source: &str, sb: Arc<dyn SuperBlock>) -> Self {
+        Self {
+            mountpoint,
+            source: source.to_string(),
+            root: sb.clone().root_inode(),
+            sb,
+        }
+    }
+}
+
+lazy_static! {
+    // 内核支持的文件系统类型
+    static ref FS_TYPES: Vec<Arc<dyn FileSystemType>> = vec![
+        Arc::new(EasyFsType),
+        Arc::new(Fat32Type),
+        Arc::new(TmpfsType),
+        Arc::new(ProcfsType),
+        Arc::new(DevfsType),
+    ];
+    // 挂载表，按挂载的先后顺序排列。第一项是根文件系统，即磁盘vda上的easy-fs或FAT32。
+    static ref MOUNTS: UPIntrFreeCell<Vec<Mount>> = {
+        init_block_cache(BLOCK_CACHE_SIZE);
+        // 依次尝试需要块设备的文件系统类型，直到某一个能识别vda上的超级块
+        let sb = FS_TYPES
+            .iter()
+            .filter(|t| t.requires_device())
+            .find_map(|t| t.mount(Some(BLOCK_DEVICE.clone())))
+            .expect("no root file system on vda");
+        unsafe { UPIntrFreeCell::new(vec![Mount::new(None, "vda", sb)]) }
+    };
+}
+
+// inode在挂载表中的标识
+fn inode_id(inode: &Arc<dyn Inode>) -> (usize, usize) {
+    (superblock_id(&inode.superblock()), inode.ino())
+}
+
+// 根文件系统的根目录
+pub fn root_inode() -> Arc<dyn Inode> {
+    MOUNTS.exclusive_access()[0].root.clone()
+}
+
+// 如果inode是挂载点，返回挂载在它上面的文件系统的根目录；否则返回inode本身。
+// 同一个挂载点上挂载了多个文件系统时，返回最后挂载的那个。
+pub fn covering(inode: Arc<dyn Inode>) -> Arc<dyn Inode> {
+    let mut id = inode_id(&inode);
+    let mut inode = inode;
+    loop {
+        let mounts = MOUNTS.exclusive_access();
+        let Some(mount) = mounts
+            .iter()
+            .rev()
+            .find(|mount| mount.mountpoint == Some(id))
+        else {
+            return inode;
+        };
+        inode = mount.root.clone();
+        id = (superblock_id(&mount.sb), inode.ino());
+    }
+}
+
+// 将块设备source上、类型为fs_type的文件系统挂载到目录target上。
+// 不需要块设备的文件系统忽略source。
+// 文件系统类型不存在、块设备不存在或已被挂载、target不是目录时返回false。
+pub fn mount(source: &str, target: &Arc<dyn Inode>, fs_type: &str) -> bool {
+    if !target.is_dir() {
+        return false;
+    }
+    let Some(fs_type) = FS_TYPES.iter().find(|t| t.name() == fs_type) else {
+        return false;
+    };
+    let device = if fs_type.requires_device() {
+        match block_device_by_name(source) {
+            Some(device) if !device_mounted(source) => Some(device),
+            _ => return false,
+        }
+    } else {
+        None
+    };
+    // 读取超级块需要访问块设备，不能在持有挂载表的锁时进行
+    let Some(sb) = fs_type.mount(device) else {
+        return false;
+    };
+    let mut mounts = MOUNTS.exclusive_access();
+    // 读取超级块期间，其他任务可能挂载了同一个块设备
+    if fs_type.requires_device() && mounts.iter().any(|m| m.source == source) {
+        return false;
+    }
+    mounts.push(Mount::new(Some(inode_id(target)), source, sb));
+    true
+}
+
+fn device_mounted(source: &str) -> bool {
+    MOUNTS.exclusive_access().iter().any(|m| m.source == source)
+}
+
+// 卸载根目录为target的文件系统。
+// target不是某个文件系统的根目录、是根文件系统、其上还挂载着其他文件系统，
+// 或其中还有文件被打开时返回false。
+pub fn umount(target: &Arc<dyn Inode>) -> bool {
+    let id = inode_id(target);
+    let fs = id.0;
+    let find = |mounts: &Vec<Mount>| {
+        mounts
+            .iter()
+            .position(|mount| superblock_id(&mount.sb) == fs && mount.root.ino() == id.1)
+    };
+    match find(&MOUNTS.exclusive_access()) {
+        Some(index) if index > 0 => {}
+        _ => return false,
+    }
+    // 目录项缓存中的inode也持有超级块的引用，先将它们移出缓存
+    dcache::purge(fs);
+    let mut mounts = MOUNTS.exclusive_access();
+    let Some(index) = find(&mounts) else {
+        return false;
+    };
+    if mounts.iter().any(|m| m.mountpoint.map(|p| p.0) == Some(fs)) {
+        return false;
+    }
+    // 挂载表和根目录的inode各持有一个超级块的引用，其余的引用来自被打开的文件等
+    if Arc::strong_count(&mounts[index].sb) > 2 {
+        return false;
+    }
+    let mount = mounts.remove(index);
+    drop(mounts);
+    mount.sb.sync();
+    true
+}
+
+// 将所有已挂载文件系统的修改写回存储设备
+pub fn sync_all() {
+    let sbs: Vec<_> = MOUNTS
+        .exclusive_access()
+        .iter()
+        .map(|mount| mount.sb.clone())
+        .collect();
+    for sb in sbs {
+        sb.sync();
+    }
+}
@@ -1,6 +1,11 @@
-use super::File;
-use crate::{mm::UserBuffer, sync::UPIntrFreeCell, task::suspend_current_and_run_next};
+use super::{File, OpenFlags, PollEvents, EAGAIN, EPIPE};
+use crate::{
+    mm::UserBuffer,
+    sync::{UPIntrFreeCell, WaitQueue, Waiter},
+    task::{current_add_signal, SignalFlags},
+};
 use alloc::sync::{Arc, Weak};
+use core::any::Any;
 
 const RING_BUFFER_SIZE: usize = 32;
 
@@ -8,6 +13,8 @@ const RING_BUFFER_SIZE: usize = 32;
 pub struct Pipe {
     readable: bool,
     writable: bool,
+    // 状态标志，只支持NONBLOCK
+    status: UPIntrFreeCell<OpenFlags>,
     // 不同的管道可能共用同一个环形缓冲区，所以要用Arc
     buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>,
 }
@@ -26,7 +33,12 @@ pub struct PipeRingBuffer {
     head: usize,
     tail: usize,
     status: RingBufferStatus,
-    write_end: Option<Weak<Pipe>>, // 使用Weak，避免循环引用
+    read_end: Option<Weak<Pipe>>, // 使用Weak，避免循环引用
+    write_end: Option<Weak<Pipe>>,
+    // 等待缓冲区中有数据可读（或写端全部关闭）的任务
+    readers: WaitQueue,
+    // 等待缓冲区中有空间可写的任务
+    writers: WaitQueue,
 }
 
 impl Pipe {
@@ -34,6 +46,7 @@ impl Pipe {
         Self {
             readable: true,
             writable: false,
+            status: unsafe { UPIntrFreeCell::new(OpenFlags::empty()) },
             buffer,
         }
     }
@@ -41,9 +54,14 @@ impl Pipe {
         Self {
             readable: false,
             writable: true,
+            status: unsafe { UPIntrFreeCell::new(OpenFlags::empty()) },
             buffer,
         }
     }
+
+    fn nonblock(&self) -> bool {
+        self.status.exclusive_access().contains(OpenFlags::NONBLOCK)
+    }
 }
 
 impl File for Pipe {
@@ -53,11 +71,54 @@ impl File for Pipe {
     fn writable(&self) -> bool {
         self.writable
     }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn status_flags(&self) -> OpenFlags {
+        *self.status.exclusive_access()
+    }
+    fn set_status_flags(&self, flags: OpenFlags) -> bool {
+        if !(flags - OpenFlags::NONBLOCK).is_empty() {
+            return false;
+        }
+        *self.status.exclusive_access() = flags;
+        true
+    }
+    // 读端在缓冲区有数据时可读，写端全部关闭时报告HUP；
+    // 写端在缓冲区有空间时可写，读端全部关闭时报告ERR
+    fn poll(&self, events: PollEvents, waiter: Option<&Arc<Waiter>>) -> PollEvents {
+        let ring_buffer = self.buffer.exclusive_access();
+        let mut revents = PollEvents::empty();
+        if self.readable {
+            if ring_buffer.available_read() > 0 {
+                revents |= PollEvents::IN;
+            }
+            if ring_buffer.all_write_ends_closed() {
+                revents |= PollEvents::HUP;
+            }
+            if let Some(waiter) = waiter {
+                ring_buffer.readers.register(waiter);
+            }
+        }
+        if self.writable {
+            if ring_buffer.available_write() > 0 {
+                revents |= PollEvents::OUT;
+            }
+            if ring_buffer.all_read_ends_closed() {
+                revents |= PollEvents::ERR;
+            }
+            if let Some(waiter) = waiter {
+                ring_buffer.writers.register(waiter);
+            }
+        }
+        revents & (events | PollEvents::ERR | PollEvents::HUP)
+    }
 
     // 从管道中读出数据
     // 如果写端已经关闭，则读出剩余可读的数据。
     // 如果写端尚未关闭，则会等待，使得最后读出数据的长度为buf.len()。
-    fn read(&self, buf: UserBuffer) -> usize {
+    // 设置了NONBLOCK时不等待，只读出已有的数据；一个字节都没有读到时返回-EAGAIN。
+    fn read(&self, buf: UserBuffer) -> isize {
         assert!(self.readable());
         let want_to_read = buf.len();
         let mut buf_iter = buf.into_iter();
@@ -67,11 +128,20 @@ impl File for Pipe {
             let loop_read = ring_buffer.available_read();
             if loop_read == 0 {
                 if ring_buffer.all_write_ends_closed() {
-                    return already_read;
+                    return already_read as isize;
                 }
+                if self.nonblock() {
+                    return if already_read > 0 {
+                        already_read as isize
+                    } else {
+                        -EAGAIN
+                    };
+                }
+                // 队列中没有数据，且写端尚未关闭时，则阻塞到有数据写入或写端关闭
+                let waiter = Waiter::new();
+                ring_buffer.readers.register(&waiter);
                 drop(ring_buffer);
-                // 队列中没有数据，且写端尚未关闭时，则让出CPU
-                suspend_current_and_run_next();
+                waiter.wait();
                 continue;
             }
             for _ in 0..loop_read {
@@ -82,26 +152,48 @@ impl File for Pipe {
                     }
                     already_read += 1;
                     if already_read == want_to_read {
-                        return want_to_read;
+                        return want_to_read as isize;
                     }
                 } else {
-                    return already_read;
+                    return already_read as isize;
                 }
             }
         }
     }
 
-    fn write(&self, buf: UserBuffer) -> usize {
+    // 向管道写入数据，缓冲区已满时等待读端读出数据。
+    // 设置了NONBLOCK时不等待，只写入缓冲区能容纳的部分；一个字节都没有写入时返回-EAGAIN。
+    // 读端已经全部关闭时，向进程发送SIGPIPE，返回已经写入的字节数；一个字节都没有写入时返回-EPIPE。
+    fn write(&self, buf: UserBuffer) -> isize {
         assert!(self.writable());
         let want_to_write = buf.len();
         let mut buf_iter = buf.into_iter();
         let mut already_write = 0usize;
         loop {
             let mut ring_buffer = self.buffer.exclusive_access();
+            if ring_buffer.all_read_ends_closed() {
+                drop(ring_buffer);
+                current_add_signal(SignalFlags::SIGPIPE);
+                return if already_write > 0 {
+                    already_write as isize
+                } else {
+                    -EPIPE
+                };
+            }
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
+                if self.nonblock() {
+                    return if already_write > 0 {
+                        already_write as isize
+                    } else {
+                        -EAGAIN
+                    };
+                }
+                // 队列已满，阻塞到有数据被读出或读端关闭
+                let waiter = Waiter::new();
+                ring_buffer.writers.register(&waiter);
                 drop(ring_buffer);
-                suspend_current_and_run_next();
+                waiter.wait();
                 continue;
             }
             // write at most loop_write bytes
@@ -111,16 +203,30 @@ impl File for Pipe {
                     ring_buffer.write_byte(unsafe { *byte_ref });
                     already_write += 1;
                     if already_write == want_to_write {
-                        return want_to_write;
+                        return want_to_write as isize;
                     }
                 } else {
-                    return already_write;
+                    return already_write as isize;
                 }
             }
         }
     }
 }
 
+// 写端的最后一个引用被释放时，唤醒等待读的任务，它们将读到文件末尾；
+// 读端的最后一个引用被释放时，唤醒等待写的任务，它们将收到SIGPIPE
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        let ring_buffer = self.buffer.exclusive_access();
+        if self.writable {
+            ring_buffer.readers.wake_all();
+        }
+        if self.readable {
+            ring_buffer.writers.wake_all();
+        }
+    }
+}
+
 impl Default for PipeRingBuffer {
     fn default() -> Self {
         Self::new()
@@ -134,15 +240,27 @@ impl PipeRingBuffer {
             head: 0,
             tail: 0,
             status: RingBufferStatus::Empty,
+            read_end: None,
             write_end: None,
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
         }
     }
 
+    pub fn set_read_end(&mut self, read_end: &Arc<Pipe>) {
+        self.read_end = Some(Arc::downgrade(read_end));
+    }
+
     pub fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
         self.write_end = Some(Arc::downgrade(write_end));
     }
 
+    // 向管道写入一个字节。缓冲区原来为空时，唤醒等待读的任务。
+    // 使用前需要先检查管道是否已满。
     pub fn write_byte(&mut self, byte: u8) {
+        if self.status == RingBufferStatus::Empty {
+            self.readers.wake_all();
+        }
         self.status = RingBufferStatus::Normal;
         self.arr[self.tail] = byte;
         self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
@@ -151,9 +269,12 @@ impl PipeRingBuffer {
         }
     }
 
-    // 从管道中读取一个字节。
+    // 从管道中读取一个字节。缓冲区原来已满时，唤醒等待写的任务。
     // 使用前需要先检查管道是否为空。
     pub fn read_byte(&mut self) -> u8 {
+        if self.status == RingBufferStatus::Full {
+            self.writers.wake_all();
+        }
         self.status = RingBufferStatus::Normal;
         let c = self.arr[self.head];
         self.head = (self.head + 1) % RING_BUFFER_SIZE;
@@ -187,6 +308,10 @@ impl PipeRingBuffer {
         // 查看是否还有写端的强引用
         self.write_end.as_ref().unwrap().upgrade().is_none()
     }
+
+    pub fn all_read_ends_closed(&self) -> bool {
+        self.read_end.as_ref().unwrap().upgrade().is_none()
+    }
 }
 
 // 创建新的管道，返回它的读端和写端
@@ -194,6 +319,9 @@ pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
     let buffer = Arc::new(unsafe { UPIntrFreeCell::new(PipeRingBuffer::new()) });
     let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
     let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
-    buffer.exclusive_access().set_write_end(&write_end);
+    let mut ring_buffer = buffer.exclusive_access();
+    ring_buffer.set_read_end(&read_end);
+    ring_buffer.set_write_end(&write_end);
+    drop(ring_buffer);
     (read_end, write_end)
 }
@@ -0,0 +1,179 @@
+//! 等待多个文件就绪：poll、select和epoll共用的部分。
+//!
+//! 每个文件通过File::poll报告它现在是否可以不阻塞地读写。等待时，任务的Waiter被注册到所有文件的等待队列中，
+//! 任意一个文件的状态改变或超时都会唤醒任务，然后重新检查所有的文件。
+
+use super::File;
+use crate::mm::UserBuffer;
+use crate::sync::{UPIntrFreeCell, Waiter};
+use crate::timer::get_time_ms;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use bitflags::*;
+use core::any::Any;
+
+bitflags! {
+    // 文件的就绪事件，取值与Linux的POLLIN、EPOLLIN等相同
+    pub struct PollEvents: u32 {
+        const IN = 1 << 0;      // 可以不阻塞地读
+        const OUT = 1 << 2;     // 可以不阻塞地写
+        const ERR = 1 << 3;     // 出错，如管道的读端已经全部关闭。总是会被报告。
+        const HUP = 1 << 4;     // 挂断，如管道的写端已经全部关闭。总是会被报告。
+        const NVAL = 1 << 5;    // 文件描述符没有打开，只由poll报告
+    }
+}
+
+// 等待check报告有文件就绪，返回就绪的文件数。
+// check(waiter)检查所有的文件，返回就绪的文件数。waiter不为None时，还要将它注册到所有文件的等待队列中。
+// timeout_ms为None时一直等待，为Some(0)时只检查一次，不等待。超时时返回0。
+pub fn wait_ready<F>(timeout_ms: Option<usize>, mut check: F) -> usize
+where
+    F: FnMut(Option<&Arc<Waiter>>) -> usize,
+{
+    let expire_ms = timeout_ms.map(|ms| get_time_ms() + ms);
+    let ready = check(None);
+    if ready > 0 || timeout_ms == Some(0) {
+        return ready;
+    }
+    loop {
+        // 先注册再检查：检查之后发生的事件一定会唤醒任务
+        let waiter = Waiter::new();
+        let ready = check(Some(&waiter));
+        if ready > 0 {
+            waiter.cancel();
+            return ready;
+        }
+        if let Some(expire_ms) = expire_ms {
+            if get_time_ms() >= expire_ms {
+                waiter.cancel();
+                return 0;
+            }
+            waiter.set_timeout(expire_ms);
+        }
+        waiter.wait();
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+// epoll_ctl和epoll_wait使用的事件描述，与Linux（RISC-V）的struct epoll_event相同
+pub struct EpollEvent {
+    pub events: u32,
+    // 用户数据，epoll_wait原样返回
+    pub data: u64,
+}
+
+// epoll关注的一个文件
+struct EpollItem {
+    // 关闭文件的所有描述符后，文件自动从关注列表中移除
+    file: Weak<dyn File + Send + Sync>,
+    events: PollEvents,
+    data: u64,
+}
+
+// epoll实例：关注的文件描述符的列表。只支持水平触发，即文件就绪时，每次epoll_wait都会报告它。
+pub struct Epoll {
+    items: UPIntrFreeCell<BTreeMap<usize, EpollItem>>,
+}
+
+impl Epoll {
+    pub fn new() -> Self {
+        Self {
+            items: unsafe { UPIntrFreeCell::new(BTreeMap::new()) },
+        }
+    }
+
+    // 关注文件描述符fd。fd已经被关注时返回false。
+    pub fn add(&self, fd: usize, file: &Arc<dyn File + Send + Sync>, event: EpollEvent) -> bool {
+        let mut items = self.items.exclusive_access();
+        if items
+            .get(&fd)
+            .is_some_and(|item| item.file.strong_count() > 0)
+        {
+            return false;
+        }
+        items.insert(
+            fd,
+            EpollItem {
+                file: Arc::downgrade(file),
+                events: PollEvents::from_bits_truncate(event.events),
+                data: event.data,
+            },
+        );
+        true
+    }
+
+    // 修改关注的事件和用户数据。fd没有被关注时返回false。
+    pub fn modify(&self, fd: usize, event: EpollEvent) -> bool {
+        let mut items = self.items.exclusive_access();
+        match items.get_mut(&fd) {
+            Some(item) if item.file.strong_count() > 0 => {
+                item.events = PollEvents::from_bits_truncate(event.events);
+                item.data = event.data;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // 不再关注fd。fd没有被关注时返回false。
+    pub fn remove(&self, fd: usize) -> bool {
+        let mut items = self.items.exclusive_access();
+        items
+            .remove(&fd)
+            .is_some_and(|item| item.file.strong_count() > 0)
+    }
+
+    // 检查关注的文件，将就绪的文件和事件放入ready中，最多max_events个
+    pub fn check(
+        &self,
+        waiter: Option<&Arc<Waiter>>,
+        ready: &mut Vec<EpollEvent>,
+        max_events: usize,
+    ) {
+        ready.clear();
+        let mut items = self.items.exclusive_access();
+        items.retain(|_, item| item.file.strong_count() > 0);
+        for item in items.values() {
+            let Some(file) = item.file.upgrade() else {
+                continue;
+            };
+            let revents = file.poll(item.events, waiter);
+            if !revents.is_empty() && ready.len() < max_events {
+                ready.push(EpollEvent {
+                    events: revents.bits(),
+                    data: item.data,
+                });
+            }
+        }
+    }
+}
+
+impl File for Epoll {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn read(&self, _buf: UserBuffer) -> isize {
+        0
+    }
+    fn write(&self, _buf: UserBuffer) -> isize {
+        0
+    }
+    // 有关注的文件就绪时，epoll实例可读
+    fn poll(&self, events: PollEvents, waiter: Option<&Arc<Waiter>>) -> PollEvents {
+        let mut ready = Vec::new();
+        self.check(waiter, &mut ready, 1);
+        if ready.is_empty() {
+            PollEvents::empty()
+        } else {
+            events & PollEvents::IN
+        }
+    }
+}
@@ -0,0 +1,483 @@
+//! procfs：反映进程和内核状态的虚拟文件系统，挂载在/proc上。
+//!
+//! 其中的文件不占用存储空间，每次读取时根据进程控制块、物理页帧分配器等内核数据结构即时生成内容。
+//! 目录结构如下：
+//! - /proc/meminfo、/proc/uptime、/proc/interrupts：内存的使用情况、开机以来的时间、各个中断发生的次数
+//! - /proc/self：指向当前进程的目录的符号链接
+//! - /proc/<pid>/status、maps、fd、cmdline：进程的状态、地址空间、打开的文件和命令行参数
+//! - /proc/<pid>/task/<tid>/status：线程的状态
+
+use super::devfs::DeviceFile;
+use super::pipe::Pipe;
+use super::poll::Epoll;
+use super::vfs::{FileSystemType, Inode, InodeType, SuperBlock};
+use super::{File, OSInode};
+use crate::board::irq_counts;
+use crate::config::PAGE_SIZE;
+use crate::mm::{frame_stats, heap_stats, MapPermission, VirtAddr};
+use crate::task::{current_process, pid2process, pids, TaskStatus};
+use crate::timer::{get_time_ms, timer_ticks};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::fmt::Write;
+use easy_fs::BlockDevice;
+
+pub struct ProcfsType;
+
+impl FileSystemType for ProcfsType {
+    fn name(&self) -> &'static str {
+        "proc"
+    }
+    fn requires_device(&self) -> bool {
+        false
+    }
+    fn mount(&self, _device: Option<Arc<dyn BlockDevice>>) -> Option<Arc<dyn SuperBlock>> {
+        Some(Arc::new(ProcfsSuperBlock))
+    }
+}
+
+pub struct ProcfsSuperBlock;
+
+impl SuperBlock for ProcfsSuperBlock {
+    fn root_inode(self: Arc<Self>) -> Arc<dyn Inode> {
+        Arc::new(ProcfsInode {
+            node: Node::Root,
+            sb: self,
+        })
+    }
+    fn sync(&self) {}
+    // 进程随时创建和退出，目录的内容每次都要重新生成
+    fn cacheable(&self) -> bool {
+        false
+    }
+}
+
+// procfs中的一个节点。进程和线程的节点只记录pid和tid，访问时再查找进程控制块，
+// 因此进程退出后，已经打开的节点读不到内容。
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Node {
+    Root,
+    Meminfo,
+    Uptime,
+    Interrupts,
+    SelfLink,
+    Process(usize),
+    Status(usize),
+    Maps(usize),
+    Fd(usize),
+    Cmdline(usize),
+    Tasks(usize),
+    Task(usize, usize),
+    TaskStatus(usize, usize),
+}
+
+// 根目录下的全局文件
+const ROOT_ENTRIES: [(&str, Node); 4] = [
+    ("meminfo", Node::Meminfo),
+    ("uptime", Node::Uptime),
+    ("interrupts", Node::Interrupts),
+    ("self", Node::SelfLink),
+];
+
+// 进程目录下的各项
+const PROCESS_ENTRIES: [&str; 5] = ["status", "maps", "fd", "cmdline", "task"];
+
+impl Node {
+    // 全局节点的编号为1~5。进程和线程的节点，编号的高32位是pid+1，接下来是tid+1，低8位区分节点的种类。
+    fn ino(&self) -> usize {
+        let (pid, tid, kind) = match *self {
+            Node::Root => return 1,
+            Node::Meminfo => return 2,
+            Node::Uptime => return 3,
+            Node::Interrupts => return 4,
+            Node::SelfLink => return 5,
+            Node::Process(pid) => (pid, 0, 0),
+            Node::Status(pid) => (pid, 0, 1),
+            Node::Maps(pid) => (pid, 0, 2),
+            Node::Fd(pid) => (pid, 0, 3),
+            Node::Cmdline(pid) => (pid, 0, 4),
+            Node::Tasks(pid) => (pid, 0, 5),
+            Node::Task(pid, tid) => (pid, tid + 1, 6),
+            Node::TaskStatus(pid, tid) => (pid, tid + 1, 7),
+        };
+        ((pid + 1) << 32) | (tid << 8) | kind
+    }
+
+    fn inode_type(&self) -> InodeType {
+        match self {
+            Node::Root | Node::Process(_) | Node::Tasks(_) | Node::Task(..) => InodeType::Dir,
+            Node::SelfLink => InodeType::Symlink,
+            _ => InodeType::File,
+        }
+    }
+
+    // 目录中的各项。节点不是目录，或进程已经退出时为空。
+    fn entries(&self) -> Vec<(String, Node)> {
+        match *self {
+            Node::Root => {
+                let mut entries: Vec<_> = ROOT_ENTRIES
+                    .iter()
+                    .map(|(name, node)| (name.to_string(), *node))
+                    .collect();
+                entries.extend(
+                    pids()
+                        .into_iter()
+                        .map(|pid| (pid.to_string(), Node::Process(pid))),
+                );
+                entries
+            }
+            Node::Process(pid) if pid2process(pid).is_some() => PROCESS_ENTRIES
+                .iter()
+                .map(|name| (name.to_string(), Node::process_entry(pid, name)))
+                .collect(),
+            Node::Tasks(pid) => tids(pid)
+                .into_iter()
+                .map(|tid| (tid.to_string(), Node::Task(pid, tid)))
+                .collect(),
+            Node::Task(pid, tid) if tids(pid).contains(&tid) => {
+                vec![("status".to_string(), Node::TaskStatus(pid, tid))]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn process_entry(pid: usize, name: &str) -> Node {
+        match name {
+            "status" => Node::Status(pid),
+            "maps" => Node::Maps(pid),
+            "fd" => Node::Fd(pid),
+            "cmdline" => Node::Cmdline(pid),
+            _ => Node::Tasks(pid),
+        }
+    }
+
+    // 文件的内容。进程或线程已经退出时返回None。
+    fn content(&self) -> Option<String> {
+        match *self {
+            Node::Meminfo => Some(meminfo()),
+            Node::Uptime => Some(uptime()),
+            Node::Interrupts => Some(interrupts()),
+            Node::Status(pid) => process_status(pid),
+            Node::Maps(pid) => maps(pid),
+            Node::Fd(pid) => fds(pid),
+            Node::Cmdline(pid) => cmdline(pid),
+            Node::TaskStatus(pid, tid) => task_status(pid, tid),
+            _ => None,
+        }
+    }
+}
+
+pub struct ProcfsInode {
+    node: Node,
+    sb: Arc<ProcfsSuperBlock>,
+}
+
+impl Inode for ProcfsInode {
+    fn superblock(&self) -> Arc<dyn SuperBlock> {
+        self.sb.clone()
+    }
+    fn ino(&self) -> usize {
+        self.node.ino()
+    }
+    fn inode_type(&self) -> InodeType {
+        self.node.inode_type()
+    }
+    fn size(&self) -> usize {
+        match self.node.inode_type() {
+            InodeType::Dir => self.node.entries().len(),
+            _ => self.node.content().map_or(0, |content| content.len()),
+        }
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let Some(content) = self.node.content() else {
+            return 0;
+        };
+        let content = content.as_bytes();
+        if offset >= content.len() {
+            return 0;
+        }
+        let len = min(buf.len(), content.len() - offset);
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        len
+    }
+    // procfs是只读的
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+    fn truncate(&self, _size: usize) -> bool {
+        false
+    }
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        let (_, node) = self
+            .node
+            .entries()
+            .into_iter()
+            .find(|(entry, _)| entry == name)?;
+        Some(Arc::new(Self {
+            node,
+            sb: self.sb.clone(),
+        }))
+    }
+    fn create(&self, _name: &str, _type_: InodeType) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    fn symlink(&self, _name: &str, _target: &str) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    fn readlink(&self) -> Option<String> {
+        match self.node {
+            Node::SelfLink => Some(current_process().getpid().to_string()),
+            _ => None,
+        }
+    }
+    fn unlink(&self, _name: &str) -> bool {
+        false
+    }
+    fn list(&self) -> Vec<String> {
+        self.node
+            .entries()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+// 进程中所有线程的tid
+fn tids(pid: usize) -> Vec<usize> {
+    let Some(process) = pid2process(pid) else {
+        return Vec::new();
+    };
+    let inner = process.inner_exclusive_access();
+    (0..inner.tasks.len())
+        .filter(|tid| inner.tasks[*tid].is_some())
+        .collect()
+}
+
+fn meminfo() -> String {
+    let (total, free) = frame_stats();
+    let heap = heap_stats();
+    let mut s = String::new();
+    writeln!(s, "MemTotal:\t{} kB", total * PAGE_SIZE / 1024).unwrap();
+    writeln!(s, "MemFree:\t{} kB", free * PAGE_SIZE / 1024).unwrap();
+    writeln!(s, "HeapTotal:\t{} kB", heap.total_bytes / 1024).unwrap();
+    writeln!(s, "HeapUsed:\t{} kB", heap.allocated_bytes / 1024).unwrap();
+    writeln!(s, "Slab:\t\t{} kB", heap.slab_frames * PAGE_SIZE / 1024).unwrap();
+    s
+}
+
+// 开机以来的秒数，精确到0.01秒
+fn uptime() -> String {
+    let ms = get_time_ms();
+    format!("{}.{:02}\n", ms / 1000, ms % 1000 / 10)
+}
+
+fn interrupts() -> String {
+    let mut s = String::new();
+    writeln!(s, "{:>8} {:>10}  {}", "IRQ", "COUNT", "DEVICE").unwrap();
+    writeln!(s, "{:>8} {:>10}  {}", "-", timer_ticks(), "timer").unwrap();
+    for (irq, device, count) in irq_counts() {
+        writeln!(s, "{:>8} {:>10}  {}", irq, count, device).unwrap();
+    }
+    s
+}
+
+fn task_state(status: TaskStatus) -> &'static str {
+    match status {
+        TaskStatus::Ready => "R (ready)",
+        TaskStatus::Running => "R (running)",
+        TaskStatus::Blocked => "S (sleeping)",
+    }
+}
+
+fn process_status(pid: usize) -> Option<String> {
+    let process = pid2process(pid)?;
+    let inner = process.inner_exclusive_access();
+    let ppid = inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.getpid());
+    let statuses: Vec<TaskStatus> = inner
+        .tasks
+        .iter()
+        .flatten()
+        .map(|task| task.inner_exclusive_access().task_status)
+        .collect();
+    // 进程的状态取其中最活跃的线程的状态
+    let state = if inner.frozen {
+        "T (stopped)"
+    } else if statuses.contains(&TaskStatus::Running) {
+        task_state(TaskStatus::Running)
+    } else if statuses.contains(&TaskStatus::Ready) {
+        task_state(TaskStatus::Ready)
+    } else {
+        task_state(TaskStatus::Blocked)
+    };
+    let name = inner
+        .cmdline
+        .first()
+        .and_then(|path| path.rsplit('/').next())
+        .unwrap_or("");
+    let mut s = String::new();
+    writeln!(s, "Name:\t{}", name).unwrap();
+    writeln!(s, "State:\t{}", state).unwrap();
+    writeln!(s, "Pid:\t{}", pid).unwrap();
+    writeln!(s, "PPid:\t{}", ppid).unwrap();
+    writeln!(s, "Threads:\t{}", statuses.len()).unwrap();
+    writeln!(s, "VmSize:\t{} kB", inner.address_space_size() / 1024).unwrap();
+    writeln!(
+        s,
+        "VmHeap:\t{} kB",
+        (inner.program_brk - inner.heap_bottom) / 1024
+    )
+    .unwrap();
+    writeln!(s, "FDSize:\t{}", inner.fd_table.len()).unwrap();
+    writeln!(s, "SigPnd:\t{:08x}", inner.signals.bits()).unwrap();
+    writeln!(s, "SigBlk:\t{:08x}", inner.signal_mask.bits()).unwrap();
+    writeln!(s, "CpuTime:\t{} ms", inner.cpu_time_ms).unwrap();
+    Some(s)
+}
+
+fn task_status(pid: usize, tid: usize) -> Option<String> {
+    let process = pid2process(pid)?;
+    let task = process.inner_exclusive_access().tasks.get(tid)?.clone()?;
+    let inner = task.inner_exclusive_access();
+    let mut s = String::new();
+    writeln!(s, "Tid:\t{}", tid).unwrap();
+    // 已经退出、但还没有被等待的线程
+    match inner.exit_code {
+        Some(exit_code) => {
+            writeln!(s, "State:\tZ (zombie)").unwrap();
+            writeln!(s, "ExitCode:\t{}", exit_code).unwrap();
+        }
+        None => writeln!(s, "State:\t{}", task_state(inner.task_status)).unwrap(),
+    }
+    Some(s)
+}
+
+// 映射权限的字符串形式，如"r-xp"。最后一位表示映射是共享的（s）还是私有的（p）。
+fn perm_string(perm: MapPermission, shared: bool) -> String {
+    let flag = |set: bool, c: char| if set { c } else { '-' };
+    let mut s = String::new();
+    s.push(flag(perm.contains(MapPermission::R), 'r'));
+    s.push(flag(perm.contains(MapPermission::W), 'w'));
+    s.push(flag(perm.contains(MapPermission::X), 'x'));
+    s.push(if shared { 's' } else { 'p' });
+    s
+}
+
+// 每个映射区域一行：起止地址、权限、文件中的偏移量和名字
+fn maps(pid: usize) -> Option<String> {
+    let process = pid2process(pid)?;
+    let inner = process.inner_exclusive_access();
+    let stack_tops: Vec<usize> = inner
+        .tasks
+        .iter()
+        .flatten()
+        .filter_map(|task| {
+            let task_inner = task.inner_exclusive_access();
+            task_inner.res.as_ref().map(|res| res.ustack_top())
+        })
+        .collect();
+    let mut s = String::new();
+    let mut line = |start: VirtAddr, end: VirtAddr, perm: String, offset: usize, name: &str| {
+        writeln!(
+            s,
+            "{:016x}-{:016x} {} {:08x} {}",
+            start.0, end.0, perm, offset, name
+        )
+        .unwrap();
+    };
+    for (start, end, perm) in inner.memory_set.area_ranges() {
+        let name = if start.0 == inner.heap_bottom {
+            "[heap]"
+        } else if stack_tops.contains(&end.0) {
+            "[stack]"
+        } else {
+            ""
+        };
+        line(start, end, perm_string(perm, false), 0, name);
+    }
+    for mapping in inner.file_mappings.iter() {
+        let name = format!("inode {}", mapping.file.ino());
+        for range in mapping.ranges() {
+            line(
+                range.start(),
+                range.end(),
+                perm_string(range.perm, true),
+                range.offset(),
+                &name,
+            );
+        }
+    }
+    for attachment in inner.shm_attachments.iter() {
+        let end = VirtAddr(attachment.start.0 + attachment.segment.pages() * PAGE_SIZE);
+        let name = format!("[shm key {}]", attachment.segment.key);
+        line(
+            attachment.start,
+            end,
+            perm_string(attachment.perm, true),
+            0,
+            &name,
+        );
+    }
+    Some(s)
+}
+
+// 打开的文件的描述
+fn describe(file: &Arc<dyn File + Send + Sync>) -> String {
+    let any = file.as_any();
+    if let Some(inode) = any.downcast_ref::<OSInode>() {
+        match inode.try_ino_and_offset() {
+            Some((ino, offset)) => format!("inode {} offset {}", ino, offset),
+            None => "inode".to_string(),
+        }
+    } else if any.is::<Pipe>() {
+        "pipe".to_string()
+    } else if any.is::<Epoll>() {
+        "epoll".to_string()
+    } else if let Some(device) = any.downcast_ref::<DeviceFile>() {
+        format!("/dev/{}", device.name())
+    } else {
+        "unknown".to_string()
+    }
+}
+
+// 每个打开的文件一行：文件描述符、读写权限和文件的描述
+fn fds(pid: usize) -> Option<String> {
+    let process = pid2process(pid)?;
+    let files: Vec<(usize, Arc<dyn File + Send + Sync>)> = process
+        .inner_exclusive_access()
+        .fd_table
+        .iter()
+        .enumerate()
+        .filter_map(|(fd, file)| Some((fd, file.clone()?)))
+        .collect();
+    let mut s = String::new();
+    for (fd, file) in files {
+        let mode = match (file.readable(), file.writable()) {
+            (true, true) => "rw",
+            (true, false) => "r-",
+            (false, true) => "-w",
+            (false, false) => "--",
+        };
+        writeln!(s, "{}\t{}\t{}", fd, mode, describe(&file)).unwrap();
+    }
+    Some(s)
+}
+
+// 命令行参数，每个参数以'\0'结尾
+fn cmdline(pid: usize) -> Option<String> {
+    let process = pid2process(pid)?;
+    let inner = process.inner_exclusive_access();
+    let mut s = String::new();
+    for arg in inner.cmdline.iter() {
+        s.push_str(arg);
+        s.push('\0');
+    }
+    Some(s)
+}
@@ -0,0 +1,332 @@
+//! tmpfs：数据保存在内存中的文件系统，用于/tmp等存放临时文件的目录。
+//!
+//! 文件的数据按页存放在从物理页帧分配器申请的页帧中。文件中从未写过的页（空洞）不占用页帧，读到的是0。
+//! 读写不经过块设备和块缓存。文件系统被卸载后，其中的数据全部丢失。
+
+use super::vfs::{FileSystemType, Inode, InodeType, SuperBlock};
+use crate::config::{PAGE_SIZE, TMPFS_MAX_PAGES};
+use crate::mm::{frame_alloc, FrameTracker};
+use crate::sync::UPIntrFreeCell;
+use alloc::collections::btree_map::Entry;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::min;
+use easy_fs::{BlockDevice, SYMLINK_TARGET_LIMIT};
+
+// 文件名的最大长度（字节）
+const NAME_LENGTH_LIMIT: usize = 255;
+
+pub struct TmpfsType;
+
+impl FileSystemType for TmpfsType {
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+    fn requires_device(&self) -> bool {
+        false
+    }
+    fn mount(&self, _device: Option<Arc<dyn BlockDevice>>) -> Option<Arc<dyn SuperBlock>> {
+        Some(Arc::new(TmpfsSuperBlock::new()))
+    }
+}
+
+// 一个tmpfs实例中，文件数据占用的页数。每个实例最多占用TMPFS_MAX_PAGES页。
+struct PageQuota {
+    used: UPIntrFreeCell<usize>,
+}
+
+impl PageQuota {
+    // 为文件数据申请一个清零的页帧。超过限额或内存不足时返回None。
+    fn alloc(&self) -> Option<FrameTracker> {
+        let mut used = self.used.exclusive_access();
+        if *used >= TMPFS_MAX_PAGES {
+            return None;
+        }
+        let frame = frame_alloc()?;
+        *used += 1;
+        Some(frame)
+    }
+
+    fn release(&self, pages: usize) {
+        *self.used.exclusive_access() -= pages;
+    }
+}
+
+enum NodeData {
+    File {
+        size: usize,
+        // 页号到存放该页数据的页帧
+        pages: BTreeMap<usize, FrameTracker>,
+    },
+    Dir {
+        // 名字到子节点
+        children: BTreeMap<String, Arc<Node>>,
+        // inode号到名字。inode号单调增加，作为目录项的位置时不会因为增删其他项而改变
+        names: BTreeMap<usize, String>,
+    },
+    Symlink(String),
+}
+
+// tmpfs中的一个文件、目录或符号链接
+struct Node {
+    ino: usize,
+    quota: Arc<PageQuota>,
+    data: UPIntrFreeCell<NodeData>,
+}
+
+impl Node {
+    fn new(ino: usize, quota: Arc<PageQuota>, data: NodeData) -> Self {
+        Self {
+            ino,
+            quota,
+            data: unsafe { UPIntrFreeCell::new(data) },
+        }
+    }
+}
+
+impl NodeData {
+    fn empty_dir() -> Self {
+        NodeData::Dir {
+            children: BTreeMap::new(),
+            names: BTreeMap::new(),
+        }
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        if let NodeData::File { pages, .. } = &*self.data.exclusive_access() {
+            self.quota.release(pages.len());
+        }
+    }
+}
+
+pub struct TmpfsSuperBlock {
+    root: Arc<Node>,
+    // 下一个分配的inode号
+    next_ino: UPIntrFreeCell<usize>,
+    quota: Arc<PageQuota>,
+}
+
+impl TmpfsSuperBlock {
+    fn new() -> Self {
+        let quota = Arc::new(PageQuota {
+            used: unsafe { UPIntrFreeCell::new(0) },
+        });
+        let root = Node::new(0, quota.clone(), NodeData::empty_dir());
+        Self {
+            root: Arc::new(root),
+            next_ino: unsafe { UPIntrFreeCell::new(1) },
+            quota,
+        }
+    }
+
+    fn new_node(&self, data: NodeData) -> Arc<Node> {
+        let mut next_ino = self.next_ino.exclusive_access();
+        let ino = *next_ino;
+        *next_ino += 1;
+        Arc::new(Node::new(ino, self.quota.clone(), data))
+    }
+}
+
+impl SuperBlock for TmpfsSuperBlock {
+    fn root_inode(self: Arc<Self>) -> Arc<dyn Inode> {
+        Arc::new(TmpfsInode {
+            node: self.root.clone(),
+            sb: self,
+        })
+    }
+    fn sync(&self) {}
+}
+
+pub struct TmpfsInode {
+    node: Arc<Node>,
+    sb: Arc<TmpfsSuperBlock>,
+}
+
+impl TmpfsInode {
+    fn wrap(&self, node: Arc<Node>) -> Arc<dyn Inode> {
+        Arc::new(Self {
+            node,
+            sb: self.sb.clone(),
+        })
+    }
+
+    // 在目录中加入一个新的节点。name无效或已经存在时失败。
+    fn add_child(&self, name: &str, data: NodeData) -> Option<Arc<dyn Inode>> {
+        if name.is_empty() || name.len() > NAME_LENGTH_LIMIT || name.contains(['/', '\0']) {
+            return None;
+        }
+        if name == "." || name == ".." {
+            return None;
+        }
+        let mut dir = self.node.data.exclusive_access();
+        let NodeData::Dir { children, names } = &mut *dir else {
+            return None;
+        };
+        let Entry::Vacant(entry) = children.entry(name.to_string()) else {
+            return None;
+        };
+        let node = entry.insert(self.sb.new_node(data)).clone();
+        names.insert(node.ino, name.to_string());
+        drop(dir);
+        Some(self.wrap(node))
+    }
+}
+
+impl Inode for TmpfsInode {
+    fn superblock(&self) -> Arc<dyn SuperBlock> {
+        self.sb.clone()
+    }
+    fn ino(&self) -> usize {
+        self.node.ino
+    }
+    fn inode_type(&self) -> InodeType {
+        match &*self.node.data.exclusive_access() {
+            NodeData::File { .. } => InodeType::File,
+            NodeData::Dir { .. } => InodeType::Dir,
+            NodeData::Symlink(_) => InodeType::Symlink,
+        }
+    }
+    fn size(&self) -> usize {
+        match &*self.node.data.exclusive_access() {
+            NodeData::File { size, .. } => *size,
+            NodeData::Dir { children, .. } => children.len(),
+            NodeData::Symlink(target) => target.len(),
+        }
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let data = self.node.data.exclusive_access();
+        let NodeData::File { size, pages } = &*data else {
+            return 0;
+        };
+        let end = min(*size, offset + buf.len());
+        let mut pos = offset;
+        while pos < end {
+            let in_page = pos % PAGE_SIZE;
+            let len = min(PAGE_SIZE - in_page, end - pos);
+            let dst = &mut buf[pos - offset..pos - offset + len];
+            match pages.get(&(pos / PAGE_SIZE)) {
+                Some(frame) => {
+                    dst.copy_from_slice(&frame.ppn.get_bytes_array()[in_page..in_page + len])
+                }
+                None => dst.fill(0),
+            }
+            pos += len;
+        }
+        end.saturating_sub(offset)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        let mut data = self.node.data.exclusive_access();
+        let NodeData::File { size, pages } = &mut *data else {
+            return 0;
+        };
+        let mut written = 0;
+        while written < buf.len() {
+            let pos = offset + written;
+            let in_page = pos % PAGE_SIZE;
+            let len = min(PAGE_SIZE - in_page, buf.len() - written);
+            let frame = match pages.entry(pos / PAGE_SIZE) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                // 没有内存时，只写入已经写完的部分
+                Entry::Vacant(entry) => match self.node.quota.alloc() {
+                    Some(frame) => entry.insert(frame),
+                    None => break,
+                },
+            };
+            frame.ppn.get_bytes_array()[in_page..in_page + len]
+                .copy_from_slice(&buf[written..written + len]);
+            written += len;
+        }
+        if written > 0 && offset + written > *size {
+            *size = offset + written;
+        }
+        written
+    }
+    fn truncate(&self, new_size: usize) -> bool {
+        let mut data = self.node.data.exclusive_access();
+        let NodeData::File { size, pages } = &mut *data else {
+            return false;
+        };
+        if new_size < *size {
+            // 丢弃新的末尾之后的整页
+            let dropped = pages.split_off(&new_size.div_ceil(PAGE_SIZE));
+            self.node.quota.release(dropped.len());
+            // 新的末尾所在的页中，末尾之后的部分清零。以后扩大文件时，这部分读到的是0。
+            if let Some(frame) = pages.get(&(new_size / PAGE_SIZE)) {
+                frame.ppn.get_bytes_array()[new_size % PAGE_SIZE..].fill(0);
+            }
+        }
+        *size = new_size;
+        true
+    }
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        let node = match &*self.node.data.exclusive_access() {
+            NodeData::Dir { children, .. } => children.get(name)?.clone(),
+            _ => return None,
+        };
+        Some(self.wrap(node))
+    }
+    fn create(&self, name: &str, type_: InodeType) -> Option<Arc<dyn Inode>> {
+        let data = match type_ {
+            InodeType::File => NodeData::File {
+                size: 0,
+                pages: BTreeMap::new(),
+            },
+            InodeType::Dir => NodeData::empty_dir(),
+            _ => return None,
+        };
+        self.add_child(name, data)
+    }
+    fn symlink(&self, name: &str, target: &str) -> Option<Arc<dyn Inode>> {
+        if target.is_empty() || target.len() > SYMLINK_TARGET_LIMIT {
+            return None;
+        }
+        self.add_child(name, NodeData::Symlink(target.to_string()))
+    }
+    fn readlink(&self) -> Option<String> {
+        match &*self.node.data.exclusive_access() {
+            NodeData::Symlink(target) => Some(target.clone()),
+            _ => None,
+        }
+    }
+    fn unlink(&self, name: &str) -> bool {
+        let mut dir = self.node.data.exclusive_access();
+        let NodeData::Dir { children, names } = &mut *dir else {
+            return false;
+        };
+        let Some(child) = children.get(name) else {
+            return false;
+        };
+        let nonempty_dir = matches!(
+            &*child.data.exclusive_access(),
+            NodeData::Dir { children, .. } if !children.is_empty()
+        );
+        if nonempty_dir {
+            return false;
+        }
+        // 被删除的节点在释放目录的锁之后再drop。还被打开的文件要等到关闭后才释放数据。
+        names.remove(&child.ino);
+        let child = children.remove(name);
+        drop(dir);
+        drop(child);
+        true
+    }
+    fn list(&self) -> Vec<String> {
+        match &*self.node.data.exclusive_access() {
+            NodeData::Dir { children, .. } => children.keys().cloned().collect(),
+            _ => Vec::new(),
+        }
+    }
+    fn next_entry(&self, pos: usize) -> Option<(usize, String)> {
+        match &*self.node.data.exclusive_access() {
+            NodeData::Dir { names, .. } => names
+                .range(pos..)
+                .next()
+                .map(|(&ino, name)| (ino, name.clone())),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! 内核的虚拟文件系统（VFS）接口。
+//!
+//! 每种文件系统实现FileSystemType、SuperBlock和Inode三个trait：
+//! - FileSystemType：一种文件系统，如easy-fs。挂载时由它创建超级块。
+//! - SuperBlock：一个已挂载的文件系统实例，提供根目录的inode。
+//! - Inode：文件系统中的一个文件、目录或符号链接。
+//!
+//! 内核的其他部分只通过这些trait访问文件系统，不关心具体的实现。
+
+use super::File;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::BlockDevice;
+
+// inode的类型
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InodeType {
+    File,
+    Dir,
+    Symlink,
+    // 设备文件，如/dev下的文件。打开时得到设备自己的File对象。
+    Device,
+}
+
+// 一种文件系统
+pub trait FileSystemType: Send + Sync {
+    // 文件系统类型的名字，挂载时用它指定文件系统的类型
+    fn name(&self) -> &'static str;
+    // 是否需要块设备。不需要块设备的文件系统（如内存中的文件系统）挂载时忽略device。
+    fn requires_device(&self) -> bool;
+    // 挂载文件系统，返回它的超级块。块设备上没有该类型的文件系统时返回None。
+    fn mount(&self, device: Option<Arc<dyn BlockDevice>>) -> Option<Arc<dyn SuperBlock>>;
+}
+
+// 一个已挂载的文件系统
+pub trait SuperBlock: Send + Sync {
+    // 根目录的inode
+    fn root_inode(self: Arc<Self>) -> Arc<dyn Inode>;
+    // 将文件系统的修改写回存储设备
+    fn sync(&self);
+    // 目录查找的结果能否放入目录项缓存。目录的内容随时变化、不经过VFS修改的文件系统（如procfs）返回false。
+    fn cacheable(&self) -> bool {
+        true
+    }
+}
+
+// 文件系统中的一个inode。
+// 每个Inode对象都持有其超级块的引用，卸载文件系统时据此判断它是否还在被使用。
+// 因此文件系统内部不应长期持有Inode对象，只能持有自己的数据结构。
+// 对目录的操作在inode不是目录时失败；对文件内容的操作在inode是目录时的行为由文件系统决定，
+// 调用者应事先检查inode的类型。
+pub trait Inode: Send + Sync {
+    // inode所在的文件系统
+    fn superblock(&self) -> Arc<dyn SuperBlock>;
+    // inode在文件系统内的编号
+    fn ino(&self) -> usize;
+    fn inode_type(&self) -> InodeType;
+    // 文件的长度（字节）
+    fn size(&self) -> usize;
+    // 从offset处读取数据到buf中，返回读到的字节数。读到文件末尾时返回0。
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+    // 将buf写入offset处，文件长度不足时自动扩大。返回写入的字节数。
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize;
+    // 将buf写到文件末尾，返回写入的位置和写入的字节数。
+    // 读取文件长度和写入之间不应被其他写入打断，写入可能阻塞的文件系统需要自己实现。
+    fn append(&self, buf: &[u8]) -> (usize, usize) {
+        let offset = self.size();
+        (offset, self.write_at(offset, buf))
+    }
+    // 将文件的长度改为size：超出的部分被丢弃，不足的部分用0填充。文件系统不支持时返回false。
+    fn truncate(&self, size: usize) -> bool;
+    // 在目录中按名字查找inode
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>>;
+    // 在目录中创建一个常规文件或目录。name已经存在时失败。
+    fn create(&self, name: &str, type_: InodeType) -> Option<Arc<dyn Inode>>;
+    // 在目录中创建一个指向target的符号链接
+    fn symlink(&self, name: &str, target: &str) -> Option<Arc<dyn Inode>>;
+    // 符号链接的目标路径。inode不是符号链接时返回None。
+    fn readlink(&self) -> Option<String>;
+    // 删除目录中的一项。不能删除非空的目录。
+    fn unlink(&self, name: &str) -> bool;
+    // 目录中所有项的名字
+    fn list(&self) -> Vec<String>;
+    // 目录中位置不小于pos的第一项，返回它的位置和名字，没有更多的项时返回None。
+    // 位置由文件系统决定，增删其他项时已有的项的位置不变，用作getdents的偏移量。
+    // 默认的位置是list中的下标，只适用于内容由内核生成、不会被用户修改的目录。
+    fn next_entry(&self, pos: usize) -> Option<(usize, String)> {
+        self.list().into_iter().nth(pos).map(|name| (pos, name))
+    }
+    // 打开设备文件，返回读写该设备的File对象。inode不是设备文件时返回None。
+    fn open_device(&self, _readable: bool, _writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        None
+    }
+
+    fn is_dir(&self) -> bool {
+        self.inode_type() == InodeType::Dir
+    }
+    fn is_symlink(&self) -> bool {
+        self.inode_type() == InodeType::Symlink
+    }
+}
+
+// 超级块的标识，即其数据的地址。用于在挂载表和目录项缓存中区分不同的文件系统实例。
+pub fn superblock_id(sb: &Arc<dyn SuperBlock>) -> usize {
+    Arc::as_ptr(sb) as *const () as usize
+}
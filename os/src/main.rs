@@ -17,6 +17,7 @@ pub mod fs;
 mod lang_items;
 mod logging;
 mod mm;
+mod random;
 mod sbi;
 mod sync;
 pub mod syscall;
@@ -54,6 +55,7 @@ pub fn rust_main() -> ! {
     task::add_initproc();
     timer::set_next_trigger();
     board::device_init();
+    fs::init();
     fs::list_apps();
     *DEV_NON_BLOCKING_ACCESS.exclusive_access() = true;
     println_kernel!("Hello, world!");
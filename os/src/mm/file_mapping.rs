@@ -5,9 +5,12 @@ use alloc::{
     sync::Arc,
     vec::Vec,
 };
-use easy_fs::Inode;
 
-use crate::config::PAGE_SIZE;
+use crate::{
+    config::{ASLR_MMAP_PAGES, PAGE_SIZE},
+    fs::Inode,
+    random::aslr_offset,
+};
 
 use super::{
     address::VirtPageNum, frame_alloc, FrameTracker, MapPermission, PhysPageNum, VirtAddr,
@@ -19,7 +22,7 @@ use super::{
 // 当前只允许将单个进程的文件映射到多个区域，不允许多个进程映射到同一个文件。
 pub struct FileMapping {
     // 被映射的文件。只能是常规文件，所以使用Inode。
-    pub file: Arc<Inode>,
+    pub file: Arc<dyn Inode>,
     // 映射到的虚拟内存区域。
     // 注意，当前不允许该虚拟地址区域重叠
     ranges: Vec<MapRange>,
@@ -45,7 +48,7 @@ pub struct MapRange {
 }
 
 impl FileMapping {
-    pub fn new_empty(file: Arc<Inode>) -> Self {
+    pub fn new_empty(file: Arc<dyn Inode>) -> Self {
         Self {
             file,
             ranges: Vec::new(),
@@ -64,6 +67,21 @@ impl FileMapping {
         });
     }
 
+    // 映射区域占用的虚拟地址空间大小（字节），按页对齐
+    pub fn mapped_bytes(&self) -> usize {
+        self.ranges
+            .iter()
+            .map(|r| {
+                let end = VirtAddr::from(r.start.0 + r.len).ceil();
+                (end.0 - r.start.floor().0) * PAGE_SIZE
+            })
+            .sum()
+    }
+
+    pub fn ranges(&self) -> &[MapRange] {
+        &self.ranges
+    }
+
     pub fn contains(&self, va: VirtAddr) -> bool {
         self.ranges.iter().any(|r| r.contains(va))
     }
@@ -136,6 +154,19 @@ impl MapRange {
         va >= start && va < start + self.len
     }
 
+    pub fn start(&self) -> VirtAddr {
+        self.start
+    }
+
+    // 区间的结束地址，按页对齐
+    pub fn end(&self) -> VirtAddr {
+        VirtAddr::from(self.start.0 + self.len).ceil().into()
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
     // 计算给定虚拟页号在文件中的偏移量
     pub fn file_offset(&self, vpn: VirtPageNum) -> usize {
         let start: usize = self.start.into();
@@ -147,29 +178,82 @@ impl MapRange {
 
 // 选一段没人用的地址空间作为mmap的基址
 pub const MMAP_AREA_BASE: usize = 0x0000_0001_0000_0000;
+// mmap区域的结束地址，即低256GB的末尾
+pub const MMAP_AREA_END: usize = 0x0000_0040_0000_0000;
 
+// 管理mmap区域中的虚拟地址。mmap和shmat都从这里分配地址，
+// 用户指定地址的shmat也要在这里保留，避免之后的分配与它重叠。
+#[derive(Clone)]
 pub struct VirtualAddressAllocator {
-    cur_va: VirtAddr,
+    // 自动分配时，从该页开始查找空闲的区域
+    base: VirtPageNum,
+    // 已分配的区域：起始页号 -> 结束页号（不含）
+    used: BTreeMap<VirtPageNum, VirtPageNum>,
 }
 
 impl Default for VirtualAddressAllocator {
     fn default() -> Self {
-        Self::new(MMAP_AREA_BASE)
+        // 开启ASLR时，mmap区域的起始地址是随机的
+        Self::new(MMAP_AREA_BASE + aslr_offset(ASLR_MMAP_PAGES))
     }
 }
 
 impl VirtualAddressAllocator {
     pub fn new(base: usize) -> Self {
         Self {
-            cur_va: base.into(),
+            base: VirtAddr::from(base).ceil(),
+            used: BTreeMap::new(),
+        }
+    }
+
+    // 分配一段虚拟地址区域：从base开始，取第一段足够大的空闲区域。
+    // 区域会超出mmap区域的末尾时，返回None。
+    pub fn alloc(&mut self, len: usize) -> Option<VirtAddr> {
+        let pages = len.checked_add(PAGE_SIZE - 1)? / PAGE_SIZE;
+        let mut start = self.base;
+        for (&used_start, &used_end) in self.used.iter() {
+            if used_end <= start {
+                continue;
+            }
+            if used_start.0 >= start.0 + pages {
+                break;
+            }
+            start = used_end;
+        }
+        let end = start.0.checked_add(pages)?;
+        if end > MMAP_AREA_END / PAGE_SIZE {
+            return None;
+        }
+        if pages > 0 {
+            self.used.insert(start, VirtPageNum(end));
+        }
+        Some(start.into())
+    }
+
+    // 保留用户指定的区域[start, start + len)，start按页对齐。
+    // 区域必须位于mmap区域内，且不能与已分配的区域重叠，否则返回false。
+    pub fn reserve(&mut self, start: usize, len: usize) -> bool {
+        let end = match start.checked_add(len) {
+            Some(end) => end,
+            None => return false,
+        };
+        if start < MMAP_AREA_BASE || end > MMAP_AREA_END || len == 0 {
+            return false;
+        }
+        let start_vpn = VirtAddr::from(start).floor();
+        let end_vpn = VirtAddr::from(end).ceil();
+        // 起始地址在end_vpn之前的最后一个区域，如果它的结束地址在start_vpn之后，就重叠了
+        if let Some((_, &used_end)) = self.used.range(..end_vpn).next_back() {
+            if used_end > start_vpn {
+                return false;
+            }
         }
+        self.used.insert(start_vpn, end_vpn);
+        true
     }
 
-    // 分配一段虚拟地址区域
-    pub fn alloc(&mut self, len: usize) -> VirtAddr {
-        let start = self.cur_va;
-        let end: VirtAddr = (self.cur_va.0 + len).into();
-        self.cur_va = end.ceil().into();
-        start
+    // 释放从start开始的区域，之后可以再被分配
+    pub fn free(&mut self, start: VirtAddr) {
+        self.used.remove(&start.floor());
     }
 }
@@ -17,6 +17,7 @@ trait FrameAllocator {
 
 // 栈式物理页帧分配器
 pub struct StackFrameAllocator {
+    start: usize,   // 可供分配的内存的起始物理页号
     current: usize, // 空闲内存的起始物理页号
     end: usize,     // 空闲内存的结束物理页号
     recycled: Vec<usize>,
@@ -24,14 +25,30 @@ pub struct StackFrameAllocator {
 
 impl StackFrameAllocator {
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.start = l.0;
         self.current = l.0;
         self.end = r.0;
     }
+
+    // 分配连续的多个物理页帧，返回第一个页帧的物理页号。
+    // 回收的页帧不一定连续，因此只从未分配过的区域中分配。
+    pub fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum> {
+        if pages == 1 {
+            return self.alloc();
+        }
+        if self.current + pages > self.end {
+            return None;
+        }
+        let allocated = self.current;
+        self.current += pages;
+        Some(allocated.into())
+    }
 }
 
 impl FrameAllocator for StackFrameAllocator {
     fn new() -> Self {
         Self {
+            start: 0,
             current: 0,
             end: 0,
             recycled: Vec::new(),
@@ -118,7 +135,34 @@ pub fn frame_alloc() -> Option<FrameTracker> {
         .map(FrameTracker::new)
 }
 
+// 分配连续的多个物理页帧，用于内核堆的扩容。这些页帧不会被回收，也不会被清零。
+// 如果物理页帧分配器正在被使用（例如回收页帧时，Vec扩容引发了堆扩容），返回None。
+pub fn frame_alloc_contiguous(pages: usize) -> Option<PhysPageNum> {
+    FRAME_ALLOCATOR
+        .try_exclusive_access()
+        .and_then(|mut allocator| allocator.alloc_contiguous(pages))
+}
+
+// 物理页帧的总数和空闲的页帧数
+pub fn frame_stats() -> (usize, usize) {
+    let allocator = FRAME_ALLOCATOR.exclusive_access();
+    let total = allocator.end - allocator.start;
+    let free = allocator.end - allocator.current + allocator.recycled.len();
+    (total, free)
+}
+
 // 回收物理页帧
 pub fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
 }
+
+// 尝试回收物理页帧。分配器正在被使用时（如内核堆在分配器扩容回收列表时重入），返回false。
+pub fn frame_try_dealloc(ppn: PhysPageNum) -> bool {
+    match FRAME_ALLOCATOR.try_exclusive_access() {
+        Some(mut allocator) => {
+            allocator.dealloc(ppn);
+            true
+        }
+        None => false,
+    }
+}
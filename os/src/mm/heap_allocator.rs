@@ -1,29 +1,238 @@
 //! 实现动态地分配堆内存
+//!
+//! 内核堆由两层组成：
+//! - 小对象（不超过1KB）从slab缓存中分配，见slab.rs；
+//! - 其余请求由伙伴系统分配。
+//!
+//! 伙伴系统最初只管理.bss段中的HEAP_SPACE。空间耗尽时，会从物理页帧分配器申请连续的页帧来扩容。
+//! slab所需的页直接向物理页帧分配器申请，slab完全空闲时归还，这样堆的使用量可以回落；
+//! 物理页帧分配器还没有初始化，或者正在被使用时，才从伙伴系统分配。
 
-use crate::config::KERNEL_HEAP_SIZE;
-use buddy_system_allocator::LockedHeap;
+use super::address::{PhysAddr, PhysPageNum};
+use super::frame_allocator::{frame_alloc_contiguous, frame_try_dealloc};
+use super::slab::{
+    size_class, slab_layout, SlabCache, SlabSource, SlabStats, SLAB_CLASS_COUNT, SLAB_SIZE_CLASSES,
+};
+use crate::config::{KERNEL_HEAP_GROW_PAGES, KERNEL_HEAP_SIZE, PAGE_SIZE};
+use crate::sync::UPIntrFreeCell;
+use buddy_system_allocator::Heap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{null_mut, NonNull};
+use lazy_static::*;
 
 // 指定全局内存分配器，以使用需要堆分配的数据结构，如String、Vec等。
-// LockedHeap实现了std::alloc::GlobalAlloc
+// KernelHeapAllocator实现了core::alloc::GlobalAlloc
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+static HEAP_ALLOCATOR: KernelHeapAllocator = KernelHeapAllocator;
 
 static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 
+lazy_static! {
+    static ref KERNEL_HEAP: UPIntrFreeCell<KernelHeap> =
+        unsafe { UPIntrFreeCell::new(KernelHeap::new()) };
+}
+
+pub struct KernelHeapAllocator;
+
+struct KernelHeap {
+    buddy: Heap,
+    caches: [SlabCache; SLAB_CLASS_COUNT],
+    // 扩容时从物理页帧分配器申请的页数
+    grown_pages: usize,
+    // slab当前直接占用的物理页帧数
+    slab_frames: usize,
+    // 分配失败的次数
+    failed_allocs: usize,
+}
+
+#[derive(Copy, Clone, Debug)]
+// 内核堆的统计信息，用于排查内存泄漏
+pub struct HeapStats {
+    // 伙伴系统管理的总字节数，包括初始堆和扩容的部分
+    pub total_bytes: usize,
+    // 伙伴系统实际分配出去的字节数（包括slab占用的页）
+    pub allocated_bytes: usize,
+    // 伙伴系统的使用者请求的字节数
+    pub requested_bytes: usize,
+    pub grown_pages: usize,
+    pub slab_frames: usize,
+    pub failed_allocs: usize,
+}
+
+impl KernelHeap {
+    fn new() -> Self {
+        Self {
+            buddy: Heap::new(),
+            caches: SLAB_SIZE_CLASSES.map(SlabCache::new),
+            grown_pages: 0,
+            slab_frames: 0,
+            failed_allocs: 0,
+        }
+    }
+
+    fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let ptr = match size_class(&layout) {
+            Some(class) => self.slab_alloc(class),
+            None => self.buddy_alloc(layout),
+        };
+        match ptr {
+            Some(ptr) => ptr.as_ptr(),
+            None => {
+                self.failed_allocs += 1;
+                null_mut()
+            }
+        }
+    }
+
+    // 释放内存。slab完全空闲时，将页归还到它的来源：
+    // 来自物理页帧分配器的页由调用者在释放内核堆的锁之后归还，因为回收时可能要分配堆内存。
+    fn dealloc(&mut self, ptr: *mut u8, layout: Layout) -> Option<PhysPageNum> {
+        let ptr = NonNull::new(ptr).unwrap();
+        match size_class(&layout) {
+            Some(class) => match self.caches[class].dealloc(ptr)? {
+                (page, SlabSource::Buddy) => self.buddy.dealloc(page, slab_layout()),
+                (page, SlabSource::Frame) => {
+                    self.slab_frames -= 1;
+                    return Some(PhysAddr::from(page.as_ptr() as usize).floor());
+                }
+            },
+            None => self.buddy.dealloc(ptr, layout),
+        }
+        None
+    }
+
+    // 物理页帧分配器暂时无法回收slab的页时，将页并入伙伴系统，不会丢失
+    fn add_frame(&mut self, ppn: PhysPageNum) {
+        let start: PhysAddr = ppn.into();
+        unsafe {
+            self.buddy.add_to_heap(start.0, start.0 + PAGE_SIZE);
+        }
+        self.grown_pages += 1;
+    }
+
+    fn slab_alloc(&mut self, class: usize) -> Option<NonNull<u8>> {
+        if let Some(ptr) = self.caches[class].alloc() {
+            return Some(ptr);
+        }
+        let (page, source) = match frame_alloc_contiguous(1) {
+            Some(ppn) => {
+                self.slab_frames += 1;
+                let start: PhysAddr = ppn.into();
+                (NonNull::new(start.0 as *mut u8)?, SlabSource::Frame)
+            }
+            None => (self.buddy_alloc(slab_layout())?, SlabSource::Buddy),
+        };
+        self.caches[class].add_slab(page, source);
+        self.caches[class].alloc()
+    }
+
+    fn buddy_alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        if let Ok(ptr) = self.buddy.alloc(layout) {
+            return Some(ptr);
+        }
+        self.grow(&layout);
+        self.buddy.alloc(layout).ok()
+    }
+
+    // 从物理页帧分配器申请连续的页帧，加入伙伴系统。
+    // 内核地址空间中，物理内存是恒等映射的，因此物理地址可以直接作为堆地址使用。
+    fn grow(&mut self, layout: &Layout) {
+        // 伙伴系统按2的幂分块，且新加入的区域不一定对齐，因此多申请一倍的空间
+        let block = layout.size().max(layout.align()).next_power_of_two();
+        let pages = KERNEL_HEAP_GROW_PAGES.max((2 * block + PAGE_SIZE - 1) / PAGE_SIZE);
+        if let Some(ppn) = frame_alloc_contiguous(pages) {
+            let start: PhysAddr = ppn.into();
+            let start = start.0;
+            unsafe {
+                self.buddy.add_to_heap(start, start + pages * PAGE_SIZE);
+            }
+            self.grown_pages += pages;
+        }
+    }
+
+    fn stats(&self) -> HeapStats {
+        HeapStats {
+            total_bytes: self.buddy.stats_total_bytes(),
+            allocated_bytes: self.buddy.stats_alloc_actual(),
+            requested_bytes: self.buddy.stats_alloc_user(),
+            grown_pages: self.grown_pages,
+            slab_frames: self.slab_frames,
+            failed_allocs: self.failed_allocs,
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeapAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        KERNEL_HEAP.exclusive_access().alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let frame = KERNEL_HEAP.exclusive_access().dealloc(ptr, layout);
+        if let Some(ppn) = frame {
+            if !frame_try_dealloc(ppn) {
+                KERNEL_HEAP.exclusive_access().add_frame(ppn);
+            }
+        }
+    }
+}
+
 // 初始化堆空间：提供一块内存空间（HEAP_SPACE），作为「初始堆」。
 // HEAP_SPACE是一个未初始化的静态变量，它位于.bss段中。因此这个堆也位于.bss段。
 pub fn init_heap() {
     unsafe {
-        // LockedHeap是被Mutex<T>包装的类型
-        HEAP_ALLOCATOR
-            .lock()
+        KERNEL_HEAP
+            .exclusive_access()
+            .buddy
             .init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
     }
 }
 
+// 获取内核堆的统计信息
+pub fn heap_stats() -> HeapStats {
+    KERNEL_HEAP.exclusive_access().stats()
+}
+
+// 获取各个slab缓存的统计信息
+pub fn slab_stats() -> [SlabStats; SLAB_CLASS_COUNT] {
+    let heap = KERNEL_HEAP.exclusive_access();
+    let mut stats = [SlabStats::default(); SLAB_CLASS_COUNT];
+    for (stat, cache) in stats.iter_mut().zip(heap.caches.iter()) {
+        *stat = cache.stats();
+    }
+    stats
+}
+
+// 打印内核堆的使用情况。打印时可能会分配内存，因此先复制出统计信息，再打印。
+pub fn print_heap_stats() {
+    let heap = heap_stats();
+    let slabs = slab_stats();
+    println_kernel!(
+        "heap: total {:#x} bytes, allocated {:#x} bytes, requested {:#x} bytes, grown {} pages, {} slab frames, {} failed allocations",
+        heap.total_bytes,
+        heap.allocated_bytes,
+        heap.requested_bytes,
+        heap.grown_pages,
+        heap.slab_frames,
+        heap.failed_allocs
+    );
+    for slab in slabs.iter().filter(|slab| slab.slabs > 0) {
+        println_kernel!(
+            "slab-{}: {} slabs, {}/{} objects in use, {} allocs, {} frees",
+            slab.object_size,
+            slab.slabs,
+            slab.active_objects,
+            slab.slabs * slab.objects_per_slab,
+            slab.alloc_count,
+            slab.free_count
+        );
+    }
+}
+
 #[alloc_error_handler]
-// 处理内存分配错误
+// 处理内存分配错误。堆已无法扩容，打印堆的使用情况以便排查
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
+    print_heap_stats();
     panic!("Heap allocation error, layout = {:?}", layout);
 }
 
@@ -50,5 +259,44 @@ pub fn heap_test() {
     }
     assert!(bss_range.contains(&(v.as_ptr() as usize)));
     drop(v);
+    // 小对象来自slab缓存，释放后计数应恢复原值
+    let active_objects = || {
+        slab_stats()
+            .iter()
+            .find(|slab| slab.object_size == 64)
+            .unwrap()
+            .active_objects
+    };
+    let before = active_objects();
+    let objects: Vec<Box<[u8; 48]>> = (0..200).map(|_| Box::new([0u8; 48])).collect();
+    assert_eq!(active_objects(), before + 200);
+    drop(objects);
+    assert_eq!(active_objects(), before);
+    print_heap_stats();
     println_kernel!("heap_test passed!");
 }
+
+// 检查slab的页在完全空闲后归还给物理页帧分配器。需要在物理页帧分配器初始化之后调用。
+pub fn slab_reclaim_test() {
+    use super::frame_allocator::frame_stats;
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    const OBJECTS: usize = 2000;
+    let per_slab = slab_stats()
+        .iter()
+        .find(|slab| slab.object_size == 96)
+        .unwrap()
+        .objects_per_slab;
+    // Vec本身从伙伴系统分配，不占用slab
+    let mut objects: Vec<Box<[u8; 80]>> = Vec::with_capacity(OBJECTS);
+    let (_, free_before) = frame_stats();
+    let slab_frames = heap_stats().slab_frames;
+    (0..OBJECTS).for_each(|_| objects.push(Box::new([0u8; 80])));
+    assert!(heap_stats().slab_frames >= slab_frames + OBJECTS / per_slab);
+    objects.clear();
+    // 每个尺寸类别最多保留一个空闲slab
+    assert!(heap_stats().slab_frames <= slab_frames + 1);
+    let (_, free_after) = frame_stats();
+    assert!(free_after + 1 >= free_before);
+    println_kernel!("slab_reclaim_test passed!");
+}
@@ -3,17 +3,28 @@
 use super::{
     address::{PhysAddr, PhysPageNum, VPNRange, VirtAddr, VirtPageNum},
     frame_allocator::{frame_alloc, FrameTracker},
-    page_table::{PTEFlags, PageTable, PageTableEntry},
+    page_table::{translated_refmut, PTEFlags, PageSize, PageTable, PageTableEntry},
 };
 use crate::{
-    config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE},
+    config::{
+        ASLR_ET_DYN_PAGES, ASLR_HEAP_PAGES, ASLR_STACK_PAGES, ET_DYN_BASE, MEMORY_END, MMIO,
+        PAGE_SIZE, TRAMPOLINE, USER_HEAP_BASE,
+    },
     mm::address::StepByOne,
+    random::aslr_offset,
     sync::UPIntrFreeCell,
 };
 use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec, vec::Vec};
 use core::{arch::asm, cmp::min};
 use lazy_static::*;
 use riscv::register::satp;
+use xmas_elf::dynamic::Tag;
+
+// RISC-V的重定位类型
+const R_RISCV_NONE: u32 = 0;
+const R_RISCV_RELATIVE: u32 = 3;
+// 一个Elf64_Rela重定位项的大小（字节）
+const RELA_ENTRY_SIZE: usize = 24;
 
 lazy_static! {
     // 用于管理内核地址空间的MemorySet实例
@@ -192,38 +203,61 @@ impl MemorySet {
     }
 
     // 解析应用程序的ELF格式的二进制数据，找到对应的逻辑段地址，新建该程序的地址空间
-    // 返回内容：(程序的地址空间, 用户栈顶指针, 程序入口地址)
+    // 返回内容：(程序的地址空间, 用户栈的基地址, 堆的起始地址, 程序入口地址)
+    // ELF格式不合法、类型不支持，或者含有不支持的重定位时返回None。
     //
     // 地址空间的内容：
     // 低256GB（从低位到高位）
-    // - 0x10000：起始位置
     // - 逻辑段：.text、.rodata、.data、.bss
+    //   - 普通的可执行文件（ET_EXEC）加载到链接时指定的地址（0x10000）
+    //   - 位置无关的可执行文件（ET_DYN）加载到ET_DYN_BASE，并处理重定位
     // - 保护页（guard page）：大小为一个页
-    // - 用户栈：大小为USER_STACK_SIZE
+    // - 用户栈：每个线程一个，大小为USER_STACK_SIZE，之间用保护页隔开
+    // - 堆：起始于USER_HEAP_BASE，通过系统调用sbrk改变大小
+    // - mmap区域：起始于MMAP_AREA_BASE
     // 高256GB（从高位到低位）
     // - 跳板（Trampoline）：存放切换地址空间的汇编代码，大小为一个页
     // - Trap Context
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new_bare();
-        // 映射跳板
-        memory_set.map_trampoline();
+    //
+    // 开启aslr特性时，ET_DYN的加载地址、用户栈、堆的起始地址会加上随机的偏移。
+    pub fn from_elf(elf_data: &[u8]) -> Option<(Self, usize, usize, usize)> {
         // 使用库xmas_elf来解析ELF数据
         // 可以用rust-readobj -all target/debug/os命令，来查看ELF文件的结构
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
+        let elf = xmas_elf::ElfFile::new(elf_data).ok()?;
         let elf_header = elf.header;
         // 检查魔数
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
+        if elf_header.pt1.magic != [0x7f, 0x45, 0x4c, 0x46] {
+            return None;
+        }
+        // 位置无关的可执行文件，其中的地址都相对于0，需要加上加载地址（load bias）
+        let load_bias = match elf_header.pt2.type_().as_type() {
+            xmas_elf::header::Type::Executable => 0,
+            xmas_elf::header::Type::SharedObject => ET_DYN_BASE + aslr_offset(ASLR_ET_DYN_PAGES),
+            _ => return None,
+        };
+        let mut memory_set = Self::new_bare();
+        // 映射跳板
+        memory_set.map_trampoline();
         // 遍历头（program header，ph），将各个区域加到对应的逻辑段中
         let ph_count = elf_header.pt2.ph_count();
         let mut max_end_vpn = VirtPageNum(0);
         for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
+            let ph = elf.program_header(i).ok()?;
             // 类型为Load，表示该区域需要被加载进内核
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                // 得到该区域的起始和结束地址
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
+            if ph.get_type() == Ok(xmas_elf::program::Type::Load) {
+                // 得到该区域的起始和结束地址。逻辑段必须按地址排序、互不重叠，且位于堆之下
+                let start = load_bias + ph.virtual_addr() as usize;
+                let end = start.checked_add(ph.mem_size() as usize)?;
+                let data_end = ph.offset().checked_add(ph.file_size())? as usize;
+                if end > USER_HEAP_BASE
+                    || ph.file_size() > ph.mem_size()
+                    || data_end > elf.input.len()
+                    || VirtAddr::from(start).floor() < max_end_vpn
+                {
+                    return None;
+                }
+                let start_va: VirtAddr = start.into();
+                let end_va: VirtAddr = end.into();
                 // 读取访问权限
                 let mut map_perm = MapPermission::U;
                 let ph_flags = ph.flags();
@@ -241,41 +275,128 @@ impl MemorySet {
                 // 这里的header是按地址排序的，因此不需要再用max方法比较取值
                 max_end_vpn = map_area.vpn_range.get_end();
                 // 当前program header数据被存放的位置，可通过ph.offset()和ph.file_size()来找到
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                memory_set.push(map_area, Some(&elf.input[ph.offset() as usize..data_end]));
             }
         }
+        if load_bias != 0 {
+            memory_set.relocate(&elf, load_bias)?;
+        }
         // 映射保护页（guard page），隔离用户栈
         let max_end_va: VirtAddr = max_end_vpn.into();
         let mut user_stack_base: usize = max_end_va.into();
-        user_stack_base += PAGE_SIZE;
+        user_stack_base += PAGE_SIZE + aslr_offset(ASLR_STACK_PAGES);
         // 注：线程位于进程地址空间的独有资源，包括用户栈和TrapContext，
         // 在进程创建时不分配，线程创建时才分配。
 
-        // TODO：映射堆
-        // 映射堆。通过系统调用sbrk可以申请/释放内存，改变堆的大小。
-        // memory_set.push(
-        //     MapArea::new(
-        //         user_stack_top.into(),
-        //         user_stack_top.into(),
-        //         MapType::Framed,
-        //         MapPermission::R | MapPermission::W | MapPermission::U,
-        //     ),
-        //     None,
-        // );
-        (
+        // 映射堆。堆的初始大小为0，通过系统调用sbrk可以申请/释放内存，改变堆的大小。
+        let heap_bottom = USER_HEAP_BASE + aslr_offset(ASLR_HEAP_PAGES);
+        memory_set.push(
+            MapArea::new(
+                heap_bottom.into(),
+                heap_bottom.into(),
+                MapType::Framed,
+                MapPermission::R | MapPermission::W | MapPermission::U,
+            ),
+            None,
+        );
+        Some((
             memory_set,
             user_stack_base,
-            elf.header.pt2.entry_point() as usize,
-        )
+            heap_bottom,
+            load_bias + elf.header.pt2.entry_point() as usize,
+        ))
+    }
+
+    // 处理位置无关的可执行文件的重定位。
+    // 静态链接的位置无关程序只有R_RISCV_RELATIVE类型的重定位：在offset处写入load_bias + addend。
+    // 重定位表不合法、有其他类型的重定位，或者要修改的位置不在已加载的逻辑段中时返回None。
+    fn relocate(&self, elf: &xmas_elf::ElfFile, load_bias: usize) -> Option<()> {
+        // 从动态段（PT_DYNAMIC）中找到重定位表的位置和大小
+        let (mut rela, mut rela_size, mut rela_ent) = (0, 0, RELA_ENTRY_SIZE);
+        for ph in elf.program_iter() {
+            if ph.get_type() != Ok(xmas_elf::program::Type::Dynamic) {
+                continue;
+            }
+            if let Ok(xmas_elf::program::SegmentData::Dynamic64(dynamics)) = ph.get_data(elf) {
+                for dynamic in dynamics {
+                    match dynamic.get_tag() {
+                        Ok(Tag::Rela) => rela = dynamic.get_ptr().ok()? as usize,
+                        Ok(Tag::RelaSize) => rela_size = dynamic.get_val().ok()? as usize,
+                        Ok(Tag::RelaEnt) => rela_ent = dynamic.get_val().ok()? as usize,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        if rela_size == 0 {
+            return Some(());
+        }
+        if rela_ent < RELA_ENTRY_SIZE {
+            return None;
+        }
+        // 重定位表在已加载的逻辑段中，通过它在ELF文件中的偏移来读取
+        let offset = elf
+            .program_iter()
+            .find(|ph| {
+                ph.get_type() == Ok(xmas_elf::program::Type::Load)
+                    && (ph.virtual_addr() as usize..(ph.virtual_addr() + ph.file_size()) as usize)
+                        .contains(&rela)
+            })
+            .map(|ph| rela - ph.virtual_addr() as usize + ph.offset() as usize)?;
+        let table = elf.input.get(offset..offset.checked_add(rela_size)?)?;
+        let token = self.token();
+        for entry in table.chunks_exact(rela_ent) {
+            let read = |i: usize| u64::from_le_bytes(entry[i * 8..i * 8 + 8].try_into().unwrap());
+            let (r_offset, r_info, r_addend) = (read(0), read(1), read(2));
+            match r_info as u32 {
+                R_RISCV_NONE => {}
+                R_RISCV_RELATIVE => {
+                    let addr = load_bias.checked_add(r_offset as usize)?;
+                    // 要修改的8字节必须在已经映射的页中，且不跨页
+                    let mapped = self
+                        .translate(VirtAddr::from(addr).floor())
+                        .map_or(false, |pte| pte.is_valid());
+                    if !mapped || addr % PAGE_SIZE > PAGE_SIZE - 8 {
+                        return None;
+                    }
+                    *translated_refmut(token, addr as *mut usize) =
+                        load_bias.wrapping_add(r_addend as usize);
+                }
+                _ => return None,
+            }
+        }
+        Some(())
     }
 
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.translate(vpn)
     }
 
+    // 各个逻辑段的起止地址和权限，按地址排序
+    pub fn area_ranges(&self) -> Vec<(VirtAddr, VirtAddr, MapPermission)> {
+        let mut ranges: Vec<(VirtAddr, VirtAddr, MapPermission)> = self
+            .areas
+            .iter()
+            .map(|area| {
+                (
+                    area.vpn_range.get_start().into(),
+                    area.vpn_range.get_end().into(),
+                    area.map_perm,
+                )
+            })
+            .collect();
+        ranges.sort_by_key(|(start, _, _)| start.0);
+        ranges
+    }
+
+    // 逻辑段占用的虚拟地址空间大小（字节）
+    pub fn mapped_bytes(&self) -> usize {
+        self.areas
+            .iter()
+            .map(|area| (area.vpn_range.get_end().0 - area.vpn_range.get_start().0) * PAGE_SIZE)
+            .sum()
+    }
+
     pub fn shrink_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
         if let Some(area) = self
             .areas
@@ -367,6 +488,11 @@ impl MemorySet {
         self.page_table
             .map(vpn, ppn, PTEFlags::from_bits(map_perm.bits).unwrap());
     }
+
+    // 取消由map建立的映射。物理页不归MemorySet管理，不会被回收。
+    pub fn unmap(&mut self, vpn: VirtPageNum) {
+        self.page_table.unmap(vpn);
+    }
 }
 
 impl MapArea {
@@ -435,14 +561,43 @@ impl MapArea {
 
     // 为整个逻辑段分配物理页号，并更新到页表上
     pub fn map(&mut self, page_table: &mut PageTable) {
+        if self.map_type == MapType::Identical {
+            self.map_identical(page_table);
+            return;
+        }
         for vpn in self.vpn_range {
             self.map_one(page_table, vpn);
         }
     }
 
+    // 恒等映射的物理页号是连续的，尽可能使用1GB或2MB的大页，以减少页表项和页表所占的物理页
+    fn map_identical(&mut self, page_table: &mut PageTable) {
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        let mut vpn = self.vpn_range.get_start();
+        let end = self.vpn_range.get_end();
+        while vpn < end {
+            // 选出最大的、对齐且不超出逻辑段范围的页
+            let size = PageSize::ALL
+                .into_iter()
+                .find(|size| vpn.0 % size.pages() == 0 && vpn.0 + size.pages() <= end.0)
+                .unwrap();
+            page_table.map_sized(vpn, PhysPageNum(vpn.0), pte_flags, size);
+            vpn = VirtPageNum(vpn.0 + size.pages());
+        }
+    }
+
     // 回收整个逻辑段映射到的物理页，并在页表上取消这些映射关系
     #[allow(unused)]
     pub fn unmap(&mut self, page_table: &mut PageTable) {
+        if self.map_type == MapType::Identical {
+            // 恒等映射可能使用了大页，按实际映射的页的大小跳过
+            let mut vpn = self.vpn_range.get_start();
+            while vpn < self.vpn_range.get_end() {
+                let size = page_table.unmap(vpn);
+                vpn = VirtPageNum(vpn.0 + size.pages());
+            }
+            return;
+        }
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn);
         }
@@ -517,5 +672,16 @@ pub fn remap_test() {
         .translate(mid_data.floor())
         .unwrap()
         .executable(),);
+    // 物理内存区域使用了大页映射，检查大页中的地址也能被正确翻译
+    let last_page: VirtAddr = (MEMORY_END - PAGE_SIZE).into();
+    assert_eq!(
+        kernel_space
+            .page_table
+            .translate(last_page.floor())
+            .unwrap()
+            .ppn()
+            .0,
+        last_page.floor().0
+    );
     println_kernel!("remap_test passed!");
 }
@@ -9,15 +9,19 @@ mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod shm;
+mod slab;
 
 pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr};
 pub use file_mapping::{FileMapping, VirtualAddressAllocator};
-pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
+pub use frame_allocator::{frame_alloc, frame_dealloc, frame_stats, FrameTracker};
+pub use heap_allocator::heap_stats;
 pub use memory_set::{kernel_token, MapArea, MapPermission, MapType, MemorySet, KERNEL_SPACE};
 pub use page_table::{
     translated_byte_buffer, translated_ref, translated_refmut, translated_str, PageTable,
     UserBuffer,
 };
+pub use shm::{ShmAttachment, ShmFlags, ShmIdDs, SHM_MANAGER};
 
 // 初始化内存管理模块
 pub fn init() {
@@ -26,6 +30,7 @@ pub fn init() {
     heap_allocator::heap_test();
     // 初始化物理页帧分配器
     frame_allocator::init_frame_allocator();
+    heap_allocator::slab_reclaim_test();
     // 初始化内核空间
     KERNEL_SPACE.exclusive_access().activate();
     memory_set::remap_test();
@@ -72,6 +72,55 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+
+    // 合法且R、W、X中至少有一位为1的页表项是叶子页表项，它直接指向物理页。
+    // 否则，它指向下一级页表。
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid()
+            && (self.flags() & (PTEFlags::R | PTEFlags::W | PTEFlags::X)) != PTEFlags::empty()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+// SV39支持的页面大小。叶子页表项位于第几级页表，决定了它映射的页的大小。
+pub enum PageSize {
+    // 叶子页表项位于第三级页表
+    Size4K,
+    // 叶子页表项位于第二级页表，称为大页（megapage）
+    Size2M,
+    // 叶子页表项位于根页表，称为巨页（gigapage）
+    Size1G,
+}
+
+impl PageSize {
+    // 从大到小排列，便于优先尝试更大的页
+    pub const ALL: [PageSize; 3] = [PageSize::Size1G, PageSize::Size2M, PageSize::Size4K];
+
+    // 该页包含的4KB页的个数
+    pub fn pages(&self) -> usize {
+        match self {
+            PageSize::Size4K => 1,
+            PageSize::Size2M => 512,
+            PageSize::Size1G => 512 * 512,
+        }
+    }
+
+    // 叶子页表项所在的页表级别，0表示根页表
+    fn level(&self) -> usize {
+        match self {
+            PageSize::Size4K => 2,
+            PageSize::Size2M => 1,
+            PageSize::Size1G => 0,
+        }
+    }
+
+    fn from_level(level: usize) -> Self {
+        match level {
+            0 => PageSize::Size1G,
+            1 => PageSize::Size2M,
+            _ => PageSize::Size4K,
+        }
+    }
 }
 
 // 多级页表。每个应用程序都有自己的页表。
@@ -103,14 +152,18 @@ impl PageTable {
     }
 
     // 找到虚拟页号对应的页表项，返回其拷贝。
+    // 如果该虚拟页号位于大页中，返回的页表项的物理页号是该虚拟页号所对应的4KB物理页的页号。
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).map(|pte| *pte)
+        self.find_pte(vpn).map(|(pte, size)| {
+            let offset = vpn.0 & (size.pages() - 1);
+            PageTableEntry::new(PhysPageNum(pte.ppn().0 + offset), pte.flags())
+        })
     }
 
     // 找到虚拟地址对应的物理地址
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
         let vpn = va.clone().floor();
-        self.find_pte(vpn).map(|pte| {
+        self.translate(vpn).map(|pte| {
             let aligned_pa: PhysAddr = pte.ppn().into();
             let offset = va.page_offset();
             let aligned_pa_usize: usize = aligned_pa.into();
@@ -118,16 +171,20 @@ impl PageTable {
         })
     }
 
-    // 找到虚拟页号对应的页表项，如果不存在则创建。
+    // 找到虚拟页号在指定大小的页下对应的页表项，如果中间的页表不存在则创建。
     // 但返回的页表项不一定合法，需要调用者进一步判断。
-    fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&'static mut PageTableEntry> {
+    fn find_pte_create(
+        &mut self,
+        vpn: VirtPageNum,
+        size: PageSize,
+    ) -> Option<&'static mut PageTableEntry> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
         let mut result: Option<&mut PageTableEntry> = None;
         for (i, idx) in idxs.iter().enumerate() {
             // 找到页表中对应的页表项
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
+            if i == size.level() {
                 result = Some(pte);
                 break;
             }
@@ -136,45 +193,74 @@ impl PageTable {
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
             }
+            // 路径上已经有大页映射了，无法再往下创建页表
+            if pte.is_leaf() {
+                return None;
+            }
             ppn = pte.ppn();
         }
         result
     }
 
-    // 找到虚拟页号对应的页表项。如果不存在，则返回None。
-    fn find_pte(&self, vpn: VirtPageNum) -> Option<&'static mut PageTableEntry> {
+    // 找到虚拟页号对应的叶子页表项，以及它映射的页的大小。如果不存在，则返回None。
+    // 叶子页表项可能位于上层页表中，此时它映射的是大页。
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<(&'static mut PageTableEntry, PageSize)> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result: Option<&mut PageTableEntry> = None;
         for (i, idx) in idxs.iter().enumerate() {
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
-                result = Some(pte);
-                break;
+            let size = PageSize::from_level(i);
+            if size == PageSize::Size4K || pte.is_leaf() {
+                return Some((pte, size));
             }
             if !pte.is_valid() {
                 return None;
             }
             ppn = pte.ppn();
         }
-        result
+        None
     }
 
     // 将虚拟页号映射到物理页号
     // 页表是存储在内核的地址空间中的，因此采用恒等映射，即存放页表的虚拟页号等于物理页号
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
+        self.map_sized(vpn, ppn, flags, PageSize::Size4K);
+    }
+
+    // 以指定大小的页，将虚拟页号映射到物理页号。
+    // 使用大页时，虚拟页号和物理页号都必须按该大小对齐。
+    pub fn map_sized(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        flags: PTEFlags,
+        size: PageSize,
+    ) {
+        assert!(
+            vpn.0 % size.pages() == 0 && ppn.0 % size.pages() == 0,
+            "vpn {:?} or ppn {:?} is not aligned to {:?}",
+            vpn,
+            ppn,
+            size
+        );
+        let pte = self.find_pte_create(vpn, size);
         // 如果找到的页表项是合法的，则表示之前已经映射过了，报错。
-        assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
-        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        assert!(
+            pte.as_ref().map_or(false, |pte| !pte.is_valid()),
+            "vpn {:?} is mapped before mapping",
+            vpn
+        );
+        *pte.unwrap() = PageTableEntry::new(ppn, flags | PTEFlags::V);
     }
 
-    // 取消虚拟页号的映射
-    pub fn unmap(&mut self, vpn: VirtPageNum) {
-        let pte = self.find_pte(vpn).unwrap();
+    // 取消虚拟页号的映射。如果它位于大页中，整个大页的映射都会被取消。
+    // 返回被取消映射的页的大小。
+    pub fn unmap(&mut self, vpn: VirtPageNum) -> PageSize {
+        let (pte, size) = self.find_pte(vpn).unwrap();
         // 如果找到的页表项是非法的，则表示之前没有映射过，报错。
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
+        size
     }
 
     // 构造CSR寄存器satp的值，使得分页模式为SV39。satp用于控制MMU的行为。
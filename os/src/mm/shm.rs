@@ -0,0 +1,176 @@
+//! System V风格的共享内存段。
+//!
+//! 共享内存段由一组物理页帧组成，由内核统一管理，通过共享内存标识符（shmid）访问。
+//! 进程可以将其挂接（attach）到自己的地址空间，多个进程挂接同一个段时，映射到的是相同的物理页帧。
+//! 共享内存段的物理页帧采用引用计数管理：段被删除（IPC_RMID）、且所有挂接都被解除后，才会被回收。
+
+use super::{
+    address::{VirtAddr, VirtPageNum},
+    frame_alloc, FrameTracker, MapPermission, MemorySet,
+};
+use crate::{config::PAGE_SIZE, sync::UPIntrFreeCell};
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use lazy_static::*;
+
+// 私有的共享内存段。使用该key时，总是创建新的段。
+pub const IPC_PRIVATE: usize = 0;
+
+bitflags! {
+    // shmget和shmat的标志位
+    pub struct ShmFlags: u32 {
+        const CREAT = 0o1000;    // 段不存在时，创建它
+        const EXCL = 0o2000;     // 与CREAT一起使用，段已存在时报错
+        const RDONLY = 0o10000;  // 以只读方式挂接
+    }
+}
+
+// 共享内存段
+pub struct SharedMemory {
+    // 创建时指定的key
+    pub key: usize,
+    // 创建时指定的大小（字节）。实际分配的物理页帧按页对齐。
+    pub size: usize,
+    frames: Vec<FrameTracker>,
+    // 当前被挂接的次数
+    nattch: UPIntrFreeCell<usize>,
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+// shmctl的IPC_STAT命令返回的段信息
+pub struct ShmIdDs {
+    pub key: usize,
+    pub size: usize,
+    pub nattch: usize,
+}
+
+impl SharedMemory {
+    // 创建共享内存段，并为其分配（清零的）物理页帧。物理内存不足时返回None。
+    fn new(key: usize, size: usize) -> Option<Self> {
+        let pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+        let mut frames = Vec::with_capacity(pages);
+        for _ in 0..pages {
+            frames.push(frame_alloc()?);
+        }
+        Some(Self {
+            key,
+            size,
+            frames,
+            nattch: unsafe { UPIntrFreeCell::new(0) },
+        })
+    }
+
+    // 段所占的页数
+    pub fn pages(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn stat(&self) -> ShmIdDs {
+        ShmIdDs {
+            key: self.key,
+            size: self.size,
+            nattch: *self.nattch.exclusive_access(),
+        }
+    }
+}
+
+// 进程对共享内存段的一次挂接。它持有共享内存段的引用，drop时解除挂接。
+pub struct ShmAttachment {
+    // 挂接到的虚拟地址，按页对齐
+    pub start: VirtAddr,
+    pub segment: Arc<SharedMemory>,
+    pub perm: MapPermission,
+}
+
+impl ShmAttachment {
+    pub fn new(segment: Arc<SharedMemory>, start: VirtAddr, perm: MapPermission) -> Self {
+        *segment.nattch.exclusive_access() += 1;
+        Self {
+            start,
+            segment,
+            perm,
+        }
+    }
+
+    // 将共享内存段的物理页帧，映射到地址空间中
+    pub fn map(&self, memory_set: &mut MemorySet) {
+        let start_vpn = self.start.floor();
+        for (i, frame) in self.segment.frames.iter().enumerate() {
+            memory_set.map(VirtPageNum(start_vpn.0 + i), frame.ppn, self.perm);
+        }
+    }
+
+    // 在地址空间中取消映射。物理页帧仍由共享内存段管理，不会被回收。
+    pub fn unmap(&self, memory_set: &mut MemorySet) {
+        let start_vpn = self.start.floor();
+        for i in 0..self.segment.pages() {
+            memory_set.unmap(VirtPageNum(start_vpn.0 + i));
+        }
+    }
+
+    // 为fork出的子进程复制挂接关系。子进程映射到相同的物理页帧。
+    pub fn duplicate(&self, memory_set: &mut MemorySet) -> Self {
+        let attachment = Self::new(Arc::clone(&self.segment), self.start, self.perm);
+        attachment.map(memory_set);
+        attachment
+    }
+}
+
+impl Drop for ShmAttachment {
+    fn drop(&mut self) {
+        *self.segment.nattch.exclusive_access() -= 1;
+    }
+}
+
+// 管理系统中所有的共享内存段
+pub struct ShmManager {
+    next_id: usize,
+    segments: BTreeMap<usize, Arc<SharedMemory>>,
+}
+
+impl ShmManager {
+    fn new() -> Self {
+        Self {
+            next_id: 0,
+            segments: BTreeMap::new(),
+        }
+    }
+
+    // 根据key查找或创建共享内存段，返回其标识符。失败时返回None。
+    pub fn get(&mut self, key: usize, size: usize, flags: ShmFlags) -> Option<usize> {
+        if key != IPC_PRIVATE {
+            if let Some((&id, segment)) = self.segments.iter().find(|(_, s)| s.key == key) {
+                if flags.contains(ShmFlags::CREAT | ShmFlags::EXCL) || size > segment.size {
+                    return None;
+                }
+                return Some(id);
+            }
+            if !flags.contains(ShmFlags::CREAT) {
+                return None;
+            }
+        }
+        if size == 0 {
+            return None;
+        }
+        let segment = SharedMemory::new(key, size)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.segments.insert(id, Arc::new(segment));
+        Some(id)
+    }
+
+    pub fn find(&self, id: usize) -> Option<Arc<SharedMemory>> {
+        self.segments.get(&id).cloned()
+    }
+
+    // 删除共享内存段的标识符。此后不能再挂接该段，但已有的挂接不受影响。
+    // 物理页帧在最后一个挂接被解除时回收。
+    pub fn remove(&mut self, id: usize) -> bool {
+        self.segments.remove(&id).is_some()
+    }
+}
+
+lazy_static! {
+    pub static ref SHM_MANAGER: UPIntrFreeCell<ShmManager> =
+        unsafe { UPIntrFreeCell::new(ShmManager::new()) };
+}
@@ -0,0 +1,238 @@
+//! Slab分配器，用于频繁分配和释放的小对象，如TaskControlBlock、BlockCache、FileMapping等。
+//!
+//! 每种对象尺寸（size class）对应一个SlabCache。每个slab占用一个页：
+//! 页的末尾存放slab的元数据SlabHeader，其余空间被切分成大小相同的对象。
+//! 由于对象从页的开头排列，尺寸为2的幂的对象天然按其尺寸对齐。
+//! slab的页优先直接从物理页帧分配器申请，完全空闲时归还，见heap_allocator.rs。
+
+use crate::config::PAGE_SIZE;
+use core::alloc::Layout;
+use core::mem::size_of;
+use core::ptr::{null_mut, NonNull};
+
+// 对象的尺寸类别。96、192等非2的幂的类别，用于减少内部碎片。
+// 超过最大类别的分配请求，直接交给伙伴系统处理。
+pub const SLAB_SIZE_CLASSES: [usize; 12] = [8, 16, 32, 64, 96, 128, 192, 256, 384, 512, 768, 1024];
+pub const SLAB_CLASS_COUNT: usize = SLAB_SIZE_CLASSES.len();
+
+// 空闲对象。对象空闲时，其开头存放下一个空闲对象的地址，形成链表。
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+// slab所在页的来源，归还时要还到原处
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlabSource {
+    // 伙伴系统管理的堆空间
+    Buddy,
+    // 直接从物理页帧分配器申请的页帧
+    Frame,
+}
+
+// slab的元数据，位于slab所在页的末尾
+struct SlabHeader {
+    // 链接同一个SlabCache中，尚有空闲对象的slab
+    prev: *mut SlabHeader,
+    next: *mut SlabHeader,
+    // 该slab的空闲对象链表
+    free: *mut FreeObject,
+    // 该slab中已被分配的对象数
+    in_use: usize,
+    source: SlabSource,
+}
+
+// 同一尺寸的对象的缓存
+pub struct SlabCache {
+    object_size: usize,
+    // 尚有空闲对象的slab链表。全满的slab不在链表中，释放对象时再加回来。
+    partial: *mut SlabHeader,
+    // 完全空闲的slab。保留一个，避免在分配和释放之间反复申请和归还页。
+    empty: *mut SlabHeader,
+    // 统计信息
+    slabs: usize,
+    active_objects: usize,
+    alloc_count: usize,
+    free_count: usize,
+}
+
+// SlabCache独占它管理的页，裸指针只指向这些页，因此可以在线程间转移
+unsafe impl Send for SlabCache {}
+
+#[derive(Copy, Clone, Debug, Default)]
+// SlabCache的统计信息，用于排查内存泄漏
+pub struct SlabStats {
+    // 对象的大小
+    pub object_size: usize,
+    // 每个slab能容纳的对象数
+    pub objects_per_slab: usize,
+    // 当前持有的slab（页）数
+    pub slabs: usize,
+    // 当前正在使用的对象数
+    pub active_objects: usize,
+    // 累计分配和释放的次数。两者之差就是active_objects。
+    pub alloc_count: usize,
+    pub free_count: usize,
+}
+
+// 找到能满足layout的尺寸类别的下标。
+// 除了大小，还要保证对象的对齐（即尺寸的最低位的1）满足layout的要求。
+pub fn size_class(layout: &Layout) -> Option<usize> {
+    SLAB_SIZE_CLASSES
+        .iter()
+        .position(|&size| size >= layout.size() && lowbit(size) >= layout.align())
+}
+
+// slab所占页的内存布局。slab页要按页对齐，这样才能从对象地址找到SlabHeader。
+pub fn slab_layout() -> Layout {
+    Layout::from_size_align(PAGE_SIZE, PAGE_SIZE).unwrap()
+}
+
+fn lowbit(x: usize) -> usize {
+    x & (!x + 1)
+}
+
+// 根据对象的地址，找到它所在slab的SlabHeader
+fn header_of(ptr: usize) -> *mut SlabHeader {
+    let page = ptr & !(PAGE_SIZE - 1);
+    (page + PAGE_SIZE - size_of::<SlabHeader>()) as *mut SlabHeader
+}
+
+impl SlabCache {
+    pub const fn new(object_size: usize) -> Self {
+        Self {
+            object_size,
+            partial: null_mut(),
+            empty: null_mut(),
+            slabs: 0,
+            active_objects: 0,
+            alloc_count: 0,
+            free_count: 0,
+        }
+    }
+
+    // 每个slab能容纳的对象数
+    fn objects_per_slab(&self) -> usize {
+        (PAGE_SIZE - size_of::<SlabHeader>()) / self.object_size
+    }
+
+    // 将一个新的页加入该缓存，切分成对象
+    pub fn add_slab(&mut self, page: NonNull<u8>, source: SlabSource) {
+        let page = page.as_ptr() as usize;
+        assert_eq!(page % PAGE_SIZE, 0, "slab page {:#x} is not aligned", page);
+        let header = header_of(page);
+        // 将页中的对象串成空闲链表
+        let mut free: *mut FreeObject = null_mut();
+        for i in (0..self.objects_per_slab()).rev() {
+            let object = (page + i * self.object_size) as *mut FreeObject;
+            unsafe {
+                (*object).next = free;
+            }
+            free = object;
+        }
+        unsafe {
+            *header = SlabHeader {
+                prev: null_mut(),
+                next: null_mut(),
+                free,
+                in_use: 0,
+                source,
+            };
+        }
+        self.slabs += 1;
+        self.push_partial(header);
+    }
+
+    // 分配一个对象。如果没有空闲对象，返回None，由调用者调用add_slab补充页后再试。
+    pub fn alloc(&mut self) -> Option<NonNull<u8>> {
+        if self.partial.is_null() {
+            if self.empty.is_null() {
+                return None;
+            }
+            // 启用保留的空闲slab
+            let empty = self.empty;
+            self.empty = null_mut();
+            self.push_partial(empty);
+        }
+        let header = unsafe { &mut *self.partial };
+        let object = header.free;
+        header.free = unsafe { (*object).next };
+        header.in_use += 1;
+        // slab已满，移出partial链表
+        if header.free.is_null() {
+            self.remove_partial(header);
+        }
+        self.active_objects += 1;
+        self.alloc_count += 1;
+        NonNull::new(object as *mut u8)
+    }
+
+    // 释放一个对象。如果它所在的slab变得完全空闲，且已经保留了一个空闲slab，
+    // 则返回该slab的页及其来源，由调用者归还给伙伴系统或物理页帧分配器。
+    pub fn dealloc(&mut self, ptr: NonNull<u8>) -> Option<(NonNull<u8>, SlabSource)> {
+        let object = ptr.as_ptr() as *mut FreeObject;
+        let header_ptr = header_of(object as usize);
+        let header = unsafe { &mut *header_ptr };
+        // 原先全满的slab，重新加回partial链表
+        if header.free.is_null() {
+            self.push_partial(header_ptr);
+        }
+        unsafe {
+            (*object).next = header.free;
+        }
+        header.free = object;
+        header.in_use -= 1;
+        self.active_objects -= 1;
+        self.free_count += 1;
+        if header.in_use > 0 {
+            return None;
+        }
+        self.remove_partial(header_ptr);
+        if self.empty.is_null() {
+            self.empty = header_ptr;
+            None
+        } else {
+            self.slabs -= 1;
+            let page = NonNull::new((header_ptr as usize & !(PAGE_SIZE - 1)) as *mut u8)?;
+            Some((page, header.source))
+        }
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        SlabStats {
+            object_size: self.object_size,
+            objects_per_slab: self.objects_per_slab(),
+            slabs: self.slabs,
+            active_objects: self.active_objects,
+            alloc_count: self.alloc_count,
+            free_count: self.free_count,
+        }
+    }
+
+    fn push_partial(&mut self, header: *mut SlabHeader) {
+        unsafe {
+            (*header).prev = null_mut();
+            (*header).next = self.partial;
+            if !self.partial.is_null() {
+                (*self.partial).prev = header;
+            }
+        }
+        self.partial = header;
+    }
+
+    fn remove_partial(&mut self, header: *mut SlabHeader) {
+        unsafe {
+            let prev = (*header).prev;
+            let next = (*header).next;
+            if prev.is_null() {
+                self.partial = next;
+            } else {
+                (*prev).next = next;
+            }
+            if !next.is_null() {
+                (*next).prev = prev;
+            }
+            (*header).prev = null_mut();
+            (*header).next = null_mut();
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! 内核的熵源和伪随机数生成器。
+//!
+//! 熵池在启动时用计时器的值初始化，之后每次发生中断时，把当时的时钟周期数混入熵池。
+//! 中断到来的时刻受外设和用户程序行为的影响，难以预测，可以作为随机性的来源。
+//! 随机数由熵池的状态经splitmix64算法生成，它不是密码学安全的，只用于地址空间布局随机化等场景。
+
+use crate::config::PAGE_SIZE;
+use crate::sync::UPIntrFreeCell;
+use crate::timer::get_time;
+use lazy_static::*;
+
+// splitmix64的增量（黄金分割数）
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+struct EntropyPool {
+    state: u64,
+}
+
+impl EntropyPool {
+    // 将一个值混入熵池
+    fn mix(&mut self, value: u64) {
+        self.state = splitmix64(self.state ^ value);
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        splitmix64(self.state)
+    }
+}
+
+fn splitmix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+lazy_static! {
+    static ref ENTROPY_POOL: UPIntrFreeCell<EntropyPool> = unsafe {
+        UPIntrFreeCell::new(EntropyPool {
+            state: splitmix64(get_time() as u64),
+        })
+    };
+}
+
+// 将事件发生的时刻混入熵池。在中断处理中调用。
+pub fn add_entropy(value: usize) {
+    ENTROPY_POOL
+        .exclusive_access()
+        .mix(value as u64 ^ get_time() as u64);
+}
+
+pub fn random_u64() -> u64 {
+    ENTROPY_POOL.exclusive_access().next()
+}
+
+// 返回[0, bound)范围内的随机数。bound为0时返回0。
+pub fn random_below(bound: usize) -> usize {
+    if bound == 0 {
+        return 0;
+    }
+    (random_u64() % bound as u64) as usize
+}
+
+// 用随机数填满buf
+#[allow(unused)]
+pub fn fill_bytes(buf: &mut [u8]) {
+    let mut pool = ENTROPY_POOL.exclusive_access();
+    for chunk in buf.chunks_mut(8) {
+        let bytes = pool.next().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+// 地址空间布局随机化（ASLR）使用的随机偏移：[0, max_pages)页中的随机页数，按页对齐。
+// 未开启aslr特性时总是返回0，地址空间布局保持固定。
+pub fn aslr_offset(max_pages: usize) -> usize {
+    if cfg!(feature = "aslr") {
+        random_below(max_pages) * PAGE_SIZE
+    } else {
+        0
+    }
+}
@@ -3,8 +3,10 @@ mod condvar;
 mod mutex;
 mod semaphore;
 mod up;
+mod wait_queue;
 
 pub use condvar::Condvar;
 pub use mutex::{Mutex, MutexBlocking, MutexSpin};
 pub use semaphore::Semaphore;
 pub use up::{UPIntrFreeCell, UPIntrRefMut};
+pub use wait_queue::{WaitQueue, Waiter};
@@ -54,6 +54,19 @@ impl<T> UPIntrFreeCell<T> {
         UPIntrRefMut(Some(self.inner.borrow_mut()))
     }
 
+    // 如果数据已经被借用，返回None而不是panic。
+    // 用于可能重入的场景，如内核堆扩容时向物理页帧分配器申请页帧。
+    pub fn try_exclusive_access(&self) -> Option<UPIntrRefMut<'_, T>> {
+        INTR_MASKING_INFO.get_mut().enter();
+        match self.inner.try_borrow_mut() {
+            Ok(inner) => Some(UPIntrRefMut(Some(inner))),
+            Err(_) => {
+                INTR_MASKING_INFO.get_mut().exit();
+                None
+            }
+        }
+    }
+
     pub fn exclusive_session<F, V>(&self, f: F) -> V
     where
         F: FnOnce(&mut T) -> V,
@@ -0,0 +1,108 @@
+use crate::sync::UPIntrFreeCell;
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use crate::timer::{add_timer, remove_timer};
+use alloc::{sync::Arc, vec::Vec};
+
+// 一个等待事件的任务。
+// 同一个Waiter可以同时注册到多个等待队列中，还可以设置超时时间，但只有第一个发生的事件会唤醒任务。
+pub struct Waiter {
+    task: Arc<TaskControlBlock>,
+    inner: UPIntrFreeCell<WaiterInner>,
+}
+
+struct WaiterInner {
+    // 任务已经被唤醒，或者不再等待
+    done: bool,
+    // 是否设置了超时的定时器
+    timer: bool,
+}
+
+impl Waiter {
+    // 当前任务的Waiter
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            task: current_task().unwrap(),
+            inner: unsafe {
+                UPIntrFreeCell::new(WaiterInner {
+                    done: false,
+                    timer: false,
+                })
+            },
+        })
+    }
+
+    // 到达expire_ms（毫秒）时，由定时器唤醒任务
+    pub fn set_timeout(&self, expire_ms: usize) {
+        let mut inner = self.inner.exclusive_access();
+        inner.timer = true;
+        add_timer(expire_ms, Arc::clone(&self.task));
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.inner.exclusive_access().done
+    }
+
+    // 唤醒任务。任务已经被唤醒或不再等待时，什么也不做。
+    pub fn wake(&self) {
+        let mut inner = self.inner.exclusive_access();
+        if inner.done {
+            return;
+        }
+        inner.done = true;
+        // 定时器已经触发时，任务已经被定时器唤醒了
+        if inner.timer && !remove_timer(Arc::clone(&self.task)) {
+            return;
+        }
+        inner.timer = false;
+        drop(inner);
+        // 进程退出时，它的其他线程可能还在等待队列中，不能再被调度
+        if self.task.inner_exclusive_access().res.is_none() {
+            return;
+        }
+        wakeup_task(Arc::clone(&self.task));
+    }
+
+    // 阻塞当前任务，直到被唤醒或超时
+    pub fn wait(&self) {
+        block_current_and_run_next();
+        self.cancel();
+    }
+
+    // 不再等待。之后发生的事件不会唤醒任务，等待队列会在之后丢弃它。
+    pub fn cancel(&self) {
+        let mut inner = self.inner.exclusive_access();
+        inner.done = true;
+        if inner.timer {
+            remove_timer(Arc::clone(&self.task));
+            inner.timer = false;
+        }
+    }
+}
+
+// 等待同一个事件的任务队列。事件发生时唤醒所有的任务，由它们自己重新检查等待的条件。
+pub struct WaitQueue {
+    waiters: UPIntrFreeCell<Vec<Arc<Waiter>>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self {
+            waiters: unsafe { UPIntrFreeCell::new(Vec::new()) },
+        }
+    }
+
+    // 在事件发生时唤醒waiter。同时丢弃已经不再等待的Waiter。
+    pub fn register(&self, waiter: &Arc<Waiter>) {
+        let mut waiters = self.waiters.exclusive_access();
+        waiters.retain(|waiter| !waiter.is_done());
+        waiters.push(Arc::clone(waiter));
+    }
+
+    // 事件发生，唤醒所有等待的任务
+    pub fn wake_all(&self) {
+        let waiters = core::mem::take(&mut *self.waiters.exclusive_access());
+        for waiter in waiters {
+            waiter.wake();
+        }
+    }
+}
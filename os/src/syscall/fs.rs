@@ -1,20 +1,28 @@
 //! 文件系统相关的系统调用
 use alloc::sync::Arc;
-use core::any::Any;
+use alloc::vec::Vec;
+use core::cmp::min;
 
 use crate::config::PAGE_SIZE;
-use crate::fs::{make_pipe, open_file, OSInode, OpenFlags};
+use crate::fs::{
+    acquire_lock, conflicting_lock, create_dir, create_symlink, lookup, make_pipe, mount_at, open,
+    release_lock, sync_all, umount_at, FileLock, Flock, Inode, InodeType, LockOwner, OSInode,
+    OpenFlags,
+};
 use crate::mm::{
-    translated_byte_buffer, translated_refmut, translated_str, FileMapping, UserBuffer,
+    translated_byte_buffer, translated_refmut, translated_str, FileMapping, MapArea, MapPermission,
+    MapType, PhysAddr, UserBuffer,
 };
-use crate::task::{current_process, current_user_token};
+use crate::task::{current_process, current_user_token, SignalFlags, RLIMIT_FSIZE};
 
 // 将buf中长度为len的字节，写入到文件fd中
 // 返回值：成功写入的字节数。如果出错则返回-1。
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
+// 文件设置了NONBLOCK且需要等待时返回-EAGAIN；管道的读端已经全部关闭时发送SIGPIPE，返回-EPIPE。
+// 写常规文件时，文件长度不能超过RLIMIT_FSIZE：超出的部分不会被写入；如果一个字节都写不了，则发送SIGXFSZ。
+pub fn sys_write(fd: usize, buf: *const u8, mut len: usize) -> isize {
     let token = current_user_token();
     let process = current_process();
-    let inner = process.inner_exclusive_access();
+    let mut inner = process.inner_exclusive_access();
     if fd >= inner.fd_table.len() {
         return -1;
     }
@@ -23,15 +31,24 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
             return -1;
         }
         let file = file.clone();
+        if let Some(inode) = file.as_any().downcast_ref::<OSInode>() {
+            let limit = inner.rlimits.cur(RLIMIT_FSIZE);
+            let offset = inode.write_offset();
+            if len > 0 && offset >= limit {
+                inner.signals |= SignalFlags::SIGXFSZ;
+                return -1;
+            }
+            len = min(len, limit.saturating_sub(offset));
+        }
         drop(inner);
-        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len)))
     } else {
         -1
     }
 }
 
 // 从文件fd中读取长度为len的字节，写入到buf中
-// 返回值：成功读取的字节数。如果出错则返回-1。
+// 返回值：成功读取的字节数。如果出错则返回-1，文件设置了NONBLOCK且需要等待时返回-EAGAIN。
 pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
     let token = current_user_token();
     let process = current_process();
@@ -45,7 +62,7 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
             return -1;
         }
         drop(inner);
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len)))
     } else {
         -1
     }
@@ -54,31 +71,235 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
 // 打开一个文件
 // - path: 文件路径
 // - flags: 打开文件的标志
-// 返回值：返回打开文件的文件描述符。如果出错则返回 -1。
+// 返回值：返回打开文件的文件描述符。如果出错（包括flags中有未知的标志）则返回 -1。
 pub fn sys_open(path: *const u8, flags: u32) -> isize {
     let process = current_process();
     let token = current_user_token();
     let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
+    let Some(flags) = OpenFlags::from_bits(flags) else {
+        return -1;
+    };
+    if let Some(inode) = open(path.as_str(), flags) {
         let mut inner = process.inner_exclusive_access();
-        let fd = inner.alloc_fd();
-        inner.fd_table[fd] = Some(inode);
-        fd as isize
+        if let Some(fd) = inner.alloc_fd() {
+            inner.fd_table[fd] = Some(inode);
+            if flags.contains(OpenFlags::CLOEXEC) {
+                inner.cloexec_fds.insert(fd);
+            }
+            fd as isize
+        } else {
+            -1
+        }
     } else {
         -1
     }
 }
 
-pub fn sys_close(fd: usize) -> isize {
+// 将文件的长度改为len：缩小时丢弃超出的部分，扩大时新增的部分读出来是0，在写入之前不占用数据块。
+// 文件长度不能超过RLIMIT_FSIZE，超出时发送SIGXFSZ。
+fn truncate_inode(inode: &Arc<dyn Inode>, len: usize) -> isize {
+    if inode.inode_type() != InodeType::File {
+        return -1;
+    }
     let process = current_process();
     let mut inner = process.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
+    if len > inner.rlimits.cur(RLIMIT_FSIZE) {
+        inner.signals |= SignalFlags::SIGXFSZ;
         return -1;
     }
-    if inner.fd_table[fd].is_none() {
+    drop(inner);
+    if inode.truncate(len) {
+        0
+    } else {
+        -1
+    }
+}
+
+// 将路径path指向的常规文件的长度改为len，路径中的符号链接会被跟随
+// 返回值：成功返回0；path不存在、不是常规文件、len超过文件大小的上限或RLIMIT_FSIZE时返回-1。
+pub fn sys_truncate(path: *const u8, len: usize) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    match lookup(path.as_str(), true) {
+        Some(inode) => truncate_inode(&inode, len),
+        None => -1,
+    }
+}
+
+// 将文件fd的长度改为len，不改变fd的读写偏移量
+// 返回值：成功返回0；fd无效、不可写、不是常规文件、len超过文件大小的上限或RLIMIT_FSIZE时返回-1。
+pub fn sys_ftruncate(fd: usize, len: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return -1;
+    };
+    let file = file.clone();
+    drop(inner);
+    if !file.writable() {
+        return -1;
+    }
+    match file.as_any().downcast_ref::<OSInode>() {
+        Some(inode) => truncate_inode(&inode.clone_inner_inode(), len),
+        None => -1,
+    }
+}
+
+// 创建一个指向target的符号链接linkpath。target只被保存下来，不要求存在。
+// 返回值：成功返回0；linkpath已存在、所在目录不存在或target为空时返回-1。
+pub fn sys_symlink(target: *const u8, linkpath: *const u8) -> isize {
+    let token = current_user_token();
+    let target = translated_str(token, target);
+    let linkpath = translated_str(token, linkpath);
+    match create_symlink(target.as_str(), linkpath.as_str()) {
+        Some(_) => 0,
+        None => -1,
+    }
+}
+
+// 读出符号链接path的目标路径，写入长度为len的buf中。目标路径比buf长时被截断，不以\0结尾。
+// 返回值：写入buf的字节数；path不存在或不是符号链接时返回-1。
+pub fn sys_readlink(path: *const u8, buf: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let Some(target) = lookup(path.as_str(), false).and_then(|inode| inode.readlink()) else {
         return -1;
+    };
+    let len = min(len, target.len());
+    let mut copied = 0;
+    for slice in translated_byte_buffer(token, buf, len) {
+        slice.copy_from_slice(&target.as_bytes()[copied..copied + slice.len()]);
+        copied += slice.len();
+    }
+    copied as isize
+}
+
+// 从目录fd中读取目录项，写入长度为len的buf中。每项是一个linux_dirent64记录，
+// 包含inode号、下一项的偏移量、记录长度、文件类型和以\0结尾的名字。
+// 返回值：写入buf的字节数，目录已经读完时返回0；fd不是目录或buf放不下下一项时返回-1。
+pub fn sys_getdents64(fd: usize, buf: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return -1;
+    };
+    let file = file.clone();
+    drop(inner);
+    let Some(dirents) = file
+        .as_any()
+        .downcast_ref::<OSInode>()
+        .and_then(|inode| inode.read_dirents(len))
+    else {
+        return -1;
+    };
+    let mut copied = 0;
+    for slice in translated_byte_buffer(token, buf, dirents.len()) {
+        slice.copy_from_slice(&dirents[copied..copied + slice.len()]);
+        copied += slice.len();
+    }
+    copied as isize
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    if current_process().close_fd(fd) {
+        0
+    } else {
+        -1
+    }
+}
+
+// close_range的标志
+// 文件描述符表只属于一个进程，不与其他进程共享，CLOSE_RANGE_UNSHARE没有效果
+const CLOSE_RANGE_UNSHARE: u32 = 1 << 1;
+// 不关闭描述符，而是为它们设置FD_CLOEXEC
+const CLOSE_RANGE_CLOEXEC: u32 = 1 << 2;
+
+// 关闭[first, last]范围内所有打开的文件描述符，范围内没有打开的描述符会被跳过。
+// 返回值：成功返回0；first大于last或flags中有未知的标志时返回-1。
+pub fn sys_close_range(first: usize, last: usize, flags: u32) -> isize {
+    if first > last || flags & !(CLOSE_RANGE_UNSHARE | CLOSE_RANGE_CLOEXEC) != 0 {
+        return -1;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let end = min(last.saturating_add(1), inner.fd_table.len());
+    let fds: Vec<usize> = (first..end)
+        .filter(|fd| inner.fd_table[*fd].is_some())
+        .collect();
+    if flags & CLOSE_RANGE_CLOEXEC != 0 {
+        inner.cloexec_fds.extend(fds);
+        return 0;
+    }
+    drop(inner);
+    for fd in fds {
+        process.close_fd(fd);
+    }
+    0
+}
+
+// 创建目录path
+// 返回值：成功返回0；path已存在或所在目录不存在时返回-1。
+pub fn sys_mkdir(path: *const u8) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    match create_dir(path.as_str()) {
+        Some(_) => 0,
+        None => -1,
+    }
+}
+
+// 将块设备source上、类型为fstype的文件系统挂载到目录target上。不需要块设备的文件系统忽略source。
+// 返回值：成功返回0；文件系统类型不存在、块设备不存在或已被挂载、target不是目录时返回-1。
+pub fn sys_mount(source: *const u8, target: *const u8, fstype: *const u8) -> isize {
+    let token = current_user_token();
+    let source = translated_str(token, source);
+    let target = translated_str(token, target);
+    let fstype = translated_str(token, fstype);
+    if mount_at(source.as_str(), target.as_str(), fstype.as_str()) {
+        0
+    } else {
+        -1
     }
-    inner.fd_table[fd].take();
+}
+
+// 卸载挂载在target上的文件系统
+// 返回值：成功返回0；target不是挂载点、是根目录、其上还挂载着其他文件系统，
+// 或其中还有文件被打开时返回-1。
+pub fn sys_umount(target: *const u8) -> isize {
+    let token = current_user_token();
+    let target = translated_str(token, target);
+    if umount_at(target.as_str()) {
+        0
+    } else {
+        -1
+    }
+}
+
+// 将所有文件系统中被修改过的数据写回磁盘
+pub fn sys_sync() -> isize {
+    sync_all();
+    0
+}
+
+// 将文件fd的修改写回磁盘，包括当前进程通过mmap映射该文件时，写入内存的内容。
+// 返回值：成功返回0；fd无效或不是常规文件时返回-1。
+pub fn sys_fsync(fd: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -1,
+    };
+    let inode = match file.as_any().downcast_ref::<OSInode>() {
+        Some(inode) => inode.clone_inner_inode(),
+        None => return -1,
+    };
+    if let Some(mapping) = inner.find_file_mapping_mut(&inode) {
+        mapping.sync();
+    }
+    drop(inner);
+    inode.superblock().sync();
     0
 }
 
@@ -103,10 +324,39 @@ pub fn sys_mmap(fd: usize, len: usize, offset: usize) -> isize {
         return -1;
     }
 
-    let fp = tcb.fd_table[fd].as_ref().unwrap();
-    let any: &dyn Any = fp;
-    let opt_inode = any.downcast_ref::<OSInode>();
-    // let opt_inode = fp.as_any().downcast_ref::<OSInode>();
+    let fp = tcb.fd_table[fd].as_ref().unwrap().clone();
+    // 设备的物理内存（如显存）直接线性映射到用户空间，不经过文件映射
+    if let Some((pa, size)) = fp.mmap_region() {
+        if offset >= size || len > size - offset {
+            return -1;
+        }
+        if !tcb.can_grow_address_space(len) {
+            return -1;
+        }
+        let mut perm = MapPermission::U;
+        if fp.readable() {
+            perm |= MapPermission::R;
+        }
+        if fp.writable() {
+            perm |= MapPermission::W;
+        }
+        let start = match tcb.mmap_va_allocator.alloc(len) {
+            Some(start) => start,
+            None => return -1,
+        };
+        let pn_offset = PhysAddr::from(pa.0 + offset).floor().0 as isize - start.floor().0 as isize;
+        tcb.memory_set.push(
+            MapArea::new(
+                start,
+                (start.0 + len).into(),
+                MapType::Linear(pn_offset),
+                perm,
+            ),
+            None,
+        );
+        return start.0 as isize;
+    }
+    let opt_inode = fp.as_any().downcast_ref::<OSInode>();
     if opt_inode.is_none() {
         // must be a regular file
         return -1;
@@ -120,7 +370,15 @@ pub fn sys_mmap(fd: usize, len: usize, offset: usize) -> isize {
         return -1;
     }
 
-    let start = tcb.mmap_va_allocator.alloc(len);
+    // 检查地址空间的大小（RLIMIT_AS）
+    if !tcb.can_grow_address_space(len) {
+        return -1;
+    }
+    // mmap区域已经用完时失败
+    let start = match tcb.mmap_va_allocator.alloc(len) {
+        Some(start) => start,
+        None => return -1,
+    };
     // 现在只记录映射关系，不实际分配物理页。访问时再分配。
     if let Some(m) = tcb.find_file_mapping_mut(&file) {
         m.push(start, len, offset, perm);
@@ -140,15 +398,241 @@ pub fn sys_pipe(pipe: *mut usize) -> isize {
     let token = current_user_token();
     let mut inner = process.inner_exclusive_access();
     let (pipe_read, pipe_write) = make_pipe();
-    let read_fd = inner.alloc_fd();
+    let read_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -1,
+    };
     inner.fd_table[read_fd] = Some(pipe_read);
-    let write_fd = inner.alloc_fd();
+    let write_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => {
+            inner.fd_table[read_fd] = None;
+            return -1;
+        }
+    };
     inner.fd_table[write_fd] = Some(pipe_write);
     *translated_refmut(token, pipe) = read_fd;
     *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
     0
 }
 
+// 对设备文件fd执行控制命令cmd，arg通常是用户空间中参数结构的地址，由设备解释。
+// 返回值：成功返回0；fd无效，或文件不支持该命令时返回-1。
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return -1;
+    };
+    let file = file.clone();
+    drop(inner);
+    file.ioctl(cmd, arg)
+}
+
+// flock的操作
+const LOCK_SH: usize = 1;
+const LOCK_EX: usize = 2;
+const LOCK_NB: usize = 4;
+const LOCK_UN: usize = 8;
+
+// 对文件fd加整个文件的建议锁。operation为LOCK_SH（共享锁）、LOCK_EX（独占锁）或LOCK_UN（解锁），
+// 可以与LOCK_NB组合，锁被其他打开的文件持有时不阻塞。
+// 锁属于打开的文件，复制的文件描述符和子进程共享同一把锁，引用它的所有描述符都关闭时自动释放。
+// 返回值：成功返回0；fd无效、不是常规文件或目录、operation无效，或指定了LOCK_NB而锁被占用时返回-1。
+pub fn sys_flock(fd: usize, operation: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return -1;
+    };
+    let file = file.clone();
+    drop(inner);
+    let Some(inode) = file.as_any().downcast_ref::<OSInode>() else {
+        return -1;
+    };
+    let key = inode.lock_key();
+    let owner = inode.flock_owner();
+    let exclusive = match operation & !LOCK_NB {
+        LOCK_SH => false,
+        LOCK_EX => true,
+        LOCK_UN => {
+            release_lock(key, owner, 0, usize::MAX);
+            return 0;
+        }
+        _ => return -1,
+    };
+    // 与Linux一样，转换锁的类型时先释放原来的锁，两个持有共享锁的进程同时升级时不会互相等待
+    release_lock(key, owner, 0, usize::MAX);
+    let lock = FileLock {
+        owner,
+        exclusive,
+        start: 0,
+        end: usize::MAX,
+    };
+    if acquire_lock(key, lock, operation & LOCK_NB == 0) {
+        0
+    } else {
+        -1
+    }
+}
+
+// fcntl的命令
+const F_GETFD: usize = 1;
+const F_SETFD: usize = 2;
+const F_GETFL: usize = 3;
+const F_SETFL: usize = 4;
+const F_GETLK: usize = 5;
+const F_SETLK: usize = 6;
+const F_SETLKW: usize = 7;
+
+// 文件描述符的标志
+const FD_CLOEXEC: usize = 1;
+
+// 记录锁的类型
+const F_RDLCK: i16 = 0;
+const F_WRLCK: i16 = 1;
+const F_UNLCK: i16 = 2;
+
+// Flock中l_start的起点
+const SEEK_SET: i16 = 0;
+const SEEK_CUR: i16 = 1;
+const SEEK_END: i16 = 2;
+
+// 将Flock描述的范围转换为[start, end)，范围无效时返回None
+fn lock_range(inode: &OSInode, flock: &Flock) -> Option<(usize, usize)> {
+    let base = match flock.l_whence {
+        SEEK_SET => 0,
+        SEEK_CUR => inode.offset() as i64,
+        SEEK_END => inode.clone_inner_inode().size() as i64,
+        _ => return None,
+    };
+    let mut start = base.checked_add(flock.l_start)?;
+    let mut len = flock.l_len;
+    if len < 0 {
+        start = start.checked_add(len)?;
+        len = len.checked_neg()?;
+    }
+    if start < 0 {
+        return None;
+    }
+    let end = match len {
+        0 => usize::MAX,
+        len => (start as usize).checked_add(len as usize)?,
+    };
+    Some((start as usize, end))
+}
+
+// 对文件fd执行控制命令cmd：
+// - F_GETFD：返回文件描述符的标志，即是否设置了FD_CLOEXEC。
+// - F_SETFD：将文件描述符的标志设为arg。设置了FD_CLOEXEC的描述符在执行新程序时被关闭。
+// - F_GETFL：返回打开文件时的读写方式和状态标志（APPEND、NONBLOCK）。
+// - F_SETFL：将状态标志设为arg中的APPEND和NONBLOCK，忽略其他标志。
+// - F_GETLK：arg是Flock的地址。若Flock描述的锁与其他进程的记录锁冲突，将Flock改为其中一把冲突的锁，
+//   否则将l_type改为F_UNLCK。
+// - F_SETLK：获取或释放（l_type为F_UNLCK）Flock描述的记录锁，与其他进程的锁冲突时立即失败。
+// - F_SETLKW：同F_SETLK，但冲突时阻塞等待，等待会导致进程间死锁时失败。
+// 记录锁属于进程，关闭该文件的任意一个描述符或进程退出时释放。读锁要求fd可读，写锁要求fd可写。
+// 返回值：F_GETFD和F_GETFL返回标志，其他命令成功返回0；fd无效、命令或参数无效、文件不支持该状态标志，
+// 对记录锁的命令中fd不是常规文件或目录、锁被占用或会导致死锁时返回-1。
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return -1;
+    };
+    let file = file.clone();
+    match cmd {
+        F_GETFD => {
+            return match inner.cloexec_fds.contains(&fd) {
+                true => FD_CLOEXEC as isize,
+                false => 0,
+            };
+        }
+        F_SETFD => {
+            if arg & FD_CLOEXEC != 0 {
+                inner.cloexec_fds.insert(fd);
+            } else {
+                inner.cloexec_fds.remove(&fd);
+            }
+            return 0;
+        }
+        _ => {}
+    }
+    drop(inner);
+    match cmd {
+        F_GETFL => {
+            let mode = match (file.readable(), file.writable()) {
+                (true, true) => OpenFlags::RDWR,
+                (false, true) => OpenFlags::WRONLY,
+                _ => OpenFlags::RDONLY,
+            };
+            (mode | file.status_flags()).bits() as isize
+        }
+        F_SETFL => {
+            let flags = OpenFlags::from_bits_truncate(arg as u32);
+            if file.set_status_flags(flags.status()) {
+                0
+            } else {
+                -1
+            }
+        }
+        F_GETLK | F_SETLK | F_SETLKW => {
+            let Some(inode) = file.as_any().downcast_ref::<OSInode>() else {
+                return -1;
+            };
+            let flock = translated_refmut(token, arg as *mut Flock);
+            let Some((start, end)) = lock_range(inode, flock) else {
+                return -1;
+            };
+            let key = inode.lock_key();
+            let owner = LockOwner::Process(process.getpid());
+            let exclusive = match flock.l_type {
+                F_RDLCK if file.readable() => false,
+                F_WRLCK if file.writable() => true,
+                F_UNLCK if cmd != F_GETLK => {
+                    release_lock(key, owner, start, end);
+                    return 0;
+                }
+                _ => return -1,
+            };
+            let lock = FileLock {
+                owner,
+                exclusive,
+                start,
+                end,
+            };
+            if cmd != F_GETLK {
+                return if acquire_lock(key, lock, cmd == F_SETLKW) {
+                    0
+                } else {
+                    -1
+                };
+            }
+            match conflicting_lock(key, &lock) {
+                Some(held) => {
+                    let LockOwner::Process(pid) = held.owner else {
+                        unreachable!();
+                    };
+                    *flock = Flock {
+                        l_type: if held.exclusive { F_WRLCK } else { F_RDLCK },
+                        l_whence: SEEK_SET,
+                        l_start: held.start as i64,
+                        l_len: match held.end {
+                            usize::MAX => 0,
+                            end => (end - held.start) as i64,
+                        },
+                        l_pid: pid as i32,
+                    };
+                }
+                None => flock.l_type = F_UNLCK,
+            }
+            0
+        }
+        _ => -1,
+    }
+}
+
 // 将当前进程的已打开的文件，复制并分配到一个新的文件描述符中。
 // 实质是分配一个新的文件描述符，指向同一个文件对象。
 // - fd：进程的已经打开文件的描述符。
@@ -162,7 +646,28 @@ pub fn sys_dup(fd: usize) -> isize {
     if inner.fd_table[fd].is_none() {
         return -1;
     }
-    let new_fd = inner.alloc_fd();
+    let new_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -1,
+    };
     inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
     new_fd as isize
 }
+
+// 将文件描述符new_fd指向old_fd打开的文件。new_fd已经打开时，先关闭它，替换是原子的。
+// - flags：只能是0或CLOEXEC，CLOEXEC为new_fd设置FD_CLOEXEC。
+// - 返回值：成功返回new_fd；old_fd没有打开、old_fd等于new_fd、new_fd超出RLIMIT_NOFILE或flags无效时返回-1。
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    let Some(flags) = OpenFlags::from_bits(flags) else {
+        return -1;
+    };
+    if old_fd == new_fd || !(flags - OpenFlags::CLOEXEC).is_empty() {
+        return -1;
+    }
+    let cloexec = flags.contains(OpenFlags::CLOEXEC);
+    if current_process().dup_fd(old_fd, new_fd, cloexec) {
+        new_fd as isize
+    } else {
+        -1
+    }
+}
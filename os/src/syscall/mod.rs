@@ -1,9 +1,28 @@
+const SYSCALL_EPOLL_CREATE: usize = 20;
+const SYSCALL_EPOLL_CTL: usize = 21;
+const SYSCALL_EPOLL_WAIT: usize = 22;
 const SYSCALL_DUP: usize = 24;
+const SYSCALL_FCNTL: usize = 25;
+const SYSCALL_DUP3: usize = 26;
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_FLOCK: usize = 32;
+const SYSCALL_MKDIR: usize = 34;
+const SYSCALL_SYMLINK: usize = 36;
+const SYSCALL_UMOUNT: usize = 39;
+const SYSCALL_MOUNT: usize = 40;
+const SYSCALL_TRUNCATE: usize = 45;
+const SYSCALL_FTRUNCATE: usize = 46;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE: usize = 59;
+const SYSCALL_GETDENTS64: usize = 61;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_SELECT: usize = 72;
+const SYSCALL_POLL: usize = 73;
+const SYSCALL_READLINK: usize = 78;
+const SYSCALL_SYNC: usize = 81;
+const SYSCALL_FSYNC: usize = 82;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_SLEEP: usize = 101;
 const SYSCALL_YIELD: usize = 124;
@@ -11,13 +30,20 @@ const SYSCALL_KILL: usize = 129;
 const SYSCALL_SIGACTION: usize = 134;
 const SYSCALL_SIGPROCMASK: usize = 135;
 const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_GETRLIMIT: usize = 163;
+const SYSCALL_SETRLIMIT: usize = 164;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_SBRK: usize = 214;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_MMAP: usize = 222;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_CLOSE_RANGE: usize = 436;
 const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GETTID: usize = 1001;
 const SYSCALL_WAITTID: usize = 1002;
@@ -35,29 +61,69 @@ const SYSCALL_FRAMEBUFFER_FLUSH: usize = 2001;
 
 mod fs;
 mod gui;
+mod poll;
 mod process;
+mod shm;
 mod sync;
 mod thread;
 
 use fs::*;
 use gui::*;
+use poll::*;
 use process::*;
+use shm::*;
 use sync::*;
 use thread::*;
 
-use crate::task::SignalAction;
+use crate::fs::EpollEvent;
+use crate::mm::ShmIdDs;
+use crate::task::{RLimit, SignalAction};
 
 // 实现系统调用
 // 程序调用ecall指令时，将触发系统调用（UserEnvCall类型的异常），并由trap_handler方法处理，最后进入本方法。
 // 这里不关心哪些寄存器存放参数和返回值。这由trap_handler方法确定。
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
     match syscall_id {
+        SYSCALL_EPOLL_CREATE => sys_epoll_create1(args[0] as u32),
+        SYSCALL_EPOLL_CTL => sys_epoll_ctl(args[0], args[1], args[2], args[3] as *const EpollEvent),
+        SYSCALL_EPOLL_WAIT => sys_epoll_wait(
+            args[0],
+            args[1] as *mut EpollEvent,
+            args[2],
+            args[3] as isize,
+        ),
         SYSCALL_DUP => sys_dup(args[0]),
+        SYSCALL_DUP3 => sys_dup3(args[0], args[1], args[2] as u32),
+        SYSCALL_FCNTL => sys_fcntl(args[0], args[1], args[2]),
+        SYSCALL_IOCTL => sys_ioctl(args[0], args[1], args[2]),
+        SYSCALL_FLOCK => sys_flock(args[0], args[1]),
+        SYSCALL_MKDIR => sys_mkdir(args[0] as *const u8),
+        SYSCALL_SYMLINK => sys_symlink(args[0] as *const u8, args[1] as *const u8),
+        SYSCALL_UMOUNT => sys_umount(args[0] as *const u8),
+        SYSCALL_MOUNT => sys_mount(
+            args[0] as *const u8,
+            args[1] as *const u8,
+            args[2] as *const u8,
+        ),
+        SYSCALL_TRUNCATE => sys_truncate(args[0] as *const u8, args[1]),
+        SYSCALL_FTRUNCATE => sys_ftruncate(args[0], args[1]),
         SYSCALL_OPEN => sys_open(args[0] as *const u8, args[1] as u32),
         SYSCALL_CLOSE => sys_close(args[0]),
         SYSCALL_PIPE => sys_pipe(args[0] as *mut usize),
+        SYSCALL_GETDENTS64 => sys_getdents64(args[0], args[1] as *mut u8, args[2]),
         SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_SELECT => sys_select(
+            args[0],
+            args[1] as *mut FdSet,
+            args[2] as *mut FdSet,
+            args[3] as *mut FdSet,
+            args[4] as isize,
+        ),
+        SYSCALL_POLL => sys_poll(args[0] as *mut PollFd, args[1], args[2] as isize),
+        SYSCALL_READLINK => sys_readlink(args[0] as *const u8, args[1] as *mut u8, args[2]),
+        SYSCALL_SYNC => sys_sync(),
+        SYSCALL_FSYNC => sys_fsync(args[0]),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
         SYSCALL_SLEEP => sys_sleep(args[0]),
         SYSCALL_YIELD => sys_yield(),
@@ -69,13 +135,20 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         ),
         SYSCALL_SIGPROCMASK => sys_sigprocmask(args[0] as u32),
         SYSCALL_SIGRETURN => sys_sigreturn(),
+        SYSCALL_GETRLIMIT => sys_getrlimit(args[0], args[1] as *mut RLimit),
+        SYSCALL_SETRLIMIT => sys_setrlimit(args[0], args[1] as *const RLimit),
         SYSCALL_GET_TIME => sys_get_time(),
         SYSCALL_SBRK => sys_sbrk(args[0] as i32),
         SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_SHMGET => sys_shmget(args[0], args[1], args[2] as u32),
+        SYSCALL_SHMCTL => sys_shmctl(args[0], args[1], args[2] as *mut ShmIdDs),
+        SYSCALL_SHMAT => sys_shmat(args[0], args[1], args[2] as u32),
+        SYSCALL_SHMDT => sys_shmdt(args[0]),
         SYSCALL_FORK => sys_fork(),
         SYSCALL_EXEC => sys_exec(args[0] as *const u8, args[1] as *const usize),
         SYSCALL_MMAP => sys_mmap(args[0] as usize, args[1] as usize, args[2] as usize),
         SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_CLOSE_RANGE => sys_close_range(args[0], args[1], args[2] as u32),
         SYSCALL_THREAD_CREATE => sys_thread_create(args[0], args[1]),
         SYSCALL_GETTID => sys_gettid(),
         SYSCALL_WAITTID => sys_waittid(args[0]) as isize,
@@ -0,0 +1,251 @@
+//! 等待多个文件描述符就绪的系统调用：poll、select和epoll
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::fs::{wait_ready, Epoll, EpollEvent, File, OpenFlags, PollEvents};
+use crate::mm::{translated_ref, translated_refmut};
+use crate::task::{current_process, current_user_token, RLIMIT_NOFILE};
+
+#[repr(C)]
+// poll检查的一个文件描述符，与Linux的struct pollfd相同
+pub struct PollFd {
+    // 为负数时忽略该项，revents为0
+    fd: i32,
+    events: i16,
+    revents: i16,
+}
+
+// select的文件描述符集合能容纳的描述符数
+const FD_SETSIZE: usize = 1024;
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+// select使用的文件描述符集合，每一位对应一个描述符，与Linux的fd_set相同
+pub struct FdSet {
+    bits: [u64; FD_SETSIZE / 64],
+}
+
+impl FdSet {
+    fn contains(&self, fd: usize) -> bool {
+        self.bits[fd / 64] & (1 << (fd % 64)) != 0
+    }
+
+    fn insert(&mut self, fd: usize) {
+        self.bits[fd / 64] |= 1 << (fd % 64);
+    }
+}
+
+// epoll_ctl的操作
+const EPOLL_CTL_ADD: usize = 1;
+const EPOLL_CTL_DEL: usize = 2;
+const EPOLL_CTL_MOD: usize = 3;
+
+// 超时时间（毫秒）。负数表示一直等待。
+fn timeout_ms(timeout: isize) -> Option<usize> {
+    (timeout >= 0).then_some(timeout as usize)
+}
+
+// 等待fds中的任意一个文件描述符就绪，或者超时。
+// - fds：nfds个PollFd组成的数组。返回时，每一项的revents被设为就绪的事件。
+//   IN、OUT只在events中指定时才会返回；ERR、HUP总是会返回；描述符没有打开时返回NVAL。
+// - timeout：超时时间（毫秒），为0时不等待，为负数时一直等待。
+// 返回值：revents不为0的项数，超时时返回0；nfds超出RLIMIT_NOFILE时返回-1。
+pub fn sys_poll(fds: *mut PollFd, nfds: usize, timeout: isize) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if nfds > inner.rlimits.cur(RLIMIT_NOFILE) {
+        return -1;
+    }
+    // 每一项的文件和关心的事件。忽略的项为None，没有打开的描述符为Some(None)。
+    let mut polled = Vec::with_capacity(nfds);
+    for i in 0..nfds {
+        let pollfd = translated_ref(token, unsafe { fds.add(i) });
+        let file = usize::try_from(pollfd.fd)
+            .ok()
+            .map(|fd| inner.fd_table.get(fd).cloned().flatten());
+        let events = PollEvents::from_bits_truncate(pollfd.events as u16 as u32);
+        polled.push((file, events));
+    }
+    drop(inner);
+    let mut revents = vec![PollEvents::empty(); nfds];
+    let ready = wait_ready(timeout_ms(timeout), |waiter| {
+        for ((file, events), revents) in polled.iter().zip(revents.iter_mut()) {
+            *revents = match file {
+                None => PollEvents::empty(),
+                Some(None) => PollEvents::NVAL,
+                Some(Some(file)) => file.poll(*events, waiter),
+            };
+        }
+        revents.iter().filter(|revents| !revents.is_empty()).count()
+    });
+    for (i, revents) in revents.iter().enumerate() {
+        translated_refmut(token, unsafe { fds.add(i) }).revents = revents.bits() as i16;
+    }
+    ready as isize
+}
+
+// 等待[0, nfds)中的文件描述符可读或可写，或者超时。
+// - readfds、writefds、exceptfds：要检查的描述符集合，可以为空指针。返回时只留下就绪的描述符。
+//   出错或挂断的描述符也算作可读，出错的描述符也算作可写。没有描述符会出现异常情况，exceptfds总是被清空。
+// - timeout：超时时间（毫秒），为0时不等待，为负数时一直等待。
+// 返回值：三个集合中就绪的描述符总数，超时时返回0；nfds超出FD_SETSIZE或集合中有没有打开的描述符时返回-1。
+pub fn sys_select(
+    nfds: usize,
+    readfds: *mut FdSet,
+    writefds: *mut FdSet,
+    exceptfds: *mut FdSet,
+    timeout: isize,
+) -> isize {
+    if nfds > FD_SETSIZE {
+        return -1;
+    }
+    let token = current_user_token();
+    let sets = [readfds, writefds, exceptfds].map(|set| {
+        if set.is_null() {
+            FdSet::default()
+        } else {
+            *translated_ref(token, set)
+        }
+    });
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let mut selected: Vec<(usize, Arc<dyn File + Send + Sync>)> = Vec::new();
+    for fd in (0..nfds).filter(|fd| sets.iter().any(|set| set.contains(*fd))) {
+        let Some(Some(file)) = inner.fd_table.get(fd) else {
+            return -1;
+        };
+        selected.push((fd, file.clone()));
+    }
+    drop(inner);
+    let mut result = [FdSet::default(); 3];
+    let ready = wait_ready(timeout_ms(timeout), |waiter| {
+        result = [FdSet::default(); 3];
+        let mut ready = 0;
+        for (fd, file) in selected.iter() {
+            let mut events = PollEvents::empty();
+            if sets[0].contains(*fd) {
+                events |= PollEvents::IN;
+            }
+            if sets[1].contains(*fd) {
+                events |= PollEvents::OUT;
+            }
+            let revents = file.poll(events, waiter);
+            if sets[0].contains(*fd)
+                && revents.intersects(PollEvents::IN | PollEvents::HUP | PollEvents::ERR)
+            {
+                result[0].insert(*fd);
+                ready += 1;
+            }
+            if sets[1].contains(*fd) && revents.intersects(PollEvents::OUT | PollEvents::ERR) {
+                result[1].insert(*fd);
+                ready += 1;
+            }
+        }
+        ready
+    });
+    for (set, result) in [readfds, writefds, exceptfds].into_iter().zip(result) {
+        if !set.is_null() {
+            *translated_refmut(token, set) = result;
+        }
+    }
+    ready as isize
+}
+
+// 创建一个epoll实例，返回引用它的文件描述符。
+// - flags：0或OpenFlags::CLOEXEC（EPOLL_CLOEXEC）
+// 返回值：成功返回文件描述符；flags无效或文件描述符已用完时返回-1。
+pub fn sys_epoll_create1(flags: u32) -> isize {
+    let Some(flags) = OpenFlags::from_bits(flags) else {
+        return -1;
+    };
+    if !(flags - OpenFlags::CLOEXEC).is_empty() {
+        return -1;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let Some(fd) = inner.alloc_fd() else {
+        return -1;
+    };
+    inner.fd_table[fd] = Some(Arc::new(Epoll::new()));
+    if flags.contains(OpenFlags::CLOEXEC) {
+        inner.cloexec_fds.insert(fd);
+    }
+    fd as isize
+}
+
+// 修改epoll实例epfd关注的文件描述符。
+// - op：EPOLL_CTL_ADD关注fd，EPOLL_CTL_MOD修改关注的事件，EPOLL_CTL_DEL不再关注fd（忽略event）。
+// - event：关注的事件（IN、OUT）和epoll_wait时原样返回的用户数据。不支持边沿触发等其他标志。
+// 关闭fd引用的文件的所有描述符后，文件自动从关注列表中移除。
+// 返回值：成功返回0；epfd不是epoll实例、fd没有打开或是epoll实例、op或事件无效，
+// 添加已经关注的fd、修改或删除没有关注的fd时返回-1。
+pub fn sys_epoll_ctl(epfd: usize, op: usize, fd: usize, event: *const EpollEvent) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let (Some(Some(epoll)), Some(Some(file))) = (inner.fd_table.get(epfd), inner.fd_table.get(fd))
+    else {
+        return -1;
+    };
+    let (epoll, file) = (epoll.clone(), file.clone());
+    drop(inner);
+    let Some(epoll) = epoll.as_any().downcast_ref::<Epoll>() else {
+        return -1;
+    };
+    // 不支持嵌套的epoll实例
+    if file.as_any().is::<Epoll>() {
+        return -1;
+    }
+    let read_event = || {
+        let event = *translated_ref(token, event);
+        PollEvents::from_bits(event.events).map(|_| event)
+    };
+    let done = match op {
+        EPOLL_CTL_ADD => read_event().is_some_and(|event| epoll.add(fd, &file, event)),
+        EPOLL_CTL_MOD => read_event().is_some_and(|event| epoll.modify(fd, event)),
+        EPOLL_CTL_DEL => epoll.remove(fd),
+        _ => false,
+    };
+    if done {
+        0
+    } else {
+        -1
+    }
+}
+
+// 等待epoll实例epfd关注的文件就绪，或者超时。
+// - events：最多max_events个EpollEvent组成的数组，存放就绪的文件的事件和用户数据。
+// - timeout：超时时间（毫秒），为0时不等待，为负数时一直等待。
+// 返回值：就绪的文件数，超时时返回0；epfd不是epoll实例或max_events为0时返回-1。
+pub fn sys_epoll_wait(
+    epfd: usize,
+    events: *mut EpollEvent,
+    max_events: usize,
+    timeout: isize,
+) -> isize {
+    if max_events == 0 {
+        return -1;
+    }
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(epfd) else {
+        return -1;
+    };
+    let file = file.clone();
+    drop(inner);
+    let Some(epoll) = file.as_any().downcast_ref::<Epoll>() else {
+        return -1;
+    };
+    let mut ready = Vec::new();
+    wait_ready(timeout_ms(timeout), |waiter| {
+        epoll.check(waiter, &mut ready, max_events);
+        ready.len()
+    });
+    for (i, event) in ready.iter().enumerate() {
+        *translated_refmut(token, unsafe { events.add(i) }) = *event;
+    }
+    ready.len() as isize
+}
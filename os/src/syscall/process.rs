@@ -3,8 +3,8 @@ use crate::{
     mm::{translated_ref, translated_refmut, translated_str},
     task::{
         current_process, current_task, current_task_pid, current_user_token,
-        exit_current_and_run_next, pid2process, suspend_current_and_run_next, SignalAction,
-        SignalFlags, MAX_SIG,
+        exit_current_and_run_next, pid2process, suspend_current_and_run_next, task_count, RLimit,
+        SignalAction, SignalFlags, MAX_SIG, RLIMIT_NPROC,
     },
     timer::get_time_ms,
 };
@@ -89,6 +89,14 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
 // 返回值：当前进程返回子进程的PID，子进程则返回0
 pub fn sys_fork() -> isize {
     let current_process = current_process();
+    // 检查系统中的任务数（RLIMIT_NPROC）
+    let nproc_limit = current_process
+        .inner_exclusive_access()
+        .rlimits
+        .cur(RLIMIT_NPROC);
+    if task_count() >= nproc_limit {
+        return -1;
+    }
     let new_process = current_process.fork();
     let new_pid = new_process.getpid();
 
@@ -104,7 +112,7 @@ pub fn sys_fork() -> isize {
 }
 
 // 将程序加载到当前进程的地址空间，并开始执行。
-// - path：该程序的名字，系统能通过它找到其ELF二进制数据。从根目录找。
+// - path：该程序的路径，系统能通过它找到其ELF二进制数据。从根目录找，路径中的符号链接会被跟随。
 // - args：参数列表。类型为字符串数组，每个元素是一个字符串的起始地址。
 // - 返回值：执行成功则不返回，失败则返回-1。
 pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
@@ -123,10 +131,17 @@ pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
         }
     }
     if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
+        // 目录不是可执行文件
+        if app_inode.clone_inner_inode().is_dir() {
+            return -1;
+        }
         let data = app_inode.read_all();
         let process = current_process();
         let argc = args_vec.len();
-        process.exec(data.as_slice(), args_vec);
+        // 不是合法的ELF文件时，exec失败，当前程序继续执行
+        if !process.exec(data.as_slice(), args_vec) {
+            return -1;
+        }
         argc as isize // 这个返回值会被赋给x[10]
     } else {
         -1
@@ -225,3 +240,33 @@ pub fn sys_kill(pid: usize, signum: i32) -> isize {
         -1
     }
 }
+
+// 获取当前进程的资源限制
+// - resource：资源的编号，如RLIMIT_NOFILE
+// - rlim：保存资源限制的地址
+// - 返回值：成功返回0，失败返回-1（如资源不存在）
+pub fn sys_getrlimit(resource: usize, rlim: *mut RLimit) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if let Some(limit) = inner.rlimits.get(resource) {
+        *translated_refmut(token, rlim) = limit;
+        0
+    } else {
+        -1
+    }
+}
+
+// 设置当前进程的资源限制。软限制不能超过硬限制，硬限制只能调低。
+// - 返回值：成功返回0，失败返回-1
+pub fn sys_setrlimit(resource: usize, rlim: *const RLimit) -> isize {
+    let token = current_user_token();
+    let limit = *translated_ref(token, rlim);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.rlimits.set(resource, limit) {
+        0
+    } else {
+        -1
+    }
+}
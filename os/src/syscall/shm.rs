@@ -0,0 +1,114 @@
+use crate::config::PAGE_SIZE;
+use crate::mm::{
+    translated_refmut, MapPermission, ShmAttachment, ShmFlags, ShmIdDs, VirtAddr, SHM_MANAGER,
+};
+use crate::task::{current_process, current_user_token};
+
+// shmctl的命令
+const IPC_RMID: usize = 0;
+const IPC_STAT: usize = 2;
+
+// 获取共享内存段的标识符。如果key不存在，且flags包含CREAT，则创建大小为size的段。
+// - 返回值：成功返回标识符，失败返回-1。
+pub fn sys_shmget(key: usize, size: usize, flags: u32) -> isize {
+    let flags = match ShmFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -1,
+    };
+    match SHM_MANAGER.exclusive_access().get(key, size, flags) {
+        Some(id) => id as isize,
+        None => -1,
+    }
+}
+
+// 将共享内存段挂接到当前进程的地址空间。
+// - addr：挂接的虚拟地址，必须按页对齐，且整段位于mmap区域内。为0时由内核选择。
+// - 返回值：成功返回挂接的虚拟地址，失败返回-1。
+pub fn sys_shmat(shmid: usize, addr: usize, flags: u32) -> isize {
+    let flags = match ShmFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -1,
+    };
+    if addr % PAGE_SIZE != 0 {
+        return -1;
+    }
+    let segment = match SHM_MANAGER.exclusive_access().find(shmid) {
+        Some(segment) => segment,
+        None => return -1,
+    };
+    let mut perm = MapPermission::R | MapPermission::U;
+    if !flags.contains(ShmFlags::RDONLY) {
+        perm |= MapPermission::W;
+    }
+
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    // 检查地址空间的大小（RLIMIT_AS）
+    let len = segment.pages() * PAGE_SIZE;
+    if !inner.can_grow_address_space(len) {
+        return -1;
+    }
+    let start = if addr == 0 {
+        // mmap区域已经用完时失败
+        match inner.mmap_va_allocator.alloc(len) {
+            Some(start) => start,
+            None => return -1,
+        }
+    } else {
+        // 指定的地址范围必须位于mmap区域内，且没有被其他映射占用
+        if !inner.mmap_va_allocator.reserve(addr, len) {
+            return -1;
+        }
+        VirtAddr::from(addr)
+    };
+    let attachment = ShmAttachment::new(segment, start, perm);
+    attachment.map(&mut inner.memory_set);
+    inner.shm_attachments.push(attachment);
+    start.0 as isize
+}
+
+// 解除挂接在addr处的共享内存段
+// - 返回值：成功返回0，失败返回-1。
+pub fn sys_shmdt(addr: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let idx = match inner
+        .shm_attachments
+        .iter()
+        .position(|attachment| attachment.start.0 == addr)
+    {
+        Some(idx) => idx,
+        None => return -1,
+    };
+    let attachment = inner.shm_attachments.remove(idx);
+    attachment.unmap(&mut inner.memory_set);
+    inner.mmap_va_allocator.free(attachment.start);
+    0
+}
+
+// 控制共享内存段
+// - cmd：IPC_RMID删除该段；IPC_STAT将段信息写入buf。
+// - 返回值：成功返回0，失败返回-1。
+pub fn sys_shmctl(shmid: usize, cmd: usize, buf: *mut ShmIdDs) -> isize {
+    match cmd {
+        IPC_RMID => {
+            if SHM_MANAGER.exclusive_access().remove(shmid) {
+                0
+            } else {
+                -1
+            }
+        }
+        IPC_STAT => {
+            let segment = match SHM_MANAGER.exclusive_access().find(shmid) {
+                Some(segment) => segment,
+                None => return -1,
+            };
+            if buf.is_null() {
+                return -1;
+            }
+            *translated_refmut(current_user_token(), buf) = segment.stat();
+            0
+        }
+        _ => -1,
+    }
+}
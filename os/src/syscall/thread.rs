@@ -1,20 +1,34 @@
 use alloc::sync::Arc;
 
 use crate::{
+    config::{PAGE_SIZE, USER_STACK_SIZE},
     mm::kernel_token,
-    task::{add_task, current_task, TaskControlBlock},
+    task::{add_task, current_task, task_count, TaskControlBlock, RLIMIT_NPROC, RLIMIT_STACK},
     trap::{trap_handler, TrapContext},
 };
 
 // 在当前进程里，创建一个新的线程
 // - entry：线程的入口函数地址
 // - arg：入口函数的参数。0 表示没有参数。
-// - 返回值：创建的线程的 TID
+// - 返回值：创建的线程的 TID。失败返回-1（如超出资源限制）。
 // 内核会为每个线程分配专属于该线程的资源：用户栈、Trap上下文、内核栈
 // 前面两个在进程地址空间中，内核栈在内核地址空间中。
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     let task = current_task().unwrap();
     let process = task.process.upgrade().unwrap();
+    // 检查系统中的任务数（RLIMIT_NPROC）、用户栈大小（RLIMIT_STACK）和地址空间大小（RLIMIT_AS）
+    let nproc_limit = process.inner_exclusive_access().rlimits.cur(RLIMIT_NPROC);
+    if task_count() >= nproc_limit {
+        return -1;
+    }
+    {
+        let inner = process.inner_exclusive_access();
+        if USER_STACK_SIZE > inner.rlimits.cur(RLIMIT_STACK)
+            || !inner.can_grow_address_space(USER_STACK_SIZE + PAGE_SIZE)
+        {
+            return -1;
+        }
+    }
     // 创建新线程
     let new_task = Arc::new(TaskControlBlock::new(
         Arc::clone(&process),
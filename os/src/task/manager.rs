@@ -6,6 +6,7 @@ use crate::sync::UPIntrFreeCell;
 use alloc::collections::btree_map::BTreeMap;
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 
 // 任务管理器，使用FIFO调度算法。
@@ -81,6 +82,24 @@ pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
     map.get(&pid).map(Arc::clone)
 }
 
+// 系统中所有进程的PID，按从小到大的顺序
+pub fn pids() -> Vec<usize> {
+    PID2PCB.exclusive_access().keys().copied().collect()
+}
+
+// 系统中的任务数，即所有进程的线程数之和。已经退出、还没有被回收的进程算作一个任务。
+// 调用时不能持有任何进程的inner。
+pub fn task_count() -> usize {
+    PID2PCB
+        .exclusive_access()
+        .values()
+        .map(|process| {
+            let inner = process.inner_exclusive_access();
+            inner.tasks.iter().filter(|t| t.is_some()).count().max(1)
+        })
+        .sum()
+}
+
 // 增加一对PID->进程控制块映射
 pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
     PID2PCB.exclusive_access().insert(pid, process);
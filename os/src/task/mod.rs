@@ -9,16 +9,20 @@ mod id;
 mod manager;
 mod process;
 mod processor;
+mod rlimit;
 mod signal;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
 use crate::fs::open_file;
+use crate::fs::release_process_locks;
 use crate::fs::OpenFlags;
 use crate::sbi::shutdown;
 use crate::timer::remove_timer;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use id::TaskUserRes;
 use id::IDLE_PID;
@@ -29,11 +33,13 @@ use process::ProcessControlBlock;
 
 pub use action::SignalAction;
 pub use id::pid_alloc;
-pub use manager::{add_task, pid2process, wakeup_task};
+pub use manager::task_count;
+pub use manager::{add_task, pid2process, pids, wakeup_task};
 pub use processor::{
     current_kstack_top, current_process, current_task, current_task_pid, current_trap_cx,
     current_trap_cx_user_va, current_user_token, run_tasks, schedule, take_current_task,
 };
+pub use rlimit::{RLimit, RLIMIT_FSIZE, RLIMIT_NOFILE, RLIMIT_NPROC, RLIMIT_STACK};
 pub use signal::{SignalFlags, MAX_SIG};
 pub use task::{TaskControlBlock, TaskStatus};
 
@@ -134,6 +140,11 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         for mapping in process_inner.file_mappings.iter() {
             mapping.sync();
         }
+        // 解除共享内存段的挂接
+        process_inner.shm_attachments.clear();
+        // 关闭所有文件，释放进程持有的文件锁
+        process_inner.fd_table.clear();
+        release_process_locks(pid);
         while process_inner.tasks.len() > 1 {
             process_inner.tasks.pop();
         }
@@ -149,7 +160,7 @@ lazy_static! {
     pub static ref INITPROC: Arc<ProcessControlBlock> = {
         let inode = open_file("initproc", OpenFlags::RDONLY).unwrap();
         let v = inode.read_all();
-        ProcessControlBlock::new(v.as_slice())
+        ProcessControlBlock::new(v.as_slice(), vec![String::from("initproc")])
     };
 }
 
@@ -4,24 +4,27 @@ use super::{
     id::{PidHandle, RecycleAllocator},
     manager::insert_into_pid2process,
     pid_alloc,
+    rlimit::{ResourceLimits, RLIMIT_AS, RLIMIT_CPU, RLIMIT_DATA, RLIMIT_NOFILE},
     task::TaskControlBlock,
     SignalFlags,
 };
 use crate::{
-    fs::{File, Stdin, Stdout},
+    config::PAGE_SIZE,
+    fs::{open_console, release_lock, File, Inode, LockOwner, OSInode},
     mm::{
-        kernel_token, translated_refmut, FileMapping, MemorySet, VirtAddr, VirtualAddressAllocator,
+        kernel_token, translated_refmut, FileMapping, MemorySet, ShmAttachment, VirtAddr,
+        VirtualAddressAllocator,
     },
     sync::{Condvar, Mutex, Semaphore, UPIntrFreeCell, UPIntrRefMut},
     trap::{trap_handler, TrapContext},
 };
 use alloc::{
+    collections::BTreeSet,
     string::String,
     sync::{Arc, Weak},
     vec,
     vec::Vec,
 };
-use easy_fs::Inode;
 
 // 进程的控制块。进程的执行状态、资源控制等元数据，都保存在该结构体中。
 pub struct ProcessControlBlock {
@@ -43,6 +46,8 @@ pub struct ProcessControlBlockInner {
     // 文件描述符表
     // 下标就是文件描述符。如果元素为None，则表示该文件描述符未被使用，可以重新被分配。
     pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    // 设置了FD_CLOEXEC的文件描述符，执行新程序时被关闭
+    pub cloexec_fds: BTreeSet<usize>,
 
     // 线程列表。下标就是tid。
     pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
@@ -81,6 +86,15 @@ pub struct ProcessControlBlockInner {
     // mmap
     pub mmap_va_allocator: VirtualAddressAllocator,
     pub file_mappings: Vec<FileMapping>,
+    // 挂接的共享内存段。它们与mmap共用虚拟地址分配器。
+    pub shm_attachments: Vec<ShmAttachment>,
+
+    // 资源限制
+    pub rlimits: ResourceLimits,
+    // 进程已使用的CPU时间（毫秒）
+    pub cpu_time_ms: usize,
+    // 执行程序时的命令行参数，第一个参数是程序的路径
+    pub cmdline: Vec<String>,
 }
 
 impl ProcessControlBlockInner {
@@ -92,13 +106,19 @@ impl ProcessControlBlockInner {
         self.is_zombie
     }
 
-    pub fn alloc_fd(&mut self) -> usize {
+    // 分配最小的可用文件描述符，新的描述符没有设置FD_CLOEXEC。如果超出RLIMIT_NOFILE，返回None。
+    pub fn alloc_fd(&mut self) -> Option<usize> {
+        let limit = self.rlimits.cur(RLIMIT_NOFILE);
         if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
-            fd
-        } else {
+            if fd < limit {
+                self.cloexec_fds.remove(&fd);
+                return Some(fd);
+            }
+        } else if self.fd_table.len() < limit {
             self.fd_table.push(None);
-            self.fd_table.len() - 1
+            return Some(self.fd_table.len() - 1);
         }
+        None
     }
 
     pub fn alloc_tid(&mut self) -> usize {
@@ -117,7 +137,45 @@ impl ProcessControlBlockInner {
         self.tasks[tid].as_ref().unwrap().clone()
     }
 
-    pub fn find_file_mapping_mut(&mut self, file: &Arc<Inode>) -> Option<&mut FileMapping> {
+    // 地址空间已使用的大小（字节），包括逻辑段、文件映射和共享内存段
+    pub fn address_space_size(&self) -> usize {
+        self.memory_set.mapped_bytes()
+            + self
+                .file_mappings
+                .iter()
+                .map(|m| m.mapped_bytes())
+                .sum::<usize>()
+            + self
+                .shm_attachments
+                .iter()
+                .map(|a| a.segment.pages() * PAGE_SIZE)
+                .sum::<usize>()
+    }
+
+    // 地址空间再增加bytes字节后，是否仍在RLIMIT_AS以内
+    pub fn can_grow_address_space(&self, bytes: usize) -> bool {
+        self.address_space_size()
+            .checked_add(bytes)
+            .map_or(false, |size| size <= self.rlimits.cur(RLIMIT_AS))
+    }
+
+    // 累加进程使用的CPU时间。每满一秒检查一次RLIMIT_CPU：
+    // 超过软限制时发送SIGXCPU，超过硬限制时发送SIGKILL。
+    pub fn account_cpu_time(&mut self, ms: usize) {
+        let old_secs = self.cpu_time_ms / 1000;
+        self.cpu_time_ms += ms;
+        let secs = self.cpu_time_ms / 1000;
+        if secs == old_secs {
+            return;
+        }
+        if secs >= self.rlimits.max(RLIMIT_CPU) {
+            self.signals |= SignalFlags::SIGKILL;
+        } else if secs >= self.rlimits.cur(RLIMIT_CPU) {
+            self.signals |= SignalFlags::SIGXCPU;
+        }
+    }
+
+    pub fn find_file_mapping_mut(&mut self, file: &Arc<dyn Inode>) -> Option<&mut FileMapping> {
         self.file_mappings
             .iter_mut()
             .find(|m| Arc::ptr_eq(&m.file, file))
@@ -133,18 +191,68 @@ impl ProcessControlBlock {
         self.pid.0
     }
 
+    // 关闭文件描述符fd。关闭文件的任意一个描述符时，释放进程在该文件上的所有记录锁。
+    // fd没有打开时返回false。
+    pub fn close_fd(&self, fd: usize) -> bool {
+        let mut inner = self.inner_exclusive_access();
+        let Some(file) = inner.fd_table.get_mut(fd).and_then(Option::take) else {
+            return false;
+        };
+        inner.cloexec_fds.remove(&fd);
+        drop(inner);
+        self.release_file(file);
+        true
+    }
+
+    // 将new_fd指向old_fd打开的文件，cloexec决定new_fd是否设置FD_CLOEXEC。
+    // new_fd已经打开时，在同一步中替换并关闭原来的文件，其他线程不会看到new_fd空闲的中间状态。
+    // old_fd没有打开、new_fd超出RLIMIT_NOFILE时返回false。
+    pub fn dup_fd(&self, old_fd: usize, new_fd: usize, cloexec: bool) -> bool {
+        let mut inner = self.inner_exclusive_access();
+        let Some(Some(file)) = inner.fd_table.get(old_fd) else {
+            return false;
+        };
+        let file = file.clone();
+        if new_fd >= inner.rlimits.cur(RLIMIT_NOFILE) {
+            return false;
+        }
+        if new_fd >= inner.fd_table.len() {
+            inner.fd_table.resize(new_fd + 1, None);
+        }
+        let replaced = inner.fd_table[new_fd].replace(file);
+        if cloexec {
+            inner.cloexec_fds.insert(new_fd);
+        } else {
+            inner.cloexec_fds.remove(&new_fd);
+        }
+        drop(inner);
+        if let Some(replaced) = replaced {
+            self.release_file(replaced);
+        }
+        true
+    }
+
+    // 进程关闭了文件的一个描述符：释放进程在该文件上的所有记录锁
+    fn release_file(&self, file: Arc<dyn File + Send + Sync>) {
+        if let Some(inode) = file.as_any().downcast_ref::<OSInode>() {
+            let owner = LockOwner::Process(self.getpid());
+            release_lock(inode.lock_key(), owner, 0, usize::MAX);
+        }
+    }
+
     fn init_fd_table() -> Vec<Option<Arc<dyn File + Send + Sync>>> {
         vec![
-            Some(Arc::new(Stdin)),  // 0 -> stdin
-            Some(Arc::new(Stdout)), // 1 -> stdout
-            Some(Arc::new(Stdout)), // 2 -> stderr
+            Some(open_console(true, false)), // 0 -> stdin
+            Some(open_console(false, true)), // 1 -> stdout
+            Some(open_console(false, true)), // 2 -> stderr
         ]
     }
 
     // 解析ELF格式的二进制数据，创建一个新的进程
-    pub fn new(elf_data: &[u8]) -> Arc<Self> {
+    pub fn new(elf_data: &[u8], cmdline: Vec<String>) -> Arc<Self> {
         // 解析ELF，得到地址空间、用户栈顶、入口地址
-        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, ustack_base, heap_bottom, entry_point) =
+            MemorySet::from_elf(elf_data).expect("invalid elf!");
         // 分配新的PID
         let pid_handle = pid_alloc();
         let process = Self {
@@ -157,6 +265,7 @@ impl ProcessControlBlock {
                     children: vec![],
                     exit_code: 0,
                     fd_table: Self::init_fd_table(),
+                    cloexec_fds: BTreeSet::new(),
                     tasks: vec![],
                     task_res_allocator: RecycleAllocator::new(),
                     signal_actions: SignalActions::default(),
@@ -169,10 +278,14 @@ impl ProcessControlBlock {
                     mutex_list: vec![],
                     semaphore_list: vec![],
                     condvar_list: vec![],
-                    heap_bottom: ustack_base,
-                    program_brk: ustack_base,
+                    heap_bottom,
+                    program_brk: heap_bottom,
                     mmap_va_allocator: VirtualAddressAllocator::default(),
                     file_mappings: vec![],
+                    shm_attachments: vec![],
+                    rlimits: ResourceLimits::default(),
+                    cpu_time_ms: 0,
+                    cmdline,
                 })
             },
         };
@@ -213,7 +326,13 @@ impl ProcessControlBlock {
         // 目前只支持单线程
         assert_eq!(parent.thread_count(), 1);
         // 为子进程分配新的地址空间
-        let memory_set = MemorySet::from_existed_user(&parent.memory_set);
+        let mut memory_set = MemorySet::from_existed_user(&parent.memory_set);
+        // 共享内存段不会被复制，子进程映射到相同的物理页帧
+        let shm_attachments = parent
+            .shm_attachments
+            .iter()
+            .map(|attachment| attachment.duplicate(&mut memory_set))
+            .collect();
         // 为子进程分配新的PID
         let pid = pid_alloc();
         // 复制父进程的fd
@@ -228,6 +347,7 @@ impl ProcessControlBlock {
                     children: Vec::new(),
                     exit_code: 0,
                     fd_table,
+                    cloexec_fds: parent.cloexec_fds.clone(),
                     tasks: vec![],
                     task_res_allocator: RecycleAllocator::new(),
                     signal_actions: parent.signal_actions.clone(),
@@ -242,8 +362,13 @@ impl ProcessControlBlock {
                     condvar_list: vec![],
                     heap_bottom: parent.heap_bottom,
                     program_brk: parent.program_brk,
-                    mmap_va_allocator: VirtualAddressAllocator::default(),
+                    // 继承已分配的虚拟地址，避免新的映射与挂接的共享内存段重叠
+                    mmap_va_allocator: parent.mmap_va_allocator.clone(),
                     file_mappings: vec![],
+                    shm_attachments,
+                    rlimits: parent.rlimits.clone(),
+                    cpu_time_ms: 0,
+                    cmdline: parent.cmdline.clone(),
                 };
                 UPIntrFreeCell::new(value)
             },
@@ -282,20 +407,31 @@ impl ProcessControlBlock {
 
     // 申请新的地址空间，加载ELF文件。这将替换原来的地址空间，同时初始化TrapContext。
     // 在操作系统上执行程序，都会fork父进程，然后再调用这个方法。
-    pub fn exec(&self, elf_data: &[u8], args: Vec<String>) {
+    // ELF文件不合法时返回false，原来的地址空间保持不变。
+    pub fn exec(&self, elf_data: &[u8], args: Vec<String>) -> bool {
         // 目前只支持单线程
         assert_eq!(self.inner_exclusive_access().thread_count(), 1);
         // 申请新的地址空间，加载ELF文件
-        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let Some((memory_set, ustack_base, heap_bottom, entry_point)) =
+            MemorySet::from_elf(elf_data)
+        else {
+            return false;
+        };
         let new_token = memory_set.token();
 
         let mut inner = self.inner_exclusive_access();
         inner.memory_set = memory_set;
-        inner.heap_bottom = ustack_base; // TODO: fix new heap bottom
-        inner.program_brk = ustack_base;
+        inner.heap_bottom = heap_bottom;
+        inner.program_brk = heap_bottom;
         inner.mmap_va_allocator = VirtualAddressAllocator::default();
         inner.file_mappings = vec![];
+        inner.shm_attachments = vec![];
+        inner.cmdline = args.clone();
+        let cloexec_fds = core::mem::take(&mut inner.cloexec_fds);
         drop(inner);
+        for fd in cloexec_fds {
+            self.close_fd(fd);
+        }
 
         // 替换主线程
         let task = self.inner_exclusive_access().get_task(0);
@@ -347,6 +483,7 @@ impl ProcessControlBlock {
         trap_cx.x[10] = args.len(); // argc
         trap_cx.x[11] = argv_base; // argv
         *task_inner.get_trap_cx() = trap_cx;
+        true
     }
 
     // 增加或减少堆的大小
@@ -358,6 +495,14 @@ impl ProcessControlBlock {
         if new_brk < inner.heap_bottom as isize {
             return None;
         }
+        if size > 0 {
+            // 检查堆的大小（RLIMIT_DATA）和地址空间的大小（RLIMIT_AS）
+            if new_brk as usize - inner.heap_bottom > inner.rlimits.cur(RLIMIT_DATA)
+                || !inner.can_grow_address_space(size as usize)
+            {
+                return None;
+            }
+        }
         let heap_bottom = VirtAddr(inner.heap_bottom);
         let new_end = VirtAddr(new_brk as usize);
         let result = if size < 0 {
@@ -0,0 +1,108 @@
+//! 进程的资源限制（resource limit）。
+//!
+//! 每种资源有软限制（cur）和硬限制（max）两个值。超过软限制时，相应的操作会失败或收到信号；
+//! 进程可以调低硬限制，也可以在硬限制以内调整软限制，但不能调高硬限制。
+//! 子进程在fork时继承父进程的资源限制。
+
+use crate::config::USER_STACK_SIZE;
+
+// 资源的编号，与Linux保持一致
+// CPU时间（秒）。超过软限制时收到SIGXCPU，超过硬限制时收到SIGKILL。
+pub const RLIMIT_CPU: usize = 0;
+// 可写入的文件的最大长度（字节）。超过时收到SIGXFSZ。
+pub const RLIMIT_FSIZE: usize = 1;
+// 堆的最大大小（字节）
+pub const RLIMIT_DATA: usize = 2;
+// 线程用户栈的最大大小（字节）
+pub const RLIMIT_STACK: usize = 3;
+// 系统中的最大任务数：和Linux一样，进程的每个线程都计入。在fork和创建线程时检查
+pub const RLIMIT_NPROC: usize = 6;
+// 最大的文件描述符加1
+pub const RLIMIT_NOFILE: usize = 7;
+// 地址空间的最大大小（字节）
+pub const RLIMIT_AS: usize = 9;
+const RLIM_NLIMITS: usize = 16;
+
+// 不限制
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+// 一种资源的限制，getrlimit/setrlimit在用户态和内核态之间传递的结构
+pub struct RLimit {
+    // 软限制
+    pub cur: usize,
+    // 硬限制，软限制不能超过它
+    pub max: usize,
+}
+
+impl RLimit {
+    const fn new(cur: usize, max: usize) -> Self {
+        Self { cur, max }
+    }
+
+    const fn infinity() -> Self {
+        Self::new(RLIM_INFINITY, RLIM_INFINITY)
+    }
+}
+
+#[derive(Clone)]
+// 一个进程的所有资源限制
+pub struct ResourceLimits {
+    limits: [RLimit; RLIM_NLIMITS],
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        let mut limits = [RLimit::infinity(); RLIM_NLIMITS];
+        limits[RLIMIT_STACK] = RLimit::new(USER_STACK_SIZE, RLIM_INFINITY);
+        limits[RLIMIT_NPROC] = RLimit::new(256, 256);
+        limits[RLIMIT_NOFILE] = RLimit::new(1024, 4096);
+        Self { limits }
+    }
+}
+
+impl ResourceLimits {
+    // 是否是支持的资源
+    fn supported(resource: usize) -> bool {
+        matches!(
+            resource,
+            RLIMIT_CPU
+                | RLIMIT_FSIZE
+                | RLIMIT_DATA
+                | RLIMIT_STACK
+                | RLIMIT_NPROC
+                | RLIMIT_NOFILE
+                | RLIMIT_AS
+        )
+    }
+
+    pub fn get(&self, resource: usize) -> Option<RLimit> {
+        if Self::supported(resource) {
+            Some(self.limits[resource])
+        } else {
+            None
+        }
+    }
+
+    // 资源的软限制。程序通过它检查是否超出限制。
+    pub fn cur(&self, resource: usize) -> usize {
+        self.limits[resource].cur
+    }
+
+    pub fn max(&self, resource: usize) -> usize {
+        self.limits[resource].max
+    }
+
+    // 设置资源限制。软限制不能超过硬限制，硬限制不能调高。
+    pub fn set(&mut self, resource: usize, limit: RLimit) -> bool {
+        if !Self::supported(resource)
+            || limit.cur > limit.max
+            || limit.max > self.limits[resource].max
+        {
+            return false;
+        }
+        self.limits[resource] = limit;
+        true
+    }
+}
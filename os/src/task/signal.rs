@@ -49,6 +49,9 @@ impl SignalFlags {
             (Self::SIGFPE, -8, "Erroneous Arithmetic Operation, SIGFPE=8"),
             (Self::SIGKILL, -9, "Killed, SIGKILL=9"),
             (Self::SIGSEGV, -11, "Segmentation Fault, SIGSEGV=11"),
+            (Self::SIGPIPE, -13, "Broken Pipe, SIGPIPE=13"),
+            (Self::SIGXCPU, -24, "CPU Time Limit Exceeded, SIGXCPU=24"),
+            (Self::SIGXFSZ, -25, "File Size Limit Exceeded, SIGXFSZ=25"),
         ];
         for (flag, code, msg) in errors {
             if self.contains(flag) {
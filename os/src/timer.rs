@@ -13,6 +13,8 @@ use riscv::register::time;
 // 这里是每秒100次，所以时钟中断的间隔是10ms。
 const TICKS_PER_SEC: usize = 100;
 const MSEC_PER_SEC: usize = 1_000;
+// 每次时钟中断的间隔（毫秒）
+pub const MSEC_PER_TICK: usize = MSEC_PER_SEC / TICKS_PER_SEC;
 const USEC_PER_SEC: usize = 1_000_000;
 
 // 返回时间
@@ -74,6 +76,8 @@ lazy_static! {
     // 用二插堆（优先队列）实现排序，每次从堆顶取出最小的时间
     static ref TIMERS: UPIntrFreeCell<BinaryHeap<TimerCondVar>> =
         unsafe { UPIntrFreeCell::new(BinaryHeap::<TimerCondVar>::new()) };
+    // 开机以来时钟中断发生的次数
+    static ref TICKS: UPIntrFreeCell<usize> = unsafe { UPIntrFreeCell::new(0) };
 }
 
 pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
@@ -82,20 +86,26 @@ pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
 }
 
 // 移除task所在的定时器。这在任务被唤醒时调用。
-pub fn remove_timer(task: Arc<TaskControlBlock>) {
+// 返回值：是否有定时器被移除。返回false时，定时器已经触发过了，或者task没有定时器。
+pub fn remove_timer(task: Arc<TaskControlBlock>) -> bool {
     let mut timers = TIMERS.exclusive_access();
     let mut temp = BinaryHeap::<TimerCondVar>::new();
+    let mut removed = false;
     for condvar in timers.drain() {
         if Arc::as_ptr(&task) != Arc::as_ptr(&condvar.task) {
             temp.push(condvar);
+        } else {
+            removed = true;
         }
     }
     timers.clear();
     timers.append(&mut temp);
+    removed
 }
 
-// 检查时间，唤醒超时的任务
+// 检查时间，唤醒超时的任务。每次时钟中断时调用。
 pub fn check_timer() {
+    *TICKS.exclusive_access() += 1;
     let current_ms = get_time_ms();
     let mut timers = TIMERS.exclusive_access();
     while let Some(timer) = timers.peek() {
@@ -108,3 +118,8 @@ pub fn check_timer() {
         }
     }
 }
+
+// 开机以来时钟中断发生的次数
+pub fn timer_ticks() -> usize {
+    *TICKS.exclusive_access()
+}
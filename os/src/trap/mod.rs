@@ -2,14 +2,16 @@ mod context;
 
 use crate::{
     config::{PAGE_SIZE, TRAMPOLINE},
+    fs::flush_block_cache_periodically,
     mm::VirtAddr,
+    random::add_entropy,
     syscall::syscall,
     task::{
         check_signals_error_of_current, current_add_signal, current_process, current_task_pid,
         current_trap_cx, current_trap_cx_user_va, current_user_token, exit_current_and_run_next,
         handle_signals, suspend_current_and_run_next, SignalFlags,
     },
-    timer::{check_timer, set_next_trigger},
+    timer::{check_timer, set_next_trigger, MSEC_PER_TICK},
 };
 use alloc::sync::Arc;
 use core::{
@@ -90,19 +92,30 @@ pub fn trap_handler() -> ! {
             // 需要让sepc移动4字节，指向下一条指令，以便系统调用返回后，继续执行用户态的指令。
             let mut cx = current_trap_cx();
             cx.sepc += 4;
-            // 从寄存器x17中读取系统调用号，从x10, x11, x12中读取参数。
+            // 从寄存器x17中读取系统调用号，从x10到x15中读取参数。
             // 执行系统调用，并将结果写回x10。
-            // x10，x11，x12，x17，又名a0，a1，a2，a7
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            // x10到x15，x17，又名a0到a5，a7
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
             // sys_exec会替换掉当前任务的Trap上下文。因此要重新拿一遍。
             cx = current_trap_cx();
             cx.x[10] = result as usize;
+            // 返回用户态之前，周期性地将文件系统的脏块写回磁盘。不在时钟中断中写磁盘。
+            flush_block_cache_periodically();
         }
         // 时钟中断
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
             // 检查定时器，看是否有阻塞的任务可以唤醒。
             check_timer();
+            // 时钟中断打断用户程序的位置难以预测，将其混入熵池
+            add_entropy(current_trap_cx().sepc);
+            // 将这个时间片计入当前进程的CPU时间，并检查RLIMIT_CPU
+            current_process()
+                .inner_exclusive_access()
+                .account_cpu_time(MSEC_PER_TICK);
             suspend_current_and_run_next();
         }
         // 外部中断
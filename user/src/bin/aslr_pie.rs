@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{sbrk, shmat, shmctl, shmdt, shmget, ShmFlags, IPC_PRIVATE, IPC_RMID};
+
+// 位置无关的可执行文件（ET_DYN），由Makefile以PIE方式链接，内核加载时处理重定位。
+// 输出代码、用户栈、堆和mmap区域的地址，由aslr_test比较多次执行时的地址空间布局。
+#[no_mangle]
+pub fn main() -> i32 {
+    let code = main as usize;
+    let local = 0usize;
+    let stack = &local as *const usize as usize;
+    let heap = sbrk(0) as usize;
+    let shmid = shmget(IPC_PRIVATE, 4096, ShmFlags::empty());
+    assert!(shmid >= 0);
+    let mmap = shmat(shmid as usize, 0, ShmFlags::empty());
+    assert!(mmap > 0);
+    assert_eq!(shmdt(mmap as usize), 0);
+    assert_eq!(shmctl(shmid as usize, IPC_RMID, None), 0);
+    // 格式化输出要经过rodata中的虚函数表，重定位出错时无法运行到这里
+    println!("{:x} {:x} {:x} {:x}", code, stack, heap, mmap);
+    0
+}
@@ -0,0 +1,123 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dup2, exec, exit, fork, open, pipe, read, sbrk, wait, waitpid, write, OpenFlags,
+};
+
+const PAGE_SIZE: usize = 4096;
+// 堆的起始地址范围，与内核的USER_HEAP_BASE和ASLR_HEAP_PAGES一致
+const USER_HEAP_BASE: usize = 0x4000_0000;
+const HEAP_RANGE: usize = 0x4000 * PAGE_SIZE;
+// 位置无关的可执行文件的加载地址，与内核的ET_DYN_BASE一致
+const ET_DYN_BASE: usize = 0x2000_0000;
+
+// 执行位置无关的程序aslr_pie，读出它输出的代码、用户栈、堆和mmap区域的地址
+fn pie_layout() -> [usize; 4] {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        // 子进程的标准输出重定向到管道
+        close(pipe_fd[0]);
+        assert_eq!(dup2(pipe_fd[1], 1), 1);
+        close(pipe_fd[1]);
+        exec("aslr_pie\0", &[core::ptr::null::<u8>()]);
+        exit(-1);
+    }
+    close(pipe_fd[1]);
+    let mut buf = [0u8; 128];
+    let mut len = 0;
+    loop {
+        let read_len = read(pipe_fd[0], &mut buf[len..]);
+        if read_len <= 0 {
+            break;
+        }
+        len += read_len as usize;
+    }
+    close(pipe_fd[0]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    let line = core::str::from_utf8(&buf[..len]).unwrap().trim_end();
+    let mut layout = [0usize; 4];
+    for (value, field) in layout.iter_mut().zip(line.split(' ')) {
+        *value = usize::from_str_radix(field, 16).unwrap();
+    }
+    layout
+}
+
+// 多次执行位置无关的程序：没有开启ASLR时布局完全相同；开启时栈、堆和mmap区域的起始地址都是随机的。
+// 以代码的地址是否变化判断是否开启了ASLR，开启时某个区域三次都相同的概率可以忽略。
+fn pie_test() {
+    let layouts = [pie_layout(), pie_layout(), pie_layout()];
+    for layout in layouts.iter() {
+        // 作为ET_DYN加载，而不是加载到链接时指定的地址
+        assert!((ET_DYN_BASE..USER_HEAP_BASE).contains(&layout[0]));
+        assert!((USER_HEAP_BASE..USER_HEAP_BASE + HEAP_RANGE).contains(&layout[2]));
+    }
+    let aslr = layouts.iter().any(|layout| layout[0] != layouts[0][0]);
+    for i in 1..4 {
+        let same = layouts.iter().all(|layout| layout[i] == layouts[0][i]);
+        assert_eq!(same, !aslr);
+    }
+    println!("pie layout: {:x?}, aslr: {}", layouts[0], aslr);
+}
+
+// 不是ELF格式的文件不能执行，exec失败后程序继续运行
+fn exec_invalid_test() {
+    let fd = open(
+        "aslr_not_elf\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"not an elf file"), 15);
+    close(fd as usize);
+    assert_eq!(exec("aslr_not_elf\0", &[core::ptr::null::<u8>()]), -1);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let heap_bottom = sbrk(0);
+    assert!(heap_bottom > 0);
+    let heap_bottom = heap_bottom as usize;
+    // 堆的起始地址按页对齐，且位于堆区域内
+    assert_eq!(heap_bottom % PAGE_SIZE, 0);
+    assert!((USER_HEAP_BASE..USER_HEAP_BASE + HEAP_RANGE).contains(&heap_bottom));
+    // 用户栈位于堆之下
+    let local = 0usize;
+    let stack_addr = &local as *const usize as usize;
+    assert!(stack_addr < heap_bottom);
+    println!("heap: {:#x}, stack: {:#x}", heap_bottom, stack_addr);
+
+    // 扩大堆并写入数据
+    assert_eq!(sbrk((2 * PAGE_SIZE) as i32), heap_bottom as isize);
+    let heap = unsafe { core::slice::from_raw_parts_mut(heap_bottom as *mut u8, 2 * PAGE_SIZE) };
+    heap.fill(0x5a);
+
+    // 子进程继承父进程的地址空间布局
+    if fork() == 0 {
+        assert_eq!(sbrk(0), (heap_bottom + 2 * PAGE_SIZE) as isize);
+        assert!(heap.iter().all(|&b| b == 0x5a));
+        return 0;
+    }
+    let mut exit_code: i32 = 0;
+    wait(&mut exit_code);
+    assert_eq!(exit_code, 0);
+
+    // 堆不能缩小到起始地址之下
+    assert_eq!(sbrk(-(3 * PAGE_SIZE as i32)), -1);
+    assert_eq!(
+        sbrk(-(2 * PAGE_SIZE as i32)),
+        (heap_bottom + 2 * PAGE_SIZE) as isize
+    );
+    assert_eq!(sbrk(0), heap_bottom as isize);
+
+    pie_test();
+    exec_invalid_test();
+    println!("aslr_test passed!");
+    0
+}
@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, ioctl, mmap, open, read, write, FbVarScreenInfo, OpenFlags, WinSize, BLKGETSIZE64,
+    FBIOGET_VSCREENINFO, FBIOPAN_DISPLAY, TIOCGWINSZ,
+};
+
+// easy-fs超级块开头的魔数
+const EFS_MAGIC: u32 = 0x3b800004;
+
+fn open_dev(path: &str, flags: OpenFlags) -> usize {
+    let fd = open(path, flags);
+    assert!(fd > 0);
+    fd as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0xffu8; 64];
+
+    // null：写入的数据被丢弃，读到文件末尾
+    let fd = open_dev("/dev/null\0", OpenFlags::RDWR);
+    assert_eq!(write(fd, b"discarded"), 9);
+    assert_eq!(read(fd, &mut buf), 0);
+    close(fd);
+
+    // zero：读到的都是0
+    let fd = open_dev("/dev/zero\0", OpenFlags::RDONLY);
+    assert_eq!(read(fd, &mut buf), buf.len() as isize);
+    assert!(buf.iter().all(|b| *b == 0));
+    close(fd);
+
+    // random：两次读到的数据不同
+    let fd = open_dev("/dev/random\0", OpenFlags::RDWR);
+    let mut other = [0u8; 64];
+    assert_eq!(read(fd, &mut buf), buf.len() as isize);
+    assert_eq!(write(fd, b"entropy"), 7);
+    assert_eq!(read(fd, &mut other), other.len() as isize);
+    assert_ne!(buf, other);
+    close(fd);
+
+    // 终端的窗口大小
+    let fd = open_dev("/dev/tty\0", OpenFlags::WRONLY);
+    let mut ws = WinSize::default();
+    assert_eq!(ioctl(fd, TIOCGWINSZ, &mut ws as *mut _ as usize), 0);
+    assert_eq!((ws.ws_row, ws.ws_col), (24, 80));
+    let msg = b"devfs_test: writing to /dev/tty\n";
+    assert_eq!(write(fd, msg), msg.len() as isize);
+    // 设备不支持的命令
+    assert_eq!(ioctl(fd, BLKGETSIZE64, &mut ws as *mut _ as usize), -1);
+    close(fd);
+
+    // 直接读取块设备，开头是根文件系统的超级块
+    let fd = open_dev("/dev/vda\0", OpenFlags::RDONLY);
+    let mut size = 0u64;
+    assert_eq!(ioctl(fd, BLKGETSIZE64, &mut size as *mut _ as usize), 0);
+    assert!(size > 0 && size % 512 == 0);
+    let mut block = [0u8; 512];
+    assert_eq!(read(fd, &mut block[..8]), 8);
+    assert_eq!(read(fd, &mut block[8..]), 504);
+    let magic = u32::from_le_bytes(block[0..4].try_into().unwrap());
+    let total_blocks = u32::from_le_bytes(block[4..8].try_into().unwrap());
+    assert_eq!(magic, EFS_MAGIC);
+    assert!(total_blocks as u64 * 512 <= size);
+    close(fd);
+
+    // 显存：mmap写入的像素可以通过read读到
+    let fd = open_dev("/dev/fb0\0", OpenFlags::RDWR);
+    let mut info = FbVarScreenInfo::default();
+    assert_eq!(
+        ioctl(fd, FBIOGET_VSCREENINFO, &mut info as *mut _ as usize),
+        0
+    );
+    assert_eq!(info.bits_per_pixel, 32);
+    let fb_len = (info.xres * info.yres * info.bits_per_pixel / 8) as usize;
+    assert_eq!(mmap(fd, fb_len + 1, 0), -1);
+    let fb = mmap(fd, 4096, 0);
+    assert!(fb > 0);
+    let pixels = unsafe { core::slice::from_raw_parts_mut(fb as *mut u8, 4096) };
+    for (i, p) in pixels[..64].iter_mut().enumerate() {
+        *p = i as u8;
+    }
+    assert_eq!(read(fd, &mut buf), buf.len() as isize);
+    for (i, b) in buf.iter().enumerate() {
+        assert_eq!(*b, i as u8);
+    }
+    assert_eq!(ioctl(fd, FBIOPAN_DISPLAY, 0), 0);
+    close(fd);
+
+    // 设备文件由内核提供，不能创建；普通文件不支持ioctl
+    assert_eq!(open("/dev/new_device\0", OpenFlags::CREATE), -1);
+    assert_eq!(open("/dev/no_such_device\0", OpenFlags::RDONLY), -1);
+    let fd = open_dev("/proc/uptime\0", OpenFlags::RDONLY);
+    assert_eq!(ioctl(fd, TIOCGWINSZ, &mut ws as *mut _ as usize), -1);
+    close(fd);
+    println!("devfs_test passed!");
+    0
+}
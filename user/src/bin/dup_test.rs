@@ -0,0 +1,126 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, close_range, dup, dup2, dup3, exit, fcntl, fork, getrlimit, open, pipe, read, setrlimit,
+    waitpid, write, OpenFlags, RLimit, CLOSE_RANGE_CLOEXEC, FD_CLOEXEC, F_GETFD, F_SETFD,
+    RLIMIT_NOFILE,
+};
+
+const NAME: &str = "dup_file\0";
+
+// 文件描述符是否打开
+fn is_open(fd: usize) -> bool {
+    fcntl(fd, F_GETFD, 0) >= 0
+}
+
+// dup2替换已经打开的描述符：原来的文件被关闭，描述符指向新的文件
+fn dup2_test() {
+    let file = open(
+        NAME,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(file > 0);
+    let file = file as usize;
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let (read_end, write_end) = (pipe_fd[0], pipe_fd[1]);
+    // write_end原来是管道的写端，被替换后，管道的写端全部关闭，读端读到文件末尾
+    assert_eq!(dup2(file, write_end), write_end as isize);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(read_end, &mut buf), 0);
+    assert_eq!(write(write_end, b"dup2"), 4);
+    // 两个描述符共享偏移量
+    assert_eq!(write(file, b"!"), 1);
+    close(file);
+    close(write_end);
+    close(read_end);
+    let fd = open(NAME, OpenFlags::RDONLY) as usize;
+    assert_eq!(read(fd, &mut buf), 5);
+    assert_eq!(&buf[..5], b"dup2!");
+
+    // 目标描述符可以超出当前描述符表的长度
+    assert_eq!(dup2(fd, 100), 100);
+    assert!(is_open(100));
+    assert_eq!(close(100), 0);
+    // old_fd等于new_fd时，old_fd有效则什么也不做
+    assert_eq!(dup2(fd, fd), fd as isize);
+    close(fd);
+    assert_eq!(dup2(fd, fd), -1);
+    assert_eq!(dup2(fd, 10), -1);
+    assert!(!is_open(10));
+}
+
+// dup3可以设置FD_CLOEXEC，不设置时清除目标描述符原来的FD_CLOEXEC
+fn dup3_test() {
+    let fd = open(NAME, OpenFlags::RDONLY) as usize;
+    assert_eq!(dup3(fd, fd, OpenFlags::empty()), -1);
+    assert_eq!(dup3(fd, 20, OpenFlags::CLOEXEC), 20);
+    assert_eq!(fcntl(20, F_GETFD, 0), FD_CLOEXEC as isize);
+    assert_eq!(dup3(fd, 20, OpenFlags::empty()), 20);
+    assert_eq!(fcntl(20, F_GETFD, 0), 0);
+    // 只支持CLOEXEC
+    assert_eq!(dup3(fd, 21, OpenFlags::APPEND), -1);
+    assert!(!is_open(21));
+    // dup分配的新描述符没有FD_CLOEXEC
+    assert_eq!(fcntl(fd, F_SETFD, FD_CLOEXEC), 0);
+    let new_fd = dup(fd);
+    assert!(new_fd > 0);
+    assert_eq!(fcntl(new_fd as usize, F_GETFD, 0), 0);
+    close(new_fd as usize);
+    close(20);
+    close(fd);
+}
+
+// close_range关闭范围内所有打开的描述符，或为它们设置FD_CLOEXEC
+fn close_range_test() {
+    let fd = open(NAME, OpenFlags::RDONLY) as usize;
+    for new_fd in 30..35 {
+        assert_eq!(dup2(fd, new_fd), new_fd as isize);
+    }
+    assert_eq!(close_range(31, 30, 0), -1);
+    assert_eq!(close_range(30, 31, 1 << 10), -1);
+    assert_eq!(close_range(31, 32, CLOSE_RANGE_CLOEXEC), 0);
+    assert_eq!(fcntl(30, F_GETFD, 0), 0);
+    assert_eq!(fcntl(31, F_GETFD, 0), FD_CLOEXEC as isize);
+    assert_eq!(fcntl(32, F_GETFD, 0), FD_CLOEXEC as isize);
+    assert_eq!(fcntl(33, F_GETFD, 0), 0);
+    // 范围内没有打开的描述符被跳过，范围可以超出描述符表
+    assert_eq!(close_range(31, 33, 0), 0);
+    assert!(is_open(30) && !is_open(31) && !is_open(33) && is_open(34));
+    assert_eq!(close_range(30, usize::MAX, 0), 0);
+    assert!(!is_open(30) && !is_open(34));
+    assert!(is_open(fd));
+    close(fd);
+}
+
+// 目标描述符不能达到RLIMIT_NOFILE
+fn limit_test() {
+    let pid = fork();
+    if pid == 0 {
+        let mut limit = RLimit::default();
+        assert_eq!(getrlimit(RLIMIT_NOFILE, &mut limit), 0);
+        limit.cur = 16;
+        assert_eq!(setrlimit(RLIMIT_NOFILE, &limit), 0);
+        assert_eq!(dup2(0, 15), 15);
+        assert_eq!(dup2(0, 16), -1);
+        assert_eq!(dup3(0, 1000, OpenFlags::empty()), -1);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    dup2_test();
+    dup3_test();
+    close_range_test();
+    limit_test();
+    println!("dup_test passed!");
+    0
+}
@@ -0,0 +1,154 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fcntl_lock, flock, fork, getpid, open, read, sleep, waitpid, write, Flock,
+    OpenFlags, F_GETLK, F_RDLCK, F_SETLK, F_SETLKW, F_UNLCK, F_WRLCK, LOCK_EX, LOCK_NB, LOCK_SH,
+    LOCK_UN,
+};
+
+const NAME: &str = "flock_file\0";
+
+fn open_file() -> usize {
+    let fd = open(NAME, OpenFlags::RDWR);
+    assert!(fd > 0);
+    fd as usize
+}
+
+// 等待子进程退出，检查它的退出码
+fn wait_child(pid: isize) {
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+// 整个文件的flock锁：属于打开的文件，关闭时释放
+fn flock_test() {
+    let fd = open_file();
+    assert_eq!(flock(fd, LOCK_EX), 0);
+    let pid = fork();
+    if pid == 0 {
+        // fork出的描述符和父进程共享同一把锁，不会冲突
+        assert_eq!(flock(fd, LOCK_EX | LOCK_NB), 0);
+        // 子进程自己打开的文件是另一个持有者
+        let own = open_file();
+        assert_eq!(flock(own, LOCK_SH | LOCK_NB), -1);
+        close(fd);
+        // 阻塞到父进程也关闭共享的文件
+        assert_eq!(flock(own, LOCK_EX), 0);
+        let mut buf = [0u8; 6];
+        assert_eq!(read(own, &mut buf), 6);
+        assert_eq!(&buf, b"parent");
+        exit(0);
+    }
+    // 等子进程开始等待锁。锁不限制没有加锁的写入，这里写入的内容用来检查子进程获得锁的时机。
+    sleep(100);
+    let other = open_file();
+    assert_eq!(write(other, b"parent"), 6);
+    close(other);
+    close(fd);
+    wait_child(pid);
+
+    // 共享锁之间不冲突，LOCK_UN释放锁
+    let a = open_file();
+    let b = open_file();
+    assert_eq!(flock(a, LOCK_SH), 0);
+    assert_eq!(flock(b, LOCK_SH | LOCK_NB), 0);
+    assert_eq!(flock(a, LOCK_EX | LOCK_NB), -1);
+    assert_eq!(flock(b, LOCK_UN), 0);
+    assert_eq!(flock(a, LOCK_EX | LOCK_NB), 0);
+    assert_eq!(flock(a, 0), -1);
+    close(a);
+    close(b);
+}
+
+// 字节范围的fcntl记录锁：属于进程，进程退出时释放
+fn record_lock_test() {
+    let fd = open_file();
+    let mut lock = Flock::new(F_WRLCK, 0, 10);
+    assert_eq!(fcntl_lock(fd, F_SETLK, &mut lock), 0);
+    // 同一个进程再次加锁时替换原来的锁，不会冲突
+    let mut lock = Flock::new(F_RDLCK, 5, 5);
+    assert_eq!(fcntl_lock(fd, F_SETLK, &mut lock), 0);
+    let parent = getpid();
+    let pid = fork();
+    if pid == 0 {
+        let fd = open_file();
+        // [0, 5)仍是写锁，[5, 10)已经改成了读锁
+        let mut lock = Flock::new(F_RDLCK, 0, 5);
+        assert_eq!(fcntl_lock(fd, F_SETLK, &mut lock), -1);
+        let mut lock = Flock::new(F_RDLCK, 5, 0);
+        assert_eq!(fcntl_lock(fd, F_SETLK, &mut lock), 0);
+        let mut lock = Flock::new(F_WRLCK, 2, 20);
+        assert_eq!(fcntl_lock(fd, F_GETLK, &mut lock), 0);
+        assert_eq!(lock.l_type, F_WRLCK);
+        assert_eq!((lock.l_start, lock.l_len), (0, 5));
+        assert_eq!(lock.l_pid as isize, parent);
+        let mut lock = Flock::new(F_WRLCK, 20, 10);
+        assert_eq!(fcntl_lock(fd, F_GETLK, &mut lock), 0);
+        assert_eq!(lock.l_type, F_UNLCK);
+        // 范围无效
+        let mut lock = Flock::new(F_WRLCK, -1, 10);
+        assert_eq!(fcntl_lock(fd, F_SETLK, &mut lock), -1);
+        // 退出时释放[5, ...)的读锁
+        exit(0);
+    }
+    wait_child(pid);
+    let mut lock = Flock::new(F_WRLCK, 0, 0);
+    assert_eq!(fcntl_lock(fd, F_SETLK, &mut lock), 0);
+    // 关闭文件的任意一个描述符，释放进程在该文件上的所有记录锁
+    let other = open_file();
+    close(other);
+    let pid = fork();
+    if pid == 0 {
+        let fd = open_file();
+        let mut lock = Flock::new(F_WRLCK, 0, 0);
+        assert_eq!(fcntl_lock(fd, F_SETLK, &mut lock), 0);
+        exit(0);
+    }
+    wait_child(pid);
+    close(fd);
+}
+
+// 两个进程各持有一把锁，又等待对方的锁时，后等待的一方失败，而不是永远阻塞
+fn deadlock_test() {
+    let fd = open_file();
+    let mut lock = Flock::new(F_WRLCK, 0, 10);
+    assert_eq!(fcntl_lock(fd, F_SETLK, &mut lock), 0);
+    let pid = fork();
+    if pid == 0 {
+        let fd = open_file();
+        let mut lock = Flock::new(F_WRLCK, 10, 10);
+        assert_eq!(fcntl_lock(fd, F_SETLK, &mut lock), 0);
+        // 阻塞到父进程释放[0, 10)
+        let mut lock = Flock::new(F_WRLCK, 0, 10);
+        assert_eq!(fcntl_lock(fd, F_SETLKW, &mut lock), 0);
+        exit(0);
+    }
+    // 等子进程开始等待父进程的锁
+    sleep(100);
+    let mut lock = Flock::new(F_WRLCK, 10, 10);
+    assert_eq!(fcntl_lock(fd, F_SETLKW, &mut lock), -1);
+    let mut lock = Flock::new(F_UNLCK, 0, 10);
+    assert_eq!(fcntl_lock(fd, F_SETLK, &mut lock), 0);
+    wait_child(pid);
+    close(fd);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(
+        NAME,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    close(fd as usize);
+    flock_test();
+    record_lock_test();
+    deadlock_test();
+    println!("flock_test passed!");
+    0
+}
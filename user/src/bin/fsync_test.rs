@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, fsync, open, pipe, read, sync, write, OpenFlags};
+
+// 写入的数据比块缓存（256个块，128KB）更大，读回时部分块需要从磁盘重新加载
+const CHUNK: usize = 4096;
+const CHUNKS: usize = 48;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let name = "fsync_file\0";
+    let fd = open(
+        name,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; CHUNK];
+    for i in 0..CHUNKS {
+        buffer.fill(i as u8);
+        assert_eq!(write(fd, &buffer), CHUNK as isize);
+    }
+    assert_eq!(fsync(fd), 0);
+    close(fd);
+    // 无效的文件描述符
+    assert_eq!(fsync(fd), -1);
+    // 管道不是常规文件
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(fsync(pipe_fd[1]), -1);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+
+    let fd = open(name, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    for i in 0..CHUNKS {
+        assert_eq!(read(fd, &mut buffer), CHUNK as isize);
+        assert!(buffer.iter().all(|&b| b == i as u8));
+    }
+    assert_eq!(read(fd, &mut buffer), 0);
+    close(fd);
+    assert_eq!(sync(), 0);
+    println!("fsync_test passed!");
+    0
+}
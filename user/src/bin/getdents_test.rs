@@ -0,0 +1,192 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{
+    close, dir_entries, getdents64, mkdir, open, symlink, OpenFlags, DT_CHR, DT_DIR, DT_LNK, DT_REG,
+};
+
+const NAMES: [&str; 5] = [
+    "a_file",
+    "b_file",
+    "subdir",
+    "link",
+    "a name of exactly 32 characters",
+];
+
+// 遍历过程中修改目录时，原有的项数和新建的项数
+const OLD_COUNT: usize = 8;
+const NEW_COUNT: usize = 8;
+
+fn open_dir(path: &str) -> usize {
+    let fd = open(path, OpenFlags::RDONLY | OpenFlags::DIRECTORY);
+    assert!(fd > 0);
+    fd as usize
+}
+
+fn create_file(path: &str) {
+    let fd = open(
+        path,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    close(fd as usize);
+}
+
+// 一边读目录一边在其中新建文件：原有的项恰好出现一次，新建的项最多出现一次。
+// 新建的项名字排在原有的项前面，在按名字排序的目录中会插到已经读过的项之前。
+fn modify_during_iteration(dir: &str) {
+    assert_eq!(mkdir(&format!("{}\0", dir)), 0);
+    for i in 0..OLD_COUNT {
+        create_file(&format!("{}/old{}\0", dir, i));
+    }
+    let fd = open_dir(&format!("{}\0", dir));
+    let mut buf = [0u8; 64];
+    let mut old_seen = [0usize; OLD_COUNT];
+    let mut new_seen = [0usize; NEW_COUNT];
+    let mut created = 0;
+    loop {
+        let len = getdents64(fd, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for entry in dir_entries(&buf[..len as usize]) {
+            let (seen, index) = match entry.name.strip_prefix("old") {
+                Some(index) => (&mut old_seen, index),
+                None => (&mut new_seen, entry.name.strip_prefix("new").unwrap()),
+            };
+            seen[index.parse::<usize>().unwrap()] += 1;
+        }
+        // 每读一批，新建两个文件
+        for _ in 0..2 {
+            if created < NEW_COUNT {
+                create_file(&format!("{}/new{}\0", dir, created));
+                created += 1;
+            }
+        }
+    }
+    close(fd);
+    assert_eq!(created, NEW_COUNT);
+    assert_eq!(old_seen, [1; OLD_COUNT]);
+    assert!(new_seen.iter().all(|&count| count <= 1));
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdir("/tmp/getdents\0"), 0);
+    create_file("/tmp/getdents/a_file\0");
+    create_file("/tmp/getdents/b_file\0");
+    create_file("/tmp/getdents/a name of exactly 32 characters\0");
+    assert_eq!(mkdir("/tmp/getdents/subdir\0"), 0);
+    assert_eq!(symlink("a_file\0", "/tmp/getdents/link\0"), 0);
+
+    // 缓冲区很小时，每次只能读到一两项，多次调用依次读出所有的项，每一项恰好出现一次
+    let fd = open_dir("/tmp/getdents\0");
+    let mut buf = [0u8; 64];
+    let mut seen = [0usize; NAMES.len()];
+    let mut last_off = 0;
+    loop {
+        let len = getdents64(fd, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for entry in dir_entries(&buf[..len as usize]) {
+            assert!(entry.off > last_off);
+            last_off = entry.off;
+            assert!(entry.ino > 0);
+            let i = NAMES.iter().position(|name| *name == entry.name).unwrap();
+            seen[i] += 1;
+            let expected = match entry.name {
+                "subdir" => DT_DIR,
+                "link" => DT_LNK,
+                _ => DT_REG,
+            };
+            assert_eq!(entry.d_type, expected);
+        }
+    }
+    assert_eq!(seen, [1; NAMES.len()]);
+    // 读完之后一直返回0
+    assert_eq!(getdents64(fd, &mut buf), 0);
+    close(fd);
+
+    // 缓冲区放不下一项时出错：长名字的记录需要19 + 32 = 51字节，按8字节对齐为56字节
+    let fd = open_dir("/tmp/getdents\0");
+    let mut small = [0u8; 32];
+    let mut total = 0;
+    loop {
+        let len = getdents64(fd, &mut small);
+        if len == -1 {
+            break;
+        }
+        assert!(len > 0);
+        total += dir_entries(&small[..len as usize]).count();
+    }
+    assert!(total < NAMES.len());
+    assert_eq!(getdents64(fd, &mut buf), 56);
+    assert_eq!(dir_entries(&buf[..56]).next().unwrap().name, NAMES[4]);
+    close(fd);
+
+    // 空目录没有任何项；设备文件的类型是字符设备
+    let fd = open_dir("/tmp/getdents/subdir\0");
+    assert_eq!(getdents64(fd, &mut buf), 0);
+    close(fd);
+    let fd = open_dir("/dev\0");
+    let mut found = false;
+    loop {
+        let len = getdents64(fd, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        for entry in dir_entries(&buf[..len as usize]) {
+            if entry.name == "null" {
+                assert_eq!(entry.d_type, DT_CHR);
+                found = true;
+            }
+        }
+    }
+    assert!(found);
+    close(fd);
+
+    // DIRECTORY要求路径是已经存在的目录；普通文件不能读取目录项
+    assert_eq!(
+        open(
+            "/tmp/getdents/a_file\0",
+            OpenFlags::RDONLY | OpenFlags::DIRECTORY
+        ),
+        -1
+    );
+    assert_eq!(
+        open(
+            "/tmp/getdents/new\0",
+            OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::DIRECTORY
+        ),
+        -1
+    );
+    assert_eq!(
+        open(
+            "/tmp/getdents/link\0",
+            OpenFlags::RDONLY | OpenFlags::DIRECTORY
+        ),
+        -1
+    );
+    let fd = open("/tmp/getdents/a_file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    assert_eq!(getdents64(fd as usize, &mut buf), -1);
+    close(fd as usize);
+    // 通过跟随的符号链接打开目录
+    assert_eq!(symlink("subdir\0", "/tmp/getdents/dirlink\0"), 0);
+    let fd = open_dir("/tmp/getdents/dirlink\0");
+    close(fd);
+
+    // 在tmpfs和easy-fs中，遍历的同时修改目录
+    modify_during_iteration("/tmp/getdents_mod");
+    modify_during_iteration("/getdents_mod");
+    println!("getdents_test passed!");
+    0
+}
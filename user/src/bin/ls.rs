@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, dir_entries, getdents64, open, OpenFlags, DT_DIR, DT_LNK};
+
+// 列出目录中的文件，默认列出根目录。目录名后加"/"，符号链接名后加"@"。
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let path = if argc > 1 { argv[1] } else { "/\0" };
+    let fd = open(path, OpenFlags::RDONLY | OpenFlags::DIRECTORY);
+    if fd < 0 {
+        println!("ls: cannot open directory {}", path.trim_end_matches('\0'));
+        return -1;
+    }
+    let fd = fd as usize;
+    let mut buf = [0u8; 512];
+    loop {
+        let len = getdents64(fd, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        for entry in dir_entries(&buf[..len as usize]) {
+            let suffix = match entry.d_type {
+                DT_DIR => "/",
+                DT_LNK => "@",
+                _ => "",
+            };
+            println!("{}{}", entry.name, suffix);
+        }
+    }
+    close(fd);
+    0
+}
@@ -0,0 +1,106 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mkdir, mount, open, read, umount, write, OpenFlags};
+
+// 挂载期间直接写入/dev/vdb的字节数，大于32 KiB
+const RAW_SIZE: usize = 40 * 1024;
+
+// 创建文件并写入内容
+fn write_file(path: &str, data: &[u8]) -> isize {
+    let fd = open(
+        path,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    if fd < 0 {
+        return fd;
+    }
+    let len = write(fd as usize, data);
+    close(fd as usize);
+    len
+}
+
+// 读出文件的全部内容，返回读到的字节数
+fn read_file(path: &str, buf: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return fd;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdir("/mount_dir\0"), 0);
+    assert_eq!(mkdir("/mount_dir\0"), -1);
+    assert_eq!(mkdir("/no_such_dir/sub\0"), -1);
+    assert_eq!(write_file("/mount_dir/below\0", b"below"), 5);
+    assert_eq!(write_file("/mount_file\0", b"file"), 4);
+    let mut buf = [0u8; 64];
+    assert_eq!(read_file("/mount_dir/../mount_dir/below\0", &mut buf), 5);
+
+    // 不存在的文件系统类型、不存在或已被挂载的块设备、不是目录的挂载点
+    assert_eq!(mount("vdb\0", "/mount_dir\0", "nofs\0"), -1);
+    assert_eq!(mount("vdz\0", "/mount_dir\0", "easyfs\0"), -1);
+    assert_eq!(mount("vda\0", "/mount_dir\0", "easyfs\0"), -1);
+    assert_eq!(mount("vdb\0", "/mount_file\0", "easyfs\0"), -1);
+    // 根文件系统和普通目录不能卸载
+    assert_eq!(umount("/\0"), -1);
+    assert_eq!(umount("/mount_dir\0"), -1);
+
+    if mount("vdb\0", "/mount_dir\0", "easyfs\0") != 0 {
+        println!("no second disk (vdb), skipping the rest of mount_test");
+        println!("mount_test passed!");
+        return 0;
+    }
+    // 挂载点原有的内容被覆盖，经过挂载点访问的是第二块磁盘上的文件系统
+    assert_eq!(read_file("/mount_dir/below\0", &mut buf), -1);
+    assert_eq!(write_file("/mount_dir/above\0", b"above"), 5);
+    assert_eq!(read_file("/mount_dir/above\0", &mut buf), 5);
+    assert_eq!(&buf[..5], b"above");
+    // 在被挂载的文件系统的根目录处，".."回到挂载点所在的目录
+    assert_eq!(read_file("/mount_dir/../mount_file\0", &mut buf), 4);
+    // 同一块设备不能挂载两次
+    assert_eq!(mkdir("/mount_dir2\0"), 0);
+    assert_eq!(mount("vdb\0", "/mount_dir2\0", "easyfs\0"), -1);
+
+    // 挂载期间直接写块设备：把开头的RAW_SIZE字节原样写回，
+    // 写入的块不会作为脏块留在块缓存中，之后文件系统仍能正常写入
+    let src = open("/dev/vdb\0", OpenFlags::RDONLY);
+    let dst = open("/dev/vdb\0", OpenFlags::WRONLY);
+    assert!(src > 0 && dst > 0);
+    let mut block = [0u8; 512];
+    for _ in 0..RAW_SIZE / block.len() {
+        assert_eq!(read(src as usize, &mut block), block.len() as isize);
+        assert_eq!(write(dst as usize, &block), block.len() as isize);
+    }
+    close(src as usize);
+    close(dst as usize);
+    assert_eq!(write_file("/mount_dir/after_raw\0", &[b'r'; 512]), 512);
+    assert_eq!(read_file("/mount_dir/after_raw\0", &mut block), 512);
+    assert_eq!(block, [b'r'; 512]);
+
+    // 有文件被打开时不能卸载
+    let fd = open("/mount_dir/above\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    assert_eq!(umount("/mount_dir\0"), -1);
+    close(fd as usize);
+    assert_eq!(umount("/mount_dir\0"), 0);
+    assert_eq!(umount("/mount_dir\0"), -1);
+
+    // 卸载之后，挂载点原有的内容重新可见
+    assert_eq!(read_file("/mount_dir/below\0", &mut buf), 5);
+    assert_eq!(&buf[..5], b"below");
+    assert_eq!(read_file("/mount_dir/above\0", &mut buf), -1);
+    // 重新挂载到另一个目录，之前写入的文件仍然存在
+    assert_eq!(mount("vdb\0", "/mount_dir2\0", "easyfs\0"), 0);
+    assert_eq!(read_file("/mount_dir2/above\0", &mut buf), 5);
+    assert_eq!(umount("/mount_dir2\0"), 0);
+    println!("mount_test passed!");
+    0
+}
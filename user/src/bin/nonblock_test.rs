@@ -0,0 +1,133 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use user_lib::{
+    close, exit, fcntl, fork, open, pipe, read, sigaction, sigreturn, sleep, waitpid, write,
+    OpenFlags, SignalAction, EAGAIN, EPIPE, F_GETFL, F_SETFL, SIGPIPE,
+};
+
+// 管道缓冲区的大小
+const PIPE_SIZE: usize = 32;
+
+static GOT_SIGPIPE: AtomicBool = AtomicBool::new(false);
+
+fn sigpipe_handler() {
+    GOT_SIGPIPE.store(true, Ordering::SeqCst);
+    sigreturn();
+}
+
+fn make_pipe() -> (usize, usize) {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    (pipe_fd[0], pipe_fd[1])
+}
+
+fn set_nonblock(fd: usize) {
+    let flags = fcntl(fd, F_GETFL, 0);
+    assert!(flags >= 0);
+    let flags = flags as usize | OpenFlags::NONBLOCK.bits as usize;
+    assert_eq!(fcntl(fd, F_SETFL, flags), 0);
+    assert!(fcntl(fd, F_GETFL, 0) as u32 & OpenFlags::NONBLOCK.bits != 0);
+}
+
+// 非阻塞的读：没有数据时返回-EAGAIN，只读出已有的数据
+fn pipe_read_test() {
+    let (read_end, write_end) = make_pipe();
+    set_nonblock(read_end);
+    // 管道不支持APPEND
+    assert_eq!(
+        fcntl(read_end, F_SETFL, OpenFlags::APPEND.bits as usize),
+        -1
+    );
+    let mut buf = [0u8; 8];
+    assert_eq!(read(read_end, &mut buf), -EAGAIN);
+    assert_eq!(write(write_end, b"abc"), 3);
+    assert_eq!(read(read_end, &mut buf), 3);
+    assert_eq!(&buf[..3], b"abc");
+    assert_eq!(read(read_end, &mut buf), -EAGAIN);
+    // 写端全部关闭后读到文件末尾
+    close(write_end);
+    assert_eq!(read(read_end, &mut buf), 0);
+    close(read_end);
+}
+
+// 非阻塞的写：只写入缓冲区能容纳的部分，缓冲区已满时返回-EAGAIN
+fn pipe_write_test() {
+    let (read_end, write_end) = make_pipe();
+    set_nonblock(write_end);
+    let data = [b'x'; PIPE_SIZE + 8];
+    assert_eq!(write(write_end, &data), PIPE_SIZE as isize);
+    assert_eq!(write(write_end, &data), -EAGAIN);
+    let mut buf = [0u8; 10];
+    assert_eq!(read(read_end, &mut buf), 10);
+    assert_eq!(write(write_end, &data), 10);
+    close(read_end);
+    close(write_end);
+}
+
+// 读端全部关闭后写管道：默认终止进程，注册了处理函数时返回-EPIPE
+fn broken_pipe_test() {
+    let (read_end, write_end) = make_pipe();
+    close(read_end);
+    let pid = fork();
+    if pid == 0 {
+        write(write_end, b"x");
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGPIPE);
+
+    let action = SignalAction {
+        handler: sigpipe_handler as usize,
+        ..Default::default()
+    };
+    let mut old_action = SignalAction::default();
+    assert_eq!(sigaction(SIGPIPE, Some(&action), Some(&mut old_action)), 0);
+    assert_eq!(write(write_end, b"x"), -EPIPE);
+    assert!(GOT_SIGPIPE.swap(false, Ordering::SeqCst));
+    close(write_end);
+
+    // 阻塞在写上的进程，在读端关闭时返回已经写入的部分
+    let (read_end, write_end) = make_pipe();
+    let pid = fork();
+    if pid == 0 {
+        close(write_end);
+        sleep(50);
+        exit(0);
+    }
+    close(read_end);
+    let data = [b'x'; PIPE_SIZE * 2];
+    assert_eq!(write(write_end, &data), PIPE_SIZE as isize);
+    assert!(GOT_SIGPIPE.load(Ordering::SeqCst));
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    close(write_end);
+    let mut action = SignalAction::default();
+    assert_eq!(sigaction(SIGPIPE, Some(&old_action), Some(&mut action)), 0);
+}
+
+// 非阻塞地打开终端：没有输入时读返回-EAGAIN，写不受影响
+fn tty_test() {
+    let fd = open("/dev/console\0", OpenFlags::RDWR | OpenFlags::NONBLOCK);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert!(fcntl(fd, F_GETFL, 0) as u32 & OpenFlags::NONBLOCK.bits != 0);
+    let mut buf = [0u8; 1];
+    assert_eq!(read(fd, &mut buf), -EAGAIN);
+    assert_eq!(write(fd, b"nonblock tty ok\n"), 16);
+    close(fd);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    pipe_read_test();
+    pipe_write_test();
+    broken_pipe_test();
+    tty_test();
+    println!("nonblock_test passed!");
+    0
+}
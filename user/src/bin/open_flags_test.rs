@@ -0,0 +1,224 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{
+    close, exec, exit, fcntl, fork, open, read, symlink, waitpid, write, OpenFlags, FD_CLOEXEC,
+    F_GETFD, F_GETFL, F_SETFD, F_SETFL,
+};
+
+const NAME: &str = "open_flags_file\0";
+
+// 读出文件的全部内容，返回读到的字节数
+fn read_file(path: &str, buf: &mut [u8]) -> usize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut total = 0;
+    loop {
+        let len = read(fd as usize, &mut buf[total..]);
+        if len <= 0 {
+            break;
+        }
+        total += len as usize;
+    }
+    close(fd as usize);
+    total
+}
+
+// 等待子进程退出，检查它的退出码
+fn wait_child(pid: isize) {
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+// CREATE不截断已经存在的文件，TRUNC才截断
+fn create_test() {
+    let fd = open(
+        NAME,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"hello"), 5);
+    close(fd as usize);
+    let fd = open(NAME, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    let mut buf = [0u8; 64];
+    assert_eq!(read_file(NAME, &mut buf), 5);
+    assert_eq!(&buf[..5], b"hello");
+}
+
+// 同时指定CREATE和EXCL时，路径已经存在则失败
+fn excl_test() {
+    let flags = OpenFlags::CREATE | OpenFlags::EXCL | OpenFlags::WRONLY;
+    assert_eq!(open(NAME, flags), -1);
+    // 指向不存在的文件的符号链接也算存在
+    assert_eq!(symlink("open_flags_missing\0", "open_flags_link\0"), 0);
+    assert_eq!(open("open_flags_link\0", flags), -1);
+    assert_eq!(open("open_flags_missing\0", OpenFlags::RDONLY), -1);
+    let fd = open("open_flags_excl\0", flags);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(open("open_flags_excl\0", flags), -1);
+}
+
+// APPEND：每次写入都在文件末尾，即使其他描述符在此期间写入过
+fn append_test() {
+    let a = open(NAME, OpenFlags::WRONLY | OpenFlags::APPEND);
+    let b = open(NAME, OpenFlags::WRONLY | OpenFlags::APPEND);
+    assert!(a > 0 && b > 0);
+    assert_eq!(write(a as usize, b" a"), 2);
+    assert_eq!(write(b as usize, b" b"), 2);
+    assert_eq!(write(a as usize, b" a"), 2);
+    close(a as usize);
+    close(b as usize);
+    let mut buf = [0u8; 64];
+    assert_eq!(read_file(NAME, &mut buf), 11);
+    assert_eq!(&buf[..11], b"hello a b a");
+
+    // F_SETFL清除APPEND后，从描述符自己的偏移量写入
+    let fd = open(NAME, OpenFlags::WRONLY | OpenFlags::APPEND) as usize;
+    let flags = OpenFlags::WRONLY | OpenFlags::APPEND;
+    assert_eq!(fcntl(fd, F_GETFL, 0), flags.bits() as isize);
+    assert_eq!(fcntl(fd, F_SETFL, 0), 0);
+    assert_eq!(fcntl(fd, F_GETFL, 0), OpenFlags::WRONLY.bits() as isize);
+    assert_eq!(write(fd, b"H"), 1);
+    // 读写方式不能修改，只有状态标志会改变
+    let arg = (OpenFlags::RDWR | OpenFlags::APPEND).bits() as usize;
+    assert_eq!(fcntl(fd, F_SETFL, arg), 0);
+    assert_eq!(fcntl(fd, F_GETFL, 0), flags.bits() as isize);
+    assert_eq!(write(fd, b"!"), 1);
+    close(fd);
+    assert_eq!(read_file(NAME, &mut buf), 12);
+    assert_eq!(&buf[..12], b"Hello a b a!");
+}
+
+// 跨过页边界的缓冲区。每个进程写入时，只用其中跨页的一段
+#[repr(align(4096))]
+struct TwoPages([u8; 8192]);
+static mut RECORD_BUF: TwoPages = TwoPages([0; 8192]);
+const RECORD_START: usize = 4096 - 100;
+const RECORD_SIZE: usize = 300;
+const APPENDERS: usize = 4;
+const RECORDS: usize = 20;
+
+// 多个进程用APPEND同时写入跨页的缓冲区，每条记录完整地写在一起，不会被其他进程的写入分开
+fn append_page_boundary_test() {
+    const FILE: &str = "open_flags_append\0";
+    let fd = open(
+        FILE,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    close(fd as usize);
+    let mut pids = [0isize; APPENDERS];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid == 0 {
+            let record = unsafe {
+                &mut (*core::ptr::addr_of_mut!(RECORD_BUF)).0
+                    [RECORD_START..RECORD_START + RECORD_SIZE]
+            };
+            record.fill(b'0' + i as u8);
+            let fd = open(FILE, OpenFlags::WRONLY | OpenFlags::APPEND);
+            assert!(fd > 0);
+            for _ in 0..RECORDS {
+                assert_eq!(write(fd as usize, record), RECORD_SIZE as isize);
+            }
+            close(fd as usize);
+            exit(0);
+        }
+    }
+    for pid in pids {
+        wait_child(pid);
+    }
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut counts = [0usize; APPENDERS];
+    let mut record = [0u8; RECORD_SIZE];
+    for _ in 0..APPENDERS * RECORDS {
+        assert_eq!(read(fd as usize, &mut record), RECORD_SIZE as isize);
+        assert!(record.iter().all(|&b| b == record[0]));
+        counts[(record[0] - b'0') as usize] += 1;
+    }
+    assert_eq!(read(fd as usize, &mut record), 0);
+    close(fd as usize);
+    assert_eq!(counts, [RECORDS; APPENDERS]);
+}
+
+// CLOEXEC：fork时保留，执行新程序时关闭
+fn cloexec_test() {
+    let closed = open(NAME, OpenFlags::RDONLY | OpenFlags::CLOEXEC) as usize;
+    let kept = open(NAME, OpenFlags::RDONLY) as usize;
+    assert_eq!(fcntl(closed, F_GETFD, 0), FD_CLOEXEC as isize);
+    assert_eq!(fcntl(kept, F_GETFD, 0), 0);
+    // F_SETFD修改标志
+    assert_eq!(fcntl(kept, F_SETFD, FD_CLOEXEC), 0);
+    assert_eq!(fcntl(kept, F_GETFD, 0), FD_CLOEXEC as isize);
+    assert_eq!(fcntl(kept, F_SETFD, 0), 0);
+    assert_eq!(fcntl(kept, F_GETFD, 0), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        // fork出的子进程保留描述符和它的标志
+        assert_eq!(fcntl(closed, F_GETFD, 0), FD_CLOEXEC as isize);
+        let closed_arg = format!("{}\0", closed);
+        let kept_arg = format!("{}\0", kept);
+        let args = [
+            "open_flags_test\0".as_ptr(),
+            closed_arg.as_ptr(),
+            kept_arg.as_ptr(),
+            core::ptr::null::<u8>(),
+        ];
+        exec("open_flags_test\0", &args);
+        panic!("exec open_flags_test failed");
+    }
+    wait_child(pid);
+    // 子进程执行新程序不影响父进程的描述符
+    assert_eq!(fcntl(closed, F_GETFD, 0), FD_CLOEXEC as isize);
+    close(closed);
+    close(kept);
+    assert_eq!(fcntl(closed, F_GETFD, 0), -1);
+}
+
+// 执行新程序后：设置了CLOEXEC的描述符已被关闭，其他描述符仍然可用
+fn exec_child(closed: usize, kept: usize) -> i32 {
+    assert_eq!(fcntl(closed, F_GETFD, 0), -1);
+    let mut buf = [0u8; 5];
+    assert_eq!(read(closed, &mut buf), -1);
+    assert_eq!(read(kept, &mut buf), 5);
+    assert_eq!(&buf, b"Hello");
+    0
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 3 {
+        exit(exec_child(
+            argv[1].parse().unwrap(),
+            argv[2].parse().unwrap(),
+        ));
+    }
+    create_test();
+    excl_test();
+    append_test();
+    append_page_boundary_test();
+    cloexec_test();
+    // DIRECTORY：路径指向的不是目录时失败
+    assert_eq!(open(NAME, OpenFlags::RDONLY | OpenFlags::DIRECTORY), -1);
+    let fd = open("/\0", OpenFlags::RDONLY | OpenFlags::DIRECTORY);
+    assert!(fd > 0);
+    close(fd as usize);
+    // 未知的标志位
+    assert_eq!(
+        open(NAME, unsafe { OpenFlags::from_bits_unchecked(1 << 30) }),
+        -1
+    );
+    println!("open_flags_test passed!");
+    0
+}
@@ -0,0 +1,222 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, epoll_create1, epoll_ctl, epoll_wait, exit, fcntl, fork, get_time, open, pipe, poll,
+    read, select, sleep, waitpid, write, EpollEvent, FdSet, OpenFlags, PollFd, EPOLLHUP, EPOLLIN,
+    EPOLLOUT, EPOLL_CTL_ADD, EPOLL_CTL_DEL, EPOLL_CTL_MOD, FD_CLOEXEC, F_GETFD, POLLHUP, POLLIN,
+    POLLNVAL, POLLOUT,
+};
+
+fn make_pipe() -> (usize, usize) {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    (pipe_fd[0], pipe_fd[1])
+}
+
+// fork一个子进程，睡眠ms毫秒后向fd写入一个字节
+fn write_later(fd: usize, ms: usize) -> isize {
+    let pid = fork();
+    if pid == 0 {
+        sleep(ms);
+        assert_eq!(write(fd, b"x"), 1);
+        exit(0);
+    }
+    pid
+}
+
+fn wait_child(pid: isize) {
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+fn poll_test() {
+    let (read_end, write_end) = make_pipe();
+    let mut fds = [
+        PollFd::new(read_end as i32, POLLIN),
+        PollFd::new(write_end as i32, POLLIN | POLLOUT),
+        // 负数的fd被忽略
+        PollFd::new(-1, POLLIN),
+    ];
+    // 管道为空：读端不可读，写端可写，写端不会报告可读
+    assert_eq!(poll(&mut fds, 0), 1);
+    assert_eq!(fds[0].revents, 0);
+    assert_eq!(fds[1].revents, POLLOUT);
+    assert_eq!(fds[2].revents, 0);
+
+    // 超时
+    let mut fds = [PollFd::new(read_end as i32, POLLIN)];
+    let start = get_time();
+    assert_eq!(poll(&mut fds, 50), 0);
+    assert!(get_time() - start >= 50);
+    assert_eq!(fds[0].revents, 0);
+
+    // 阻塞到其他进程写入数据
+    let pid = write_later(write_end, 50);
+    assert_eq!(poll(&mut fds, -1), 1);
+    assert_eq!(fds[0].revents, POLLIN);
+    let mut buf = [0u8; 1];
+    assert_eq!(read(read_end, &mut buf), 1);
+    wait_child(pid);
+
+    // 写端全部关闭后，读端报告POLLHUP
+    close(write_end);
+    assert_eq!(poll(&mut fds, -1), 1);
+    assert_eq!(fds[0].revents, POLLHUP);
+    close(read_end);
+
+    // 没有打开的描述符报告POLLNVAL，普通文件总是就绪
+    let file = open("poll_file\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(file > 0);
+    let mut fds = [
+        PollFd::new(200, POLLIN),
+        PollFd::new(file as i32, POLLIN | POLLOUT),
+    ];
+    assert_eq!(poll(&mut fds, -1), 2);
+    assert_eq!(fds[0].revents, POLLNVAL);
+    assert_eq!(fds[1].revents, POLLIN | POLLOUT);
+    close(file as usize);
+}
+
+fn select_test() {
+    let (read_end, write_end) = make_pipe();
+    let (other_read, other_write) = make_pipe();
+    let nfds = other_write + 1;
+    let mut readfds = FdSet::default();
+    readfds.insert(read_end);
+    readfds.insert(other_read);
+    let mut writefds = FdSet::default();
+    writefds.insert(write_end);
+    // 只有写端就绪
+    let (mut r, mut w) = (readfds, writefds);
+    assert_eq!(select(nfds, Some(&mut r), Some(&mut w), None, 0), 1);
+    assert_eq!(r, FdSet::default());
+    assert_eq!(w, writefds);
+
+    // 阻塞到other_read可读，read_end仍然不可读
+    let pid = write_later(other_write, 50);
+    let mut r = readfds;
+    assert_eq!(select(nfds, Some(&mut r), None, None, -1), 1);
+    assert!(r.contains(other_read) && !r.contains(read_end));
+    wait_child(pid);
+
+    // 超时
+    let (mut r, mut e) = (FdSet::default(), FdSet::default());
+    r.insert(read_end);
+    e.insert(read_end);
+    assert_eq!(select(nfds, Some(&mut r), None, Some(&mut e), 20), 0);
+    assert_eq!(r, FdSet::default());
+    assert_eq!(e, FdSet::default());
+
+    // 集合中有没有打开的描述符
+    close(other_read);
+    let mut r = readfds;
+    assert_eq!(select(nfds, Some(&mut r), None, None, 0), -1);
+    close(read_end);
+    close(write_end);
+    close(other_write);
+}
+
+fn epoll_test() {
+    let epfd = epoll_create1(OpenFlags::CLOEXEC);
+    assert!(epfd > 0);
+    let epfd = epfd as usize;
+    assert_eq!(fcntl(epfd, F_GETFD, 0), FD_CLOEXEC as isize);
+    assert_eq!(epoll_create1(OpenFlags::APPEND), -1);
+
+    let (read_a, write_a) = make_pipe();
+    let (read_b, write_b) = make_pipe();
+    let event_a = EpollEvent {
+        events: EPOLLIN,
+        data: 0xa,
+    };
+    let event_b = EpollEvent {
+        events: EPOLLIN,
+        data: 0xb,
+    };
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, read_a, &event_a), 0);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, read_b, &event_b), 0);
+    // 重复添加、修改或删除没有关注的描述符、关注epoll实例自己
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, read_a, &event_a), -1);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_MOD, write_a, &event_a), -1);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_DEL, write_a, &event_a), -1);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, epfd, &event_a), -1);
+    // 不支持的标志（EPOLLET）
+    let edge = EpollEvent {
+        events: EPOLLIN | 1 << 31,
+        data: 0,
+    };
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, write_a, &edge), -1);
+
+    let mut events = [EpollEvent::default(); 4];
+    assert_eq!(epoll_wait(epfd, &mut events, 20), 0);
+    // 阻塞到b可读
+    let pid = write_later(write_b, 50);
+    assert_eq!(epoll_wait(epfd, &mut events, -1), 1);
+    assert_eq!(
+        events[0],
+        EpollEvent {
+            events: EPOLLIN,
+            data: 0xb
+        }
+    );
+    wait_child(pid);
+    // 水平触发：数据没有被读走时，再次报告
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 1);
+    let mut buf = [0u8; 1];
+    assert_eq!(read(read_b, &mut buf), 1);
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 0);
+
+    // 最多返回events.len()个就绪的文件
+    assert_eq!(write(write_a, b"a"), 1);
+    assert_eq!(write(write_b, b"b"), 1);
+    assert_eq!(epoll_wait(epfd, &mut events[..1], 0), 1);
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 2);
+
+    // 修改关注的事件，删除关注的描述符
+    let out_a = EpollEvent {
+        events: EPOLLOUT,
+        data: 0xa0,
+    };
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_MOD, read_a, &out_a), 0);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_DEL, read_b, &event_b), 0);
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 0);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, write_a, &out_a), 0);
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 1);
+    assert_eq!(events[0].events, EPOLLOUT);
+
+    // epoll实例本身可以被poll
+    let mut fds = [PollFd::new(epfd as i32, POLLIN)];
+    assert_eq!(poll(&mut fds, 0), 1);
+
+    // 关闭的描述符自动从关注列表中移除
+    close(write_a);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_MOD, write_a, &out_a), -1);
+    let hup = EpollEvent {
+        events: EPOLLIN,
+        data: 0xb0,
+    };
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, read_b, &hup), 0);
+    close(write_b);
+    // read_a只关注EPOLLOUT，但仍然报告EPOLLHUP；read_b中还有数据
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 2);
+    assert_eq!(events[0].events, EPOLLHUP);
+    assert_eq!(events[1].events, EPOLLIN | EPOLLHUP);
+    close(read_a);
+    close(read_b);
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 0);
+    close(epfd);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    poll_test();
+    select_test();
+    epoll_test();
+    println!("poll_test passed!");
+    0
+}
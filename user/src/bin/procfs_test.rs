@@ -0,0 +1,118 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use user_lib::{
+    close, exit, fork, getpid, mkdir, open, read, readlink, sleep, thread_create, waitpid, waittid,
+    write, OpenFlags,
+};
+
+// 读出文件的全部内容。文件不存在时返回None。
+fn read_file(path: &str) -> Option<String> {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut content = String::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        content.push_str(core::str::from_utf8(&buf[..len as usize]).unwrap());
+    }
+    close(fd as usize);
+    Some(content)
+}
+
+fn sleeping_thread() -> ! {
+    sleep(200);
+    exit(7)
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let pid = getpid() as usize;
+
+    // 全局文件
+    assert!(read_file("/proc/meminfo\0").unwrap().contains("MemFree:"));
+    assert!(read_file("/proc/uptime\0").unwrap().contains('.'));
+    assert!(read_file("/proc/interrupts\0").unwrap().contains("timer"));
+
+    // /proc/self指向当前进程的目录
+    let mut buf = [0u8; 32];
+    let len = readlink("/proc/self\0", &mut buf);
+    assert!(len > 0);
+    assert_eq!(&buf[..len as usize], format!("{}", pid).as_bytes());
+    let status = read_file("/proc/self/status\0").unwrap();
+    assert!(status.contains(&format!("Pid:\t{}\n", pid)));
+    assert!(status.contains("State:\tR (running)\n"));
+    assert!(status.contains("Name:\tprocfs_test\n"));
+
+    // 命令行参数以'\0'分隔
+    let cmdline = read_file(&format!("/proc/{}/cmdline\0", pid)).unwrap();
+    let mut expected = String::new();
+    for arg in argv.iter().take(argc) {
+        expected.push_str(arg);
+        expected.push('\0');
+    }
+    assert_eq!(cmdline, expected);
+
+    // 地址空间和打开的文件
+    let maps = read_file("/proc/self/maps\0").unwrap();
+    assert!(maps.contains("r-xp"));
+    assert!(maps.contains("[stack]"));
+    let fd = open("/proc/self/status\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fds = read_file("/proc/self/fd\0").unwrap();
+    assert!(fds.contains("0\tr-\t/dev/console\n"));
+    assert!(fds.contains("1\t-w\t/dev/console\n"));
+    assert!(fds.contains(&format!("{}\tr-\tinode", fd)));
+    close(fd as usize);
+
+    // procfs是只读的
+    let fd = open("/proc/self/status\0", OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"x"), 0);
+    close(fd as usize);
+    assert_eq!(open("/proc/new_file\0", OpenFlags::CREATE), -1);
+    assert_eq!(mkdir("/proc/new_dir\0"), -1);
+    assert!(read_file("/proc/no_such_file\0").is_none());
+
+    // fork要求进程只有一个线程，先测试子进程。
+    // 子进程退出之后，它的目录随之消失
+    let child = fork();
+    if child == 0 {
+        sleep(200);
+        exit(0);
+    }
+    // 等子进程开始睡眠
+    sleep(20);
+    let child_status = read_file(&format!("/proc/{}/status\0", child)).unwrap();
+    assert!(child_status.contains(&format!("PPid:\t{}\n", pid)));
+    assert!(child_status.contains("State:\tS (sleeping)\n"));
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert!(read_file(&format!("/proc/{}/status\0", child)).is_none());
+
+    // 线程
+    assert!(read_file("/proc/self/task/0/status\0")
+        .unwrap()
+        .contains("Tid:\t0\n"));
+    let tid = thread_create(sleeping_thread as usize, 0) as usize;
+    let thread_status = read_file(&format!("/proc/self/task/{}/status\0", tid)).unwrap();
+    assert!(thread_status.contains("State:\tR"));
+    assert!(read_file("/proc/self/status\0")
+        .unwrap()
+        .contains("Threads:\t2\n"));
+    assert_eq!(waittid(tid), 7);
+    assert!(read_file(&format!("/proc/self/task/{}/status\0", tid)).is_none());
+    println!("procfs_test passed!");
+    0
+}
@@ -0,0 +1,272 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use user_lib::{
+    close, exit, fork, ftruncate, getrlimit, open, pipe, read, sbrk, setrlimit, sigaction,
+    sigreturn, thread_create, wait, waitpid, waittid, write, OpenFlags, RLimit, SignalAction,
+    RLIMIT_AS, RLIMIT_CPU, RLIMIT_DATA, RLIMIT_FSIZE, RLIMIT_NOFILE, RLIMIT_NPROC, RLIMIT_STACK,
+    RLIM_INFINITY, SIGKILL, SIGXCPU, SIGXFSZ,
+};
+
+const PAGE_SIZE: usize = 4096;
+const USER_STACK_SIZE: usize = 4096;
+
+static GOT_SIGNAL: AtomicBool = AtomicBool::new(false);
+
+fn thread_exit() -> ! {
+    exit(0)
+}
+
+fn signal_handler() {
+    GOT_SIGNAL.store(true, Ordering::SeqCst);
+    sigreturn();
+}
+
+fn set_handler(signum: i32) {
+    let action = SignalAction {
+        handler: signal_handler as usize,
+        ..Default::default()
+    };
+    let mut old_action = SignalAction::default();
+    assert_eq!(sigaction(signum, Some(&action), Some(&mut old_action)), 0);
+}
+
+fn set_cur(resource: usize, cur: usize) {
+    let mut limit = RLimit::default();
+    assert_eq!(getrlimit(resource, &mut limit), 0);
+    limit.cur = cur;
+    assert_eq!(setrlimit(resource, &limit), 0);
+}
+
+// 在子进程中运行f，返回子进程的退出码
+fn run_child(f: fn()) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    assert!(pid > 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+fn spin() -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+// RLIMIT_CPU：CPU时间达到软限制时发送SIGXCPU，达到硬限制时发送SIGKILL
+fn cpu_test() {
+    // 没有注册处理函数时，SIGXCPU终止进程
+    let exit_code = run_child(|| {
+        set_cur(RLIMIT_CPU, 1);
+        spin();
+    });
+    assert_eq!(exit_code, -SIGXCPU);
+
+    // 注册了处理函数时继续运行，直到硬限制
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[0]);
+        set_handler(SIGXCPU);
+        assert_eq!(setrlimit(RLIMIT_CPU, &RLimit { cur: 1, max: 2 }), 0);
+        while !GOT_SIGNAL.load(Ordering::SeqCst) {
+            core::hint::spin_loop();
+        }
+        write(pipe_fd[1], b"x");
+        spin();
+    }
+    close(pipe_fd[1]);
+    let mut buf = [0u8; 1];
+    assert_eq!(read(pipe_fd[0], &mut buf), 1);
+    close(pipe_fd[0]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGKILL);
+}
+
+fn open_xfsz_file() -> usize {
+    let fd = open(
+        "rlimit_xfsz\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    fd as usize
+}
+
+// RLIMIT_FSIZE：在限制处或之后写入时发送SIGXFSZ
+fn xfsz_test() {
+    // 没有注册处理函数时，SIGXFSZ终止进程
+    let exit_code = run_child(|| {
+        set_cur(RLIMIT_FSIZE, 10);
+        let fd = open_xfsz_file();
+        assert_eq!(write(fd, &[b'x'; 10]), 10);
+        write(fd, b"x");
+        exit(0);
+    });
+    assert_eq!(exit_code, -SIGXFSZ);
+
+    // 注册了处理函数时，写入返回-1
+    let exit_code = run_child(|| {
+        set_handler(SIGXFSZ);
+        set_cur(RLIMIT_FSIZE, 10);
+        let fd = open_xfsz_file();
+        assert_eq!(write(fd, &[b'x'; 10]), 10);
+        assert!(!GOT_SIGNAL.load(Ordering::SeqCst));
+        assert_eq!(write(fd, b"x"), -1);
+        assert!(GOT_SIGNAL.swap(false, Ordering::SeqCst));
+        // 截断文件同样受限制
+        assert_eq!(ftruncate(fd, 11), -1);
+        assert!(GOT_SIGNAL.swap(false, Ordering::SeqCst));
+        assert_eq!(ftruncate(fd, 5), 0);
+        assert!(!GOT_SIGNAL.load(Ordering::SeqCst));
+        close(fd);
+    });
+    assert_eq!(exit_code, 0);
+}
+
+// RLIMIT_DATA限制堆的大小，RLIMIT_AS限制整个地址空间的大小
+fn memory_test() {
+    let exit_code = run_child(|| {
+        let heap_bottom = sbrk(0);
+        set_cur(RLIMIT_DATA, 2 * PAGE_SIZE);
+        assert_eq!(sbrk((2 * PAGE_SIZE) as i32), heap_bottom);
+        assert_eq!(sbrk(1), -1);
+        assert_eq!(
+            sbrk(-((2 * PAGE_SIZE) as i32)),
+            heap_bottom + (2 * PAGE_SIZE) as isize
+        );
+        set_cur(RLIMIT_DATA, RLIM_INFINITY);
+
+        // 地址空间已经超过一页，不能再增长
+        set_cur(RLIMIT_AS, PAGE_SIZE);
+        assert_eq!(sbrk(PAGE_SIZE as i32), -1);
+        assert_eq!(thread_create(thread_exit as usize, 0), -1);
+        set_cur(RLIMIT_AS, RLIM_INFINITY);
+        assert_eq!(sbrk(PAGE_SIZE as i32), heap_bottom);
+    });
+    assert_eq!(exit_code, 0);
+}
+
+// RLIMIT_STACK：线程用户栈的大小不能超过软限制
+fn stack_test() {
+    let exit_code = run_child(|| {
+        set_cur(RLIMIT_STACK, USER_STACK_SIZE - 1);
+        assert_eq!(thread_create(thread_exit as usize, 0), -1);
+        set_cur(RLIMIT_STACK, USER_STACK_SIZE);
+        let tid = thread_create(thread_exit as usize, 0);
+        assert!(tid > 0);
+        assert_eq!(waittid(tid as usize), 0);
+    });
+    assert_eq!(exit_code, 0);
+}
+
+// RLIMIT_NPROC：系统中的任务数不能达到软限制，进程的每个线程都计入
+fn nproc_test() {
+    const NPROC: usize = 64;
+    let exit_code = run_child(|| {
+        set_cur(RLIMIT_NPROC, 1);
+        assert_eq!(fork(), -1);
+        assert_eq!(thread_create(thread_exit as usize, 0), -1);
+
+        // 不断创建线程，直到达到限制。已退出但还没有被等待的线程仍然计入。
+        set_cur(RLIMIT_NPROC, NPROC);
+        let mut tids = [0usize; NPROC];
+        let mut count = 0;
+        loop {
+            let tid = thread_create(thread_exit as usize, 0);
+            if tid < 0 {
+                break;
+            }
+            assert!(count < NPROC);
+            tids[count] = tid as usize;
+            count += 1;
+        }
+        assert!(count > 0);
+        assert_eq!(fork(), -1);
+        // 回收一个线程后，又可以创建新的线程
+        assert_eq!(waittid(tids[count - 1]), 0);
+        let tid = thread_create(thread_exit as usize, 0);
+        assert!(tid > 0);
+        tids[count - 1] = tid as usize;
+        for &tid in tids[..count].iter() {
+            assert_eq!(waittid(tid), 0);
+        }
+        // 所有线程都回收后，可以创建子进程
+        assert_eq!(run_child(|| {}), 0);
+    });
+    assert_eq!(exit_code, 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut limit = RLimit::default();
+    // 不支持的资源
+    assert_eq!(getrlimit(100, &mut limit), -1);
+
+    // RLIMIT_NOFILE：文件描述符不能达到软限制
+    assert_eq!(getrlimit(RLIMIT_NOFILE, &mut limit), 0);
+    let old_nofile = limit;
+    // 软限制不能超过硬限制
+    let invalid = RLimit {
+        cur: old_nofile.max + 1,
+        max: old_nofile.max,
+    };
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &invalid), -1);
+    // 已打开stdin、stdout、stderr，只剩一个文件描述符可用
+    let nofile = RLimit {
+        cur: 4,
+        max: old_nofile.max,
+    };
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &nofile), 0);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), -1);
+    let fd = open(
+        "rlimit_file\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert_eq!(fd, 3);
+    assert_eq!(open("rlimit_file\0", OpenFlags::RDONLY), -1);
+
+    // RLIMIT_FSIZE：超出限制的部分不会被写入
+    let fsize = RLimit {
+        cur: 10,
+        max: RLIM_INFINITY,
+    };
+    assert_eq!(setrlimit(RLIMIT_FSIZE, &fsize), 0);
+    assert_eq!(write(fd as usize, &[b'x'; 20]), 10);
+    close(fd as usize);
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &old_nofile), 0);
+
+    // 子进程继承资源限制
+    if fork() == 0 {
+        let mut limit = RLimit::default();
+        assert_eq!(getrlimit(RLIMIT_FSIZE, &mut limit), 0);
+        assert_eq!(limit, fsize);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    wait(&mut exit_code);
+    assert_eq!(exit_code, 0);
+
+    // 硬限制只能调低，不能调高
+    let lowered = RLimit { cur: 10, max: 10 };
+    assert_eq!(setrlimit(RLIMIT_FSIZE, &lowered), 0);
+    assert_eq!(setrlimit(RLIMIT_FSIZE, &fsize), -1);
+
+    cpu_test();
+    xfsz_test();
+    memory_test();
+    stack_test();
+    nproc_test();
+    println!("rlimit_test passed!");
+    0
+}
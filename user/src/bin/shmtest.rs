@@ -0,0 +1,133 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fork, mmap, open, shmat, shmctl, shmdt, shmget, wait, write, OpenFlags, ShmFlags,
+    ShmIdDs, IPC_PRIVATE, IPC_RMID, IPC_STAT,
+};
+
+const SHM_KEY: usize = 0x5348;
+const SHM_SIZE: usize = 8192;
+// 内核中mmap区域的结束地址
+const MMAP_AREA_END: usize = 0x40_0000_0000;
+
+// [a, a + a_len)和[b, b + b_len)是否重叠
+fn overlaps(a: usize, a_len: usize, b: usize, b_len: usize) -> bool {
+    a < b + b_len && b < a + a_len
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 不存在的key，且没有IPC_CREAT
+    assert_eq!(shmget(SHM_KEY, SHM_SIZE, ShmFlags::empty()), -1);
+    let shmid = shmget(SHM_KEY, SHM_SIZE, ShmFlags::IPC_CREAT);
+    assert!(shmid >= 0);
+    let shmid = shmid as usize;
+    // 同一个key得到同一个段
+    assert_eq!(
+        shmget(SHM_KEY, SHM_SIZE, ShmFlags::IPC_CREAT),
+        shmid as isize
+    );
+    assert_eq!(
+        shmget(SHM_KEY, SHM_SIZE, ShmFlags::IPC_CREAT | ShmFlags::IPC_EXCL),
+        -1
+    );
+
+    let addr = shmat(shmid, 0, ShmFlags::empty());
+    assert!(addr > 0);
+    let buf = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, SHM_SIZE) };
+    assert!(buf.iter().all(|&b| b == 0));
+    buf[0] = 1;
+
+    // 子进程继承挂接，并映射到相同的物理页
+    if fork() == 0 {
+        assert_eq!(buf[0], 1);
+        buf[SHM_SIZE - 1] = 42;
+        return 0;
+    }
+    let mut exit_code: i32 = 0;
+    wait(&mut exit_code);
+    assert_eq!(exit_code, 0);
+    assert_eq!(buf[SHM_SIZE - 1], 42);
+
+    // 同一个段挂接两次
+    let addr2 = shmat(shmid, 0, ShmFlags::SHM_RDONLY);
+    assert!(addr2 > 0 && addr2 != addr);
+    assert_eq!(
+        unsafe { *((addr2 as usize + SHM_SIZE - 1) as *const u8) },
+        42
+    );
+    let mut ds = ShmIdDs::default();
+    assert_eq!(shmctl(shmid, IPC_STAT, Some(&mut ds)), 0);
+    assert_eq!(ds.key, SHM_KEY);
+    assert_eq!(ds.size, SHM_SIZE);
+    assert_eq!(ds.nattch, 2);
+
+    assert_eq!(shmdt(addr2 as usize), 0);
+    assert_eq!(shmdt(addr2 as usize), -1);
+
+    // 挂接到指定地址：必须位于mmap区域内，且之后mmap和shmat分配的地址不与它重叠。
+    // addr2被释放后，正是下一次分配的地址。
+    let next = addr2 as usize;
+    assert_eq!(shmat(shmid, 0x1000, ShmFlags::empty()), -1);
+    assert_eq!(shmat(shmid, MMAP_AREA_END - 4096, ShmFlags::empty()), -1);
+    assert_eq!(shmat(shmid, next, ShmFlags::empty()), next as isize);
+    assert_eq!(shmat(shmid, next, ShmFlags::empty()), -1);
+    let addr3 = shmat(shmid, 0, ShmFlags::empty());
+    assert!(addr3 > 0 && !overlaps(addr3 as usize, SHM_SIZE, next, SHM_SIZE));
+    let fd = open(
+        "/shm_mmap\0",
+        OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"mmap"), 4);
+    let mapped = mmap(fd as usize, 4096, 0);
+    assert!(mapped > 0);
+    assert!(!overlaps(mapped as usize, 4096, next, SHM_SIZE));
+    assert!(!overlaps(mapped as usize, 4096, addr3 as usize, SHM_SIZE));
+    // mmap区域用完之后，mmap和shmat都失败，而不是越过区域的末尾
+    if fork() == 0 {
+        assert_eq!(mmap(fd as usize, MMAP_AREA_END, 0), -1);
+        // 从大到小，用不同大小的映射填满mmap区域
+        let mut chunk = 1usize << 34;
+        while chunk >= 4096 {
+            while mmap(fd as usize, chunk, 0) > 0 {}
+            chunk >>= 2;
+        }
+        assert_eq!(mmap(fd as usize, 4096, 0), -1);
+        assert_eq!(shmat(shmid, 0, ShmFlags::empty()), -1);
+        return 0;
+    }
+    wait(&mut exit_code);
+    assert_eq!(exit_code, 0);
+    close(fd as usize);
+    // 挂接到同一个段的三个地址，看到相同的内容
+    unsafe {
+        *(next as *mut u8) = 7;
+        assert_eq!(*(addr3 as *const u8), 7);
+    }
+    assert_eq!(buf[0], 7);
+    buf[0] = 1;
+    // 解除挂接后，这段地址可以再次使用
+    assert_eq!(shmdt(next), 0);
+    assert_eq!(shmat(shmid, next, ShmFlags::empty()), next as isize);
+    assert_eq!(shmdt(next), 0);
+    assert_eq!(shmdt(addr3 as usize), 0);
+    // 删除后不能再获取，但已有的挂接仍然可用
+    assert_eq!(shmctl(shmid, IPC_RMID, None), 0);
+    assert_eq!(shmat(shmid, 0, ShmFlags::empty()), -1);
+    assert_eq!(buf[0], 1);
+    assert_eq!(shmdt(addr as usize), 0);
+
+    // 私有段总是新建
+    let private1 = shmget(IPC_PRIVATE, 4096, ShmFlags::empty());
+    let private2 = shmget(IPC_PRIVATE, 4096, ShmFlags::empty());
+    assert!(private1 >= 0 && private2 >= 0 && private1 != private2);
+    assert_eq!(shmctl(private1 as usize, IPC_RMID, None), 0);
+    assert_eq!(shmctl(private2 as usize, IPC_RMID, None), 0);
+    println!("shmtest passed!");
+    0
+}
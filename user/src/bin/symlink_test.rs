@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exec, fork, open, read, readlink, symlink, waitpid, write, OpenFlags};
+
+// 读出文件的全部内容，返回读到的字节数
+fn read_file(path: &str, buf: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return fd;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(
+        "symlink_file\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"through the link"), 16);
+    close(fd as usize);
+
+    // 通过符号链接读写目标文件
+    assert_eq!(symlink("symlink_file\0", "symlink_alias\0"), 0);
+    assert_eq!(symlink("/symlink_alias\0", "symlink_chain\0"), 0);
+    let mut buf = [0u8; 64];
+    assert_eq!(read_file("symlink_chain\0", &mut buf), 16);
+    assert_eq!(&buf[..16], b"through the link");
+    // 已存在的路径不能再创建符号链接
+    assert_eq!(symlink("symlink_file\0", "symlink_alias\0"), -1);
+
+    // readlink返回目标路径本身，不跟随符号链接
+    assert_eq!(readlink("symlink_chain\0", &mut buf), 14);
+    assert_eq!(&buf[..14], b"/symlink_alias");
+    assert_eq!(readlink("symlink_alias\0", &mut buf[..4]), 4);
+    assert_eq!(&buf[..4], b"syml");
+    assert_eq!(readlink("symlink_file\0", &mut buf), -1);
+
+    // NOFOLLOW：最后一个分量是符号链接时打开失败
+    assert_eq!(
+        open("symlink_alias\0", OpenFlags::RDONLY | OpenFlags::NOFOLLOW),
+        -1
+    );
+    let fd = open("symlink_file\0", OpenFlags::RDONLY | OpenFlags::NOFOLLOW);
+    assert!(fd > 0);
+    close(fd as usize);
+
+    // 符号链接形成循环时，解析失败而不是无限跟随
+    assert_eq!(symlink("symlink_loop_b\0", "symlink_loop_a\0"), 0);
+    assert_eq!(symlink("symlink_loop_a\0", "symlink_loop_b\0"), 0);
+    assert_eq!(read_file("symlink_loop_a\0", &mut buf), -1);
+    assert_eq!(
+        open(
+            "symlink_loop_a\0",
+            OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC
+        ),
+        -1
+    );
+
+    // 通过悬空的符号链接创建文件，创建的是它指向的文件
+    assert_eq!(symlink("symlink_target\0", "symlink_dangling\0"), 0);
+    assert_eq!(read_file("symlink_dangling\0", &mut buf), -1);
+    let fd = open(
+        "symlink_dangling\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"created"), 7);
+    close(fd as usize);
+    assert_eq!(read_file("symlink_target\0", &mut buf), 7);
+    assert_eq!(&buf[..7], b"created");
+
+    // 通过符号链接执行程序
+    assert_eq!(symlink("hello_world\0", "symlink_hello\0"), 0);
+    let pid = fork();
+    if pid == 0 {
+        exec("symlink_hello\0", &[core::ptr::null::<u8>()]);
+        panic!("exec through a symlink failed");
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("symlink_test passed!");
+    0
+}
@@ -0,0 +1,131 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mkdir, mount, open, read, readlink, symlink, umount, write, OpenFlags};
+
+// 跨越多个页的文件长度
+const LEN: usize = 3 * 4096 + 100;
+
+fn pattern(i: usize) -> u8 {
+    (i % 251) as u8
+}
+
+// 分多次向fd写入LEN字节，写入的位置不按页对齐
+fn write_pattern(fd: usize) {
+    let mut chunk = [0u8; 1000];
+    let mut offset = 0;
+    while offset < LEN {
+        let len = chunk.len().min(LEN - offset);
+        for (i, byte) in chunk[..len].iter_mut().enumerate() {
+            *byte = pattern(offset + i);
+        }
+        assert_eq!(write(fd, &chunk[..len]), len as isize);
+        offset += len;
+    }
+}
+
+// 读出文件的全部内容并检查它是否是write_pattern写入的数据，返回文件的长度
+fn check_pattern(path: &str) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return fd;
+    }
+    let mut chunk = [0u8; 512];
+    let mut offset = 0;
+    loop {
+        let len = read(fd as usize, &mut chunk);
+        if len <= 0 {
+            break;
+        }
+        for (i, byte) in chunk[..len as usize].iter().enumerate() {
+            assert_eq!(*byte, pattern(offset + i));
+        }
+        offset += len as usize;
+    }
+    close(fd as usize);
+    offset as isize
+}
+
+// 读出文件的内容，返回读到的字节数
+fn read_file(path: &str, buf: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return fd;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(
+        "/tmp/tmpfs_file\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    write_pattern(fd as usize);
+    close(fd as usize);
+    assert_eq!(check_pattern("/tmp/tmpfs_file\0"), LEN as isize);
+
+    // 截断后文件为空
+    let fd = open("/tmp/tmpfs_file\0", OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"short"), 5);
+    close(fd as usize);
+    let mut buf = [0u8; 64];
+    assert_eq!(read_file("/tmp/tmpfs_file\0", &mut buf), 5);
+    assert_eq!(&buf[..5], b"short");
+
+    // 目录和符号链接
+    assert_eq!(mkdir("/tmp/tmpfs_dir\0"), 0);
+    assert_eq!(mkdir("/tmp/tmpfs_dir\0"), -1);
+    let fd = open(
+        "/tmp/tmpfs_dir/inner\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"inner"), 5);
+    close(fd as usize);
+    assert_eq!(open("/tmp/tmpfs_dir\0", OpenFlags::WRONLY), -1);
+    assert_eq!(symlink("tmpfs_dir/inner\0", "/tmp/tmpfs_link\0"), 0);
+    assert_eq!(read_file("/tmp/tmpfs_link\0", &mut buf), 5);
+    assert_eq!(&buf[..5], b"inner");
+    assert_eq!(readlink("/tmp/tmpfs_link\0", &mut buf), 15);
+    assert_eq!(&buf[..15], b"tmpfs_dir/inner");
+    // 符号链接可以指回根文件系统
+    assert_eq!(symlink("/tmp/../tmpfs_root_file\0", "/tmp/tmpfs_up\0"), 0);
+    let fd = open(
+        "/tmp/tmpfs_up\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    close(fd as usize);
+    let fd = open("/tmpfs_root_file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+
+    // 另挂载一个tmpfs，卸载之后其中的数据全部丢失
+    assert_eq!(mount("none\0", "/tmp/tmpfs_dir\0", "tmpfs\0"), 0);
+    assert_eq!(read_file("/tmp/tmpfs_dir/inner\0", &mut buf), -1);
+    let fd = open(
+        "/tmp/tmpfs_dir/scratch\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    write_pattern(fd as usize);
+    assert_eq!(check_pattern("/tmp/tmpfs_dir/scratch\0"), LEN as isize);
+    assert_eq!(umount("/tmp/tmpfs_dir\0"), -1);
+    close(fd as usize);
+    assert_eq!(umount("/tmp\0"), -1);
+    assert_eq!(umount("/tmp/tmpfs_dir\0"), 0);
+    assert_eq!(read_file("/tmp/tmpfs_dir/inner\0", &mut buf), 5);
+    assert_eq!(mount("none\0", "/tmp/tmpfs_dir\0", "tmpfs\0"), 0);
+    assert_eq!(read_file("/tmp/tmpfs_dir/scratch\0", &mut buf), -1);
+    assert_eq!(umount("/tmp/tmpfs_dir\0"), 0);
+    println!("tmpfs_test passed!");
+    0
+}
@@ -0,0 +1,99 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, ftruncate, open, pipe, read, truncate, write, OpenFlags};
+
+// 文件大小的上限约为1GB
+const TOO_LARGE: usize = 1 << 31;
+
+// 读出文件的全部内容，返回读到的字节数
+fn read_file(path: &str, buf: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return fd;
+    }
+    let mut total = 0;
+    loop {
+        let len = read(fd as usize, &mut buf[total..]);
+        if len <= 0 {
+            break;
+        }
+        total += len as usize;
+    }
+    close(fd as usize);
+    total as isize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let name = "truncate_file\0";
+    let fd = open(
+        name,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, &[b'a'; 1000]), 1000);
+
+    // 缩小到块的中间，末尾之后的内容被丢弃
+    assert_eq!(ftruncate(fd, 300), 0);
+    let mut buf = [0u8; 8192];
+    assert_eq!(read_file(name, &mut buf), 300);
+    assert!(buf[..300].iter().all(|&b| b == b'a'));
+
+    // 扩大后新增的部分是空洞，读出来是0，原来被丢弃的内容不会重新出现
+    assert_eq!(truncate(name, 5000), 0);
+    buf.fill(0xff);
+    assert_eq!(read_file(name, &mut buf), 5000);
+    assert!(buf[..300].iter().all(|&b| b == b'a'));
+    assert!(buf[300..5000].iter().all(|&b| b == 0));
+
+    // 偏移量不随截断改变，在空洞中写入后，写入位置之外仍然是0
+    assert_eq!(ftruncate(fd, 2000), 0);
+    assert_eq!(write(fd, b"hole"), 4);
+    buf.fill(0xff);
+    assert_eq!(read_file(name, &mut buf), 2000);
+    assert_eq!(&buf[1000..1004], b"hole");
+    assert!(buf[300..1000].iter().all(|&b| b == 0));
+    assert!(buf[1004..2000].iter().all(|&b| b == 0));
+
+    // 截断为0
+    assert_eq!(ftruncate(fd, 0), 0);
+    assert_eq!(read_file(name, &mut buf), 0);
+    close(fd);
+
+    // 只读打开的文件、管道、目录和不存在的文件不能截断
+    let fd = open(name, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    assert_eq!(ftruncate(fd as usize, 10), -1);
+    close(fd as usize);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(ftruncate(pipe_fd[1], 10), -1);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    assert_eq!(truncate("/\0", 10), -1);
+    assert_eq!(truncate("truncate_missing\0", 10), -1);
+    // 超过文件大小的上限
+    assert_eq!(truncate(name, TOO_LARGE), -1);
+
+    // tmpfs中的文件也能截断
+    let tmp = "/tmp/truncate_file\0";
+    let fd = open(
+        tmp,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"tmpfs data"), 10);
+    assert_eq!(ftruncate(fd as usize, 5), 0);
+    close(fd as usize);
+    assert_eq!(truncate(tmp, 8), 0);
+    assert_eq!(read_file(tmp, &mut buf), 8);
+    assert_eq!(&buf[..8], b"tmpfs\0\0\0");
+
+    println!("truncate_test passed!");
+    0
+}
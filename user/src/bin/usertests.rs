@@ -5,7 +5,7 @@
 extern crate user_lib;
 
 // not in SUCC_TESTS & FAIL_TESTS
-// count_lines, infloop, user_shell, usertests
+// count_lines, infloop, user_shell, usertests, aslr_pie
 
 // item of TESTS : app_name(argv_0), argv_1, argv_2, argv_3, exit_code
 static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
@@ -30,6 +30,22 @@ static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
     ("adder_mutex_blocking\0", "\0", "\0", "\0", 0),
     ("adder_mutex_spin\0", "\0", "\0", "\0", 0),
     ("run_pipe_test\0", "\0", "\0", "\0", 0),
+    ("shmtest\0", "\0", "\0", "\0", 0),
+    ("rlimit_test\0", "\0", "\0", "\0", 0),
+    ("aslr_test\0", "\0", "\0", "\0", 0),
+    ("fsync_test\0", "\0", "\0", "\0", 0),
+    ("symlink_test\0", "\0", "\0", "\0", 0),
+    ("mount_test\0", "\0", "\0", "\0", 0),
+    ("tmpfs_test\0", "\0", "\0", "\0", 0),
+    ("procfs_test\0", "arg1\0", "\0", "\0", 0),
+    ("devfs_test\0", "\0", "\0", "\0", 0),
+    ("getdents_test\0", "\0", "\0", "\0", 0),
+    ("truncate_test\0", "\0", "\0", "\0", 0),
+    ("flock_test\0", "\0", "\0", "\0", 0),
+    ("open_flags_test\0", "\0", "\0", "\0", 0),
+    ("dup_test\0", "\0", "\0", "\0", 0),
+    ("poll_test\0", "\0", "\0", "\0", 0),
+    ("nonblock_test\0", "\0", "\0", "\0", 0),
     ("sleep_simple\0", "\0", "\0", "\0", 0),
     ("sleep\0", "\0", "\0", "\0", 0),
     ("sleep_simple\0", "\0", "\0", "\0", 0),
@@ -74,8 +74,14 @@ bitflags! {
         const RDONLY = 0;       // 只读
         const WRONLY = 1 << 0;  // 只写
         const RDWR = 1 << 1;    // 读写
-        const CREATE = 1 << 9;  // 创建。如果文件存在，则截断文件
+        const EXCL = 1 << 7;    // 与CREATE一起使用，文件已经存在时打开失败
+        const CREATE = 1 << 9;  // 文件不存在时创建它
         const TRUNC = 1 << 10;  // 截断，即删除文件中原有的内容
+        const NONBLOCK = 1 << 11;   // 读写不阻塞
+        const APPEND = 1 << 12;     // 每次写入前，将偏移量移到文件末尾
+        const DIRECTORY = 1 << 16;  // 路径指向的不是目录时，打开失败
+        const NOFOLLOW = 1 << 17;   // 路径的最后一个分量是符号链接时，打开失败
+        const CLOEXEC = 1 << 19;    // 为文件描述符设置FD_CLOEXEC，执行新程序时关闭它
     }
 }
 
@@ -83,18 +89,126 @@ pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
 
+// 将new_fd指向old_fd打开的文件。old_fd等于new_fd时，old_fd有效则直接返回new_fd。
+pub fn dup2(old_fd: usize, new_fd: usize) -> isize {
+    if old_fd == new_fd {
+        return if sys_fcntl(old_fd, F_GETFD, 0) < 0 {
+            -1
+        } else {
+            new_fd as isize
+        };
+    }
+    sys_dup3(old_fd, new_fd, 0)
+}
+
+// 同dup2，但old_fd等于new_fd时失败。flags为OpenFlags::CLOEXEC时为new_fd设置FD_CLOEXEC。
+pub fn dup3(old_fd: usize, new_fd: usize, flags: OpenFlags) -> isize {
+    sys_dup3(old_fd, new_fd, flags.bits)
+}
+
 pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_open(path, flags.bits)
 }
 
+pub fn symlink(target: &str, linkpath: &str) -> isize {
+    sys_symlink(target, linkpath)
+}
+
+pub fn readlink(path: &str, buf: &mut [u8]) -> isize {
+    sys_readlink(path, buf)
+}
+
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdir(path)
+}
+
+pub fn mount(source: &str, target: &str, fstype: &str) -> isize {
+    sys_mount(source, target, fstype)
+}
+
+pub fn umount(target: &str) -> isize {
+    sys_umount(target)
+}
+
+// 目录项的文件类型
+pub const DT_CHR: u8 = 2;
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+pub const DT_LNK: u8 = 10;
+
+// getdents64返回的一个目录项
+pub struct DirEntry<'a> {
+    pub ino: u64,
+    // 下一项在目录中的偏移量
+    pub off: i64,
+    pub d_type: u8,
+    pub name: &'a str,
+}
+
+// 依次解析getdents64写入buf中的目录项
+pub struct DirEntries<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for DirEntries<'a> {
+    type Item = DirEntry<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.len() < 19 {
+            return None;
+        }
+        let (record, rest) = self
+            .buf
+            .split_at(u16::from_le_bytes([self.buf[16], self.buf[17]]) as usize);
+        self.buf = rest;
+        let name = &record[19..];
+        let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        Some(DirEntry {
+            ino: u64::from_le_bytes(record[0..8].try_into().unwrap()),
+            off: i64::from_le_bytes(record[8..16].try_into().unwrap()),
+            d_type: record[18],
+            name: core::str::from_utf8(&name[..len]).unwrap(),
+        })
+    }
+}
+
+// 从目录fd中读取目录项到buf中，返回读到的字节数，目录已经读完时返回0，出错返回-1。
+// 读到的目录项可以用dir_entries解析。
+pub fn getdents64(fd: usize, buf: &mut [u8]) -> isize {
+    sys_getdents64(fd, buf)
+}
+
+pub fn dir_entries(buf: &[u8]) -> DirEntries {
+    DirEntries { buf }
+}
+
+pub fn truncate(path: &str, len: usize) -> isize {
+    sys_truncate(path, len)
+}
+
+pub fn ftruncate(fd: usize, len: usize) -> isize {
+    sys_ftruncate(fd, len)
+}
+
 pub fn close(fd: usize) -> isize {
     sys_close(fd)
 }
 
+// close_range的标志：不关闭描述符，而是为它们设置FD_CLOEXEC
+pub const CLOSE_RANGE_CLOEXEC: u32 = 1 << 2;
+
+// 关闭[first, last]范围内所有打开的文件描述符。last可以是usize::MAX，表示first之后的所有描述符。
+pub fn close_range(first: usize, last: usize, flags: u32) -> isize {
+    sys_close_range(first, last, flags)
+}
+
 pub fn pipe(pipe_fd: &mut [usize]) -> isize {
     sys_pipe(pipe_fd)
 }
 
+// read、write失败时返回的错误码（返回值为它的相反数）
+pub const EAGAIN: isize = 11; // 文件设置了NONBLOCK，读写需要等待
+pub const EPIPE: isize = 32; // 管道的读端已经全部关闭，同时进程会收到SIGPIPE
+
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
@@ -102,6 +216,12 @@ pub fn read(fd: usize, buf: &mut [u8]) -> isize {
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
+pub fn sync() -> isize {
+    sys_sync()
+}
+pub fn fsync(fd: usize) -> isize {
+    sys_fsync(fd)
+}
 pub fn exit(exit_code: i32) -> ! {
     sys_exit(exit_code)
 }
@@ -250,6 +370,266 @@ bitflags! {
     }
 }
 
+pub const IPC_PRIVATE: usize = 0;
+pub const IPC_RMID: usize = 0;
+pub const IPC_STAT: usize = 2;
+
+bitflags! {
+    pub struct ShmFlags: u32 {
+        const IPC_CREAT = 0o1000;   // 段不存在时，创建它
+        const IPC_EXCL = 0o2000;    // 与IPC_CREAT一起使用，段已存在时报错
+        const SHM_RDONLY = 0o10000; // 以只读方式挂接
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+#[repr(C)]
+// 共享内存段的信息
+pub struct ShmIdDs {
+    pub key: usize,
+    pub size: usize,
+    pub nattch: usize,
+}
+
+pub fn shmget(key: usize, size: usize, flags: ShmFlags) -> isize {
+    sys_shmget(key, size, flags.bits)
+}
+pub fn shmat(shmid: usize, addr: usize, flags: ShmFlags) -> isize {
+    sys_shmat(shmid, addr, flags.bits)
+}
+pub fn shmdt(addr: usize) -> isize {
+    sys_shmdt(addr)
+}
+pub fn shmctl(shmid: usize, cmd: usize, buf: Option<&mut ShmIdDs>) -> isize {
+    sys_shmctl(shmid, cmd, buf.map_or(core::ptr::null_mut(), |b| b))
+}
+
+pub const RLIMIT_CPU: usize = 0;
+pub const RLIMIT_FSIZE: usize = 1;
+pub const RLIMIT_DATA: usize = 2;
+pub const RLIMIT_STACK: usize = 3;
+pub const RLIMIT_NPROC: usize = 6;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_AS: usize = 9;
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+#[repr(C)]
+// 资源限制：软限制cur和硬限制max
+pub struct RLimit {
+    pub cur: usize,
+    pub max: usize,
+}
+
+pub fn getrlimit(resource: usize, rlim: &mut RLimit) -> isize {
+    sys_getrlimit(resource, rlim)
+}
+pub fn setrlimit(resource: usize, rlim: &RLimit) -> isize {
+    sys_setrlimit(resource, rlim)
+}
+
+// ioctl命令
+pub const TIOCGWINSZ: usize = 0x5413;
+pub const BLKGETSIZE64: usize = 0x8008_1272;
+pub const BLKFLSBUF: usize = 0x1261;
+pub const FBIOGET_VSCREENINFO: usize = 0x4600;
+pub const FBIOPAN_DISPLAY: usize = 0x4606;
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+#[repr(C)]
+// 终端的窗口大小（TIOCGWINSZ）
+pub struct WinSize {
+    pub ws_row: u16,
+    pub ws_col: u16,
+    pub ws_xpixel: u16,
+    pub ws_ypixel: u16,
+}
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+#[repr(C)]
+// 显示器的分辨率和像素格式（FBIOGET_VSCREENINFO）
+pub struct FbVarScreenInfo {
+    pub xres: u32,
+    pub yres: u32,
+    pub bits_per_pixel: u32,
+}
+
+pub fn ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_ioctl(fd, cmd, arg)
+}
+
+// flock的操作
+pub const LOCK_SH: usize = 1;
+pub const LOCK_EX: usize = 2;
+pub const LOCK_NB: usize = 4;
+pub const LOCK_UN: usize = 8;
+
+// 对文件fd加整个文件的建议锁。锁属于打开的文件，复制的描述符和子进程共享同一把锁。
+pub fn flock(fd: usize, operation: usize) -> isize {
+    sys_flock(fd, operation)
+}
+
+// fcntl命令
+pub const F_GETFD: usize = 1;
+pub const F_SETFD: usize = 2;
+pub const F_GETFL: usize = 3;
+pub const F_SETFL: usize = 4;
+pub const F_GETLK: usize = 5;
+pub const F_SETLK: usize = 6;
+pub const F_SETLKW: usize = 7;
+
+// 文件描述符标志（F_GETFD、F_SETFD）：执行新程序时关闭该描述符
+pub const FD_CLOEXEC: usize = 1;
+
+// 记录锁的类型
+pub const F_RDLCK: i16 = 0;
+pub const F_WRLCK: i16 = 1;
+pub const F_UNLCK: i16 = 2;
+
+// Flock中l_start的起点
+pub const SEEK_SET: i16 = 0;
+pub const SEEK_CUR: i16 = 1;
+pub const SEEK_END: i16 = 2;
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+#[repr(C)]
+// 记录锁的描述（F_GETLK、F_SETLK、F_SETLKW）
+pub struct Flock {
+    pub l_type: i16,
+    pub l_whence: i16,
+    pub l_start: i64,
+    // 0表示到文件末尾之后的所有字节
+    pub l_len: i64,
+    pub l_pid: i32,
+}
+
+impl Flock {
+    // 从文件开头算起的[start, start + len)范围的记录锁
+    pub fn new(l_type: i16, start: i64, len: i64) -> Self {
+        Self {
+            l_type,
+            l_whence: SEEK_SET,
+            l_start: start,
+            l_len: len,
+            l_pid: 0,
+        }
+    }
+}
+
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
+}
+
+// 用F_GETLK、F_SETLK或F_SETLKW命令操作记录锁
+pub fn fcntl_lock(fd: usize, cmd: usize, lock: &mut Flock) -> isize {
+    sys_fcntl(fd, cmd, lock as *mut Flock as usize)
+}
+
+// poll的事件，取值与Linux相同
+pub const POLLIN: i16 = 1 << 0;
+pub const POLLOUT: i16 = 1 << 2;
+pub const POLLERR: i16 = 1 << 3;
+pub const POLLHUP: i16 = 1 << 4;
+pub const POLLNVAL: i16 = 1 << 5;
+
+#[derive(Copy, Clone, Default, Debug)]
+#[repr(C)]
+// poll检查的一个文件描述符。fd为负数时忽略该项。
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+impl PollFd {
+    pub fn new(fd: i32, events: i16) -> Self {
+        Self {
+            fd,
+            events,
+            revents: 0,
+        }
+    }
+}
+
+// 等待fds中的任意一个文件描述符就绪。timeout为超时时间（毫秒），为负数时一直等待。
+pub fn poll(fds: &mut [PollFd], timeout: isize) -> isize {
+    sys_poll(fds, timeout)
+}
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+#[repr(C)]
+// select使用的文件描述符集合，最多容纳1024个描述符
+pub struct FdSet {
+    bits: [u64; 16],
+}
+
+impl FdSet {
+    pub fn insert(&mut self, fd: usize) {
+        self.bits[fd / 64] |= 1 << (fd % 64);
+    }
+    pub fn contains(&self, fd: usize) -> bool {
+        self.bits[fd / 64] & (1 << (fd % 64)) != 0
+    }
+}
+
+fn fd_set_ptr(set: Option<&mut FdSet>) -> *mut FdSet {
+    set.map_or(core::ptr::null_mut(), |set| set as *mut FdSet)
+}
+
+// 等待[0, nfds)中的文件描述符可读或可写。返回时，各个集合中只留下就绪的描述符。
+pub fn select(
+    nfds: usize,
+    readfds: Option<&mut FdSet>,
+    writefds: Option<&mut FdSet>,
+    exceptfds: Option<&mut FdSet>,
+    timeout: isize,
+) -> isize {
+    sys_select(
+        nfds,
+        fd_set_ptr(readfds),
+        fd_set_ptr(writefds),
+        fd_set_ptr(exceptfds),
+        timeout,
+    )
+}
+
+// epoll的事件
+pub const EPOLLIN: u32 = 1 << 0;
+pub const EPOLLOUT: u32 = 1 << 2;
+pub const EPOLLERR: u32 = 1 << 3;
+pub const EPOLLHUP: u32 = 1 << 4;
+
+// epoll_ctl的操作
+pub const EPOLL_CTL_ADD: usize = 1;
+pub const EPOLL_CTL_DEL: usize = 2;
+pub const EPOLL_CTL_MOD: usize = 3;
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+#[repr(C)]
+// epoll关注的事件，data由epoll_wait原样返回
+pub struct EpollEvent {
+    pub events: u32,
+    pub data: u64,
+}
+
+// 创建epoll实例。flags为空或OpenFlags::CLOEXEC。
+pub fn epoll_create1(flags: OpenFlags) -> isize {
+    sys_epoll_create1(flags.bits)
+}
+
+pub fn epoll_ctl(epfd: usize, op: usize, fd: usize, event: &EpollEvent) -> isize {
+    sys_epoll_ctl(epfd, op, fd, event)
+}
+
+// 等待epoll实例关注的文件就绪，最多返回events.len()个就绪的文件
+pub fn epoll_wait(epfd: usize, events: &mut [EpollEvent], timeout: isize) -> isize {
+    sys_epoll_wait(epfd, events, timeout)
+}
+
+pub fn mmap(fd: usize, len: usize, offset: usize) -> isize {
+    sys_mmap(fd, len, offset)
+}
+
 pub fn kill(pid: usize, signum: i32) -> isize {
     sys_kill(pid, signum)
 }
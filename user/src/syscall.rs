@@ -1,12 +1,31 @@
-use crate::SignalAction;
+use crate::{EpollEvent, FdSet, PollFd, RLimit, ShmIdDs, SignalAction};
 
 // 系统调用号
+const SYSCALL_EPOLL_CREATE: usize = 20;
+const SYSCALL_EPOLL_CTL: usize = 21;
+const SYSCALL_EPOLL_WAIT: usize = 22;
 const SYSCALL_DUP: usize = 24;
+const SYSCALL_FCNTL: usize = 25;
+const SYSCALL_DUP3: usize = 26;
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_FLOCK: usize = 32;
+const SYSCALL_MKDIR: usize = 34;
+const SYSCALL_SYMLINK: usize = 36;
+const SYSCALL_UMOUNT: usize = 39;
+const SYSCALL_MOUNT: usize = 40;
+const SYSCALL_TRUNCATE: usize = 45;
+const SYSCALL_FTRUNCATE: usize = 46;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE: usize = 59;
+const SYSCALL_GETDENTS64: usize = 61;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_SELECT: usize = 72;
+const SYSCALL_POLL: usize = 73;
+const SYSCALL_READLINK: usize = 78;
+const SYSCALL_SYNC: usize = 81;
+const SYSCALL_FSYNC: usize = 82;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_SLEEP: usize = 101;
 const SYSCALL_YIELD: usize = 124;
@@ -14,12 +33,20 @@ const SYSCALL_KILL: usize = 129;
 const SYSCALL_SIGACTION: usize = 134;
 const SYSCALL_SIGPROCMASK: usize = 135;
 const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_GETRLIMIT: usize = 163;
+const SYSCALL_SETRLIMIT: usize = 164;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_SBRK: usize = 214;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_CLOSE_RANGE: usize = 436;
 const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GETTID: usize = 1001;
 const SYSCALL_WAITTID: usize = 1002;
@@ -39,10 +66,71 @@ pub fn sys_open(path: &str, flags: u32) -> isize {
     syscall(SYSCALL_OPEN, [path.as_ptr() as usize, flags as usize, 0])
 }
 
+// 创建指向target的符号链接linkpath
+// - 返回值：成功返回0，linkpath已存在等情况返回-1。
+pub fn sys_symlink(target: &str, linkpath: &str) -> isize {
+    syscall(
+        SYSCALL_SYMLINK,
+        [target.as_ptr() as usize, linkpath.as_ptr() as usize, 0],
+    )
+}
+
+// 创建目录path
+// - 返回值：成功返回0，path已存在等情况返回-1。
+pub fn sys_mkdir(path: &str) -> isize {
+    syscall(SYSCALL_MKDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+// 将块设备source上、类型为fstype的文件系统挂载到目录target上
+// - 返回值：成功返回0，失败返回-1。
+pub fn sys_mount(source: &str, target: &str, fstype: &str) -> isize {
+    syscall(
+        SYSCALL_MOUNT,
+        [
+            source.as_ptr() as usize,
+            target.as_ptr() as usize,
+            fstype.as_ptr() as usize,
+        ],
+    )
+}
+
+// 卸载挂载在target上的文件系统
+// - 返回值：成功返回0，target不是挂载点或文件系统正被使用时返回-1。
+pub fn sys_umount(target: &str) -> isize {
+    syscall(SYSCALL_UMOUNT, [target.as_ptr() as usize, 0, 0])
+}
+
+// 读出符号链接path的目标路径
+// - 返回值：写入buf的字节数（不以\0结尾），path不是符号链接时返回-1。
+pub fn sys_readlink(path: &str, buf: &mut [u8]) -> isize {
+    syscall(
+        SYSCALL_READLINK,
+        [path.as_ptr() as usize, buf.as_mut_ptr() as usize, buf.len()],
+    )
+}
+
+// 将路径path指向的常规文件的长度改为len。扩大时新增的部分读出来是0。
+// - 返回值：成功返回0，path不是常规文件或len超过文件大小的上限时返回-1。
+pub fn sys_truncate(path: &str, len: usize) -> isize {
+    syscall(SYSCALL_TRUNCATE, [path.as_ptr() as usize, len, 0])
+}
+
+// 将文件fd的长度改为len，fd必须以可写的方式打开
+// - 返回值：成功返回0，失败返回-1。
+pub fn sys_ftruncate(fd: usize, len: usize) -> isize {
+    syscall(SYSCALL_FTRUNCATE, [fd, len, 0])
+}
+
 pub fn sys_close(fd: usize) -> isize {
     syscall(SYSCALL_CLOSE, [fd, 0, 0])
 }
 
+// 关闭[first, last]范围内所有打开的文件描述符
+// - flags：CLOSE_RANGE_CLOEXEC表示不关闭，而是设置FD_CLOEXEC
+pub fn sys_close_range(first: usize, last: usize, flags: u32) -> isize {
+    syscall(SYSCALL_CLOSE_RANGE, [first, last, flags as usize])
+}
+
 // 创建管道
 // - pipe：将管道创建的读和写的两个文件描述符，写入pipe数组中
 // - 返回值：成功返回0，失败返回-1。
@@ -50,6 +138,14 @@ pub fn sys_pipe(pipe: &mut [usize]) -> isize {
     syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0])
 }
 
+// 从目录fd中读取目录项，以linux_dirent64的格式写入buf
+pub fn sys_getdents64(fd: usize, buf: &mut [u8]) -> isize {
+    syscall(
+        SYSCALL_GETDENTS64,
+        [fd, buf.as_mut_ptr() as usize, buf.len()],
+    )
+}
+
 // 复制文件描述符到新的文件描述符
 // - fd：待复制的文件描述符
 // - 返回值：成功返回新的文件描述符（会是最小的可用文件描述符），失败返回-1。
@@ -57,6 +153,103 @@ pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
 
+// 复制文件描述符到指定的文件描述符
+// - old_fd：待复制的文件描述符
+// - new_fd：目标文件描述符。如果它已经打开，则先关闭它，替换是原子的。
+// - flags：0或OpenFlags::CLOEXEC
+// - 返回值：成功返回new_fd，失败返回-1。old_fd等于new_fd时也失败。
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    syscall(SYSCALL_DUP3, [old_fd, new_fd, flags as usize])
+}
+
+// 等待多个文件描述符就绪
+// - fds：要检查的文件描述符和关心的事件。返回时，revents被设为就绪的事件。
+// - timeout：超时时间（毫秒），为0时不等待，为负数时一直等待。
+// - 返回值：就绪的文件描述符数，超时返回0，出错返回-1。
+pub fn sys_poll(fds: &mut [PollFd], timeout: isize) -> isize {
+    syscall(
+        SYSCALL_POLL,
+        [fds.as_mut_ptr() as usize, fds.len(), timeout as usize],
+    )
+}
+
+// 等待[0, nfds)中的文件描述符可读或可写。返回时，各个集合中只留下就绪的文件描述符。
+// - 返回值：就绪的文件描述符总数，超时返回0，出错返回-1。
+pub fn sys_select(
+    nfds: usize,
+    readfds: *mut FdSet,
+    writefds: *mut FdSet,
+    exceptfds: *mut FdSet,
+    timeout: isize,
+) -> isize {
+    syscall6(
+        SYSCALL_SELECT,
+        [
+            nfds,
+            readfds as usize,
+            writefds as usize,
+            exceptfds as usize,
+            timeout as usize,
+            0,
+        ],
+    )
+}
+
+// 创建epoll实例，返回它的文件描述符
+pub fn sys_epoll_create1(flags: u32) -> isize {
+    syscall(SYSCALL_EPOLL_CREATE, [flags as usize, 0, 0])
+}
+
+// 修改epoll实例关注的文件描述符
+pub fn sys_epoll_ctl(epfd: usize, op: usize, fd: usize, event: *const EpollEvent) -> isize {
+    syscall6(SYSCALL_EPOLL_CTL, [epfd, op, fd, event as usize, 0, 0])
+}
+
+// 等待epoll实例关注的文件就绪，返回就绪的文件数，超时返回0
+pub fn sys_epoll_wait(epfd: usize, events: &mut [EpollEvent], timeout: isize) -> isize {
+    syscall6(
+        SYSCALL_EPOLL_WAIT,
+        [
+            epfd,
+            events.as_mut_ptr() as usize,
+            events.len(),
+            timeout as usize,
+            0,
+            0,
+        ],
+    )
+}
+
+// 对设备文件执行控制命令
+// - fd：设备文件的文件描述符
+// - cmd：命令，取值与Linux相同
+// - arg：命令的参数，通常是参数结构的地址
+// - 返回值：成功返回0，失败返回-1。
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd, arg])
+}
+
+// 对文件加整个文件的建议锁
+// - operation：LOCK_SH、LOCK_EX或LOCK_UN，可以与LOCK_NB组合
+// - 返回值：成功返回0，失败或指定了LOCK_NB而锁被占用时返回-1。
+pub fn sys_flock(fd: usize, operation: usize) -> isize {
+    syscall(SYSCALL_FLOCK, [fd, operation, 0])
+}
+
+// 对文件执行控制命令
+// - cmd：命令，如F_SETLK
+// - arg：命令的参数。记录锁的命令中是Flock的地址。
+// - 返回值：成功返回0，失败返回-1。
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_FCNTL, [fd, cmd, arg])
+}
+
+// 将文件fd中从offset开始、长度为len的部分映射到内存
+// - 返回值：成功返回映射的起始地址，失败返回-1。
+pub fn sys_mmap(fd: usize, len: usize, offset: usize) -> isize {
+    syscall(SYSCALL_MMAP, [fd, len, offset])
+}
+
 // 读取文件到内存缓冲区
 // - fd：待读取文件的文件描述符；
 // - buf：缓冲区的起始地址。读出的最大长度为buf.len()。
@@ -78,6 +271,14 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
 
+pub fn sys_sync() -> isize {
+    syscall(SYSCALL_SYNC, [0, 0, 0])
+}
+
+pub fn sys_fsync(fd: usize) -> isize {
+    syscall(SYSCALL_FSYNC, [fd, 0, 0])
+}
+
 // 退出应用程序
 pub fn sys_exit(exit_code: i32) -> ! {
     syscall(SYSCALL_EXIT, [exit_code as usize, 0, 0]);
@@ -106,6 +307,19 @@ pub fn sys_kill(pid: usize, signal: i32) -> isize {
     syscall(SYSCALL_KILL, [pid, signal as usize, 0])
 }
 
+// 获取当前进程的资源限制
+// - resource：资源的编号，如RLIMIT_NOFILE
+// - 返回值：成功返回0，失败返回-1（如资源不存在）
+pub fn sys_getrlimit(resource: usize, rlim: *mut RLimit) -> isize {
+    syscall(SYSCALL_GETRLIMIT, [resource, rlim as usize, 0])
+}
+
+// 设置当前进程的资源限制。软限制不能超过硬限制，硬限制只能调低。
+// - 返回值：成功返回0，失败返回-1
+pub fn sys_setrlimit(resource: usize, rlim: *const RLimit) -> isize {
+    syscall(SYSCALL_SETRLIMIT, [resource, rlim as usize, 0])
+}
+
 // 获取CPU时间（ms）
 pub fn sys_get_time() -> isize {
     syscall(SYSCALL_GET_TIME, [0, 0, 0])
@@ -115,6 +329,28 @@ pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+// 获取共享内存段的标识符。key不存在且flags包含IPC_CREAT时，创建大小为size的段。
+// - 返回值：成功返回标识符，失败返回-1。
+pub fn sys_shmget(key: usize, size: usize, flags: u32) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size, flags as usize])
+}
+
+// 控制共享内存段。cmd为IPC_RMID时删除该段，为IPC_STAT时将段信息写入buf。
+pub fn sys_shmctl(shmid: usize, cmd: usize, buf: *mut ShmIdDs) -> isize {
+    syscall(SYSCALL_SHMCTL, [shmid, cmd, buf as usize])
+}
+
+// 将共享内存段挂接到地址addr（为0时由内核选择）。
+// - 返回值：成功返回挂接的虚拟地址，失败返回-1。
+pub fn sys_shmat(shmid: usize, addr: usize, flags: u32) -> isize {
+    syscall(SYSCALL_SHMAT, [shmid, addr, flags as usize])
+}
+
+// 解除挂接在addr处的共享内存段
+pub fn sys_shmdt(addr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [addr, 0, 0])
+}
+
 // 复制出一个子进程，返回子进程的PID
 pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
@@ -167,6 +403,25 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+// 同syscall，用于参数多于3个的系统调用，参数依次放在寄存器x10到x15（a0到a5）中
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    use core::arch::asm;
+    let mut ret: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") args[0] => ret,
+            in("x11") args[1],
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x14") args[4],
+            in("x15") args[5],
+            in("x17") id
+        );
+    }
+    ret
+}
+
 // 为当前进程注册信号处理函数
 // - signum：信号的编号
 // - action：要注册的信号处理函数的指针